@@ -0,0 +1,93 @@
+// ★テスト用のモックスワップ（convert_vault の CPI 先）
+// SOL を受け取り、固定レートでテスト用のステーブルコインを発行する
+use anchor_lang::prelude::*;
+use anchor_spl::token::{self, Mint, MintTo, Token, TokenAccount};
+
+declare_id!("FxFSsbmt1eZuDamN1AhhVHjbjLMh1onyni67Xa42bxYT");
+
+// 1 SOL = 150 トークン（decimals 6）
+const TOKEN_DECIMALS: u8 = 6;
+const TOKENS_PER_SOL: u64 = 150_000_000;
+const LAMPORTS_PER_SOL: u64 = 1_000_000_000;
+
+#[program]
+pub mod mock_swap {
+    use super::*;
+
+    // ★出力トークンのMint作成（Mint・発行権限ともにPDA）
+    pub fn initialize_mint(_ctx: Context<InitializeMint>) -> Result<()> {
+        Ok(())
+    }
+
+    // ★lamports_in の SOL をプールへ送り、固定レートでトークンを発行する
+    pub fn swap(ctx: Context<Swap>, lamports_in: u64) -> Result<()> {
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.user.to_account_info(),
+                to: ctx.accounts.pool.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_context, lamports_in)?;
+
+        let amount_out =
+            (lamports_in as u128 * TOKENS_PER_SOL as u128 / LAMPORTS_PER_SOL as u128) as u64;
+        let bump = [ctx.bumps.mint_authority];
+        let signer_seeds: &[&[&[u8]]] = &[&[b"mint_authority", &bump]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.mint_authority.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::mint_to(cpi_context, amount_out)
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeMint<'info> {
+    #[account(
+        init,
+        payer = payer,
+        seeds = [b"mint"],
+        bump,
+        mint::decimals = TOKEN_DECIMALS,
+        mint::authority = mint_authority,
+    )]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: 発行権限のPDA（データは持たない）
+    #[account(seeds = [b"mint_authority"], bump)]
+    pub mint_authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Swap<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    // ★受け取った SOL の保管先
+    #[account(mut, seeds = [b"pool"], bump)]
+    pub pool: SystemAccount<'info>,
+
+    #[account(mut, seeds = [b"mint"], bump)]
+    pub mint: Account<'info, Mint>,
+
+    /// CHECK: 発行権限のPDA（データは持たない）
+    #[account(seeds = [b"mint_authority"], bump)]
+    pub mint_authority: UncheckedAccount<'info>,
+
+    #[account(mut, token::mint = mint)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
@@ -0,0 +1,99 @@
+// ★テスト用の統合先プログラム（持ち主がPDAのVaultを CPI で作って入金する）
+// save-to-grow の user には、このプログラムの PDA（seeds = [b"owner"]）を invoke_signed で署名させて渡す
+use anchor_lang::prelude::*;
+use save_to_grow::program::SaveToGrow;
+
+declare_id!("Gqa4j6hy4t9ZJ1XxqHJovqhovvWnfjjSa6KnRSzRdJN1");
+
+#[program]
+pub mod mock_vault_owner {
+    use super::*;
+
+    // ★PDAが持ち主のVaultを作る（レントはPDAの残高から払われる）
+    pub fn create_vault(ctx: Context<CreateVault>) -> Result<()> {
+        let bump = [ctx.bumps.owner];
+        let signer_seeds: &[&[&[u8]]] = &[&[b"owner", &bump]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.save_to_grow_program.to_account_info(),
+            save_to_grow::cpi::accounts::Initialize {
+                vault: ctx.accounts.vault.to_account_info(),
+                reward_box: ctx.accounts.reward_box.to_account_info(),
+                config: ctx.accounts.config.to_account_info(),
+                user_stats: ctx.accounts.user_stats.to_account_info(),
+                user: ctx.accounts.owner.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            },
+            signer_seeds,
+        );
+        save_to_grow::cpi::initialize(cpi_context)
+    }
+
+    // ★PDAの残高から amount をVaultに入金する
+    pub fn deposit(ctx: Context<DepositToVault>, amount: u64) -> Result<()> {
+        let bump = [ctx.bumps.owner];
+        let signer_seeds: &[&[&[u8]]] = &[&[b"owner", &bump]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.save_to_grow_program.to_account_info(),
+            save_to_grow::cpi::accounts::Deposit {
+                vault: ctx.accounts.vault.to_account_info(),
+                reward_box: ctx.accounts.reward_box.to_account_info(),
+                config: ctx.accounts.config.to_account_info(),
+                global_emission: ctx.accounts.global_emission.to_account_info(),
+                treasury: ctx.accounts.treasury.to_account_info(),
+                match_pool: None,
+                deposit_sources: None,
+                user: ctx.accounts.owner.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+            },
+            signer_seeds,
+        );
+        save_to_grow::cpi::deposit(cpi_context, amount, None)
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreateVault<'info> {
+    // ★Vaultの持ち主になるPDA（データを持たないシステムアカウントで、レントの支払いにも使う）
+    #[account(mut, seeds = [b"owner"], bump)]
+    pub owner: SystemAccount<'info>,
+
+    /// CHECK: save-to-grow 側で検証・作成される
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+    /// CHECK: save-to-grow 側で検証・作成される
+    #[account(mut)]
+    pub reward_box: UncheckedAccount<'info>,
+    /// CHECK: save-to-grow 側で検証される
+    #[account(mut)]
+    pub config: UncheckedAccount<'info>,
+    /// CHECK: save-to-grow 側で検証・作成される
+    #[account(mut)]
+    pub user_stats: UncheckedAccount<'info>,
+
+    pub save_to_grow_program: Program<'info, SaveToGrow>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DepositToVault<'info> {
+    #[account(mut, seeds = [b"owner"], bump)]
+    pub owner: SystemAccount<'info>,
+
+    /// CHECK: save-to-grow 側で検証される
+    #[account(mut)]
+    pub vault: UncheckedAccount<'info>,
+    /// CHECK: save-to-grow 側で検証される
+    #[account(mut)]
+    pub reward_box: UncheckedAccount<'info>,
+    /// CHECK: save-to-grow 側で検証される
+    pub config: UncheckedAccount<'info>,
+    /// CHECK: save-to-grow 側で検証される
+    #[account(mut)]
+    pub global_emission: UncheckedAccount<'info>,
+    /// CHECK: save-to-grow 側で検証される
+    #[account(mut)]
+    pub treasury: UncheckedAccount<'info>,
+
+    pub save_to_grow_program: Program<'info, SaveToGrow>,
+    pub system_program: Program<'info, System>,
+}
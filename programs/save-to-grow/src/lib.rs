@@ -1,188 +1,6890 @@
 use anchor_lang::prelude::*;
-use anchor_lang::system_program; // システムプログラム（送金用）を利用
+use anchor_spl::associated_token::AssociatedToken;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke;
+use anchor_spl::token::spl_token::instruction::AuthorityType;
+use anchor_spl::token::{self, Mint, MintTo, SetAuthority, Token, TokenAccount, Transfer};
 
 // ★ご自身のProgram IDのままにしてください
 declare_id!("5Y7L91KtvUumZo5fXLXtbCfpHRNYsLmV6kwsSBRUsvxT");
 
+// ★報酬計算方式のパラメータ
+const TIER_THRESHOLD: u64 = 5_000_000_000; // Tiered: 5 SOLを超えた部分は倍率アップ
+const TIER_MULTIPLIER: u128 = 2; // Tiered: しきい値超過部分の倍率
+const REWARD_BALANCE_CAP: u64 = 5_000_000_000; // Capped: 報酬対象になる残高の上限（5 SOL）
+// ★基本の利率: 毎秒 残高 / REWARD_RATE_DENOMINATOR（0.01%/秒 = 0.6%/分 = 36%/時間 = 864%/日、単利）
+const REWARD_RATE_DENOMINATOR: u128 = 10000;
+
+// ★リワード→ロック付き元本への変換ボーナス（11000 / 10000 = 1.1倍）
+const LOCK_BONUS_BPS: u128 = 11000;
+
+// ★価格フィード（Pyth Pull Oracle の PriceUpdateV2 アカウント）の所有者
+const PYTH_RECEIVER_ID: Pubkey = pubkey!("rec5EKMGg6MxZYaMdyBfgwp4d5rB9T1VQH5pJv5LtFJ");
+const LAMPORTS_PER_SOL: u128 = 1_000_000_000;
+// ★リワードの端数を 1/10^17 ポイント単位で繰り越す
+// 基本の利率（/10^4）、経年減衰・利用率・目標達成の倍率（各 /10^4）、ロックの上乗せ（/10）がすべて割り切れる桁数にしている。
+// 途中で切り捨てが起きないので、倍率が変わらない間は確定を何回に分けても通しの確定と同じリワードになる
+const REWARD_ACC_SCALE: u128 = 100_000_000_000_000_000;
+// ★連続複利（RewardStrategy::Continuous）の e^x の固定小数点近似
+const EXP_FIXED_SCALE: u128 = 1_000_000_000_000; // 固定小数点の単位（10^12 = 1.0、REWARD_ACC_SCALE を割り切る）
+const EXP_TAYLOR_TERMS: u128 = 8; // テイラー展開で計算する項数
+const EXP_MAX_X: u128 = 32 * EXP_FIXED_SCALE; // 一度に展開する rate × t の上限（超える分は区間に分ける。2乗の途中でも u128 に収まる）
+const MAX_VAULT_PAIRS: usize = 8; // crank_accrue / batch_claim で一度に処理する (Vault, RewardBox) の上限
+const MAX_SWAP_ROUTE_LEN: usize = 256; // convert_vault の route（スワップの命令データ）の上限（バイト）
+const MAX_SWAP_ACCOUNTS: usize = 16; // convert_vault でスワップに渡す remaining_accounts の上限
+const MAX_AGE_DEPOSIT_CAPS: usize = 4; // Vaultの経過時間ごとの元本の上限（Config.age_deposit_caps）の段階数
+const REWARD_POINT_DECIMALS: u8 = 9; // リワードポイントは lamport 単位（1 SOL = 10^9 ポイント）
+const MAX_RATE_BPS: u16 = 10000; // 設定できる bps の上限（100%）
+const MAX_RATE_MULTIPLIER_BPS: u16 = 50000; // 利率の倍率（default_reward_rate_bps）と rate_delta_bps の幅の上限（基本の利率の5倍）
+const MAX_WITHDRAW_DUST_TOLERANCE: u64 = 10_000; // 出金時に端数として扱える残高の上限（lamports）
+const LEADERBOARD_SIZE: usize = 5; // リーダーボードに載るVaultの数
+const REWARD_HISTORY_LEN: usize = 16; // リワード履歴（RewardHistory）に残す確定の件数
+const BALANCE_SNAPSHOT_LEN: usize = 8; // 残高の記録（BalanceSnapshots）に残す件数
+const MAX_DEPOSIT_SOURCES: usize = 4; // 入金元の記録（DepositSources）に残すアドレスの数
+const COMPACT_VAULT_TOUCHED_TAG: u8 = 0xc1; // CompactVaultTouched のバイト列の先頭1byte
+const GROWTH_VAULT_NAME: &[u8] = b"growth"; // リワードの運用先の名前付きVault（move_rewards_to_growth で作る）
+
+// ★アカウントのレイアウトのバージョン（0 = version 導入前。migrate_vault で更新する）
+const VAULT_VERSION: u8 = 1;
+const REWARD_BOX_VERSION: u8 = 2; // ★2 = liability_recorded を追加
+
+// ★焼却アドレス（ここに送られたlamportはスロット終了時に消滅する）
+const INCINERATOR_ID: Pubkey = pubkey!("1nc1nerator11111111111111111111111111111111");
+
 #[program]
 pub mod save_to_grow {
     use super::*;
 
-    // 1. 初期化（金庫とリワードBOXを作る）
-    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
-        // Vaultの初期化
-        let vault = &mut ctx.accounts.vault;
-        vault.user = ctx.accounts.user.key();
-        vault.balance = 0;
-        vault.bump = ctx.bumps.vault;
-        
-        // ★リワード計算用に現在時刻を記録
-        let clock = Clock::get()?;
-        vault.last_update_time = clock.unix_timestamp;
+    // 0. 全体設定（Config）の初期化。実行者が管理者になる
+    pub fn initialize_config(ctx: Context<InitializeConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.bump = ctx.bumps.config;
+        config.reward_strategy = RewardStrategy::Linear;
+        config.prev_reward_strategy = RewardStrategy::Linear;
+        config.last_config_update = 0;
+        config.config_version = 0;
+        config.large_withdraw_threshold = 0; // 0 = 出金キュー無効
+        config.withdraw_delay_secs = 0;
+        config.deposit_granularity = 0; // 0 = 制限なし
+        config.deposit_rounding = DepositRounding::Reject;
+        config.price_feed = Pubkey::default(); // 未設定
+        config.max_price_age_secs = 60;
+        config.decay_period_secs = 0; // 0 = 減衰なし
+        config.decay_bps_per_period = 0;
+        config.decay_floor_bps = 10000;
+        config.max_transfers_per_day = 0; // 0 = 無制限
+        config.shutdown = false;
+        config.deposit_fee_bps = 0; // 0 = 手数料なし
+        config.withdraw_fee_bps = 0;
+        config.fee_grace_period_secs = 0;
+        config.reward_start_delay_secs = 0; // 0 = 入金直後からリワード対象
+        config.early_withdraw_penalty_bps = 0; // 0 = ロック中の出金は不可
+        config.penalty_destination = PenaltyDestination::Treasury;
+        config.charity = Pubkey::default();
+        config.min_reserve_ratio_bps = 10000; // 未払いリワードの100%
+        config.utilization_half_point = 0; // 0 = 利用率による調整なし
+        config.max_lock_duration_secs = 0; // 0 = 上限なし
+        config.accrue_while_frozen = false; // 凍結中はリワードを付与しない
+        config.claim_fee_bps = 0; // 0 = 手数料なし
+        config.min_accrual_interval_secs = 0; // 0 = 毎回確定する
+        config.goal_bonus_bps = 0; // 0 = 上乗せなし
+        config.reward_token_decimals = REWARD_POINT_DECIMALS; // ポイントとトークンが1:1
+        config.locked_reward_mode = LockedRewardMode::Base; // ロック中も他の元本と同じ利率
+        config.min_deposit_interval_secs = 0; // 0 = 入金の頻度を制限しない
+        config.withdraw_dust_tolerance = 0; // 0 = 端数の切り上げなし
+        config.accrual_period_secs = 0; // 0 = 秒単位で確定する
+        config.min_accrual_per_interval = 0; // 0 = 最低保証なし
+        config.age_deposit_cap_count = 0; // 0 = 経過時間による上限なし
+        config.age_deposit_caps = [AgeDepositCap::default(); MAX_AGE_DEPOSIT_CAPS];
+        config.max_vaults_per_user = 0; // 0 = ユーザーごとのVault数の上限なし
+        config.transfer_cancel_window_secs = 0; // 0 = transfer はすぐに送金する
+        config.referral_level1_bps = 0; // 0 = 紹介者へのボーナスなし
+        config.referral_level2_bps = 0;
+        config.max_accrual_per_call = 0; // 0 = 1回の確定額の上限なし
+        config.reject_deposit_when_goal_locked = false; // 目標達成・ロック中のVaultへの入金も受け付ける
+        config.flat_reward_per_deposit = 0; // 0 = 定額リワードのVaultにも付かない
+        config.reward_forfeit_on_withdraw_bps = 0; // 0 = 出金してもリワードは減らない
+        config.reward_min_balance = 0; // 0 = 元本があればリワードが付く
+        config.require_recipient_opt_in = false; // transfer はどのウォレットにも送れる
+        config.default_reward_rate_bps = 10000; // 基本の利率（毎秒0.01%）のまま
+        config.match_ratio_bps = 0; // 0 = MatchPool を渡しても上乗せしない
+        config.compact_events = false; // VaultTouchedEvent を通常の Anchor イベントで出す
+        config.accrual_window_start_secs = 0; // start == end = 時間帯の制限なし
+        config.accrual_window_end_secs = 0;
+        config.large_deposit_bonus_bps = 0; // 0 = 大口入金のボーナスなし
+        config.growth_rate_bonus_bps = 0; // 0 = 運用先のVaultも通常と同じ利率
+        config.max_accrual_lookback_secs = 0; // 0 = 経過秒数をさかのぼれる上限なし
+        config.withdrawal_score_penalty_bps = 0; // 0 = 出金の頻度で利率を下げない
+        config.withdrawal_score_decay_secs = 0;
+        config.fee_exemption_price = 0; // 0 = 手数料の免除は買えない
+        config.accrued_event_threshold = 0; // 0 = 確定のたびに RewardAccruedEvent を出す
+        config.vault_count = 0;
+        config.vesting_duration_secs = 0; // 0 = 段階的な解放なし
+        Ok(())
+    }
 
-        // ★リワードBoxの初期化
-        let reward_box = &mut ctx.accounts.reward_box;
-        reward_box.balance = 0;
-        reward_box.bump = ctx.bumps.reward_box;
+    // ★報酬計算方式の切り替え（管理者のみ）
+    // リワードは各Vaultで遅延計算されるため、変更前の方式と変更時刻を残しておき、
+    // update_rewards が変更時刻をまたぐ期間を前後で分けて計算する（過去の期間を新しい方式で計算し直さない）
+    pub fn set_reward_strategy(ctx: Context<UpdateConfig>, strategy: RewardStrategy) -> Result<()> {
+        let clock = current_clock()?;
+        let config = &mut ctx.accounts.config;
+        config.prev_reward_strategy = config.reward_strategy;
+        config.reward_strategy = strategy;
+        config.last_config_update = clock.unix_timestamp;
+        config.config_version = config.config_version.wrapping_add(1);
+        Ok(())
+    }
 
+    // ★高額出金の2段階化の設定（管理者のみ）
+    // threshold を超える出金は request_withdraw → delay_secs 経過後に execute_withdraw が必要になる
+    pub fn set_withdraw_delay(
+        ctx: Context<UpdateConfig>,
+        threshold: u64,
+        delay_secs: i64,
+    ) -> Result<()> {
+        require!(delay_secs >= 0, ErrorCode::InvalidWithdrawDelay);
+        let config = &mut ctx.accounts.config;
+        config.large_withdraw_threshold = threshold;
+        config.withdraw_delay_secs = delay_secs;
         Ok(())
     }
 
-    // 2. 入金（リワード計算 → 入金）
-    pub fn deposit(ctx: Context<Deposit>, amount: u64) -> Result<()> {
-        // 先にリワードを更新
-        update_rewards(
-            &mut ctx.accounts.vault, 
-            &mut ctx.accounts.reward_box
-        )?;
+    // ★入金単位の設定（管理者のみ）
+    // granularity の倍数でない入金は、Reject なら拒否、RoundDown なら端数を切り捨てて入金する
+    pub fn set_deposit_granularity(
+        ctx: Context<UpdateConfig>,
+        granularity: u64,
+        rounding: DepositRounding,
+    ) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.deposit_granularity = granularity;
+        config.deposit_rounding = rounding;
+        Ok(())
+    }
 
-        let vault = &mut ctx.accounts.vault;
-        let user = &ctx.accounts.user;
+    // ★USD建て目標に使う価格フィード（SOL/USD）の設定（管理者のみ）
+    pub fn set_price_feed(
+        ctx: Context<UpdateConfig>,
+        price_feed: Pubkey,
+        max_price_age_secs: i64,
+    ) -> Result<()> {
+        require!(max_price_age_secs > 0, ErrorCode::InvalidPriceAge);
+        let config = &mut ctx.accounts.config;
+        config.price_feed = price_feed;
+        config.max_price_age_secs = max_price_age_secs;
+        Ok(())
+    }
 
-        // ユーザーからVaultへSOLを移動
+    // ★リワード率の経年減衰の設定（管理者のみ）
+    // Vault作成から period_secs 経過するごとに利率を bps_per_period ずつ下げ、floor_bps で下げ止まる
+    // （bpsは本来の利率に対する割合。10000 = 100%）
+    pub fn set_reward_decay(
+        ctx: Context<UpdateConfig>,
+        period_secs: i64,
+        bps_per_period: u16,
+        floor_bps: u16,
+    ) -> Result<()> {
+        require!(period_secs >= 0, ErrorCode::InvalidDecayParams);
+        validate_rate_bps(bps_per_period, ErrorCode::InvalidDecayParams)?;
+        validate_rate_bps(floor_bps, ErrorCode::InvalidDecayParams)?;
+        let config = &mut ctx.accounts.config;
+        config.decay_period_secs = period_secs;
+        config.decay_bps_per_period = bps_per_period;
+        config.decay_floor_bps = floor_bps;
+        Ok(())
+    }
+
+    // ★1日あたりの送金回数上限の設定（管理者のみ、0 = 無制限）
+    pub fn set_max_transfers_per_day(ctx: Context<UpdateConfig>, max_transfers: u32) -> Result<()> {
+        ctx.accounts.config.max_transfers_per_day = max_transfers;
+        Ok(())
+    }
+
+    // ★緊急の全体停止（管理者のみ）
+    // 停止中は入金・送金・リワード付与を止め、出金だけを受け付ける
+    pub fn set_shutdown(ctx: Context<UpdateConfig>, shutdown: bool) -> Result<()> {
+        ctx.accounts.config.shutdown = shutdown;
+        Ok(())
+    }
+
+    // ★Configを閉じてレントを回収する（プログラム廃止時、管理者のみ）
+    // 有効なVaultが残っている間は拒否する。force = true なら強制的に閉じる
+    // （Config がないと出金もできなくなるため、強制クローズは再作成前提で使うこと）
+    pub fn close_config(ctx: Context<CloseConfig>, force: bool) -> Result<()> {
+        require!(
+            force || ctx.accounts.config.vault_count == 0,
+            ErrorCode::VaultsStillActive
+        );
+        Ok(())
+    }
+
+    // ★入金の段階的な解放（リニアベスティング）の期間設定（管理者のみ、0 = 無効）
+    pub fn set_vesting_duration(ctx: Context<UpdateConfig>, duration_secs: i64) -> Result<()> {
+        require!(duration_secs >= 0, ErrorCode::InvalidLockDuration);
+        ctx.accounts.config.vesting_duration_secs = duration_secs;
+        Ok(())
+    }
+
+    // ★入金・出金手数料の設定（管理者のみ、bps指定）
+    // 手数料はTreasuryに入る（入金手数料は Treasury.total_funded と GlobalEmission.total_deposit_fees にも記録する）。作成から grace_period_secs 秒以内のVaultは手数料なし
+    pub fn set_fees(
+        ctx: Context<UpdateConfig>,
+        deposit_fee_bps: u16,
+        withdraw_fee_bps: u16,
+        grace_period_secs: i64,
+    ) -> Result<()> {
+        validate_rate_bps(deposit_fee_bps, ErrorCode::InvalidFee)?;
+        validate_rate_bps(withdraw_fee_bps, ErrorCode::InvalidFee)?;
+        require!(grace_period_secs >= 0, ErrorCode::InvalidFee);
+        let config = &mut ctx.accounts.config;
+        config.deposit_fee_bps = deposit_fee_bps;
+        config.withdraw_fee_bps = withdraw_fee_bps;
+        config.fee_grace_period_secs = grace_period_secs;
+        Ok(())
+    }
+
+    // ★手数料の免除（buy_fee_exemption）の価格（管理者のみ、lamports、0 = 販売しない）
+    // 変更しても、すでに免除を買ったVaultはそのまま免除される
+    pub fn set_fee_exemption_price(ctx: Context<UpdateConfig>, price: u64) -> Result<()> {
+        ctx.accounts.config.fee_exemption_price = price;
+        Ok(())
+    }
+
+    // ★リワード受け取り時の手数料率（管理者のみ、10000 = 100%）
+    pub fn set_claim_fee(ctx: Context<UpdateConfig>, claim_fee_bps: u16) -> Result<()> {
+        validate_rate_bps(claim_fee_bps, ErrorCode::InvalidFee)?;
+        ctx.accounts.config.claim_fee_bps = claim_fee_bps;
+        Ok(())
+    }
+
+    // ★出金のたびに没収する確定済みリワードの割合（管理者のみ、0 = 没収しない）
+    // 出金と再入金を繰り返す（churning）のを抑える
+    pub fn set_reward_forfeit_on_withdraw(ctx: Context<UpdateConfig>, forfeit_bps: u16) -> Result<()> {
+        validate_rate_bps(forfeit_bps, ErrorCode::InvalidFee)?;
+        ctx.accounts.config.reward_forfeit_on_withdraw_bps = forfeit_bps;
+        Ok(())
+    }
+
+    // ★リワードを確定させる最小間隔（管理者のみ、0 = 毎回確定する）
+    // 数秒ごとの細かい確定（とイベント発行）を減らす
+    pub fn set_min_accrual_interval(ctx: Context<UpdateConfig>, interval_secs: i64) -> Result<()> {
+        require!(interval_secs >= 0, ErrorCode::InvalidAccrualInterval);
+        ctx.accounts.config.min_accrual_interval_secs = interval_secs;
+        Ok(())
+    }
+
+    // ★リワードを確定させる単位期間（管理者のみ、0 = 秒単位、86400 = 日単位の「日利」）
+    // 満了した期間の分だけ確定し、端数の秒は last_update_time に残して次回へ繰り越す
+    pub fn set_accrual_period(ctx: Context<UpdateConfig>, period_secs: i64) -> Result<()> {
+        require!(period_secs >= 0, ErrorCode::InvalidAccrualInterval);
+        ctx.accounts.config.accrual_period_secs = period_secs;
+        Ok(())
+    }
+
+    // ★リワードが付く時間帯（管理者のみ、UTCの0時からの秒数、start == end で制限なし）
+    // 確定する期間のうち、毎日の start〜end に入っている秒数の分だけリワードが付く（何日またいでも数える）。
+    // start > end なら日付をまたぐ時間帯（例: 22時〜翌6時）。変更は未確定の期間にも使われる
+    pub fn set_accrual_window(ctx: Context<UpdateConfig>, start_secs: u32, end_secs: u32) -> Result<()> {
+        require!(
+            start_secs < SECS_PER_DAY as u32 && end_secs < SECS_PER_DAY as u32,
+            ErrorCode::InvalidAccrualWindow
+        );
+        let config = &mut ctx.accounts.config;
+        config.accrual_window_start_secs = start_secs;
+        config.accrual_window_end_secs = end_secs;
+        Ok(())
+    }
+
+    // ★1回の確定で付くリワードの上限（管理者のみ、0 = 上限なし）
+    // 長く放置されたVaultの確定で未払いリワードが一度に跳ね上がらないよう、上限に収まる時刻までを確定し、
+    // 残りの期間は last_update_time に残して次回以降の確定に繰り越す
+    pub fn set_max_accrual_per_call(ctx: Context<UpdateConfig>, amount: u64) -> Result<()> {
+        ctx.accounts.config.max_accrual_per_call = amount;
+        Ok(())
+    }
+
+    // ★リワードをさかのぼって付ける期間の上限（管理者のみ、0 = 上限なし）
+    // 確定時に、これより古い経過秒数は切り捨てて直近のこの秒数の分だけを付け、last_update_time は現在まで進める。
+    // max_accrual_per_call と違って切り捨てた期間は繰り越さないので、長く放置されたVaultの負債が増えすぎない
+    pub fn set_max_accrual_lookback(ctx: Context<UpdateConfig>, lookback_secs: i64) -> Result<()> {
+        require!(lookback_secs >= 0, ErrorCode::InvalidAccrualInterval);
+        ctx.accounts.config.max_accrual_lookback_secs = lookback_secs;
+        Ok(())
+    }
+
+    // ★出金の頻度による利率の引き下げ（管理者のみ、0 = 引き下げなし）
+    // 出金のたびに Vault.withdrawal_score が1増え、decay_secs 秒ごとに1ずつ減る。
+    // 利率は withdrawal_score × penalty_bps だけ下がる（0で下げ止める）。未確定の期間にも確定時点の値が使われる
+    pub fn set_withdrawal_score_penalty(
+        ctx: Context<UpdateConfig>,
+        penalty_bps: u16,
+        decay_secs: i64,
+    ) -> Result<()> {
+        validate_rate_bps(penalty_bps, ErrorCode::InvalidWithdrawalScorePenalty)?;
+        require!(
+            decay_secs >= 0 && (penalty_bps == 0 || decay_secs > 0),
+            ErrorCode::InvalidWithdrawalScorePenalty
+        );
+        let config = &mut ctx.accounts.config;
+        config.withdrawal_score_penalty_bps = penalty_bps;
+        config.withdrawal_score_decay_secs = decay_secs;
+        Ok(())
+    }
+
+    // ★RewardAccruedEvent をまとめて出す閾値（管理者のみ、ポイント、0 = 確定のたびに出す）
+    // 頻繁に確定されるVaultのログが溢れないよう、出していない確定額の合計（Vault.unemitted_reward）が
+    // この値に達したときに、合計額で1回だけ出す。RewardBox には確定のたびに加えるので残高は変わらない。
+    // 下げるときは remaining_accounts に (Vault, RewardBox) の組を渡すと、出していない分をその場で出す
+    // （渡さなかったVaultの分は、次の確定で新しい閾値に達したときに出る）
+    pub fn set_accrued_event_threshold<'info>(
+        ctx: Context<'_, '_, 'info, 'info, UpdateConfig<'info>>,
+        threshold: u64,
+    ) -> Result<()> {
+        let accounts = ctx.remaining_accounts;
+        require_vault_pair_count(accounts)?;
+        if threshold < ctx.accounts.config.accrued_event_threshold {
+            for pair in accounts.chunks(2) {
+                let (mut vault, reward_box) = load_vault_pair(&pair[0], &pair[1], ctx.program_id)?;
+                flush_unemitted_reward(&mut vault, &reward_box);
+                // remaining_accounts は自動で書き戻されないので明示的に保存する
+                vault.exit(ctx.program_id)?;
+            }
+        }
+        ctx.accounts.config.accrued_event_threshold = threshold;
+        Ok(())
+    }
+
+    // ★全Vault共通の利率の倍率（管理者のみ、10000 = 基本の利率）
+    // 各Vaultの rate_delta_bps はこの値からの差分なので、上乗せの幅を保ったまま全体の利率が動く。
+    // 未確定の期間にも変更後の値が使われる（利用率・目標達成ボーナスと同じ）
+    pub fn set_default_reward_rate(ctx: Context<UpdateConfig>, rate_bps: u16) -> Result<()> {
+        require!(rate_bps <= MAX_RATE_MULTIPLIER_BPS, ErrorCode::InvalidRewardRate);
+        ctx.accounts.config.default_reward_rate_bps = rate_bps;
+        Ok(())
+    }
+
+    // ★大口入金のボーナス（管理者のみ、入金額に対する bps、0 = なし）
+    // deposit で入金額が入金前の元本を超える（元本が2倍より大きくなる）と、入金額のこの割合のリワードを一度だけ付ける。
+    // 元本0のVaultへの入金（最初の入金など）は比べる元本がないので対象外
+    pub fn set_large_deposit_bonus(ctx: Context<UpdateConfig>, bonus_bps: u16) -> Result<()> {
+        validate_rate_bps(bonus_bps, ErrorCode::InvalidDepositBonus)?;
+        ctx.accounts.config.large_deposit_bonus_bps = bonus_bps;
+        Ok(())
+    }
+
+    // ★リワードの運用先のVault（名前 GROWTH_VAULT_NAME）の利率の上乗せ（管理者のみ、10000 = 基本の利率の分だけ上乗せ）
+    // rate_delta_bps と同じく利率の倍率に足す。未確定の期間にも変更後の値が使われる
+    pub fn set_growth_rate_bonus(ctx: Context<UpdateConfig>, bonus_bps: u16) -> Result<()> {
+        validate_rate_bps(bonus_bps, ErrorCode::InvalidGrowthRateBonus)?;
+        ctx.accounts.config.growth_rate_bonus_bps = bonus_bps;
+        Ok(())
+    }
+
+    // ★入金の上乗せ（マッチング）の割合（管理者のみ、10000 = 1:1、0 = なし）
+    // deposit に MatchPool を渡したときだけ、手数料を引いた入金額のこの割合をプールからVaultへ移す
+    pub fn set_match_ratio(ctx: Context<UpdateConfig>, ratio_bps: u16) -> Result<()> {
+        validate_rate_bps(ratio_bps, ErrorCode::InvalidMatchRatio)?;
+        ctx.accounts.config.match_ratio_bps = ratio_bps;
+        Ok(())
+    }
+
+    // ★リワードが付き始める元本の下限（管理者のみ、0 = 下限なし）
+    // 下限未満の間の経過時間には、後から下限を超えてもリワードは付かない
+    pub fn set_reward_min_balance(ctx: Context<UpdateConfig>, min_balance: u64) -> Result<()> {
+        ctx.accounts.config.reward_min_balance = min_balance;
+        Ok(())
+    }
+
+    // ★定額リワードのVaultに入金1回ごとに付くポイント（管理者のみ、0 = 付けない）
+    pub fn set_flat_reward_per_deposit(ctx: Context<UpdateConfig>, amount: u64) -> Result<()> {
+        ctx.accounts.config.flat_reward_per_deposit = amount;
+        Ok(())
+    }
+
+    // ★貯金目標を達成していてロック中のVaultへの入金を拒否するか（管理者のみ）
+    // 達成済みの目標のための資金は、ロックが明けるまで増やせないようにする
+    pub fn set_reject_deposit_when_goal_locked(ctx: Context<UpdateConfig>, reject: bool) -> Result<()> {
+        ctx.accounts.config.reject_deposit_when_goal_locked = reject;
+        Ok(())
+    }
+
+    // ★1回の確定で付くリワードの最低額（管理者のみ、0 = 最低保証なし）
+    // 元本が少なく整数計算で0ポイントになるVaultにも amount ポイントを付ける（支払いはTreasuryの原資から）。
+    // 確定のたびに付くので、min_accrual_interval_secs / accrual_period_secs と合わせて設定すること
+    pub fn set_min_accrual_per_interval(ctx: Context<UpdateConfig>, amount: u64) -> Result<()> {
+        ctx.accounts.config.min_accrual_per_interval = amount;
+        Ok(())
+    }
+
+    // ★Vaultの経過時間に応じた元本の上限（管理者のみ、空にすると上限なし）
+    // caps は min_age_secs の昇順で、最初の段階は min_age_secs = 0 から始める（MAX_AGE_DEPOSIT_CAPS 段階まで）。
+    // 作成から min_age_secs 以上経ったVaultには、その段階の max_balance までしか入金できない
+    pub fn set_age_deposit_caps(ctx: Context<UpdateConfig>, caps: Vec<AgeDepositCap>) -> Result<()> {
+        require!(caps.len() <= MAX_AGE_DEPOSIT_CAPS, ErrorCode::InvalidAgeDepositCaps);
+        require!(
+            (caps.is_empty() || caps[0].min_age_secs == 0)
+                && caps.windows(2).all(|w| w[0].min_age_secs < w[1].min_age_secs),
+            ErrorCode::InvalidAgeDepositCaps
+        );
+        let config = &mut ctx.accounts.config;
+        config.age_deposit_cap_count = caps.len() as u8;
+        config.age_deposit_caps = [AgeDepositCap::default(); MAX_AGE_DEPOSIT_CAPS];
+        config.age_deposit_caps[..caps.len()].copy_from_slice(&caps);
+        Ok(())
+    }
+
+    // ★同じVaultへの deposit の最小間隔（管理者のみ、0 = 制限なし。スパムと過剰な書き込みの防止）
+    pub fn set_min_deposit_interval(ctx: Context<UpdateConfig>, interval_secs: i64) -> Result<()> {
+        require!(interval_secs >= 0, ErrorCode::InvalidDepositInterval);
+        ctx.accounts.config.min_deposit_interval_secs = interval_secs;
+        Ok(())
+    }
+
+    // ★出金後の残高がこの額以下になる場合は、端数も含めて全額を出金する（管理者のみ、0 = 無効）
+    // 残高を計算し直す間にリワードの確定などで生じる数 lamport の差で、端数が Vault に残らないようにする
+    pub fn set_withdraw_dust_tolerance(ctx: Context<UpdateConfig>, tolerance: u64) -> Result<()> {
+        require!(
+            tolerance <= MAX_WITHDRAW_DUST_TOLERANCE,
+            ErrorCode::InvalidDustTolerance
+        );
+        ctx.accounts.config.withdraw_dust_tolerance = tolerance;
+        Ok(())
+    }
+
+    // ★transfer の取り消し期間（管理者のみ、0 = 取り消し期間なしですぐに送金する）
+    // 設定中の transfer は送金予約になり、この秒数のあいだは持ち主が cancel_transfer で取り消せる。
+    // 期間が過ぎると受取人が claim_transfer で受け取る
+    pub fn set_transfer_cancel_window(ctx: Context<UpdateConfig>, window_secs: i64) -> Result<()> {
+        require!(window_secs >= 0, ErrorCode::InvalidCancelWindow);
+        ctx.accounts.config.transfer_cancel_window_secs = window_secs;
+        Ok(())
+    }
+
+    // ★transfer の送金先に事前の受け取り登録（opt_in_recipient）を求めるか（管理者のみ）
+    pub fn set_require_recipient_opt_in(ctx: Context<UpdateConfig>, required: bool) -> Result<()> {
+        ctx.accounts.config.require_recipient_opt_in = required;
+        Ok(())
+    }
+
+    // ★VaultTouchedEvent を固定レイアウトのバイト列（CompactVaultTouched）で出すか（管理者のみ）
+    // 帯域を節約したいインデクサ向け。有効な間は VaultTouchedEvent は出ない（他のイベントはそのまま）
+    pub fn set_compact_events(ctx: Context<UpdateConfig>, enabled: bool) -> Result<()> {
+        ctx.accounts.config.compact_events = enabled;
+        Ok(())
+    }
+
+    // ★1ユーザーが同時に持てるVaultの数の上限（管理者のみ、0 = 無制限）
+    // initialize・initialize_sponsored・split_vault で UserStats.vault_count と比べる
+    pub fn set_max_vaults_per_user(ctx: Context<UpdateConfig>, max_vaults: u32) -> Result<()> {
+        ctx.accounts.config.max_vaults_per_user = max_vaults;
+        Ok(())
+    }
+
+    // ★貯金目標を達成しているVaultへのリワードの上乗せ率（管理者のみ、10000 = 2倍まで）
+    pub fn set_goal_bonus(ctx: Context<UpdateConfig>, bonus_bps: u16) -> Result<()> {
+        validate_rate_bps(bonus_bps, ErrorCode::InvalidGoalBonus)?;
+        ctx.accounts.config.goal_bonus_bps = bonus_bps;
+        Ok(())
+    }
+
+    // ★紹介者へのボーナス率（管理者のみ、入金額に対する bps）
+    // level1 は入金したVaultの紹介者、level2 はその紹介者の紹介者に付く
+    pub fn set_referral_bonus(
+        ctx: Context<UpdateConfig>,
+        level1_bps: u16,
+        level2_bps: u16,
+    ) -> Result<()> {
+        validate_rate_bps(level1_bps, ErrorCode::InvalidReferralBonus)?;
+        validate_rate_bps(level2_bps, ErrorCode::InvalidReferralBonus)?;
+        let config = &mut ctx.accounts.config;
+        config.referral_level1_bps = level1_bps;
+        config.referral_level2_bps = level2_bps;
+        Ok(())
+    }
+
+    // ★入金した元本がリワード対象になるまでの待機秒数（管理者のみ、0 = 待機なし）
+    // 一瞬だけ入金してリワードを得る"つまみ食い"を防ぐ
+    pub fn set_reward_start_delay(ctx: Context<UpdateConfig>, delay_secs: i64) -> Result<()> {
+        require!(delay_secs >= 0, ErrorCode::InvalidLockDuration);
+        ctx.accounts.config.reward_start_delay_secs = delay_secs;
+        Ok(())
+    }
+
+    // ★ロック中の早期出金のペナルティ設定（管理者のみ、0 = 早期出金不可）
+    // ペナルティの送り先は Treasury / Burn（焼却アドレス）/ Charity（charity に指定したアドレス）から選ぶ
+    pub fn set_early_withdraw_penalty(
+        ctx: Context<UpdateConfig>,
+        penalty_bps: u16,
+        destination: PenaltyDestination,
+        charity: Pubkey,
+    ) -> Result<()> {
+        validate_rate_bps(penalty_bps, ErrorCode::InvalidFee)?;
+        require!(
+            destination != PenaltyDestination::Charity || charity != Pubkey::default(),
+            ErrorCode::InvalidPenaltyDestination
+        );
+        let config = &mut ctx.accounts.config;
+        config.early_withdraw_penalty_bps = penalty_bps;
+        config.penalty_destination = destination;
+        config.charity = charity;
+        Ok(())
+    }
+
+    // ★Treasuryに残すべき原資の比率（管理者のみ、10000 = 未払いリワードの100%）
+    // 預金者のリワードを守るため、100%未満には設定できない
+    pub fn set_min_reserve_ratio(ctx: Context<UpdateConfig>, ratio_bps: u16) -> Result<()> {
+        require!(ratio_bps >= 10000, ErrorCode::InvalidReserveRatio);
+        ctx.accounts.config.min_reserve_ratio_bps = ratio_bps;
+        Ok(())
+    }
+
+    // ★利用率による利率調整（管理者のみ）
+    // 全Vaultの元本合計が half_point に達すると利率が半分になる（0 = 調整なし）
+    pub fn set_utilization_half_point(ctx: Context<UpdateConfig>, half_point: u64) -> Result<()> {
+        ctx.accounts.config.utilization_half_point = half_point;
+        Ok(())
+    }
+
+    // ★ロック期間の上限（管理者のみ、0 = 上限なし）
+    // 桁を打ち間違えて数百年ロックしてしまうような事故を防ぐ
+    pub fn set_max_lock_duration(ctx: Context<UpdateConfig>, max_secs: i64) -> Result<()> {
+        require!(max_secs >= 0, ErrorCode::InvalidLockDuration);
+        ctx.accounts.config.max_lock_duration_secs = max_secs;
+        Ok(())
+    }
+
+    // ★凍結中のVaultにもリワードを付与するか（管理者のみ、既定は付与しない）
+    pub fn set_accrue_while_frozen(ctx: Context<UpdateConfig>, accrue: bool) -> Result<()> {
+        ctx.accounts.config.accrue_while_frozen = accrue;
+        Ok(())
+    }
+
+    // ★ロック中の元本へのリワードの付け方（管理者のみ、既定は Base）
+    pub fn set_locked_reward_mode(ctx: Context<UpdateConfig>, mode: LockedRewardMode) -> Result<()> {
+        ctx.accounts.config.locked_reward_mode = mode;
+        Ok(())
+    }
+
+    // ★リワード原資（Treasury）の作成（管理者のみ）
+    pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> Result<()> {
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.bump = ctx.bumps.treasury;
+        treasury.total_funded = 0;
+        Ok(())
+    }
+
+    // ★プロトコル全体のリワード集計（GlobalEmission）の作成（管理者のみ）
+    pub fn initialize_global_emission(ctx: Context<InitializeGlobalEmission>) -> Result<()> {
+        let emission = &mut ctx.accounts.global_emission;
+        emission.bump = ctx.bumps.global_emission;
+        emission.outstanding_liabilities = 0;
+        emission.total_deposits = 0;
+        emission.total_deposit_fees = 0;
+        Ok(())
+    }
+
+    // ★リワードのリーダーボード（Leaderboard）の作成（管理者のみ）
+    // claim_reward / crank_accrue に leaderboard を渡したときだけ更新される
+    pub fn initialize_leaderboard(ctx: Context<InitializeLeaderboard>) -> Result<()> {
+        let leaderboard = &mut ctx.accounts.leaderboard;
+        leaderboard.bump = ctx.bumps.leaderboard;
+        leaderboard.entries = [LeaderboardEntry::default(); LEADERBOARD_SIZE];
+        Ok(())
+    }
+
+    // ★Treasuryからの引き出し（管理者のみ）
+    // 引き出し後も、未払いリワード × min_reserve_ratio_bps / 10000 以上の原資を残す
+    pub fn admin_drain_treasury(ctx: Context<DrainTreasury>, amount: u64) -> Result<()> {
+        let treasury_info = ctx.accounts.treasury.to_account_info();
+        let rent = Rent::get()?;
+        let available = treasury_info
+            .lamports()
+            .saturating_sub(rent.minimum_balance(treasury_info.data_len()));
+        let required = (ctx.accounts.global_emission.outstanding_liabilities as u128
+            * ctx.accounts.config.min_reserve_ratio_bps as u128
+            / 10000) as u64;
+        require!(
+            amount <= available && available - amount >= required,
+            ErrorCode::InsufficientReserve
+        );
+
+        **treasury_info.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.admin.to_account_info().try_borrow_mut_lamports()? += amount;
+        Ok(())
+    }
+
+    // ★Treasuryの閉鎖（管理者のみ、撤退時用）
+    // 未払いリワードが残っていないことを確認し、残りのSOLはすべて管理者へ返す
+    pub fn close_treasury(ctx: Context<CloseTreasury>) -> Result<()> {
+        require!(
+            ctx.accounts.global_emission.outstanding_liabilities == 0,
+            ErrorCode::OutstandingLiabilities
+        );
+        Ok(())
+    }
+
+    // ★Treasuryへの資金投入（誰でも可能）
+    pub fn fund_treasury(ctx: Context<FundTreasury>, amount: u64) -> Result<()> {
         let cpi_context = CpiContext::new(
             ctx.accounts.system_program.to_account_info(),
             anchor_lang::system_program::Transfer {
-                from: user.to_account_info(),
-                to: vault.to_account_info(),
+                from: ctx.accounts.funder.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
             },
         );
         anchor_lang::system_program::transfer(cpi_context, amount)?;
 
-        // 残高更新
-        vault.balance += amount;
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.total_funded = treasury.total_funded.saturating_add(amount);
         Ok(())
     }
 
-    // 3. 出金（リワード計算 → 出金）
-    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
-        // 先にリワードを更新
-        update_rewards(
-            &mut ctx.accounts.vault, 
-            &mut ctx.accounts.reward_box
+    // ★Treasuryへリワードトークンを入れる（誰でも可）
+    // 受け取り先は Treasury PDA のATA（なければ作成）。リワードトークン以外のMintは受け付けない
+    pub fn fund_treasury_token(ctx: Context<FundTreasuryToken>, amount: u64) -> Result<()> {
+        let cpi_context = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.funder_token_account.to_account_info(),
+                to: ctx.accounts.treasury_token_account.to_account_info(),
+                authority: ctx.accounts.funder.to_account_info(),
+            },
+        );
+        token::transfer(cpi_context, amount)
+    }
+
+    // ★リワードトークンのMint作成（管理者のみ）
+    // Mint自体もPDA（seeds = [b"reward_mint"]）で、発行権限は mint_authority PDA が持つ
+    // ★decimals はポイント（9桁）以下。少ない場合は claim_reward_token で桁を落として発行する
+    pub fn initialize_reward_mint(ctx: Context<InitializeRewardMint>, decimals: u8) -> Result<()> {
+        require!(decimals <= REWARD_POINT_DECIMALS, ErrorCode::InvalidTokenDecimals);
+        ctx.accounts.config.reward_token_decimals = decimals;
+        Ok(())
+    }
+
+    // ★リワードトークンの発行権限を mint_authority PDA から new_authority へ移す（管理者のみ）
+    // アップグレードや分散化のための手続き。移した後は claim_reward_token でトークンを発行できなくなる
+    pub fn rotate_mint_authority(
+        ctx: Context<RotateMintAuthority>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        let signer_seeds: &[&[&[u8]]] = &[&[b"mint_authority", &[ctx.bumps.mint_authority]]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            SetAuthority {
+                current_authority: ctx.accounts.mint_authority.to_account_info(),
+                account_or_mint: ctx.accounts.reward_mint.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::set_authority(cpi_context, AuthorityType::MintTokens, Some(new_authority))
+    }
+
+    // 1. 初期化（金庫とリワードBOXを作る）
+    // ★user は他のプログラムの PDA でもよい（Vault と RewardBox の seeds は user のアドレスから作られる）
+    // その場合は CPI 元が invoke_signed で PDA の signer seeds を渡して署名させる。
+    // user はレントの支払い元も兼ねるので、データを持たないシステムアカウントで、十分なSOLを持っている必要がある
+    // （例: programs/mock-vault-owner）。以後の deposit / withdraw なども同じ seeds で署名する
+    pub fn initialize(ctx: Context<Initialize>) -> Result<()> {
+        register_user_vault(
+            &mut ctx.accounts.user_stats,
+            ctx.bumps.user_stats,
+            &ctx.accounts.config,
         )?;
+        init_vault_accounts(
+            &mut ctx.accounts.vault,
+            ctx.bumps.vault,
+            &mut ctx.accounts.reward_box,
+            ctx.bumps.reward_box,
+            ctx.accounts.user.key(),
+            &mut ctx.accounts.config,
+        )
+    }
 
-        let vault = &mut ctx.accounts.vault;
-        let user = &ctx.accounts.user;
-        
-        // Vaultから減らす
-        **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
-        // ユーザーへ増やす
-        **user.to_account_info().try_borrow_mut_lamports()? += amount;
+    // ★1-0. スポンサーがレントを負担する初期化（持ち主は user のまま）
+    // ウォレットが空のユーザーでも、署名だけでVaultを作れるようにする
+    pub fn initialize_sponsored(ctx: Context<InitializeSponsored>) -> Result<()> {
+        register_user_vault(
+            &mut ctx.accounts.user_stats,
+            ctx.bumps.user_stats,
+            &ctx.accounts.config,
+        )?;
+        init_vault_accounts(
+            &mut ctx.accounts.vault,
+            ctx.bumps.vault,
+            &mut ctx.accounts.reward_box,
+            ctx.bumps.reward_box,
+            ctx.accounts.user.key(),
+            &mut ctx.accounts.config,
+        )
+    }
 
-        // 残高更新
-        vault.balance -= amount;
+    // ★1-0-2. 複数ユーザーの Vault / RewardBox をまとめて作る（オンボーディング用、payer がレントを負担する）
+    // remaining_accounts に (user, Vault, RewardBox, UserStats) の組を MAX_VAULT_PAIRS 人まで並べて渡す。
+    // 作るのは各ユーザーの基本Vaultで、持ち主はそのユーザーになる（ユーザーの署名は不要、資金も動かない）
+    pub fn batch_initialize<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchInitialize<'info>>,
+    ) -> Result<()> {
+        let accounts = ctx.remaining_accounts;
+        require!(
+            accounts.len() % 4 == 0 && accounts.len() / 4 <= MAX_VAULT_PAIRS,
+            ErrorCode::InvalidBatchInitAccounts
+        );
+        let payer = ctx.accounts.payer.to_account_info();
+        let system_program = ctx.accounts.system_program.to_account_info();
+
+        for group in accounts.chunks(4) {
+            let (user_info, vault_info, reward_box_info, stats_info) =
+                (&group[0], &group[1], &group[2], &group[3]);
+            let user = user_info.key();
+            require!(user != Pubkey::default(), ErrorCode::InvalidBatchInitAccounts);
+
+            // ★渡されたアカウントがそのユーザーのPDAであることを確認する
+            let (vault_key, vault_bump) =
+                Pubkey::find_program_address(&[b"vault", user.as_ref()], ctx.program_id);
+            let (reward_key, reward_bump) =
+                Pubkey::find_program_address(&[b"reward", user.as_ref()], ctx.program_id);
+            let (stats_key, stats_bump) =
+                Pubkey::find_program_address(&[b"user_stats", user.as_ref()], ctx.program_id);
+            require!(
+                vault_info.key() == vault_key
+                    && reward_box_info.key() == reward_key
+                    && stats_info.key() == stats_key,
+                ErrorCode::InvalidBatchInitAccounts
+            );
+
+            // ★UserStats は initialize と同じく、最初のVault作成時にだけ作る
+            if stats_info.data_is_empty() {
+                create_program_account::<UserStats>(
+                    stats_info,
+                    &payer,
+                    &system_program,
+                    UserStats::SPACE,
+                    &[b"user_stats", user.as_ref(), &[stats_bump]],
+                    ctx.program_id,
+                )?;
+            }
+            let mut stats = Account::<UserStats>::try_from(stats_info)?;
+            register_user_vault(&mut stats, stats_bump, &ctx.accounts.config)?;
+
+            // 既にVaultがあれば create_account が失敗する
+            create_program_account::<Vault>(
+                vault_info,
+                &payer,
+                &system_program,
+                Vault::SPACE,
+                &[b"vault", user.as_ref(), &[vault_bump]],
+                ctx.program_id,
+            )?;
+            create_program_account::<RewardBox>(
+                reward_box_info,
+                &payer,
+                &system_program,
+                RewardBox::SPACE,
+                &[b"reward", user.as_ref(), &[reward_bump]],
+                ctx.program_id,
+            )?;
+            let mut vault = Account::<Vault>::try_from(vault_info)?;
+            let mut reward_box = Account::<RewardBox>::try_from(reward_box_info)?;
+            init_vault_accounts(
+                &mut vault,
+                vault_bump,
+                &mut reward_box,
+                reward_bump,
+                user,
+                &mut ctx.accounts.config,
+            )?;
+
+            // remaining_accounts は自動で書き戻されないので明示的に保存する
+            stats.exit(ctx.program_id)?;
+            vault.exit(ctx.program_id)?;
+            reward_box.exit(ctx.program_id)?;
+        }
         Ok(())
     }
 
-    // ★4. 送金機能（修正版）
-    pub fn transfer(ctx: Context<TransferSol>, amount: u64) -> Result<()> {
-        // 先にリワードを更新
-        update_rewards(
-            &mut ctx.accounts.vault, 
-            &mut ctx.accounts.reward_box
+    // ★1-0-1. Vault / RewardBox の作成と最初の入金を1つの命令で行う
+    // 作成だけ成功して入金されていないVaultが残らないようにする。入金の扱いは deposit と同じ
+    // （作成したばかりなので、確定・自動組み入れ・自動受け取りするリワードはなく、紹介者もまだいない）
+    pub fn initialize_and_deposit(ctx: Context<InitializeAndDeposit>, amount: u64) -> Result<()> {
+        require_not_shutdown(&ctx.accounts.config)?;
+        register_user_vault(
+            &mut ctx.accounts.user_stats,
+            ctx.bumps.user_stats,
+            &ctx.accounts.config,
+        )?;
+        init_vault_accounts(
+            &mut ctx.accounts.vault,
+            ctx.bumps.vault,
+            &mut ctx.accounts.reward_box,
+            ctx.bumps.reward_box,
+            ctx.accounts.user.key(),
+            &mut ctx.accounts.config,
+        )?;
+
+        let amount = apply_deposit_granularity(&ctx.accounts.config, amount)?;
+        let clock = current_clock()?;
+        ctx.accounts.vault.last_deposit_time = clock.unix_timestamp;
+
+        // ★手数料を差し引いた額がVaultに入る
+        let fee = fee_for(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            amount,
+            ctx.accounts.config.deposit_fee_bps,
+            clock.unix_timestamp,
+        );
+        let amount = amount - fee;
+        require_within_age_cap(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            amount,
+            clock.unix_timestamp,
         )?;
 
         let vault = &mut ctx.accounts.vault;
-        
-        // 【修正箇所】システムプログラムを使わず、直接残高を移動させる
-        // 1. Vaultから減らす
-        **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
-        
-        // 2. 送金先（Recipient）へ増やす
-        **ctx.accounts.recipient.to_account_info().try_borrow_mut_lamports()? += amount;
+        let user = &ctx.accounts.user;
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: user.to_account_info(),
+                to: vault.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_context, amount)?;
+        if fee > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: user.to_account_info(),
+                    to: ctx.accounts.treasury.to_account_info(),
+                },
+            );
+            anchor_lang::system_program::transfer(cpi_context, fee)?;
+            record_deposit_fee(&mut ctx.accounts.treasury, &mut ctx.accounts.global_emission, fee);
+            record_fee_paid(vault, fee);
+        }
 
-        // データ上の残高も更新
-        vault.balance -= amount;
+        credit_deposit(vault, amount)?;
+        record_deposit(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, amount as i64);
+        add_vesting(vault, &ctx.accounts.config, amount, clock.unix_timestamp);
+        add_reward_delay(vault, &ctx.accounts.config, amount, clock.unix_timestamp);
 
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
         Ok(())
     }
-}
 
-// --- ヘルパー関数: リワード計算ロジック ---
-// 1分ごとに残高の1%をリワードBoxに加算する
-fn update_rewards(vault: &mut Account<Vault>, reward_box: &mut Account<RewardBox>) -> Result<()> {
-    let clock = Clock::get()?;
-    let current_time = clock.unix_timestamp;
-    let last_update = vault.last_update_time;
+    // ★1-1. RewardBoxがないVaultに後からRewardBoxを作る
+    // （RewardBox導入前のVault向け。deposit でも init_if_needed で自動的に作られる）
+    pub fn init_reward_box(ctx: Context<InitRewardBox>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        init_reward_box_if_new(&mut ctx.accounts.reward_box, ctx.bumps.reward_box);
+        Ok(())
+    }
 
-    // 経過秒数
-    let diff = current_time - last_update;
+    // 2. 入金（リワード計算 → 入金）
+    // ★deadline を指定すると、その時刻を過ぎてから処理されたトランザクションは拒否する（None = 期限なし）
+    // ★紹介者のいるVaultでは、remaining_accounts に紹介者の (Vault, RewardBox) を近い順に2段階まで渡す
+    pub fn deposit<'info>(
+        ctx: Context<'_, '_, 'info, 'info, Deposit<'info>>,
+        amount: u64,
+        deadline: Option<i64>,
+    ) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_not_shutdown(&ctx.accounts.config)?;
+        require_not_closing(&ctx.accounts.vault)?;
+        require_not_growth_vault(&ctx.accounts.vault)?;
+        if let Some(deadline) = deadline {
+            require!(
+                current_clock()?.unix_timestamp <= deadline,
+                ErrorCode::DeadlineExceeded
+            );
+        }
 
-    // 1秒以上経過していたら計算
-    if diff >= 1 {
-        // リワード計算: 残高 * 0.01% * 経過秒数
-        // 0.01% = 0.0001 = 1 / 10000
-        
-        // ※Solanaは整数演算なので、先に掛けてから割る
-        let reward_amount = (vault.balance as u128 * diff as u128 / 10000) as u64;
+        // ★入金単位に合わせる（端数切り捨ての場合、端数はユーザーの手元に残る）
+        let amount = apply_deposit_granularity(&ctx.accounts.config, amount)?;
 
-        // リワード加算
-        reward_box.balance += reward_amount;
-        
-        // 最終更新時刻を現在に更新
-        vault.last_update_time = current_time;
-    }
+        // ★RewardBoxがなかったVaultでは、ここで作られた空のRewardBoxを初期化する
+        init_reward_box_if_new(&mut ctx.accounts.reward_box, ctx.bumps.reward_box);
 
-    Ok(())
+        // 先にリワードを更新
+        update_rewards(
+            &mut ctx.accounts.vault, 
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        // ★前回の入金から min_deposit_interval_secs 経っていなければ拒否する
+        let clock = current_clock()?;
+        let interval = ctx.accounts.config.min_deposit_interval_secs;
+        require!(
+            interval == 0 || clock.unix_timestamp - ctx.accounts.vault.last_deposit_time >= interval,
+            ErrorCode::DepositTooFrequent
+        );
+        ctx.accounts.vault.last_deposit_time = clock.unix_timestamp;
+
+        // ★自動組み入れ（新しい入金より先に、確定済みのリワードだけを組み入れる）
+        maybe_auto_compound(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.treasury,
+            &mut ctx.accounts.global_emission,
+            clock.unix_timestamp,
+        )?;
+
+        // ★自動受け取り（確定済みのリワードをTreasuryからユーザーへ支払う）
+        maybe_auto_claim(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+            &ctx.accounts.treasury.to_account_info(),
+            &ctx.accounts.user.to_account_info(),
+        )?;
+
+        // ★手数料を差し引いた額がVaultに入る
+        let fee = fee_for(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            amount,
+            ctx.accounts.config.deposit_fee_bps,
+            clock.unix_timestamp,
+        );
+        let amount = amount - fee;
+        require_within_age_cap(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            amount,
+            clock.unix_timestamp,
+        )?;
+        require_within_self_cap(&ctx.accounts.vault, amount)?;
+        require_not_goal_locked(&ctx.accounts.config, &ctx.accounts.vault, clock.unix_timestamp)?;
+
+        let vault = &mut ctx.accounts.vault;
+        let user = &ctx.accounts.user;
+
+        // ユーザーからVaultへSOLを移動
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: user.to_account_info(),
+                to: vault.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_context, amount)?;
+
+        // ★手数料はTreasuryへ
+        if fee > 0 {
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: user.to_account_info(),
+                    to: ctx.accounts.treasury.to_account_info(),
+                },
+            );
+            anchor_lang::system_program::transfer(cpi_context, fee)?;
+            record_deposit_fee(&mut ctx.accounts.treasury, &mut ctx.accounts.global_emission, fee);
+            record_fee_paid(vault, fee);
+        }
+
+        // 残高更新
+        credit_deposit(vault, amount)?;
+        record_deposit(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, amount as i64);
+        emit_threshold_crossed(vault, ThresholdKind::Balance, vault.balance - amount, vault.balance);
+
+        // ★ベスティング・リワード待機の対象に追加
+        add_vesting(vault, &ctx.accounts.config, amount, clock.unix_timestamp);
+        add_reward_delay(vault, &ctx.accounts.config, amount, clock.unix_timestamp);
+        add_locked_savings(vault, amount, clock.unix_timestamp);
+
+        // ★定額リワードのVaultには、入金額や経過時間によらず入金1回ごとに定額を付ける
+        if ctx.accounts.vault.flat_reward {
+            credit_flat_reward(
+                &mut ctx.accounts.vault,
+                &mut ctx.accounts.reward_box,
+                &ctx.accounts.config,
+                &mut ctx.accounts.global_emission,
+            );
+        }
+
+        // ★元本を2倍より大きくする入金には、入金額に応じたボーナスを一度だけ付ける
+        credit_large_deposit_bonus(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+            amount,
+        );
+
+        // ★MatchPool を渡した場合は、手数料を引いた入金額に応じてプールから上乗せする
+        if let Some(match_pool) = ctx.accounts.match_pool.as_mut() {
+            apply_deposit_match(
+                &mut ctx.accounts.vault,
+                match_pool,
+                &ctx.accounts.config,
+                &mut ctx.accounts.global_emission,
+                amount,
+                clock.unix_timestamp,
+            )?;
+        }
+
+        // ★入金元の記録を渡した場合は、入金したウォレットを記録する（withdraw_to_source の出金先になる）
+        if let Some(sources) = ctx.accounts.deposit_sources.as_mut() {
+            record_deposit_source(sources, ctx.accounts.user.key());
+        }
+
+        // ★紹介者へのボーナス（手数料を引いた入金額が基準）
+        credit_referral_bonuses(
+            ctx.remaining_accounts,
+            &ctx.accounts.vault,
+            amount,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+            ctx.program_id,
+        )?;
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★2-1. 入金の上乗せ用のプール（MatchPool）を作る（スポンサーごとに1つ）
+    pub fn create_match_pool(ctx: Context<CreateMatchPool>) -> Result<()> {
+        let pool = &mut ctx.accounts.match_pool;
+        pool.bump = ctx.bumps.match_pool;
+        pool.sponsor = ctx.accounts.sponsor.key();
+        pool.total_matched = 0;
+        Ok(())
+    }
+
+    // ★2-2. MatchPool への資金投入（スポンサーのみ）
+    pub fn fund_match_pool(ctx: Context<FundMatchPool>, amount: u64) -> Result<()> {
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.sponsor.to_account_info(),
+                to: ctx.accounts.match_pool.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_context, amount)?;
+        Ok(())
+    }
+
+    // ★2-3. MatchPool を閉じる（スポンサーのみ、残りの資金とレントはスポンサーに戻る）
+    pub fn close_match_pool(_ctx: Context<CloseMatchPool>) -> Result<()> {
+        Ok(())
+    }
+
+    // 3. 出金（リワード計算 → 出金）
+    pub fn withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        // ★has_one = user と同じ確認を明示的にも行う（コンテキストの変更で制約が外れても守れるように）
+        require_keys_eq!(
+            ctx.accounts.vault.user,
+            ctx.accounts.user.key(),
+            ErrorCode::NotVaultOwner
+        );
+        require_unlocked(&ctx.accounts.vault)?;
+
+        // 先にリワードを更新
+        update_rewards(
+            &mut ctx.accounts.vault, 
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        // ★出金できるのは元本（balance）だけ。promo_balance は含まない
+        require!(amount <= ctx.accounts.vault.balance, ErrorCode::InsufficientBalance);
+        // ★確定させたリワードのうち reward_forfeit_on_withdraw_bps の割合を没収する
+        forfeit_rewards_on_withdraw(
+            &ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        );
+        // ★残りが withdraw_dust_tolerance 以下なら全額の出金として扱う
+        let remaining = ctx.accounts.vault.balance - amount;
+        let amount = if remaining <= ctx.accounts.config.withdraw_dust_tolerance {
+            ctx.accounts.vault.balance
+        } else {
+            amount
+        };
+
+        // ★高額出金はキュー経由（request_withdraw → execute_withdraw）のみ
+        let threshold = ctx.accounts.config.large_withdraw_threshold;
+        require!(
+            threshold == 0 || amount <= threshold,
+            ErrorCode::WithdrawRequiresQueue
+        );
+        require_vested(&ctx.accounts.vault, amount)?;
+
+        // ★手数料（Treasuryへ）を差し引いた額がユーザーに届く
+        let clock = current_clock()?;
+        let fee = fee_for(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            amount,
+            ctx.accounts.config.withdraw_fee_bps,
+            clock.unix_timestamp,
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let user = &ctx.accounts.user;
+        
+        // Vaultから減らす
+        // ★レント免除額は balance に含まれないため、全額出金してもVaultはレント免除のまま残る
+        // （残った少額の元本にリワードが付いても、支払いは pay_reward_claim でTreasuryの残高までに抑えられる）
+        **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+        // ユーザーへ増やす
+        **user.to_account_info().try_borrow_mut_lamports()? += amount - fee;
+        **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? += fee;
+
+        // 残高更新
+        vault.balance -= amount;
+        record_fee_paid(vault, fee);
+        record_withdrawal_score(&ctx.accounts.config, vault, clock.unix_timestamp);
+        record_withdrawal(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, -(amount as i64));
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★3-0. リワードをすべて元本に組み入れて（Treasury原資）から出金する
+    // 組み入れ後の残高から amount を出金するので、リワード分を含めた額まで引き出せる
+    pub fn withdraw_after_compound(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_unlocked(&ctx.accounts.vault)?;
+        require_own_rewards(&ctx.accounts.vault, &ctx.accounts.reward_box)?;
+
+        // 先にリワードを更新
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        let rewards = ctx.accounts.reward_box.balance;
+        let clock = current_clock()?;
+        let compounded = compound_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.treasury,
+            &mut ctx.accounts.global_emission,
+            clock.unix_timestamp,
+        )?;
+        require!(compounded == rewards, ErrorCode::InsufficientTreasury);
+
+        // 以降は通常の出金と同じ（リワードは確定済みなので再計算は0になる）
+        withdraw(ctx, amount)
+    }
+
+    // ★3-1. 出金予約（高額出金用）。ready_at 以降に execute_withdraw で実行できる
+    pub fn request_withdraw(ctx: Context<Withdraw>, amount: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_unlocked(&ctx.accounts.vault)?;
+
+        let vault = &mut ctx.accounts.vault;
+        require!(vault.pending_withdraw_amount == 0, ErrorCode::PendingWithdrawExists);
+        require!(amount > 0 && amount <= vault.balance, ErrorCode::InsufficientBalance);
+        require_vested(vault, amount)?;
+
+        let clock = current_clock()?;
+        vault.pending_withdraw_amount = amount;
+        vault.pending_withdraw_ready_at = clock.unix_timestamp + ctx.accounts.config.withdraw_delay_secs;
+        Ok(())
+    }
+
+    // ★3-2. 予約済み出金の実行（リワード計算 → 出金）
+    pub fn execute_withdraw(ctx: Context<Withdraw>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_unlocked(&ctx.accounts.vault)?;
+
+        let amount = ctx.accounts.vault.pending_withdraw_amount;
+        require!(amount > 0, ErrorCode::NoPendingWithdraw);
+        require_vested(&ctx.accounts.vault, amount)?;
+
+        let clock = current_clock()?;
+        require!(
+            clock.unix_timestamp >= ctx.accounts.vault.pending_withdraw_ready_at,
+            ErrorCode::WithdrawNotReady
+        );
+
+        // 先にリワードを更新
+        update_rewards(
+            &mut ctx.accounts.vault, 
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        let fee = fee_for(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            amount,
+            ctx.accounts.config.withdraw_fee_bps,
+            clock.unix_timestamp,
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        let user = &ctx.accounts.user;
+        require!(amount <= vault.balance, ErrorCode::InsufficientBalance);
+
+        // Vaultから減らす
+        **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+        // ユーザーへ増やす（手数料はTreasuryへ）
+        **user.to_account_info().try_borrow_mut_lamports()? += amount - fee;
+        **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? += fee;
+
+        // 残高更新 & 予約をクリア
+        vault.balance -= amount;
+        record_fee_paid(vault, fee);
+        record_withdrawal_score(&ctx.accounts.config, vault, clock.unix_timestamp);
+        record_withdrawal(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, -(amount as i64));
+        vault.pending_withdraw_amount = 0;
+        vault.pending_withdraw_ready_at = 0;
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★3-3. 出金予約の取り消し
+    pub fn cancel_withdraw(ctx: Context<UpdateVault>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let vault = &mut ctx.accounts.vault;
+        require!(vault.pending_withdraw_amount > 0, ErrorCode::NoPendingWithdraw);
+        vault.pending_withdraw_amount = 0;
+        vault.pending_withdraw_ready_at = 0;
+        Ok(())
+    }
+
+    // ★3-4. ロック中の早期出金（ペナルティを差し引いた額を受け取る）
+    // ペナルティは Config の penalty_destination に従って送られる
+    pub fn early_withdraw(ctx: Context<EarlyWithdraw>, amount: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let config = &ctx.accounts.config;
+        require!(config.early_withdraw_penalty_bps > 0, ErrorCode::EarlyWithdrawDisabled);
+        require!(!ctx.accounts.vault.frozen, ErrorCode::VaultFrozen);
+        require!(!ctx.accounts.vault.donated, ErrorCode::VaultDonated);
+        let clock = current_clock()?;
+        require!(
+            clock.unix_timestamp < ctx.accounts.vault.lock_until,
+            ErrorCode::VaultNotLocked
+        );
+        require_keys_eq!(
+            ctx.accounts.penalty_recipient.key(),
+            penalty_destination_key(config, &ctx.accounts.treasury),
+            ErrorCode::InvalidPenaltyDestination
+        );
+
+        // 先にリワードを更新
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        // ★高額出金はキュー経由のみ（早期出金でも同じ）
+        let threshold = ctx.accounts.config.large_withdraw_threshold;
+        require!(
+            threshold == 0 || amount <= threshold,
+            ErrorCode::WithdrawRequiresQueue
+        );
+        require!(amount <= ctx.accounts.vault.balance, ErrorCode::InsufficientBalance);
+        require_vested(&ctx.accounts.vault, amount)?;
+
+        let penalty =
+            (amount as u128 * ctx.accounts.config.early_withdraw_penalty_bps as u128 / 10000) as u64;
+
+        let vault = &mut ctx.accounts.vault;
+        **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? += amount - penalty;
+        **ctx.accounts.penalty_recipient.to_account_info().try_borrow_mut_lamports()? += penalty;
+        vault.balance -= amount;
+        record_withdrawal_score(&ctx.accounts.config, vault, clock.unix_timestamp);
+        record_withdrawal(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, -(amount as i64));
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★3-5. 出金の事前承認（ERC-20 の approve に相当）
+    // delegate が amount まで Vault から出金できるようにする。呼び直すと上書き（0 で取り消し）
+    pub fn approve_withdrawal(
+        ctx: Context<ApproveWithdrawal>,
+        delegate: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let allowance = &mut ctx.accounts.allowance;
+        allowance.vault = ctx.accounts.vault.key();
+        allowance.delegate = delegate;
+        allowance.amount = amount;
+        allowance.bump = ctx.bumps.allowance;
+        Ok(())
+    }
+
+    // ★3-5-1. 入金元の記録（DepositSources）を作る（Vaultの持ち主のみ、レントは持ち主が負担）
+    // 以降、deposit に渡すと入金したウォレットが記録される（直近 MAX_DEPOSIT_SOURCES 件、重複は記録しない）
+    pub fn enable_deposit_sources(ctx: Context<EnableDepositSources>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let sources = &mut ctx.accounts.deposit_sources;
+        sources.bump = ctx.bumps.deposit_sources;
+        sources.vault = ctx.accounts.vault.key();
+        Ok(())
+    }
+
+    // ★3-5-2. 入金元への出金（資金を元の入金元へ返す、Vaultの持ち主が署名）
+    // destination は DepositSources に記録された入金元でなければならない。
+    // ロック・ベスティング・手数料・リワードの没収は通常の出金と同じ扱い
+    pub fn withdraw_to_source(ctx: Context<WithdrawToSource>, amount: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_unlocked(&ctx.accounts.vault)?;
+        let destination = ctx.accounts.destination.key();
+        require!(
+            ctx.accounts.deposit_sources.contains(&destination),
+            ErrorCode::NotDepositSource
+        );
+
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        require!(amount <= ctx.accounts.vault.balance, ErrorCode::InsufficientBalance);
+        forfeit_rewards_on_withdraw(
+            &ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        );
+        let threshold = ctx.accounts.config.large_withdraw_threshold;
+        require!(
+            threshold == 0 || amount <= threshold,
+            ErrorCode::WithdrawRequiresQueue
+        );
+        require_vested(&ctx.accounts.vault, amount)?;
+
+        let clock = current_clock()?;
+        let fee = fee_for(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            amount,
+            ctx.accounts.config.withdraw_fee_bps,
+            clock.unix_timestamp,
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.destination.to_account_info().try_borrow_mut_lamports()? += amount - fee;
+        **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? += fee;
+        vault.balance -= amount;
+        record_fee_paid(vault, fee);
+        record_withdrawal_score(&ctx.accounts.config, vault, clock.unix_timestamp);
+        record_withdrawal(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, -(amount as i64));
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★3-6. 承認済みの範囲での出金（delegate が署名し、delegate が受け取る）
+    // 出金額だけ承認額を減らす。ロック・ベスティング・手数料は通常の出金と同じ扱い
+    pub fn withdraw_with_allowance(ctx: Context<WithdrawWithAllowance>, amount: u64) -> Result<()> {
+        require_unlocked(&ctx.accounts.vault)?;
+        require!(amount <= ctx.accounts.allowance.amount, ErrorCode::AllowanceExceeded);
+
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        let threshold = ctx.accounts.config.large_withdraw_threshold;
+        require!(
+            threshold == 0 || amount <= threshold,
+            ErrorCode::WithdrawRequiresQueue
+        );
+        require!(amount <= ctx.accounts.vault.balance, ErrorCode::InsufficientBalance);
+        require_vested(&ctx.accounts.vault, amount)?;
+
+        let clock = current_clock()?;
+        let fee = fee_for(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            amount,
+            ctx.accounts.config.withdraw_fee_bps,
+            clock.unix_timestamp,
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.delegate.to_account_info().try_borrow_mut_lamports()? += amount - fee;
+        **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? += fee;
+        vault.balance -= amount;
+        record_fee_paid(vault, fee);
+        record_withdrawal_score(&ctx.accounts.config, vault, clock.unix_timestamp);
+        record_withdrawal(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, -(amount as i64));
+        ctx.accounts.allowance.amount -= amount;
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★受取人（相続人）の指定（Vaultの持ち主のみ。指定し直すと上書き）
+    // 持ち主の操作が inactivity_period_secs を超えて途絶えると、受取人が claim_as_beneficiary で元本を受け取れる
+    pub fn set_beneficiary(
+        ctx: Context<SetBeneficiary>,
+        beneficiary: Pubkey,
+        inactivity_period_secs: i64,
+    ) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require!(inactivity_period_secs > 0, ErrorCode::InvalidInactivityPeriod);
+        let record = &mut ctx.accounts.beneficiary_record;
+        record.vault = ctx.accounts.vault.key();
+        record.beneficiary = beneficiary;
+        record.inactivity_period_secs = inactivity_period_secs;
+        record.bump = ctx.bumps.beneficiary_record;
+        Ok(())
+    }
+
+    // ★受取人による元本の受け取り（受取人が署名）
+    // 無操作の判定には last_owner_activity を使う（crank_accrue など持ち主以外の操作ではタイマーは戻らない）
+    pub fn claim_as_beneficiary(ctx: Context<ClaimAsBeneficiary>) -> Result<()> {
+        require!(!ctx.accounts.vault.frozen, ErrorCode::VaultFrozen);
+        require!(!ctx.accounts.vault.donated, ErrorCode::VaultDonated);
+        let now = current_clock()?.unix_timestamp;
+        require!(
+            now - ctx.accounts.vault.last_owner_activity
+                > ctx.accounts.beneficiary_record.inactivity_period_secs,
+            ErrorCode::OwnerStillActive
+        );
+
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        // ロック・ベスティング・出金予約に関わらず元本をすべて移す（リワードは RewardBox に残る）
+        let vault = &mut ctx.accounts.vault;
+        let amount = vault.balance;
+        require!(amount > 0, ErrorCode::InsufficientBalance);
+        **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.beneficiary.to_account_info().try_borrow_mut_lamports()? += amount;
+        vault.balance = 0;
+        vault.pending_withdraw_amount = 0;
+        vault.unearning_amount = 0;
+        vault.vesting_amount = 0;
+        record_withdrawal(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, -(amount as i64));
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★4. 送金機能（修正版）
+    // 送金先はレント免除額以上の残高を持っている（または今回の送金で到達する）必要がある。
+    // 0 lamportの新規アカウントへ少額だけ送ると、レント免除を満たさない
+    // システムアカウントができてしまうため、その場合は RecipientNotRentExempt で拒否する。
+    pub fn transfer(ctx: Context<TransferSol>, amount: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_not_shutdown(&ctx.accounts.config)?;
+        require_unlocked(&ctx.accounts.vault)?;
+
+        // 先にリワードを更新
+        update_rewards(
+            &mut ctx.accounts.vault, 
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        // ★Treasury PDA に送ると total_funded と実際の lamports がずれるため、所有者チェックより前に明示的に拒否する
+        // （Treasury への入金は fund_treasury で行う）
+        let (treasury, _) = Pubkey::find_program_address(&[b"treasury"], ctx.program_id);
+        require_keys_neq!(ctx.accounts.recipient.key(), treasury, ErrorCode::RecipientIsTreasury);
+
+        // ★送金先はシステムプログラム所有のアカウント（通常のウォレット）に限る
+        // （プログラム所有のアカウントへ直接lamportを足すと、相手側の会計を壊しうるため）
+        let recipient_info = ctx.accounts.recipient.to_account_info();
+        require_keys_eq!(
+            *recipient_info.owner,
+            anchor_lang::system_program::ID,
+            ErrorCode::InvalidRecipientOwner
+        );
+
+        // ★設定により、送金先が opt_in_recipient で受け取りを登録済みであることを求める
+        require!(
+            !ctx.accounts.config.require_recipient_opt_in || ctx.accounts.recipient_opt_in.is_some(),
+            ErrorCode::RecipientNotOptedIn
+        );
+
+        // ★送金後の送金先残高がレント免除額に届くかチェック
+        let rent = Rent::get()?;
+        require!(
+            rent.is_exempt(
+                recipient_info.lamports().saturating_add(amount),
+                recipient_info.data_len()
+            ),
+            ErrorCode::RecipientNotRentExempt
+        );
+
+        // ★即時の送金でも元本を超えて送れないようにする（取り消し期間の有無によらず先に判定する）
+        require!(amount <= ctx.accounts.vault.balance, ErrorCode::InsufficientBalance);
+        require_vested(&ctx.accounts.vault, amount)?;
+
+        let vault = &mut ctx.accounts.vault;
+        let window = ctx.accounts.config.transfer_cancel_window_secs;
+        if window > 0 {
+            require!(vault.pending_transfer_amount == 0, ErrorCode::PendingTransferExists);
+        }
+
+        // ★1日あたりの送金回数をカウント（上限を超えたら拒否）
+        let clock = current_clock()?;
+        record_transfer(vault, &ctx.accounts.config, clock.unix_timestamp)?;
+
+        if window > 0 {
+            // ★取り消し期間中はlamportをVaultに残したまま送金予約にする（元本からは外れる）
+            vault.pending_transfer_amount = amount;
+            vault.pending_transfer_recipient = ctx.accounts.recipient.key();
+            vault.pending_transfer_claimable_at = clock.unix_timestamp + window;
+        } else {
+            // 【修正箇所】システムプログラムを使わず、直接残高を移動させる
+            // 1. Vaultから減らす
+            **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+
+            // 2. 送金先（Recipient）へ増やす
+            **ctx.accounts.recipient.to_account_info().try_borrow_mut_lamports()? += amount;
+        }
+
+        // データ上の残高も更新
+        vault.balance -= amount;
+        record_withdrawal(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, -(amount as i64));
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★4-0. transfer の受け取りを登録する（送金先になるウォレット自身が署名し、レントも負担する）
+    // require_recipient_opt_in が有効な間は、登録したウォレットにしか送金できない
+    pub fn opt_in_recipient(ctx: Context<OptInRecipient>) -> Result<()> {
+        ctx.accounts.recipient_opt_in.bump = ctx.bumps.recipient_opt_in;
+        Ok(())
+    }
+
+    // ★4-0-1. 受け取りの登録を取り消す（レントは送金先に戻る）
+    pub fn opt_out_recipient(_ctx: Context<OptOutRecipient>) -> Result<()> {
+        Ok(())
+    }
+
+    // ★4-1. 送金予約の取り消し（持ち主のみ、取り消し期間中だけ）
+    // 予約した額を元本に戻す。戻した時点からまたリワードの対象になる
+    pub fn cancel_transfer(ctx: Context<CancelTransfer>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let amount = ctx.accounts.vault.pending_transfer_amount;
+        require!(amount > 0, ErrorCode::NoPendingTransfer);
+        let clock = current_clock()?;
+        require!(
+            clock.unix_timestamp < ctx.accounts.vault.pending_transfer_claimable_at,
+            ErrorCode::TransferCancelWindowClosed
+        );
+
+        // 先にリワードを更新（予約中の額は元本に含まれていないので、ここまでの分には付かない）
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.balance = vault.balance.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        record_deposit(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, amount as i64);
+        clear_pending_transfer(vault);
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★4-2. 送金予約の受け取り（受取人のみ、取り消し期間が過ぎてから）
+    // 予約中の額は元本から外れているので、Vault の balance は変わらない
+    pub fn claim_transfer(ctx: Context<ClaimTransfer>) -> Result<()> {
+        let amount = ctx.accounts.vault.pending_transfer_amount;
+        require!(amount > 0, ErrorCode::NoPendingTransfer);
+        let clock = current_clock()?;
+        require!(
+            clock.unix_timestamp >= ctx.accounts.vault.pending_transfer_claimable_at,
+            ErrorCode::TransferNotClaimable
+        );
+
+        let vault = &mut ctx.accounts.vault;
+        **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.recipient.to_account_info().try_borrow_mut_lamports()? += amount;
+        clear_pending_transfer(vault);
+        Ok(())
+    }
+
+    // ★5. リワードをロック付き元本に変換（ボーナス1.1倍）
+    // リワードポイント × 1.1 のSOLをTreasuryからVaultへ移し、lock_duration 秒間ロックする
+    pub fn convert_rewards_to_locked(ctx: Context<ConvertRewards>, lock_duration: i64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_not_shutdown(&ctx.accounts.config)?;
+        require_own_rewards(&ctx.accounts.vault, &ctx.accounts.reward_box)?;
+        require!(lock_duration > 0, ErrorCode::InvalidLockDuration);
+        let max_lock = ctx.accounts.config.max_lock_duration_secs;
+        require!(max_lock == 0 || lock_duration <= max_lock, ErrorCode::LockDurationTooLong);
+
+        // 先にリワードを確定
+        update_rewards(
+            &mut ctx.accounts.vault, 
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        let rewards = ctx.accounts.reward_box.balance;
+        require!(rewards > 0, ErrorCode::NoRewards);
+        let bonus_amount = (rewards as u128 * LOCK_BONUS_BPS / 10000) as u64;
+
+        // Treasuryはレント免除額を残して支払う
+        let treasury_info = ctx.accounts.treasury.to_account_info();
+        let rent = Rent::get()?;
+        let available = treasury_info
+            .lamports()
+            .saturating_sub(rent.minimum_balance(treasury_info.data_len()));
+        require!(bonus_amount <= available, ErrorCode::InsufficientTreasury);
+
+        // TreasuryからVaultへ移動
+        **treasury_info.try_borrow_mut_lamports()? -= bonus_amount;
+        **ctx.accounts.vault.to_account_info().try_borrow_mut_lamports()? += bonus_amount;
+
+        let clock = current_clock()?;
+        let vault = &mut ctx.accounts.vault;
+        vault.balance = vault.balance.checked_add(bonus_amount).ok_or(ErrorCode::MathOverflow)?;
+        vault.locked_amount = vault.locked_amount.saturating_add(bonus_amount);
+        record_deposit(&mut ctx.accounts.global_emission, bonus_amount);
+        emit_balance_delta(vault, bonus_amount as i64);
+        vault.lock_until = vault.lock_until.max(clock.unix_timestamp + lock_duration);
+
+        ctx.accounts.reward_box.balance = 0;
+        emit_reward_delta(&ctx.accounts.vault, &ctx.accounts.reward_box, -(rewards as i64));
+        release_liability(&mut ctx.accounts.global_emission, &ctx.accounts.reward_box, rewards);
+        Ok(())
+    }
+
+    // ★6. 不変条件のチェック（テスト・監視用、誰でも実行可能）
+    // - Vaultのlamports == balance + 送金予約中の額 + レント免除額（ずれていれば会計が壊れている）
+    // - last_update_time が未来になっていない
+    // - RewardBoxがレント免除を満たしている
+    // - 保存されているbumpが正規（canonical）のbumpである
+    pub fn assert_invariants(ctx: Context<AssertInvariants>) -> Result<()> {
+        let rent = Rent::get()?;
+        let clock = current_clock()?;
+
+        let vault = &ctx.accounts.vault;
+        let vault_info = vault.to_account_info();
+        let expected_lamports = vault
+            .balance
+            .checked_add(vault.pending_transfer_amount)
+            .and_then(|v| v.checked_add(rent.minimum_balance(vault_info.data_len())))
+            .ok_or(ErrorCode::VaultBalanceMismatch)?;
+        require!(
+            vault_info.lamports() == expected_lamports,
+            ErrorCode::VaultBalanceMismatch
+        );
+
+        require!(
+            vault.last_update_time <= clock.unix_timestamp,
+            ErrorCode::FutureUpdateTime
+        );
+
+        let reward_info = ctx.accounts.reward_box.to_account_info();
+        require!(
+            rent.is_exempt(reward_info.lamports(), reward_info.data_len()),
+            ErrorCode::RewardBoxNotRentExempt
+        );
+
+        let user = vault.user;
+        let name = vault.name_seed();
+        let (_, vault_bump) =
+            Pubkey::find_program_address(&[b"vault", user.as_ref(), name], ctx.program_id);
+        let (_, reward_bump) =
+            Pubkey::find_program_address(&[b"reward", user.as_ref(), name], ctx.program_id);
+        require!(
+            vault.bump == vault_bump && ctx.accounts.reward_box.bump == reward_bump,
+            ErrorCode::NonCanonicalBump
+        );
+
+        Ok(())
+    }
+
+    // ★Vault PDAへ deposit を通さず直接送られたSOL（balance + 送金予約 + レント免除額を超える分）を持ち主に返す（持ち主のみ）
+    pub fn sweep_surplus(ctx: Context<SweepSurplus>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let rent = Rent::get()?;
+        let vault = &ctx.accounts.vault;
+        let vault_info = vault.to_account_info();
+        let expected_lamports = vault
+            .balance
+            .checked_add(vault.pending_transfer_amount)
+            .and_then(|v| v.checked_add(rent.minimum_balance(vault_info.data_len())))
+            .ok_or(ErrorCode::MathOverflow)?;
+        let surplus = vault_info.lamports().saturating_sub(expected_lamports);
+        require!(surplus > 0, ErrorCode::NoSurplus);
+
+        // vault.balance は変わらないので BalanceDeltaEvent は出さない
+        **vault_info.try_borrow_mut_lamports()? -= surplus;
+        **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? += surplus;
+
+        emit!(SurplusSweptEvent {
+            vault: ctx.accounts.vault.key(),
+            amount: surplus,
+        });
+        Ok(())
+    }
+
+    // ★Vaultの元本をすべて外部のスワッププログラムでSPLトークンに替え、トークン用のVault（token_vault）に入れる
+    // route はスワッププログラムへ渡す命令データ、remaining_accounts はその命令のアカウント（順番どおり）。
+    // 元本は出金手数料を引いてユーザーへ移してからスワップするので、route の入力額はその範囲で指定する。
+    // user_token_account に届いたトークンが min_out 未満なら全体を取り消す（スリッページ保護）
+    pub fn convert_vault<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ConvertVault<'info>>,
+        min_out: u64,
+        route: Vec<u8>,
+    ) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_unlocked(&ctx.accounts.vault)?;
+        require_not_closing(&ctx.accounts.vault)?;
+        require!(
+            ctx.accounts.swap_program.key() != crate::ID,
+            ErrorCode::InvalidSwapProgram
+        );
+        // ★計算量とシリアライズの量を抑えるため、可変長の入力には上限を設ける
+        require!(route.len() <= MAX_SWAP_ROUTE_LEN, ErrorCode::SwapRouteTooLong);
+        require!(
+            ctx.remaining_accounts.len() <= MAX_SWAP_ACCOUNTS,
+            ErrorCode::TooManySwapAccounts
+        );
+
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        // ★元本の全額を withdraw と同じ条件で引き出す
+        let amount = ctx.accounts.vault.balance;
+        require!(amount > 0, ErrorCode::InsufficientBalance);
+        let threshold = ctx.accounts.config.large_withdraw_threshold;
+        require!(
+            threshold == 0 || amount <= threshold,
+            ErrorCode::WithdrawRequiresQueue
+        );
+        require_vested(&ctx.accounts.vault, amount)?;
+
+        let clock = current_clock()?;
+        let fee = fee_for(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            amount,
+            ctx.accounts.config.withdraw_fee_bps,
+            clock.unix_timestamp,
+        );
+        **ctx.accounts.vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? += amount - fee;
+        **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? += fee;
+
+        let vault = &mut ctx.accounts.vault;
+        vault.balance = 0;
+        record_fee_paid(vault, fee);
+        record_withdrawal(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, -(amount as i64));
+
+        // ★スワップ（ユーザーの署名はそのまま引き継がれる）
+        let before = ctx.accounts.user_token_account.amount;
+        let accounts = ctx.remaining_accounts;
+        let swap_ix = Instruction {
+            program_id: ctx.accounts.swap_program.key(),
+            accounts: accounts
+                .iter()
+                .map(|a| AccountMeta {
+                    pubkey: a.key(),
+                    is_signer: a.is_signer,
+                    is_writable: a.is_writable,
+                })
+                .collect(),
+            data: route,
+        };
+        let mut swap_infos = accounts.to_vec();
+        swap_infos.push(ctx.accounts.swap_program.to_account_info());
+        invoke(&swap_ix, &swap_infos)?;
+
+        ctx.accounts.user_token_account.reload()?;
+        let amount_out = ctx.accounts.user_token_account.amount.saturating_sub(before);
+        require!(amount_out >= min_out, ErrorCode::SlippageExceeded);
+
+        // ★受け取ったトークンをトークン用のVaultへ入れる
+        let cpi_context = CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.user_token_account.to_account_info(),
+                to: ctx.accounts.token_vault.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            },
+        );
+        token::transfer(cpi_context, amount_out)?;
+
+        emit!(VaultConvertedEvent {
+            vault: ctx.accounts.vault.key(),
+            sol_amount: amount,
+            fee,
+            mint: ctx.accounts.output_mint.key(),
+            amount_out,
+        });
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★トークン用のVault（token_vault）からの出金（持ち主のみ）
+    pub fn withdraw_token_vault(ctx: Context<WithdrawTokenVault>, amount: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_unlocked(&ctx.accounts.vault)?;
+        require!(
+            amount <= ctx.accounts.token_vault.amount,
+            ErrorCode::InsufficientBalance
+        );
+
+        // token_vault の権限は Vault PDA なので、Vault の seeds で署名する
+        let vault = &ctx.accounts.vault;
+        let user_key = ctx.accounts.user.key();
+        let bump = [vault.bump];
+        let signer_seeds: &[&[&[u8]]] = &[&[b"vault", user_key.as_ref(), vault.name_seed(), &bump]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            Transfer {
+                from: ctx.accounts.token_vault.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.vault.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::transfer(cpi_context, amount)
+    }
+
+    // ★7. 定期入金の登録（"先取り貯金"の自動化）
+    // スケジューラが interval_secs ごとに crank_deposit で amount を入金できるようにする。
+    // 入金の原資は RecurringDeposit PDA 自体に持ち主がSOLを送って用意しておく（事前承認）。
+    pub fn authorize_recurring(
+        ctx: Context<AuthorizeRecurring>,
+        amount: u64,
+        interval_secs: i64,
+    ) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require!(amount > 0, ErrorCode::DepositTooSmall);
+        require!(interval_secs > 0, ErrorCode::InvalidRecurringInterval);
+
+        let clock = current_clock()?;
+        let recurring = &mut ctx.accounts.recurring;
+        recurring.user = ctx.accounts.user.key();
+        recurring.amount = amount;
+        recurring.interval_secs = interval_secs;
+        recurring.next_deposit_at = clock.unix_timestamp; // 初回はすぐに実行できる
+        recurring.bump = ctx.bumps.recurring;
+        Ok(())
+    }
+
+    // ★定期入金の実行（誰でも呼べるが、間隔より早い呼び出しは拒否）
+    pub fn crank_deposit(ctx: Context<CrankDeposit>, amount: u64) -> Result<()> {
+        require_not_shutdown(&ctx.accounts.config)?;
+        require_not_closing(&ctx.accounts.vault)?;
+        require_not_growth_vault(&ctx.accounts.vault)?;
+
+        let clock = current_clock()?;
+        let recurring = &ctx.accounts.recurring;
+        require!(amount == recurring.amount, ErrorCode::RecurringAmountMismatch);
+        require!(
+            clock.unix_timestamp >= recurring.next_deposit_at,
+            ErrorCode::CrankTooEarly
+        );
+
+        // 原資（レント免除額を除いた残高）が足りるか
+        let recurring_info = recurring.to_account_info();
+        let rent = Rent::get()?;
+        let available = recurring_info
+            .lamports()
+            .saturating_sub(rent.minimum_balance(recurring_info.data_len()));
+        require!(amount <= available, ErrorCode::InsufficientRecurringFunds);
+
+        // 先にリワードを更新
+        update_rewards(
+            &mut ctx.accounts.vault, 
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        // ★自動組み入れ
+        maybe_auto_compound(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.treasury,
+            &mut ctx.accounts.global_emission,
+            clock.unix_timestamp,
+        )?;
+
+        // ★入金手数料はTreasuryへ
+        let fee = fee_for(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            amount,
+            ctx.accounts.config.deposit_fee_bps,
+            clock.unix_timestamp,
+        );
+        let net = amount - fee;
+        require_within_age_cap(
+            &ctx.accounts.config,
+            &ctx.accounts.vault,
+            net,
+            clock.unix_timestamp,
+        )?;
+        require_within_self_cap(&ctx.accounts.vault, net)?;
+        require_not_goal_locked(&ctx.accounts.config, &ctx.accounts.vault, clock.unix_timestamp)?;
+
+        // 原資からVaultへ移動
+        **recurring_info.try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.vault.to_account_info().try_borrow_mut_lamports()? += net;
+        **ctx.accounts.treasury.to_account_info().try_borrow_mut_lamports()? += fee;
+        record_deposit_fee(&mut ctx.accounts.treasury, &mut ctx.accounts.global_emission, fee);
+        record_fee_paid(&mut ctx.accounts.vault, fee);
+
+        credit_deposit(&mut ctx.accounts.vault, net)?;
+        record_deposit(&mut ctx.accounts.global_emission, net);
+        emit_balance_delta(&ctx.accounts.vault, net as i64);
+        add_vesting(&mut ctx.accounts.vault, &ctx.accounts.config, net, clock.unix_timestamp);
+        add_reward_delay(&mut ctx.accounts.vault, &ctx.accounts.config, net, clock.unix_timestamp);
+        add_locked_savings(&mut ctx.accounts.vault, net, clock.unix_timestamp);
+        let recurring = &mut ctx.accounts.recurring;
+        recurring.next_deposit_at = clock.unix_timestamp + recurring.interval_secs;
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★複数ユーザーのVaultへの一括入金（給与天引きの積立など。payer が全額を負担する）
+    // remaining_accounts に (Vault, RewardBox) の組を並べ、amounts[i] を i 組目のVaultへ入金する。
+    // 各Vaultはリワードを確定してから入金する。入金単位・手数料・経過時間ごとの上限は deposit と同じ
+    pub fn batch_deposit_to_vaults<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchDepositToVaults<'info>>,
+        amounts: Vec<u64>,
+    ) -> Result<()> {
+        require_not_shutdown(&ctx.accounts.config)?;
+        let accounts = ctx.remaining_accounts;
+        require_vault_pair_count(accounts)?;
+        require!(
+            amounts.len() == accounts.len() / 2,
+            ErrorCode::DepositAmountsMismatch
+        );
+
+        let clock = current_clock()?;
+        for (pair, &amount) in accounts.chunks(2).zip(amounts.iter()) {
+            let (mut vault, mut reward_box) = load_vault_pair(&pair[0], &pair[1], ctx.program_id)?;
+            require_not_closing(&vault)?;
+            require_not_growth_vault(&vault)?;
+            let amount = apply_deposit_granularity(&ctx.accounts.config, amount)?;
+
+            update_rewards(
+                &mut vault,
+                &mut reward_box,
+                &ctx.accounts.config,
+                &mut ctx.accounts.global_emission,
+            )?;
+            maybe_auto_compound(
+                &mut vault,
+                &mut reward_box,
+                &ctx.accounts.treasury,
+                &mut ctx.accounts.global_emission,
+                clock.unix_timestamp,
+            )?;
+
+            // ★手数料はTreasuryへ
+            let fee = fee_for(
+                &ctx.accounts.config,
+                &vault,
+                amount,
+                ctx.accounts.config.deposit_fee_bps,
+                clock.unix_timestamp,
+            );
+            let net = amount - fee;
+            require_within_age_cap(&ctx.accounts.config, &vault, net, clock.unix_timestamp)?;
+            require_within_self_cap(&vault, net)?;
+            require_not_goal_locked(&ctx.accounts.config, &vault, clock.unix_timestamp)?;
+
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.payer.to_account_info(),
+                    to: vault.to_account_info(),
+                },
+            );
+            anchor_lang::system_program::transfer(cpi_context, net)?;
+            if fee > 0 {
+                let cpi_context = CpiContext::new(
+                    ctx.accounts.system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.payer.to_account_info(),
+                        to: ctx.accounts.treasury.to_account_info(),
+                    },
+                );
+                anchor_lang::system_program::transfer(cpi_context, fee)?;
+                record_deposit_fee(
+                    &mut ctx.accounts.treasury,
+                    &mut ctx.accounts.global_emission,
+                    fee,
+                );
+                record_fee_paid(&mut vault, fee);
+            }
+
+            credit_deposit(&mut vault, net)?;
+            record_deposit(&mut ctx.accounts.global_emission, net);
+            emit_balance_delta(&vault, net as i64);
+            add_vesting(&mut vault, &ctx.accounts.config, net, clock.unix_timestamp);
+            add_reward_delay(&mut vault, &ctx.accounts.config, net, clock.unix_timestamp);
+            add_locked_savings(&mut vault, net, clock.unix_timestamp);
+            emit_vault_touched(&ctx.accounts.config, &vault, &reward_box);
+
+            // remaining_accounts は自動で書き戻されないので明示的に保存する
+            vault.exit(ctx.program_id)?;
+            reward_box.exit(ctx.program_id)?;
+        }
+        Ok(())
+    }
+
+    // ★リワードの一括確定（キーパー用。誰でも呼べる）
+    // remaining_accounts に (Vault, RewardBox) の組を並べて渡す。計算量を抑えるため MAX_VAULT_PAIRS 組まで
+    pub fn crank_accrue<'info>(ctx: Context<'_, '_, 'info, 'info, CrankAccrue<'info>>) -> Result<()> {
+        let accounts = ctx.remaining_accounts;
+        require_vault_pair_count(accounts)?;
+
+        for pair in accounts.chunks(2) {
+            let (mut vault, mut reward_box) = load_vault_pair(&pair[0], &pair[1], ctx.program_id)?;
+
+            update_rewards(
+                &mut vault,
+                &mut reward_box,
+                &ctx.accounts.config,
+                &mut ctx.accounts.global_emission,
+            )?;
+            emit_vault_touched(&ctx.accounts.config, &vault, &reward_box);
+            if let Some(leaderboard) = ctx.accounts.leaderboard.as_mut() {
+                record_leaderboard(leaderboard, vault.key(), reward_box.balance);
+            }
+
+            // remaining_accounts は自動で書き戻されないので明示的に保存する
+            vault.exit(ctx.program_id)?;
+            reward_box.exit(ctx.program_id)?;
+        }
+        Ok(())
+    }
+
+    // ★定期入金の解除（残った原資とレントは持ち主に返却）
+    pub fn cancel_recurring(_ctx: Context<CancelRecurring>) -> Result<()> {
+        Ok(())
+    }
+
+    // ★8. Vaultアカウントを現在のレイアウトに必要な最小サイズまで縮め、余分なレントを返却する
+    // Anchorの realloc 制約は縮小時にレント超過分をすべて払い出してしまう（＝預金まで返る）ため、
+    // ここではレントの差額だけを手動で移動する。
+    pub fn compact_vault(ctx: Context<CompactVault>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let vault_info = ctx.accounts.vault.to_account_info();
+        let current_len = vault_info.data_len();
+        require!(current_len > Vault::SPACE, ErrorCode::NothingToCompact);
+
+        let rent = Rent::get()?;
+        let refund = rent.minimum_balance(current_len) - rent.minimum_balance(Vault::SPACE);
+
+        // データは先頭から詰めて保存されているので、末尾を切り詰めても既存フィールドは壊れない
+        vault_info.resize(Vault::SPACE)?;
+        **vault_info.try_borrow_mut_lamports()? -= refund;
+        **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? += refund;
+        Ok(())
+    }
+
+    // ★8-1. 旧レイアウト（Vault::SPACE より小さい）のVaultを現在のサイズへ拡張する
+    // 新しいフィールドは末尾（予備領域の手前）に追加しているので、0埋めで広げれば既存データはそのまま読める。
+    // 旧サイズのままでは Account<Vault> としてデシリアライズできないため、生のアカウントとして扱う。
+    // ★サイズが最新でも version が古ければ version だけ更新する（RewardBox も同様）
+    pub fn migrate_vault(ctx: Context<MigrateVault>) -> Result<()> {
+        let vault_info = ctx.accounts.vault.to_account_info();
+        let current_len = vault_info.data_len();
+        {
+            let data = vault_info.try_borrow_data()?;
+            require!(
+                data.starts_with(Vault::DISCRIMINATOR),
+                anchor_lang::error::ErrorCode::AccountDiscriminatorMismatch
+            );
+        }
+
+        if current_len < Vault::SPACE {
+            // 増えるレント分だけをユーザーが支払う（預金残高 = lamports - レント は変わらない）
+            let rent = Rent::get()?;
+            let top_up = rent.minimum_balance(Vault::SPACE) - rent.minimum_balance(current_len);
+            let cpi_context = CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.user.to_account_info(),
+                    to: vault_info.clone(),
+                },
+            );
+            anchor_lang::system_program::transfer(cpi_context, top_up)?;
+
+            vault_info.resize(Vault::SPACE)?;
+        }
+
+        // ★version を最新にする（新しいフィールドは0埋めで、0 が初期値になるよう設計している）
+        // last_update_time と RewardBox の残高・端数はそのまま残し、ここでは確定もしない
+        // （次の確定が last_update_time から続けて計算するので、拡張の前後で期間の抜けや二重計上は起きない）
+        let mut data = vault_info.try_borrow_mut_data()?;
+        let mut vault = Vault::try_deserialize(&mut &data[..])?;
+        let reward_box = &mut ctx.accounts.reward_box;
+        require!(
+            current_len < Vault::SPACE
+                || vault.version != VAULT_VERSION
+                || reward_box.version != REWARD_BOX_VERSION,
+            ErrorCode::NothingToMigrate
+        );
+        vault.version = VAULT_VERSION;
+        // ★last_owner_activity を追加する前のVaultは0のままなので、持ち主の署名したこの移行を最初の操作とする
+        vault.last_owner_activity = current_clock()?.unix_timestamp;
+        vault.try_serialize(&mut &mut data[..])?;
+        // ★version 1 のRewardBoxは GlobalEmission の導入後に作られ、作成時から残高を集計に含めている
+        // （liability_recorded は予備領域から切り出したので0のまま）。version 0 から 1 へ移行済みのものも
+        // 区別できないので、二重に計上しない側に倒して集計済みとする。
+        // version 0 は導入前に作られた可能性があるので false のままにし、管理者が migrate_rewards で計上する
+        if reward_box.version == 1 {
+            reward_box.liability_recorded = true;
+        }
+        reward_box.version = REWARD_BOX_VERSION;
+        Ok(())
+    }
+
+    // ★8-1-1. 未払いリワードの集計（GlobalEmission）の導入前から貯まっているリワードを集計に計上する（管理者のみ、RewardBoxごとに一度だけ）
+    // 集計に含まれていないポイントは、Treasury の原資で裏付けられた支払い義務として扱われていない。
+    // 計上後の未払いリワードの合計を Treasury の原資（レント免除額を除く）でまかなえる場合だけ計上する。
+    // 作成時から集計しているRewardBox（liability_recorded）は二重に計上しないよう拒否する
+    pub fn migrate_rewards(ctx: Context<MigrateRewards>) -> Result<()> {
+        let reward_box = &mut ctx.accounts.reward_box;
+        require!(
+            reward_box.version == REWARD_BOX_VERSION,
+            ErrorCode::UnsupportedAccountVersion
+        );
+        require!(!reward_box.liability_recorded, ErrorCode::RewardsAlreadyRecorded);
+
+        let points = reward_box.balance;
+        let emission = &mut ctx.accounts.global_emission;
+        let outstanding = emission
+            .outstanding_liabilities
+            .checked_add(points)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        // ★支払い能力の確認: 計上後の未払いリワードをすべて払えること
+        let treasury_info = ctx.accounts.treasury.to_account_info();
+        let rent = Rent::get()?;
+        let available = treasury_info
+            .lamports()
+            .saturating_sub(rent.minimum_balance(treasury_info.data_len()));
+        require!(outstanding <= available, ErrorCode::InsufficientTreasury);
+
+        emission.outstanding_liabilities = outstanding;
+        reward_box.liability_recorded = true;
+        emit!(RewardsMigratedEvent {
+            reward_box: reward_box.key(),
+            points,
+            outstanding_liabilities: outstanding,
+        });
+        Ok(())
+    }
+
+    // ★8-2. RewardBox を広げてリワード履歴を有効にする（増えるレントはユーザーが支払う）
+    // 以降の確定ごとに RewardHistory へ記録する。履歴は空の状態から始まる
+    pub fn enable_reward_history(ctx: Context<RewardHistoryAccounts>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let reward_box_info = ctx.accounts.reward_box.to_account_info();
+        let current_len = reward_box_info.data_len();
+        let new_len = RewardBox::SPACE + RewardHistory::SPACE;
+        require!(current_len < new_len, ErrorCode::RewardHistoryAlreadyEnabled);
+
+        let rent = Rent::get()?;
+        let top_up = rent.minimum_balance(new_len) - rent.minimum_balance(current_len);
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.user.to_account_info(),
+                to: reward_box_info.clone(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_context, top_up)?;
+
+        reward_box_info.resize(new_len)?;
+        let mut data = reward_box_info.try_borrow_mut_data()?;
+        RewardHistory::default().serialize(&mut &mut data[RewardBox::SPACE..])?;
+        Ok(())
+    }
+
+    // ★8-3. リワード履歴を無効にし、RewardBox を元のサイズに戻してレントの差額を返却する
+    // 記録済みの履歴は破棄される（RewardBox の残高・端数は変わらない）
+    pub fn disable_reward_history(ctx: Context<RewardHistoryAccounts>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let reward_box_info = ctx.accounts.reward_box.to_account_info();
+        let current_len = reward_box_info.data_len();
+        require!(current_len > RewardBox::SPACE, ErrorCode::RewardHistoryNotEnabled);
+
+        let rent = Rent::get()?;
+        let refund = rent.minimum_balance(current_len) - rent.minimum_balance(RewardBox::SPACE);
+
+        // 履歴は RewardBox の末尾にあるので、切り詰めても既存フィールドは壊れない（compact_vault と同じ）
+        reward_box_info.resize(RewardBox::SPACE)?;
+        **reward_box_info.try_borrow_mut_lamports()? -= refund;
+        **ctx.accounts.user.to_account_info().try_borrow_mut_lamports()? += refund;
+        Ok(())
+    }
+
+    // ★9. USD建ての貯金目標を設定（セント単位、0 = 目標なし）
+    pub fn set_goal_usd(ctx: Context<UpdateVault>, usd_cents: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        ctx.accounts.vault.goal_usd_cents = usd_cents;
+        Ok(())
+    }
+
+    // ★9-1-2. 現在の元本とリワード残高を記録する（税務申告などの証跡用、持ち主のみ）
+    // 先にリワードを確定してから、直近 BALANCE_SNAPSHOT_LEN 件のリングに追記する（古いものから上書き）。
+    // 記録用のPDAは最初の呼び出しで作られる（レントは持ち主が負担）
+    pub fn snapshot_balance(ctx: Context<SnapshotBalance>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        let now = current_clock()?.unix_timestamp;
+        let snapshots = &mut ctx.accounts.balance_snapshots;
+        snapshots.bump = ctx.bumps.balance_snapshots;
+        let next = snapshots.next as usize % BALANCE_SNAPSHOT_LEN;
+        snapshots.entries[next] = BalanceSnapshot {
+            timestamp: now,
+            balance: ctx.accounts.vault.balance,
+            reward_box_balance: ctx.accounts.reward_box.balance,
+        };
+        snapshots.next = ((next + 1) % BALANCE_SNAPSHOT_LEN) as u8;
+        snapshots.len = (snapshots.len as usize + 1).min(BALANCE_SNAPSHOT_LEN) as u8;
+        Ok(())
+    }
+
+    // ★9-2. lamport建ての貯金目標を設定（0 = 目標なし）
+    // 残高が目標以上の間は、リワードに Config の goal_bonus_bps が上乗せされる
+    pub fn set_goal_amount(ctx: Context<UpdateVault>, amount: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        ctx.accounts.vault.goal_amount = amount;
+        Ok(())
+    }
+
+    // ★9-2-1. 自制のための元本の上限（持ち主のみ、下げることはできるが上げることも外すこともできない）
+    // 既に上限を超えている元本はそのまま残り、上限を下回るまで入金できなくなる
+    pub fn set_self_cap(ctx: Context<UpdateVault>, cap: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let vault = &mut ctx.accounts.vault;
+        require!(
+            cap > 0 && (vault.self_cap == 0 || cap < vault.self_cap),
+            ErrorCode::SelfCapNotLowered
+        );
+        vault.self_cap = cap;
+        Ok(())
+    }
+
+    // ★9-2-2. 閲覧用の権限を渡す（Pubkey::default() で取り消す）
+    // 家族や見守りサービスなどが、持ち主の代わりに get_vault_summary を呼べるようになる（資金は動かせない）
+    pub fn set_view_authority(ctx: Context<UpdateVault>, authority: Pubkey) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        ctx.accounts.vault.view_authority = authority;
+        Ok(())
+    }
+
+    // ★9-2-3. 通知のしきい値（0 = 通知しない）
+    // 入金で元本が、リワードの確定でリワード残高がこの値以上になった瞬間に ThresholdCrossedEvent を出す
+    pub fn set_notify_threshold(ctx: Context<UpdateVault>, threshold: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        ctx.accounts.vault.notify_threshold = threshold;
+        Ok(())
+    }
+
+    // ★9-2-4. 入金の一部をロックする積立に回す設定（持ち主のみ、強制貯金用）
+    // 以後の入金の fraction_bps の割合が locked_balance に入り、unlock_at まで出金・送金できない。
+    // 既にロック中の積立がある間は、期限を早めることはできない（割合は以後の入金にだけ効く）
+    pub fn set_locked_savings(
+        ctx: Context<UpdateVault>,
+        fraction_bps: u16,
+        unlock_at: i64,
+    ) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        validate_rate_bps(fraction_bps, ErrorCode::InvalidLockedFraction)?;
+        let now = current_clock()?.unix_timestamp;
+        let vault = &mut ctx.accounts.vault;
+        require!(
+            fraction_bps == 0 || unlock_at > now,
+            ErrorCode::InvalidLockedSavingsTerm
+        );
+        require!(
+            locked_savings(vault, now) == 0 || unlock_at >= vault.locked_balance_until,
+            ErrorCode::InvalidLockedSavingsTerm
+        );
+        vault.locked_fraction_bps = fraction_bps;
+        vault.locked_balance = locked_savings(vault, now);
+        vault.locked_balance_until = unlock_at;
+        Ok(())
+    }
+
+    // ★紹介者（referrer）の設定（一度だけ）。紹介者は他のユーザーのVault
+    // 紹介の循環を防ぐため、紹介者の紹介者がこのVaultの場合も拒否する
+    pub fn set_referrer(ctx: Context<SetReferrer>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let vault = &ctx.accounts.vault;
+        let referrer = &ctx.accounts.referrer_vault;
+        require!(vault.referrer == Pubkey::default(), ErrorCode::ReferrerAlreadySet);
+        require!(referrer.user != vault.user, ErrorCode::InvalidReferrer);
+        require!(referrer.referrer != vault.key(), ErrorCode::ReferralCycle);
+        ctx.accounts.vault.referrer = ctx.accounts.referrer_vault.key();
+        Ok(())
+    }
+
+    // ★9-3. リワードの送り先の RewardBox を設定（家族の共有プールなど。default で自分の RewardBox に戻す）
+    // 送り先の設定中に確定したリワードは pending_destination_rewards に貯まり、accrue_to_destination で届く
+    pub fn set_reward_destination(ctx: Context<UpdateVault>, destination: Pubkey) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let vault = &mut ctx.accounts.vault;
+        // 未転送分が前の送り先に届かなくなるので、先に accrue_to_destination を呼ぶこと
+        require!(
+            vault.pending_destination_rewards == 0,
+            ErrorCode::PendingDestinationRewards
+        );
+        vault.reward_destination = destination;
+        Ok(())
+    }
+
+    // ★9-4. リワードを確定して送り先の RewardBox に届ける（誰でも呼べる）
+    pub fn accrue_to_destination(ctx: Context<AccrueToDestination>) -> Result<()> {
+        // 送り先を渡しているので、今回の確定分は直接送り先に入る
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.destination_reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        // これまでに貯まっていた未転送分を届ける（負債には確定時に計上済み）
+        let pending = ctx.accounts.vault.pending_destination_rewards;
+        if pending > 0 {
+            let destination = &mut ctx.accounts.destination_reward_box;
+            destination.balance = destination
+                .balance
+                .checked_add(pending)
+                .ok_or(ErrorCode::MathOverflow)?;
+            ctx.accounts.vault.pending_destination_rewards = 0;
+            emit_reward_delta(
+                &ctx.accounts.vault,
+                &ctx.accounts.destination_reward_box,
+                pending as i64,
+            );
+        }
+        Ok(())
+    }
+
+    // ★9-0. UI表示用のラベルを設定（32byteまで、空文字でクリア）
+    pub fn set_label(ctx: Context<UpdateVault>, label: String) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require!(label.len() <= 32, ErrorCode::InvalidLabel);
+        let vault = &mut ctx.accounts.vault;
+        vault.label = [0u8; 32];
+        vault.label[..label.len()].copy_from_slice(label.as_bytes());
+        vault.label_len = label.len() as u8;
+        Ok(())
+    }
+
+    // ★9-1. リワードの自動組み入れ（複利）の設定
+    // 有効にすると、前回から interval_secs 以上経った入金のたびに、リワードをTreasury原資で元本へ組み入れる
+    // （0 = 入金のたび、86400 = 1日1回、604800 = 週1回）
+    pub fn set_auto_compound(
+        ctx: Context<UpdateVault>,
+        enabled: bool,
+        interval_secs: i64,
+    ) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require!(interval_secs >= 0, ErrorCode::InvalidCompoundInterval);
+        let clock = current_clock()?;
+        let vault = &mut ctx.accounts.vault;
+        vault.auto_compound = enabled;
+        vault.compound_interval_secs = interval_secs;
+        // 間隔は設定した時点から数える
+        vault.last_compound_at = clock.unix_timestamp;
+        Ok(())
+    }
+
+    // ★9-2. リワードの自動受け取りの設定
+    // 有効にすると、入金のたびに確定済みのリワードをTreasuryからSOLで受け取る（claim_reward と同じ手数料）
+    pub fn set_auto_claim(ctx: Context<UpdateVault>, enabled: bool) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        ctx.accounts.vault.auto_claim = enabled;
+        Ok(())
+    }
+
+    // ★9-3. 手数料の免除を買う（Vaultの持ち主のみ、一度きり）
+    // Config.fee_exemption_price をTreasuryへ払うと、以後このVaultの入金・出金・受け取りの手数料がかからない。
+    // 早期出金のペナルティは手数料ではないので免除されない
+    pub fn buy_fee_exemption(ctx: Context<BuyFeeExemption>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let price = ctx.accounts.config.fee_exemption_price;
+        require!(price > 0, ErrorCode::FeeExemptionNotForSale);
+        require!(!ctx.accounts.vault.fee_exempt, ErrorCode::AlreadyFeeExempt);
+
+        let cpi_context = CpiContext::new(
+            ctx.accounts.system_program.to_account_info(),
+            anchor_lang::system_program::Transfer {
+                from: ctx.accounts.user.to_account_info(),
+                to: ctx.accounts.treasury.to_account_info(),
+            },
+        );
+        anchor_lang::system_program::transfer(cpi_context, price)?;
+        let treasury = &mut ctx.accounts.treasury;
+        treasury.total_funded = treasury.total_funded.saturating_add(price);
+
+        let vault = &mut ctx.accounts.vault;
+        vault.fee_exempt = true;
+        record_fee_paid(vault, price);
+        emit!(FeeExemptionPurchasedEvent {
+            vault: vault.key(),
+            price,
+        });
+        Ok(())
+    }
+
+    // ★USD建て目標の達成状況（読み取り専用）
+    // 価格フィードで vault.balance をUSD（セント）に換算する。古い価格は StalePrice で拒否。
+    pub fn goal_progress_usd(ctx: Context<GoalProgressUsd>) -> Result<GoalProgress> {
+        let price = read_sol_usd_price(&ctx.accounts.price_feed, &ctx.accounts.config)?;
+        let vault = &ctx.accounts.vault;
+        let balance_usd_cents = lamports_to_usd_cents(vault.balance, &price)?;
+
+        let progress_bps = if vault.goal_usd_cents == 0 {
+            0
+        } else {
+            (balance_usd_cents as u128 * 10000 / vault.goal_usd_cents as u128).min(10000) as u16
+        };
+
+        Ok(GoalProgress {
+            balance_usd_cents,
+            goal_usd_cents: vault.goal_usd_cents,
+            progress_bps,
+        })
+    }
+
+    // ★10. リワードをSOLではなくリワードトークンで受け取る
+    // reward_box.balance を Mint の decimals に換算した量のトークンを発行する（9桁なら1:1でBoxは0になる）
+    pub fn claim_reward_token(ctx: Context<ClaimRewardToken>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_own_rewards(&ctx.accounts.vault, &ctx.accounts.reward_box)?;
+        // 先にリワードを確定
+        update_rewards(
+            &mut ctx.accounts.vault, 
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        // ★Mintの decimals に合わせて桁を落とす（1トークン未満の端数は RewardBox に残す）
+        let scale = 10u64.pow(
+            REWARD_POINT_DECIMALS.saturating_sub(ctx.accounts.config.reward_token_decimals) as u32,
+        );
+        let tokens = ctx.accounts.reward_box.balance / scale;
+        require!(tokens > 0, ErrorCode::NoRewards);
+        let amount = tokens * scale; // 消費するポイント
+
+        let signer_seeds: &[&[&[u8]]] = &[&[b"mint_authority", &[ctx.bumps.mint_authority]]];
+        let cpi_context = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            MintTo {
+                mint: ctx.accounts.reward_mint.to_account_info(),
+                to: ctx.accounts.user_token_account.to_account_info(),
+                authority: ctx.accounts.mint_authority.to_account_info(),
+            },
+            signer_seeds,
+        );
+        token::mint_to(cpi_context, tokens)?;
+
+        ctx.accounts.reward_box.balance -= amount;
+        ctx.accounts.vault.total_rewards_claimed =
+            ctx.accounts.vault.total_rewards_claimed.saturating_add(amount);
+        emit_reward_delta(&ctx.accounts.vault, &ctx.accounts.reward_box, -(amount as i64));
+        release_liability(&mut ctx.accounts.global_emission, &ctx.accounts.reward_box, amount);
+        Ok(())
+    }
+
+    // ★10-1. リワードをSOLで受け取る（1ポイント = 1 lamport、Treasuryの原資から支払う）
+    // claim_fee_bps の手数料はTreasuryに残し（＝支払わない）、RewardClaimedEvent に記録する
+    // ★原資が足りない場合は払える分だけ支払い、残りのポイントは RewardBox に残す（未払いリワードのまま）
+    pub fn claim_reward(ctx: Context<ClaimReward>) -> Result<()> {
+        // ★リワードの持ち主（transfer_reward_ownership の譲渡先）の受け取りは、Vaultの持ち主の操作として数えない
+        if ctx.accounts.user.key() == ctx.accounts.vault.user {
+            record_owner_activity(&mut ctx.accounts.vault)?;
+        }
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        require!(ctx.accounts.reward_box.balance > 0, ErrorCode::NoRewards);
+        // ★リーダーボードには支払い前のリワードで載せる
+        if let Some(leaderboard) = ctx.accounts.leaderboard.as_mut() {
+            record_leaderboard(
+                leaderboard,
+                ctx.accounts.vault.key(),
+                ctx.accounts.reward_box.balance,
+            );
+        }
+        pay_reward_claim(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+            &ctx.accounts.treasury.to_account_info(),
+            &ctx.accounts.user.to_account_info(),
+        )
+    }
+
+    // ★10-2. 複数のVaultのリワードをまとめてSOLで受け取る
+    // remaining_accounts に自分の (Vault, RewardBox) の組を並べて渡す（MAX_VAULT_PAIRS 組まで）。リワードが0の組は飛ばす
+    pub fn batch_claim<'info>(ctx: Context<'_, '_, 'info, 'info, BatchClaim<'info>>) -> Result<()> {
+        let accounts = ctx.remaining_accounts;
+        require_vault_pair_count(accounts)?;
+
+        let treasury_info = ctx.accounts.treasury.to_account_info();
+        let user_info = ctx.accounts.user.to_account_info();
+        let mut claimed = false;
+        for pair in accounts.chunks(2) {
+            let (mut vault, mut reward_box) = load_vault_pair(&pair[0], &pair[1], ctx.program_id)?;
+            require_keys_eq!(vault.user, ctx.accounts.user.key(), ErrorCode::NotVaultOwner);
+            require_own_rewards(&vault, &reward_box)?;
+
+            update_rewards(
+                &mut vault,
+                &mut reward_box,
+                &ctx.accounts.config,
+                &mut ctx.accounts.global_emission,
+            )?;
+            if reward_box.balance > 0 {
+                pay_reward_claim(
+                    &mut vault,
+                    &mut reward_box,
+                    &ctx.accounts.config,
+                    &mut ctx.accounts.global_emission,
+                    &treasury_info,
+                    &user_info,
+                )?;
+                claimed = true;
+            }
+
+            // remaining_accounts は自動で書き戻されないので明示的に保存する
+            vault.exit(ctx.program_id)?;
+            reward_box.exit(ctx.program_id)?;
+        }
+        require!(claimed, ErrorCode::NoRewards);
+        Ok(())
+    }
+
+    // ★10-3. これまでに実際に受け取ったリワードによる年率（読み取り専用、bps、10000 = 年100%）
+    // total_rewards_claimed / total_deposited を作成からの経過時間で年率に換算する（単利）。
+    // 入金のタイミングや組み入れ、利率の変更の影響を含むので、設定上の利率とは一致しない
+    pub fn get_realized_apy(ctx: Context<ViewVault>) -> Result<u64> {
+        let vault = &ctx.accounts.vault;
+        let age = current_clock()?.unix_timestamp.saturating_sub(vault.created_at);
+        if vault.total_deposited == 0 || age <= 0 {
+            return Ok(0);
+        }
+        let apy_bps = (vault.total_rewards_claimed as u128)
+            .saturating_mul(10000)
+            .saturating_mul(SECS_PER_YEAR)
+            / (vault.total_deposited as u128 * age as u128);
+        Ok(apy_bps.min(u64::MAX as u128) as u64)
+    }
+
+    // ★10-3-1. これまでにこのVaultで払った手数料の合計（読み取り専用、lamports）
+    // 入金・出金の手数料と、リワードの受け取り手数料（ポイント）、手数料の免除の購入額の合計。
+    // 早期出金のペナルティは含まない
+    pub fn get_fees_paid(ctx: Context<ViewVault>) -> Result<u64> {
+        Ok(ctx.accounts.vault.total_fees_paid)
+    }
+
+    // ★10-3-2. Vaultのレント免除に必要な最低残高（読み取り専用、lamports）
+    // Vaultの lamports のうちこの分は出金できない（出金できる最大額 = lamports - これ）
+    pub fn get_rent_reserve(ctx: Context<ViewVault>) -> Result<u64> {
+        let data_len = ctx.accounts.vault.to_account_info().data_len();
+        Ok(Rent::get()?.minimum_balance(data_len))
+    }
+
+    // ★10-4. 端数のリワードの一括整理（管理者のみ）
+    // remaining_accounts に (Vault, RewardBox) の組を並べて渡す（MAX_VAULT_PAIRS 組まで）。
+    // 残高が 1 以上 threshold 未満の RewardBox を0にし、その分を未払いリワードの合計から除く。
+    // 支払いに充てるはずだったSOLは Treasury に残る（Treasury の残高は変わらない）
+    pub fn sweep_dust<'info>(
+        ctx: Context<'_, '_, 'info, 'info, SweepDust<'info>>,
+        threshold: u64,
+    ) -> Result<()> {
+        let accounts = ctx.remaining_accounts;
+        require_vault_pair_count(accounts)?;
+
+        let mut count = 0u32;
+        let mut total = 0u64;
+        for pair in accounts.chunks(2) {
+            let (vault, mut reward_box) = load_vault_pair(&pair[0], &pair[1], ctx.program_id)?;
+            let dust = reward_box.balance;
+            if dust == 0 || dust >= threshold {
+                continue;
+            }
+            reward_box.balance = 0;
+            emit_reward_delta(&vault, &reward_box, -(dust as i64));
+            release_liability(&mut ctx.accounts.global_emission, &reward_box, dust);
+            count += 1;
+            total += dust;
+
+            // remaining_accounts は自動で書き戻されないので明示的に保存する
+            reward_box.exit(ctx.program_id)?;
+        }
+        require!(count > 0, ErrorCode::NoDust);
+        emit!(DustSweptEvent { count, total });
+        Ok(())
+    }
+
+    // ★11. N日後の残高の試算（読み取り専用）
+    // 現在の方式・利率が続くと仮定する。compound = true なら1日ごとにリワードを元本へ組み入れる。
+    // 桁あふれする場合は u64::MAX で頭打ちにする。
+    pub fn project_balance(ctx: Context<ViewVault>, days: u64, compound: bool) -> Result<u64> {
+        require!(days <= MAX_PROJECTION_DAYS, ErrorCode::InvalidProjectionDays);
+
+        let config = &ctx.accounts.config;
+        let vault = &ctx.accounts.vault;
+        let now = current_clock()?.unix_timestamp;
+
+        let projected = if compound {
+            let mut balance = vault.balance;
+            for _ in 0..days {
+                balance = balance.saturating_add(reward_for(config, vault, balance, 86400, now));
+            }
+            balance
+        } else {
+            let diff = (days * 86400) as i64;
+            vault
+                .balance
+                .saturating_add(reward_for(config, vault, vault.balance, diff, now))
+        };
+
+        Ok(projected)
+    }
+
+    // ★secs 秒後までに付くリワードの試算（読み取り専用、現在の元本・方式・減衰が続くと仮定）
+    pub fn project_reward(ctx: Context<ViewVault>, secs: i64) -> Result<u64> {
+        require!(
+            (0..=(MAX_PROJECTION_DAYS * 86400) as i64).contains(&secs),
+            ErrorCode::InvalidProjectionDays
+        );
+        let vault = &ctx.accounts.vault;
+        let now = current_clock()?.unix_timestamp;
+        Ok(reward_for(&ctx.accounts.config, vault, vault.balance, secs, now))
+    }
+
+    // ★Vaultの有無と残高・レント免除の状態を返す（読み取り専用）
+    // Vaultが存在しなくてもエラーにせず exists = false を返す。旧レイアウトのVaultも読める
+    pub fn peek(ctx: Context<Peek>) -> Result<VaultPeek> {
+        let vault_info = ctx.accounts.vault.to_account_info();
+        let data = vault_info.try_borrow_data()?;
+        let exists = vault_info.owner == &crate::ID && data.starts_with(Vault::DISCRIMINATOR);
+        if !exists {
+            return Ok(VaultPeek { exists: false, balance: 0, rent_exempt: false });
+        }
+
+        // balance は discriminator + user の直後（レイアウトが変わっても位置は変わらない）
+        let balance = u64::from_le_bytes(data[40..48].try_into().unwrap());
+        let rent = Rent::get()?;
+        Ok(VaultPeek {
+            exists,
+            balance,
+            rent_exempt: rent.is_exempt(vault_info.lamports(), data.len()),
+        })
+    }
+
+    // ★Vaultの状態の要約（読み取り専用、持ち主か view_authority の署名が必要）
+    pub fn get_vault_summary(ctx: Context<GatedView>) -> Result<VaultSummary> {
+        let vault = &ctx.accounts.vault;
+        Ok(VaultSummary {
+            balance: vault.balance,
+            reward_balance: ctx.accounts.reward_box.balance,
+            locked_amount: vault.locked_amount,
+            lock_until: vault.lock_until,
+            goal_amount: vault.goal_amount,
+            last_update_time: vault.last_update_time,
+        })
+    }
+
+    // ★プロトコル全体の預かり資産（TVL、全Vaultの元本の合計。読み取り専用）
+    pub fn get_tvl(ctx: Context<ViewEmission>) -> Result<u64> {
+        Ok(ctx.accounts.global_emission.total_deposits)
+    }
+
+    // ★未払いリワードの集計（outstanding_liabilities）と RewardBox の残高の合計を突き合わせる（読み取り専用、監視用）
+    // remaining_accounts に RewardBox を並べて渡す。1回で渡しきれない場合は分けて呼び、前回までの合計を carried_total に渡す。
+    // すべての RewardBox を渡したときに discrepancy が0でなければ、集計と残高がずれている。
+    // 集計に含まれていない RewardBox（liability_recorded = false）は、渡しても合計に含めない
+    // （Vault の pending_destination_rewards は集計に含まれるが RewardBox にはないため、その分もずれとして出る）
+    pub fn audit_liabilities<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ViewEmission<'info>>,
+        carried_total: u64,
+    ) -> Result<LiabilityAudit> {
+        let accounts = ctx.remaining_accounts;
+        let mut total = carried_total;
+        for (i, info) in accounts.iter().enumerate() {
+            // 同じ RewardBox を2回数えないようにする
+            require!(
+                accounts[..i].iter().all(|other| other.key != info.key),
+                ErrorCode::DuplicateAuditAccount
+            );
+            let reward_box: Account<RewardBox> = Account::try_from(info)?;
+            if reward_box.liability_recorded {
+                total = total.saturating_add(reward_box.balance);
+            }
+        }
+
+        let outstanding = ctx.accounts.global_emission.outstanding_liabilities;
+        Ok(LiabilityAudit {
+            reward_box_total: total,
+            outstanding_liabilities: outstanding,
+            discrepancy: (outstanding as i128 - total as i128) as i64,
+        })
+    }
+
+    // ★現在の利用率による利率の倍率（読み取り専用、10000 = 調整なし）
+    pub fn utilization_multiplier(ctx: Context<ViewUtilization>) -> Result<u64> {
+        Ok(utilization_multiplier_bps(&ctx.accounts.config, &ctx.accounts.global_emission) as u64)
+    }
+
+    // ★現在の実効利率の内訳（読み取り専用、各倍率は 10000 = 等倍）
+    // update_rewards と同じ順に 方式 → 経年減衰 → 全体の利率と上乗せ → 利用率 → 目標達成ボーナス を掛け合わせる。
+    // effective_bps が 10000 のとき、リワード対象の元本に毎秒0.01%が付く
+    pub fn get_rate_info(ctx: Context<ViewRate>) -> Result<RateInfo> {
+        let vault = &ctx.accounts.vault;
+        let now = current_clock()?.unix_timestamp;
+        let mut info = rate_info(&ctx.accounts.config, vault, &ctx.accounts.global_emission, now);
+        // ★lock_rate で固定中は固定した利率になる（停止中・凍結中は0のまま）
+        if now < vault.rate_lock_until && info.effective_bps > 0 {
+            info.effective_bps = vault.locked_rate_bps;
+        }
+        Ok(info)
+    }
+
+    // ★設定中の手数料の一覧（読み取り専用、フロントエンドの表示用）
+    // transfer には手数料がないため含まない
+    pub fn get_fee_schedule(ctx: Context<ViewConfig>) -> Result<FeeSchedule> {
+        let config = &ctx.accounts.config;
+        Ok(FeeSchedule {
+            deposit_fee_bps: config.deposit_fee_bps,
+            withdraw_fee_bps: config.withdraw_fee_bps,
+            claim_fee_bps: config.claim_fee_bps,
+            early_withdraw_penalty_bps: config.early_withdraw_penalty_bps,
+            fee_grace_period_secs: config.fee_grace_period_secs,
+        })
+    }
+
+    // ★今確定した場合に付くリワードと、次に確定額が増えるまでの秒数（読み取り専用、カウントダウン表示用）
+    // update_rewards と同じ条件で計算する。min_accrual_interval_secs に満たない間や、
+    // accrual_period_secs の単位期間が満了していない分、max_accrual_per_call を超えて繰り越す分は含めない
+    pub fn next_accrual_preview(ctx: Context<ViewAccrual>) -> Result<AccrualPreview> {
+        let config = &ctx.accounts.config;
+        let vault = &ctx.accounts.vault;
+        let now = current_clock()?.unix_timestamp;
+        let last_update = vault.last_update_time;
+        let diff = (now - last_update).max(0);
+
+        // 次に確定額が増える経過秒数: 最小間隔以上で、単位期間の区切り（単位期間なしなら次の1秒）
+        let period = config.accrual_period_secs;
+        let min_interval = config.min_accrual_interval_secs;
+        let next_diff = if period > 0 {
+            let periods = (diff / period + 1).max((min_interval + period - 1) / period);
+            periods * period
+        } else {
+            (diff + 1).max(min_interval)
+        };
+
+        let accrues = !rate_accrual_paused(config, vault)
+            && diff >= min_interval
+            && (vault.balance > 0 || vault.promo_balance > 0);
+        let accrue_until = if period > 0 {
+            last_update + diff / period * period
+        } else {
+            now
+        };
+        let amount = if accrues && accrue_until > last_update {
+            capped_accrued_reward(
+                config,
+                vault,
+                &ctx.accounts.global_emission,
+                ctx.accounts.reward_box.reward_acc,
+                last_update,
+                accrue_until,
+            )
+            .0
+        } else {
+            0
+        };
+
+        Ok(AccrualPreview {
+            amount,
+            secs_until_next: next_diff - diff,
+        })
+    }
+
+    // ★現在の実効利率を term_secs 秒間固定する（Vaultの持ち主のみ）
+    // 固定中は update_rewards が Config の変更（方式・倍率・ボーナス）を無視して固定した利率で計算する。
+    // 固定中に呼び直すと、その時点の利率と期間で上書きされる
+    pub fn lock_rate(ctx: Context<LockRate>, term_secs: i64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require!(term_secs > 0, ErrorCode::InvalidLockDuration);
+
+        // ここまでの分は固定前の利率で確定する
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        let now = current_clock()?.unix_timestamp;
+        let effective_bps =
+            rate_info(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.global_emission, now)
+                .effective_bps;
+        let vault = &mut ctx.accounts.vault;
+        vault.locked_rate_bps = effective_bps;
+        vault.rate_lock_until = now + term_secs;
+        Ok(())
+    }
+
+    // ★リワードを利率ではなく入金1回ごとの定額で受け取るかを切り替える（Vaultの持ち主のみ）
+    // 切り替える前に、ここまでの分を切り替え前の方式で確定する
+    pub fn set_flat_reward(ctx: Context<SetFlatReward>, enabled: bool) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+        ctx.accounts.vault.flat_reward = enabled;
+        Ok(())
+    }
+
+    // ★12-0. Vaultを閉鎖予定にする（close_vault で閉じるか cancel_close で取り消すまで入金できない）
+    pub fn request_close(ctx: Context<UpdateVault>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        ctx.accounts.vault.closing = true;
+        Ok(())
+    }
+
+    // ★閉鎖予定の取り消し（入金できる状態に戻す）
+    pub fn cancel_close(ctx: Context<UpdateVault>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        ctx.accounts.vault.closing = false;
+        Ok(())
+    }
+
+    // ★12. Vaultを閉じてレントを回収する
+    // 残高・リワード・出金予約がすべて空の場合のみ。Vault と RewardBox の両方を閉じる
+    pub fn close_vault(ctx: Context<CloseVault>) -> Result<()> {
+        let vault = &ctx.accounts.vault;
+        require!(vault.balance == 0, ErrorCode::VaultNotEmpty);
+        require!(vault.pending_withdraw_amount == 0, ErrorCode::PendingWithdrawExists);
+        require!(vault.pending_transfer_amount == 0, ErrorCode::PendingTransferExists);
+        // ★1ポイント未満の端数（reward_acc）は受け取れないため、残っていても閉じられる（端数は破棄する）
+        // 端数は未払いリワード（outstanding_liabilities）に含めていないので、集計を直す必要もない
+        require!(ctx.accounts.reward_box.balance == 0, ErrorCode::UnclaimedRewards);
+        require!(
+            vault.pending_destination_rewards == 0,
+            ErrorCode::PendingDestinationRewards
+        );
+        // ★閉じたあとは出せないので、出していない確定額をここで出す
+        flush_unemitted_reward(&mut ctx.accounts.vault, &ctx.accounts.reward_box);
+
+        let config = &mut ctx.accounts.config;
+        config.vault_count = config.vault_count.saturating_sub(1);
+        unregister_user_vault(ctx.accounts.user_stats.as_deref_mut());
+        Ok(())
+    }
+
+    // ★13. Vault と RewardBox の状態をBorshでシリアライズして返す（オフチェーンのバックアップ用、読み取り専用）
+    // 形式: Vault（discriminatorなし）の直後に RewardBox（discriminatorなし）
+    pub fn export_state(ctx: Context<ExportState>) -> Result<Vec<u8>> {
+        let mut data = Vec::new();
+        AnchorSerialize::serialize(&*ctx.accounts.vault, &mut data)?;
+        AnchorSerialize::serialize(&*ctx.accounts.reward_box, &mut data)?;
+        Ok(data)
+    }
+
+    // ★14. 新しい名前付きVaultを作り、元本の一部を移す（貯金の整理用）
+    // 移す前に元のVaultのリワードを確定させる。新しいVaultのリワードは作成時刻から計算する
+    pub fn split_vault(ctx: Context<SplitVault>, amount: u64, new_name: String) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require!(
+            !new_name.is_empty() && new_name.len() <= 32 && new_name.as_bytes() != GROWTH_VAULT_NAME,
+            ErrorCode::InvalidVaultName
+        );
+        require_unlocked(&ctx.accounts.vault)?;
+
+        // 先にリワードを更新
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        require!(amount <= ctx.accounts.vault.balance, ErrorCode::InsufficientBalance);
+        require_vested(&ctx.accounts.vault, amount)?;
+
+        let clock = current_clock()?;
+        let source = &mut ctx.accounts.vault;
+        let new_vault = &mut ctx.accounts.new_vault;
+        new_vault.user = ctx.accounts.user.key();
+        new_vault.bump = ctx.bumps.new_vault;
+        new_vault.version = VAULT_VERSION;
+        new_vault.last_update_time = clock.unix_timestamp;
+        new_vault.created_at = clock.unix_timestamp;
+        new_vault.last_owner_activity = clock.unix_timestamp;
+        new_vault.name[..new_name.len()].copy_from_slice(new_name.as_bytes());
+        new_vault.name_len = new_name.len() as u8;
+
+        // ★リワード待機中の元本は、待機ごと新しいVaultへ引き継ぐ
+        let unearning = source.unearning_amount.min(amount);
+        source.unearning_amount -= unearning;
+        new_vault.unearning_amount = unearning;
+        new_vault.reward_eligible_after = source.reward_eligible_after;
+
+        // 元本を直接移動（新しいVaultのレントは init で user が支払い済み）
+        **source.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **new_vault.to_account_info().try_borrow_mut_lamports()? += amount;
+        source.balance -= amount;
+        new_vault.balance = amount;
+
+        let new_reward_box = &mut ctx.accounts.new_reward_box;
+        new_reward_box.balance = 0;
+        new_reward_box.bump = ctx.bumps.new_reward_box;
+        new_reward_box.version = REWARD_BOX_VERSION;
+        new_reward_box.liability_recorded = true;
+
+        register_user_vault(
+            &mut ctx.accounts.user_stats,
+            ctx.bumps.user_stats,
+            &ctx.accounts.config,
+        )?;
+        let config = &mut ctx.accounts.config;
+        config.vault_count = config.vault_count.saturating_add(1);
+
+        emit_balance_delta(&ctx.accounts.vault, -(amount as i64));
+        emit_balance_delta(&ctx.accounts.new_vault, amount as i64);
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.new_vault, &ctx.accounts.new_reward_box);
+        Ok(())
+    }
+
+    // ★15. source_vault の元本とリワードをすべて vault へ移し、source_vault を閉じる（split_vault の逆）
+    // 両方のリワードを確定させてから移す。source_vault と RewardBox のレントはユーザーに返却する
+    pub fn merge_vaults(ctx: Context<MergeVaults>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        // ★ロック・ベスティング・出金予約を統合で回避できないようにする
+        let source = &ctx.accounts.source_vault;
+        require_unlocked(source)?;
+        require_vested(source, source.balance)?;
+        require!(source.pending_withdraw_amount == 0, ErrorCode::PendingWithdrawExists);
+        require!(source.pending_transfer_amount == 0, ErrorCode::PendingTransferExists);
+        require!(
+            source.pending_destination_rewards == 0,
+            ErrorCode::PendingDestinationRewards
+        );
+        require_own_rewards(source, &ctx.accounts.source_reward_box)?;
+        require_same_liability_tracking(&ctx.accounts.source_reward_box, &ctx.accounts.reward_box)?;
+        require_not_closing(&ctx.accounts.vault)?;
+        require_not_growth_vault(&ctx.accounts.vault)?;
+
+        // 先に両方のリワードを更新
+        update_rewards(
+            &mut ctx.accounts.source_vault,
+            &mut ctx.accounts.source_reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+        // ★source_vault は閉じるので、出していない確定額を RewardDeltaEvent で移す前に出す
+        flush_unemitted_reward(&mut ctx.accounts.source_vault, &ctx.accounts.source_reward_box);
+
+        // 元本を直接移動（source_vault にはレントだけが残り、close で返却される）
+        let source = &mut ctx.accounts.source_vault;
+        let vault = &mut ctx.accounts.vault;
+        let amount = source.balance;
+        **source.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **vault.to_account_info().try_borrow_mut_lamports()? += amount;
+        source.balance = 0;
+        vault.balance = vault
+            .balance
+            .checked_add(amount)
+            .ok_or(ErrorCode::MathOverflow)?;
+
+        // ★リワード待機中の元本は待機ごと引き継ぐ（遅い方の時刻に揃える）
+        let unearning = source.unearning_amount.min(amount);
+        if unearning > 0 {
+            vault.unearning_amount = vault.unearning_amount.saturating_add(unearning);
+            vault.reward_eligible_after = vault.reward_eligible_after.max(source.reward_eligible_after);
+        }
+
+        // ★販促残高も引き継ぐ
+        vault.promo_balance = vault.promo_balance.saturating_add(source.promo_balance);
+        source.promo_balance = 0;
+
+        // ★累計（入金・受け取ったリワード・手数料）と手数料の免除も引き継ぐ（source_vault は閉じるので二重には使えない）
+        vault.total_deposited = vault.total_deposited.saturating_add(source.total_deposited);
+        vault.total_rewards_claimed = vault
+            .total_rewards_claimed
+            .saturating_add(source.total_rewards_claimed);
+        vault.total_fees_paid = vault.total_fees_paid.saturating_add(source.total_fees_paid);
+        vault.fee_exempt |= source.fee_exempt;
+
+        // リワードポイントも移す
+        let source_reward_box = &mut ctx.accounts.source_reward_box;
+        let reward_box = &mut ctx.accounts.reward_box;
+        let rewards = source_reward_box.balance;
+        reward_box.balance = reward_box
+            .balance
+            .checked_add(rewards)
+            .ok_or(ErrorCode::MathOverflow)?;
+        source_reward_box.balance = 0;
+
+        let config = &mut ctx.accounts.config;
+        config.vault_count = config.vault_count.saturating_sub(1);
+        unregister_user_vault(ctx.accounts.user_stats.as_deref_mut());
+
+        emit_balance_delta(&ctx.accounts.source_vault, -(amount as i64));
+        emit_balance_delta(&ctx.accounts.vault, amount as i64);
+        emit_reward_delta(
+            &ctx.accounts.source_vault,
+            &ctx.accounts.source_reward_box,
+            -(rewards as i64),
+        );
+        emit_reward_delta(&ctx.accounts.vault, &ctx.accounts.reward_box, rewards as i64);
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+
+    // ★16. created_at 導入前に作られたVaultの作成時刻を補完する（管理者のみ）
+    // 既に値が入っているVaultは改ざん防止のため変更できない
+    pub fn backfill_created_at(ctx: Context<BackfillCreatedAt>, timestamp: i64) -> Result<()> {
+        let clock = current_clock()?;
+        require!(
+            timestamp > 0 && timestamp <= clock.unix_timestamp,
+            ErrorCode::InvalidTimestamp
+        );
+        let vault = &mut ctx.accounts.vault;
+        require!(vault.created_at == 0, ErrorCode::CreatedAtAlreadySet);
+        vault.created_at = timestamp;
+        Ok(())
+    }
+
+    // ★16-1. Vaultの凍結・解除（管理者のみ）
+    // 凍結中は出金・送金できない。切り替える前に、それまでの状態でリワードを確定させる
+    // （解除時は凍結期間の分を accrue_while_frozen に従って確定させてから再開する）
+    pub fn set_vault_frozen(ctx: Context<SetVaultFrozen>, frozen: bool) -> Result<()> {
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+        ctx.accounts.vault.frozen = frozen;
+        Ok(())
+    }
+
+    // ★16-1-1. Vaultごとの利率の上乗せ（管理者のみ、default_reward_rate_bps からの差分、負なら引き下げ）
+    // 変える前に、それまでの利率でリワードを確定させる
+    pub fn set_rate_delta(ctx: Context<SetRateDelta>, delta_bps: i32) -> Result<()> {
+        require!(
+            delta_bps.unsigned_abs() <= MAX_RATE_MULTIPLIER_BPS as u32,
+            ErrorCode::InvalidRateDelta
+        );
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+        ctx.accounts.vault.rate_delta_bps = delta_bps;
+        Ok(())
+    }
+
+    // ★16-1-2. 未来になっている last_update_time を現在時刻に戻す（管理者のみ）
+    // 不具合や時計のずれで未来になると経過秒数が負のままで、その時刻までリワードが付かない。
+    // 戻すだけでリワードは確定しない（未来の時刻までの間に付くはずだった分はない）
+    pub fn repair_timestamp(ctx: Context<RepairTimestamp>) -> Result<()> {
+        let now = current_clock()?.unix_timestamp;
+        let vault = &mut ctx.accounts.vault;
+        require!(vault.last_update_time > now, ErrorCode::TimestampNotInFuture);
+        let previous = vault.last_update_time;
+        vault.last_update_time = now;
+        emit!(TimestampRepairedEvent {
+            vault: vault.key(),
+            previous,
+            repaired: now,
+        });
+        Ok(())
+    }
+
+    // ★16-2. 同じ持ち主のVault間でリワードポイントを移す（ポイントの整理用）
+    // 両方のリワードを確定させてから移す。未払いリワードの合計は変わらない
+    pub fn move_rewards(ctx: Context<MoveRewards>, amount: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.from_vault)?;
+        record_owner_activity(&mut ctx.accounts.to_vault)?;
+        require!(amount > 0, ErrorCode::NoRewards);
+        require_own_rewards(&ctx.accounts.from_vault, &ctx.accounts.from_reward_box)?;
+        require_same_liability_tracking(&ctx.accounts.from_reward_box, &ctx.accounts.to_reward_box)?;
+
+        update_rewards(
+            &mut ctx.accounts.from_vault,
+            &mut ctx.accounts.from_reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+        update_rewards(
+            &mut ctx.accounts.to_vault,
+            &mut ctx.accounts.to_reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        let from = &mut ctx.accounts.from_reward_box;
+        let to = &mut ctx.accounts.to_reward_box;
+        from.balance = from
+            .balance
+            .checked_sub(amount)
+            .ok_or(ErrorCode::InsufficientRewards)?;
+        to.balance = to.balance.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        emit_reward_delta(
+            &ctx.accounts.from_vault,
+            &ctx.accounts.from_reward_box,
+            -(amount as i64),
+        );
+        emit_reward_delta(&ctx.accounts.to_vault, &ctx.accounts.to_reward_box, amount as i64);
+        Ok(())
+    }
+
+    // ★16-2-1. 確定済みのリワードをすべてSOLにして、リワードの運用先のVault（名前 GROWTH_VAULT_NAME）へ移す
+    // 元本の保全とリワードの運用を分けるためのもの。運用先は初回に作り、通常の利率に growth_rate_bonus_bps を上乗せして増える。
+    // SOLは Treasury から払い、運用先へは入金できない（リワードからの振り替えだけが入る）
+    pub fn move_rewards_to_growth(ctx: Context<MoveRewardsToGrowth>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_not_shutdown(&ctx.accounts.config)?;
+        require_own_rewards(&ctx.accounts.vault, &ctx.accounts.reward_box)?;
+        require_not_closing(&ctx.accounts.growth_vault)?;
+
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+        let rewards = ctx.accounts.reward_box.balance;
+        require!(rewards > 0, ErrorCode::NoRewards);
+
+        if ctx.accounts.growth_vault.version == 0 {
+            // ★初回は運用先を作る（レントは init_if_needed で user が支払い済み）
+            init_vault_accounts(
+                &mut ctx.accounts.growth_vault,
+                ctx.bumps.growth_vault,
+                &mut ctx.accounts.growth_reward_box,
+                ctx.bumps.growth_reward_box,
+                ctx.accounts.user.key(),
+                &mut ctx.accounts.config,
+            )?;
+            let growth = &mut ctx.accounts.growth_vault;
+            growth.name[..GROWTH_VAULT_NAME.len()].copy_from_slice(GROWTH_VAULT_NAME);
+            growth.name_len = GROWTH_VAULT_NAME.len() as u8;
+            register_user_vault(
+                &mut ctx.accounts.user_stats,
+                ctx.bumps.user_stats,
+                &ctx.accounts.config,
+            )?;
+        } else {
+            // 元本が増える前に、それまでの元本で運用先のリワードを確定
+            update_rewards(
+                &mut ctx.accounts.growth_vault,
+                &mut ctx.accounts.growth_reward_box,
+                &ctx.accounts.config,
+                &mut ctx.accounts.global_emission,
+            )?;
+        }
+
+        // Treasuryはレント免除額を残して支払う
+        let treasury_info = ctx.accounts.treasury.to_account_info();
+        let rent = Rent::get()?;
+        let available = treasury_info
+            .lamports()
+            .saturating_sub(rent.minimum_balance(treasury_info.data_len()));
+        require!(rewards <= available, ErrorCode::InsufficientTreasury);
+
+        **treasury_info.try_borrow_mut_lamports()? -= rewards;
+        **ctx.accounts.growth_vault.to_account_info().try_borrow_mut_lamports()? += rewards;
+        credit_deposit(&mut ctx.accounts.growth_vault, rewards)?;
+        record_deposit(&mut ctx.accounts.global_emission, rewards);
+
+        ctx.accounts.reward_box.balance = 0;
+        release_liability(&mut ctx.accounts.global_emission, &ctx.accounts.reward_box, rewards);
+
+        emit_reward_delta(&ctx.accounts.vault, &ctx.accounts.reward_box, -(rewards as i64));
+        emit_balance_delta(&ctx.accounts.growth_vault, rewards as i64);
+        emit!(RewardsMovedToGrowthEvent {
+            vault: ctx.accounts.vault.key(),
+            growth_vault: ctx.accounts.growth_vault.key(),
+            amount: rewards,
+        });
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.growth_vault, &ctx.accounts.growth_reward_box);
+        Ok(())
+    }
+
+    // ★16-3. 同じ持ち主のVault間で元本を直接移す（ウォレットを経由しないので手数料もかからない）
+    // 両方のリワードを確定させてから移す。ロック・凍結・ベスティングは移動元の制約をそのまま適用する
+    pub fn vault_to_vault(ctx: Context<VaultToVault>, amount: u64) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.from_vault)?;
+        record_owner_activity(&mut ctx.accounts.to_vault)?;
+        require!(amount > 0, ErrorCode::InsufficientBalance);
+        require_unlocked(&ctx.accounts.from_vault)?;
+        require_not_closing(&ctx.accounts.to_vault)?;
+        require_not_growth_vault(&ctx.accounts.to_vault)?;
+
+        update_rewards(
+            &mut ctx.accounts.from_vault,
+            &mut ctx.accounts.from_reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+        update_rewards(
+            &mut ctx.accounts.to_vault,
+            &mut ctx.accounts.to_reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        require!(amount <= ctx.accounts.from_vault.balance, ErrorCode::InsufficientBalance);
+        require_vested(&ctx.accounts.from_vault, amount)?;
+
+        let from = &mut ctx.accounts.from_vault;
+        let to = &mut ctx.accounts.to_vault;
+        **from.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **to.to_account_info().try_borrow_mut_lamports()? += amount;
+        from.balance -= amount;
+        to.balance = to.balance.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+
+        // ★リワード待機中の元本は待機ごと引き継ぐ（遅い方の時刻に揃える）
+        let unearning = from.unearning_amount.min(amount);
+        if unearning > 0 {
+            from.unearning_amount -= unearning;
+            to.unearning_amount = to.unearning_amount.saturating_add(unearning);
+            to.reward_eligible_after = to.reward_eligible_after.max(from.reward_eligible_after);
+        }
+
+        emit_balance_delta(&ctx.accounts.from_vault, -(amount as i64));
+        emit_balance_delta(&ctx.accounts.to_vault, amount as i64);
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.from_vault, &ctx.accounts.from_reward_box);
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.to_vault, &ctx.accounts.to_reward_box);
+        Ok(())
+    }
+
+    // ★17. リワードポイントを販促残高（promo_balance）へ振り替える（Treasuryを使わない簡易な複利）
+    // lamportは動かさない帳簿上の振り替えで、promo_balance は出金・送金できないがリワードの対象になる
+    pub fn sweep_rewards_to_promo(ctx: Context<SweepRewards>) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        require_not_shutdown(&ctx.accounts.config)?;
+        require_own_rewards(&ctx.accounts.vault, &ctx.accounts.reward_box)?;
+
+        // 先にリワードを確定
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        let amount = ctx.accounts.reward_box.balance;
+        require!(amount > 0, ErrorCode::NoRewards);
+
+        let vault = &mut ctx.accounts.vault;
+        vault.promo_balance = vault.promo_balance.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+        ctx.accounts.reward_box.balance = 0;
+        emit_reward_delta(&ctx.accounts.vault, &ctx.accounts.reward_box, -(amount as i64));
+        // 支払い義務のあるリワードではなくなる
+        release_liability(&mut ctx.accounts.global_emission, &ctx.accounts.reward_box, amount);
+        Ok(())
+    }
+
+    // ★RewardBox のリワードの持ち主を変更する（現在のリワードの持ち主のみ）
+    // 持ち主が Vault の持ち主と別の場合、claim_reward で受け取れるのはその持ち主だけになり、
+    // Vault の持ち主はリワードを組み入れ・振り替え・移動できなくなる（確定は今までどおり続く）。
+    // Vault の持ち主を指定すると元に戻る
+    pub fn transfer_reward_ownership(
+        ctx: Context<TransferRewardOwnership>,
+        new_owner: Pubkey,
+    ) -> Result<()> {
+        let previous = reward_owner(&ctx.accounts.vault, &ctx.accounts.reward_box);
+        // Vault の持ち主に戻す場合は未設定（default）として保存する
+        ctx.accounts.reward_box.reward_owner = if new_owner == ctx.accounts.vault.user {
+            Pubkey::default()
+        } else {
+            new_owner
+        };
+        emit!(RewardOwnershipTransferredEvent {
+            vault: ctx.accounts.vault.key(),
+            previous_owner: previous,
+            new_owner,
+        });
+        Ok(())
+    }
+
+    // ★Vaultを寄付にする（持ち主のみ、取り消し不可）
+    // 以降、持ち主は元本を出金・送金できず、charity が charity_claim で元本を受け取る（その後の入金分も含む）。
+    // リワードは今までどおり RewardBox に付く
+    pub fn donate_vault(ctx: Context<UpdateVault>, charity: Pubkey) -> Result<()> {
+        record_owner_activity(&mut ctx.accounts.vault)?;
+        let vault = &mut ctx.accounts.vault;
+        require!(!vault.donated, ErrorCode::VaultDonated);
+        require!(
+            charity != Pubkey::default() && charity != vault.user,
+            ErrorCode::InvalidCharity
+        );
+        require!(vault.pending_withdraw_amount == 0, ErrorCode::PendingWithdrawExists);
+        vault.donated = true;
+        vault.donation_charity = charity;
+        emit!(VaultDonatedEvent {
+            vault: vault.key(),
+            charity,
+        });
+        Ok(())
+    }
+
+    // ★寄付されたVaultの元本を寄付先が受け取る（寄付先のみ）
+    // ロック・ベスティングに関わらず元本をすべて移す（リワードは RewardBox に残る）
+    pub fn charity_claim(ctx: Context<CharityClaim>) -> Result<()> {
+        require!(!ctx.accounts.vault.frozen, ErrorCode::VaultFrozen);
+
+        update_rewards(
+            &mut ctx.accounts.vault,
+            &mut ctx.accounts.reward_box,
+            &ctx.accounts.config,
+            &mut ctx.accounts.global_emission,
+        )?;
+
+        let vault = &mut ctx.accounts.vault;
+        let amount = vault.balance;
+        require!(amount > 0, ErrorCode::InsufficientBalance);
+        **vault.to_account_info().try_borrow_mut_lamports()? -= amount;
+        **ctx.accounts.charity.to_account_info().try_borrow_mut_lamports()? += amount;
+        vault.balance = 0;
+        vault.unearning_amount = 0;
+        vault.vesting_amount = 0;
+        record_withdrawal(&mut ctx.accounts.global_emission, amount);
+        emit_balance_delta(vault, -(amount as i64));
+
+        emit_vault_touched(&ctx.accounts.config, &ctx.accounts.vault, &ctx.accounts.reward_box);
+        Ok(())
+    }
+}
+
+// ★紹介者へのボーナスをリワードポイントとして付ける（2段階まで）
+// accounts は紹介者の (Vault, RewardBox) を近い順に並べたもの。紹介者がいるのに渡されなければ拒否する
+fn credit_referral_bonuses<'info>(
+    accounts: &'info [AccountInfo<'info>],
+    vault: &Account<Vault>,
+    amount: u64,
+    config: &Config,
+    emission: &mut GlobalEmission,
+    program_id: &Pubkey,
+) -> Result<()> {
+    let levels = [config.referral_level1_bps, config.referral_level2_bps];
+    if levels.iter().all(|&bps| bps == 0) {
+        return Ok(());
+    }
+    let mut referrer = vault.referrer;
+    for (level, &bps) in levels.iter().enumerate() {
+        // ★循環していても、入金したVault自身には付けない
+        if referrer == Pubkey::default() || referrer == vault.key() {
+            break;
+        }
+        let pair = accounts
+            .get(level * 2..level * 2 + 2)
+            .ok_or(ErrorCode::MissingReferrerAccounts)?;
+        let (referrer_vault, mut referrer_box) = load_vault_pair(&pair[0], &pair[1], program_id)?;
+        require_keys_eq!(
+            referrer_vault.key(),
+            referrer,
+            ErrorCode::MissingReferrerAccounts
+        );
+        // ★紹介をたどって自分の別のVaultに戻った場合も付けない
+        if referrer_vault.user == vault.user {
+            break;
+        }
+
+        let bonus = (amount as u128 * bps as u128 / 10000) as u64;
+        if bonus > 0 {
+            referrer_box.balance = referrer_box.balance.saturating_add(bonus);
+            record_liability(emission, &referrer_box, bonus);
+            emit_reward_delta(&referrer_vault, &referrer_box, bonus as i64);
+            // remaining_accounts は自動で書き戻されないので明示的に保存する
+            referrer_box.exit(program_id)?;
+        }
+        referrer = referrer_vault.referrer;
+    }
+    Ok(())
+}
+
+// ★送金予約をクリアする（cancel_transfer / claim_transfer で共通）
+fn clear_pending_transfer(vault: &mut Vault) {
+    vault.pending_transfer_amount = 0;
+    vault.pending_transfer_recipient = Pubkey::default();
+    vault.pending_transfer_claimable_at = 0;
+}
+
+// ★ユーザーのVaultを1つ数える（max_vaults_per_user に達していれば作成を拒否する）
+// UserStats は最初のVault作成時に init_if_needed で作られるので、bump もここで記録する
+fn register_user_vault(stats: &mut UserStats, bump: u8, config: &Config) -> Result<()> {
+    require!(
+        config.max_vaults_per_user == 0 || stats.vault_count < config.max_vaults_per_user,
+        ErrorCode::VaultLimitExceeded
+    );
+    stats.bump = bump;
+    stats.vault_count = stats.vault_count.saturating_add(1);
+    Ok(())
+}
+
+// ★Vaultを閉じたときに数を減らす（UserStats を渡さなかった場合は減らさない）
+fn unregister_user_vault(stats: Option<&mut UserStats>) {
+    if let Some(stats) = stats {
+        stats.vault_count = stats.vault_count.saturating_sub(1);
+    }
+}
+
+// ★基本Vaultと RewardBox の初期化（initialize / initialize_sponsored で共通）
+fn init_vault_accounts(
+    vault: &mut Vault,
+    vault_bump: u8,
+    reward_box: &mut RewardBox,
+    reward_box_bump: u8,
+    user: Pubkey,
+    config: &mut Config,
+) -> Result<()> {
+    // ★時刻を先に取得する（取得できなければ何も書き込まずに失敗させる）
+    let clock = current_clock()?;
+
+    // Vaultの初期化
+    vault.user = user;
+    vault.balance = 0;
+    vault.bump = vault_bump;
+
+    // ★リワード計算用に現在時刻を記録
+    // 最初の入金までの間は元本が0なので、入金時の update_rewards でもこの期間のリワードは付かない
+    // （min_accrual_per_interval の最低額も元本0のVaultには付かない）
+    vault.last_update_time = clock.unix_timestamp;
+    vault.created_at = clock.unix_timestamp;
+    vault.last_owner_activity = clock.unix_timestamp;
+    vault.version = VAULT_VERSION;
+
+    // ★リワードBoxの初期化
+    reward_box.balance = 0;
+    reward_box.bump = reward_box_bump;
+    reward_box.version = REWARD_BOX_VERSION;
+    reward_box.liability_recorded = true;
+
+    // ★有効なVault数をカウント
+    config.vault_count = config.vault_count.saturating_add(1);
+    Ok(())
+}
+
+// ★remaining_accounts で渡されたPDAを作り、discriminator だけを書き込む（init の代わり）
+// 中身は0埋めなので、呼び出し側で Account として読み込んで初期化し、exit で保存する
+fn create_program_account<'info, T: Discriminator>(
+    target: &AccountInfo<'info>,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    seeds: &[&[u8]],
+    program_id: &Pubkey,
+) -> Result<()> {
+    let lamports = Rent::get()?.minimum_balance(space);
+    let signer_seeds = [seeds];
+    let cpi_context = CpiContext::new_with_signer(
+        system_program.clone(),
+        anchor_lang::system_program::CreateAccount {
+            from: payer.clone(),
+            to: target.clone(),
+        },
+        &signer_seeds,
+    );
+    anchor_lang::system_program::create_account(cpi_context, lamports, space as u64, program_id)?;
+    target.try_borrow_mut_data()?[..T::DISCRIMINATOR.len()].copy_from_slice(T::DISCRIMINATOR);
+    Ok(())
+}
+
+// ★Clock sysvar の取得（失敗時は汎用のエラーではなく ClockUnavailable を返す）
+fn current_clock() -> Result<Clock> {
+    Clock::get().map_err(|_| error!(ErrorCode::ClockUnavailable))
+}
+
+// ★RewardBox のリワードの持ち主（reward_owner が未設定なら Vault の持ち主）
+fn reward_owner(vault: &Vault, reward_box: &RewardBox) -> Pubkey {
+    if reward_box.reward_owner == Pubkey::default() {
+        vault.user
+    } else {
+        reward_box.reward_owner
+    }
+}
+
+// ★Vaultの持ち主がリワードを使う操作（組み入れ・振り替え・移動など）は、リワードの持ち主が本人の場合に限る
+fn require_own_rewards(vault: &Vault, reward_box: &RewardBox) -> Result<()> {
+    require_keys_eq!(
+        reward_owner(vault, reward_box),
+        vault.user,
+        ErrorCode::RewardsOwnedByOther
+    );
+    Ok(())
+}
+
+// ★RewardBox 間でポイントを移すときは、未払いリワードの集計の扱い（liability_recorded）が同じであること
+// 集計に含まれていないポイントが集計済みの RewardBox に混ざると、受け取り時に計上していない分まで集計から減らしてしまう
+fn require_same_liability_tracking(from: &RewardBox, to: &RewardBox) -> Result<()> {
+    require!(
+        from.liability_recorded == to.liability_recorded,
+        ErrorCode::LiabilityTrackingMismatch
+    );
+    Ok(())
+}
+
+// ★自動受け取りが有効なら、入金時に RewardBox のリワードを支払う
+// Treasuryに払える原資がなければ何もしない（リワードは RewardBox に残り、次回以降の入金で支払う）
+fn maybe_auto_claim(
+    vault: &mut Account<Vault>,
+    reward_box: &mut Account<RewardBox>,
+    config: &Config,
+    emission: &mut GlobalEmission,
+    treasury_info: &AccountInfo,
+    user_info: &AccountInfo,
+) -> Result<()> {
+    // ★リワードの持ち主が別の場合は、Vaultの持ち主の入金では支払わない
+    if !vault.auto_claim
+        || reward_box.balance == 0
+        || reward_owner(vault, reward_box) != vault.user
+    {
+        return Ok(());
+    }
+    let rent = Rent::get()?;
+    let available = treasury_info
+        .lamports()
+        .saturating_sub(rent.minimum_balance(treasury_info.data_len()));
+    if available == 0 {
+        return Ok(());
+    }
+    pay_reward_claim(vault, reward_box, config, emission, treasury_info, user_info)
+}
+
+// ★RewardBox のリワードを Treasury から SOL で支払う（claim_reward / batch_claim で共通）
+// claim_fee_bps の手数料はTreasuryに残す。原資が足りない場合は払える分だけ支払い、残りは RewardBox に残す
+fn pay_reward_claim(
+    vault: &mut Account<Vault>,
+    reward_box: &mut Account<RewardBox>,
+    config: &Config,
+    emission: &mut GlobalEmission,
+    treasury_info: &AccountInfo,
+    user_info: &AccountInfo,
+) -> Result<()> {
+    let total = reward_box.balance;
+    let fee_bps = if vault.fee_exempt { 0 } else { config.claim_fee_bps as u128 };
+    let fee = (total as u128 * fee_bps / 10000) as u64;
+
+    // Treasuryはレント免除額を残して支払う
+    let rent = Rent::get()?;
+    let available = treasury_info
+        .lamports()
+        .saturating_sub(rent.minimum_balance(treasury_info.data_len()));
+
+    // 全額払えない場合は available だけ支払い、手数料も支払い分に比例させる
+    let (amount, fee, payout) = if total - fee <= available {
+        (total, fee, total - fee)
+    } else {
+        require!(available > 0, ErrorCode::InsufficientTreasury);
+        let fee = (available as u128 * fee_bps / (10000 - fee_bps)) as u64;
+        let amount = available.saturating_add(fee).min(total);
+        (amount, amount - available, available)
+    };
+
+    **treasury_info.try_borrow_mut_lamports()? -= payout;
+    **user_info.try_borrow_mut_lamports()? += payout;
+
+    reward_box.balance -= amount;
+    vault.total_rewards_claimed = vault.total_rewards_claimed.saturating_add(amount);
+    record_fee_paid(vault, fee);
+    let remaining = reward_box.balance;
+    emit_reward_delta(vault, reward_box, -(amount as i64));
+    release_liability(emission, reward_box, amount);
+    emit!(RewardClaimedEvent {
+        vault: vault.key(),
+        amount,
+        fee,
+    });
+    if remaining > 0 {
+        emit!(PartialClaimEvent {
+            vault: vault.key(),
+            paid: payout,
+            remaining,
+        });
+    }
+    Ok(())
+}
+
+// ★remaining_accounts が (Vault, RewardBox) の組になっていて、上限以内かを確認する
+fn require_vault_pair_count(accounts: &[AccountInfo]) -> Result<()> {
+    require!(accounts.len() % 2 == 0, ErrorCode::InvalidVaultPairs);
+    require!(accounts.len() / 2 <= MAX_VAULT_PAIRS, ErrorCode::TooManyVaultPairs);
+    Ok(())
+}
+
+// ★remaining_accounts の (Vault, RewardBox) の組を読み込み、PDAで組になっているかを確認する
+// （名前付きVaultも同じ seeds 規則）
+fn load_vault_pair<'info>(
+    vault_info: &'info AccountInfo<'info>,
+    reward_box_info: &'info AccountInfo<'info>,
+    program_id: &Pubkey,
+) -> Result<(Account<'info, Vault>, Account<'info, RewardBox>)> {
+    let vault = Account::<Vault>::try_from(vault_info)?;
+    let reward_box = Account::<RewardBox>::try_from(reward_box_info)?;
+    let vault_key = Pubkey::create_program_address(
+        &[b"vault", vault.user.as_ref(), vault.name_seed(), &[vault.bump]],
+        program_id,
+    )
+    .map_err(|_| error!(ErrorCode::InvalidVaultPairs))?;
+    let reward_key = Pubkey::create_program_address(
+        &[b"reward", vault.user.as_ref(), vault.name_seed(), &[reward_box.bump]],
+        program_id,
+    )
+    .map_err(|_| error!(ErrorCode::InvalidVaultPairs))?;
+    require!(
+        vault_key == vault.key() && reward_key == reward_box.key(),
+        ErrorCode::InvalidVaultPairs
+    );
+    Ok((vault, reward_box))
+}
+
+// ★インデクサ向けに、Vaultが操作されたことと現在の状態を通知する
+// compact_events が有効なら、VaultTouchedEvent の代わりに固定レイアウトのバイト列（CompactVaultTouched）を出す
+fn emit_vault_touched(config: &Config, vault: &Account<Vault>, reward_box: &Account<RewardBox>) {
+    if config.compact_events {
+        let compact = CompactVaultTouched {
+            vault: vault.key(),
+            balance: vault.balance,
+            reward_box_balance: reward_box.balance,
+            last_update_time: vault.last_update_time,
+        };
+        anchor_lang::solana_program::log::sol_log_data(&[&compact.encode()]);
+        return;
+    }
+    emit!(VaultTouchedEvent {
+        vault: vault.key(),
+        balance: vault.balance,
+        reward_box_balance: reward_box.balance,
+        last_update_time: vault.last_update_time,
+    });
+}
+
+// ★インデクサ向けに、元本（balance）の増減を通知する（delta は符号付き、balance は変更後の値）
+// 元本を変えるインストラクションはすべてこれを発行するので、delta を合計すれば balance を再現できる
+fn emit_balance_delta(vault: &Account<Vault>, delta: i64) {
+    emit!(BalanceDeltaEvent {
+        vault: vault.key(),
+        delta,
+        balance: vault.balance,
+    });
+}
+
+// ★インデクサ向けに、リワード確定（RewardAccruedEvent）以外でのリワードポイントの増減を通知する
+// RewardAccruedEvent の amount とこの delta を合計すれば reward_box.balance を再現できる
+fn emit_reward_delta(vault: &Account<Vault>, reward_box: &Account<RewardBox>, delta: i64) {
+    emit!(RewardDeltaEvent {
+        vault: vault.key(),
+        delta,
+        reward_box_balance: reward_box.balance,
+    });
+}
+
+// ★通知サービス向けに、値が notify_threshold 未満から以上に変わったときだけ ThresholdCrossedEvent を出す
+fn emit_threshold_crossed(vault: &Account<Vault>, kind: ThresholdKind, before: u64, after: u64) {
+    let threshold = vault.notify_threshold;
+    if threshold > 0 && before < threshold && after >= threshold {
+        emit!(ThresholdCrossedEvent {
+            vault: vault.key(),
+            kind,
+            threshold,
+            value: after,
+        });
+    }
+}
+
+// ★リーダーボードへの記録
+// 載っているVaultは値を更新し、載っていなければ最小のエントリより多い場合だけ入れ替える
+fn record_leaderboard(leaderboard: &mut Leaderboard, vault: Pubkey, rewards: u64) {
+    let entries = &mut leaderboard.entries;
+    if let Some(entry) = entries.iter_mut().find(|e| e.vault == vault) {
+        entry.rewards = rewards;
+    } else {
+        // entries は多い順なので末尾が最小（空きがあれば空き）
+        let last = &mut entries[LEADERBOARD_SIZE - 1];
+        if last.vault != Pubkey::default() && rewards <= last.rewards {
+            return;
+        }
+        *last = LeaderboardEntry { vault, rewards };
+    }
+    entries.sort_by(|a, b| b.rewards.cmp(&a.rewards));
+}
+
+// ★bps で設定する値の範囲チェック（0〜MAX_RATE_BPS、違反時は設定ごとのエラーを返す）
+fn validate_rate_bps(bps: u16, error: ErrorCode) -> Result<()> {
+    if bps > MAX_RATE_BPS {
+        return Err(error.into());
+    }
+    Ok(())
+}
+
+// ★手数料額の計算（作成から猶予期間内のVaultと、手数料の免除を買ったVaultは無料）
+fn fee_for(config: &Config, vault: &Vault, amount: u64, fee_bps: u16, now: i64) -> u64 {
+    if vault.fee_exempt || now - vault.created_at < config.fee_grace_period_secs {
+        return 0;
+    }
+    (amount as u128 * fee_bps as u128 / 10000) as u64
+}
+
+// ★早期出金ペナルティの送り先アドレス
+fn penalty_destination_key(config: &Config, treasury: &Account<Treasury>) -> Pubkey {
+    match config.penalty_destination {
+        PenaltyDestination::Treasury => treasury.key(),
+        PenaltyDestination::Burn => INCINERATOR_ID,
+        PenaltyDestination::Charity => config.charity,
+    }
+}
+
+// ★作られたばかり（中身が0埋め）のRewardBoxを初期化する
+// 正規bumpが0になることは事実上ないため、bump が0のままなら未初期化とみなす
+fn init_reward_box_if_new(reward_box: &mut RewardBox, bump: u8) {
+    if reward_box.bump != 0 {
+        return;
+    }
+    reward_box.balance = 0;
+    reward_box.bump = bump;
+    reward_box.version = REWARD_BOX_VERSION;
+    reward_box.liability_recorded = true;
+}
+
+// ★付けたリワードを未払いリワードの合計に加える
+// 集計に含まれていない RewardBox（liability_recorded = false）は、migrate_rewards で残高ごと計上するまで集計に触れない
+fn record_liability(emission: &mut GlobalEmission, reward_box: &RewardBox, amount: u64) {
+    if reward_box.liability_recorded {
+        emission.outstanding_liabilities = emission.outstanding_liabilities.saturating_add(amount);
+    }
+}
+
+// ★支払い済みのリワードを未払いリワードの合計から除く（集計に含まれていない RewardBox の分は除かない）
+fn release_liability(emission: &mut GlobalEmission, reward_box: &RewardBox, amount: u64) {
+    if reward_box.liability_recorded {
+        emission.outstanding_liabilities = emission.outstanding_liabilities.saturating_sub(amount);
+    }
+}
+
+// ★出金時のリワードの没収（reward_forfeit_on_withdraw_bps）
+// 没収したポイントは未払いリワードの合計からも除くので、支払いに充てるはずだったSOLはTreasuryに残る
+fn forfeit_rewards_on_withdraw(
+    vault: &Account<Vault>,
+    reward_box: &mut Account<RewardBox>,
+    config: &Config,
+    emission: &mut GlobalEmission,
+) {
+    let forfeit = apply_bps(reward_box.balance as u128, config.reward_forfeit_on_withdraw_bps as u128) as u64;
+    if forfeit == 0 {
+        return;
+    }
+    reward_box.balance -= forfeit;
+    release_liability(emission, reward_box, forfeit);
+    emit_reward_delta(vault, reward_box, -(forfeit as i64));
+}
+
+// ★入金を元本に反映する。元本は溢れたら MathOverflow で止め、
+// 集計用の total_deposited は溢れても入金を止めないよう u64 の上限で頭打ちにする
+fn credit_deposit(vault: &mut Vault, amount: u64) -> Result<()> {
+    vault.balance = vault.balance.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    vault.total_deposited = vault.total_deposited.saturating_add(amount);
+    Ok(())
+}
+
+// ★全Vaultの元本合計を増減する（利用率の計算用）
+// total_deposits 導入前の元本は集計に含まれていないため、減らす側は0で下げ止める。
+// 集計用のカウンタ（total_deposits など）はすべて saturating で、上限で頭打ちになる
+fn record_deposit(emission: &mut GlobalEmission, amount: u64) {
+    emission.total_deposits = emission.total_deposits.saturating_add(amount);
+}
+
+fn record_withdrawal(emission: &mut GlobalEmission, amount: u64) {
+    emission.total_deposits = emission.total_deposits.saturating_sub(amount);
+}
+
+// ★入金手数料をTreasuryの原資として記録する（リワードは手数料でまかなえる分だけ支払い余力が増える）
+fn record_deposit_fee(treasury: &mut Treasury, emission: &mut GlobalEmission, fee: u64) {
+    treasury.total_funded = treasury.total_funded.saturating_add(fee);
+    emission.total_deposit_fees = emission.total_deposit_fees.saturating_add(fee);
+}
+
+// ★入金元を記録する（記録済みなら何もしない。いっぱいなら最も古いものを上書きする）
+fn record_deposit_source(sources: &mut DepositSources, source: Pubkey) {
+    if sources.contains(&source) {
+        return;
+    }
+    let next = sources.next as usize % MAX_DEPOSIT_SOURCES;
+    sources.sources[next] = source;
+    sources.next = ((next + 1) % MAX_DEPOSIT_SOURCES) as u8;
+    sources.len = (sources.len as usize + 1).min(MAX_DEPOSIT_SOURCES) as u8;
+}
+
+// ★まとめて出す設定で出していない確定額（unemitted_reward）を RewardAccruedEvent で出す
+fn flush_unemitted_reward(vault: &mut Account<Vault>, reward_box: &RewardBox) {
+    let amount = vault.unemitted_reward;
+    if amount == 0 {
+        return;
+    }
+    vault.unemitted_reward = 0;
+    emit!(RewardAccruedEvent {
+        vault: vault.key(),
+        amount,
+        reward_box_balance: reward_box.balance,
+        last_update_time: vault.last_update_time,
+    });
+}
+
+// ★持ち主が署名した操作の時刻を記録する（claim_as_beneficiary の無操作の判定用）
+// crank_accrue など持ち主以外も呼べる操作では呼ばない
+fn record_owner_activity(vault: &mut Vault) -> Result<()> {
+    vault.last_owner_activity = current_clock()?.unix_timestamp;
+    Ok(())
+}
+
+// ★Vaultが払った手数料（入金・出金・受け取り・免除の購入）の累計に加える（get_fees_paid 用）
+fn record_fee_paid(vault: &mut Vault, fee: u64) {
+    vault.total_fees_paid = vault.total_fees_paid.saturating_add(fee);
+}
+
+// ★リワードをTreasuryの原資で元本に組み入れる（1ポイント = 1 lamport）
+// 原資（レント免除額を除く）が足りない場合は何もせず 0 を返す
+fn compound_rewards(
+    vault: &mut Account<Vault>,
+    reward_box: &mut Account<RewardBox>,
+    treasury: &Account<Treasury>,
+    emission: &mut GlobalEmission,
+    now: i64,
+) -> Result<u64> {
+    let amount = reward_box.balance;
+    let treasury_info = treasury.to_account_info();
+    let rent = Rent::get()?;
+    let available = treasury_info
+        .lamports()
+        .saturating_sub(rent.minimum_balance(treasury_info.data_len()));
+    if amount == 0 || amount > available {
+        return Ok(0);
+    }
+
+    **treasury_info.try_borrow_mut_lamports()? -= amount;
+    **vault.to_account_info().try_borrow_mut_lamports()? += amount;
+    vault.balance = vault.balance.checked_add(amount).ok_or(ErrorCode::MathOverflow)?;
+    record_deposit(emission, amount);
+    vault.last_compound_at = now;
+    reward_box.balance = 0;
+    emit_reward_delta(vault, reward_box, -(amount as i64));
+    emit_balance_delta(vault, amount as i64);
+    release_liability(emission, reward_box, amount);
+    Ok(amount)
+}
+
+// ★自動組み入れが有効で、前回から compound_interval_secs 以上経っていれば組み入れる
+fn maybe_auto_compound(
+    vault: &mut Account<Vault>,
+    reward_box: &mut Account<RewardBox>,
+    treasury: &Account<Treasury>,
+    emission: &mut GlobalEmission,
+    now: i64,
+) -> Result<()> {
+    if !vault.auto_compound || now - vault.last_compound_at < vault.compound_interval_secs {
+        return Ok(());
+    }
+    // ★リワードの持ち主が別の場合は組み入れない
+    if reward_owner(vault, reward_box) != vault.user {
+        return Ok(());
+    }
+    compound_rewards(vault, reward_box, treasury, emission, now)?;
+    Ok(())
+}
+
+// ★Configの入金単位に従って入金額を決める
+fn apply_deposit_granularity(config: &Config, amount: u64) -> Result<u64> {
+    let granularity = config.deposit_granularity;
+    if granularity <= 1 {
+        return Ok(amount);
+    }
+
+    let remainder = amount % granularity;
+    let amount = match config.deposit_rounding {
+        DepositRounding::Reject => {
+            require!(remainder == 0, ErrorCode::DepositNotMultipleOfGranularity);
+            amount
+        }
+        DepositRounding::RoundDown => amount - remainder,
+    };
+    require!(amount > 0, ErrorCode::DepositTooSmall);
+    Ok(amount)
+}
+
+// ★価格フィードを読み取り、所有者・アドレス・鮮度を検証する
+fn read_sol_usd_price(price_feed: &AccountInfo, config: &Config) -> Result<PriceFeedMessage> {
+    require_keys_eq!(price_feed.key(), config.price_feed, ErrorCode::InvalidPriceFeed);
+    require_keys_eq!(*price_feed.owner, PYTH_RECEIVER_ID, ErrorCode::InvalidPriceFeed);
+
+    let data = price_feed.try_borrow_data()?;
+    require!(
+        data.len() > 8 && data[..8] == PRICE_UPDATE_V2_DISCRIMINATOR,
+        ErrorCode::InvalidPriceFeed
+    );
+    let update = PriceUpdateV2::deserialize(&mut &data[8..])
+        .map_err(|_| error!(ErrorCode::InvalidPriceFeed))?;
+    let price = update.price_message;
+
+    let clock = current_clock()?;
+    require!(
+        price.publish_time.saturating_add(config.max_price_age_secs) >= clock.unix_timestamp,
+        ErrorCode::StalePrice
+    );
+    require!(price.price > 0, ErrorCode::InvalidPriceFeed);
+    Ok(price)
+}
+
+// ★lamports → USDセントへの換算（価格 = price × 10^exponent USD/SOL）
+fn lamports_to_usd_cents(lamports: u64, price: &PriceFeedMessage) -> Result<u64> {
+    let mut numerator = lamports as u128 * price.price as u128 * 100;
+    let mut denominator = LAMPORTS_PER_SOL;
+    let scale = 10u128
+        .checked_pow(price.exponent.unsigned_abs())
+        .ok_or(ErrorCode::InvalidPriceFeed)?;
+    if price.exponent < 0 {
+        denominator *= scale;
+    } else {
+        numerator = numerator.checked_mul(scale).ok_or(ErrorCode::InvalidPriceFeed)?;
+    }
+    Ok((numerator / denominator) as u64)
+}
+
+// ★送金回数を日単位（UTC, unix_timestamp / 86400）で数え、上限を超えたら拒否する
+fn record_transfer(vault: &mut Vault, config: &Config, now: i64) -> Result<()> {
+    let today = now / 86400;
+    if vault.transfer_day != today {
+        // 日付が変わったらリセット
+        vault.transfer_day = today;
+        vault.transfers_today = 0;
+    }
+
+    let max = config.max_transfers_per_day;
+    require!(max == 0 || vault.transfers_today < max, ErrorCode::TransferLimitExceeded);
+    vault.transfers_today += 1;
+    Ok(())
+}
+
+// ★全体停止中は入金・送金系の操作を拒否する
+fn require_not_shutdown(config: &Config) -> Result<()> {
+    require!(!config.shutdown, ErrorCode::ProtocolShutdown);
+    Ok(())
+}
+
+// ★閉鎖予定のVaultへの入金（元本の受け入れ）を拒否する
+fn require_not_closing(vault: &Vault) -> Result<()> {
+    require!(!vault.closing, ErrorCode::VaultClosing);
+    Ok(())
+}
+
+// ★リワードの運用先のVaultへの入金を拒否する（運用先にはリワードからの振り替えだけが入る）
+fn require_not_growth_vault(vault: &Vault) -> Result<()> {
+    require!(!is_growth_vault(vault), ErrorCode::GrowthVaultDepositRejected);
+    Ok(())
+}
+
+// ★ベスティング中でまだ解放されていない額（経過時間に比例して線形に解放される）
+fn unvested_amount(vault: &Vault, now: i64) -> u64 {
+    if vault.vesting_amount == 0 || now >= vault.vesting_end {
+        return 0;
+    }
+    let total = (vault.vesting_end - vault.vesting_start) as u128;
+    let remaining = (vault.vesting_end - now.max(vault.vesting_start)) as u128;
+    (vault.vesting_amount as u128 * remaining / total) as u64
+}
+
+// ★入金をベスティングに追加する
+// トランシェは1つにまとめて管理する: 未解放の残り + 今回の入金 を、今から期間いっぱいかけて解放し直す
+fn add_vesting(vault: &mut Vault, config: &Config, amount: u64, now: i64) {
+    if config.vesting_duration_secs <= 0 {
+        return;
+    }
+    vault.vesting_amount = unvested_amount(vault, now).saturating_add(amount);
+    vault.vesting_start = now;
+    vault.vesting_end = now + config.vesting_duration_secs;
+}
+
+// ★入金した元本を、待機期間が明けるまでリワード対象外にする
+// ベスティングと同様に1つのトランシェで管理し、追加入金のたびに待機をやり直す
+// （update_rewards の後に呼ぶこと。待機が明けた分はそこで対象に戻っている）
+fn add_reward_delay(vault: &mut Vault, config: &Config, amount: u64, now: i64) {
+    if config.reward_start_delay_secs <= 0 {
+        return;
+    }
+    vault.unearning_amount = vault.unearning_amount.saturating_add(amount);
+    vault.reward_eligible_after = now + config.reward_start_delay_secs;
+}
+
+// ★入金のうち locked_fraction_bps の割合をロックする積立に加える
+// 期限を過ぎた積立は通常の元本に戻っているので、0から数え直す
+fn add_locked_savings(vault: &mut Vault, amount: u64, now: i64) {
+    if vault.locked_fraction_bps == 0 || now >= vault.locked_balance_until {
+        return;
+    }
+    let locked = (amount as u128 * vault.locked_fraction_bps as u128 / 10000) as u64;
+    vault.locked_balance = locked_savings(vault, now).saturating_add(locked);
+}
+
+// ★まだ出金できないロック中の積立（期限を過ぎていれば0、元本を超えることはない）
+fn locked_savings(vault: &Vault, now: i64) -> u64 {
+    if now >= vault.locked_balance_until {
+        return 0;
+    }
+    vault.locked_balance.min(vault.balance)
+}
+
+// ★出金・送金額が解放済みの範囲に収まっているか（ロック中の積立も出金できない）
+fn require_vested(vault: &Vault, amount: u64) -> Result<()> {
+    let clock = current_clock()?;
+    let available = vault.balance.saturating_sub(unvested_amount(vault, clock.unix_timestamp));
+    require!(amount <= available, ErrorCode::AmountNotVested);
+    require!(
+        amount <= available.saturating_sub(locked_savings(vault, clock.unix_timestamp)),
+        ErrorCode::LockedSavingsNotWithdrawable
+    );
+    Ok(())
+}
+
+// ★入金後の元本が、Vaultの経過時間に応じた上限（Config.age_deposit_caps）を超えないことを確認する
+fn require_within_age_cap(config: &Config, vault: &Vault, amount: u64, now: i64) -> Result<()> {
+    let age = now - vault.created_at;
+    let cap = config.age_deposit_caps[..config.age_deposit_cap_count as usize]
+        .iter()
+        .rev()
+        .find(|c| age >= c.min_age_secs);
+    if let Some(cap) = cap {
+        require!(
+            vault.balance.saturating_add(amount) <= cap.max_balance,
+            ErrorCode::AgeDepositCapExceeded
+        );
+    }
+    Ok(())
+}
+
+// ★入金後の元本が、持ち主が set_self_cap で決めた上限を超えないことを確認する
+fn require_within_self_cap(vault: &Vault, amount: u64) -> Result<()> {
+    require!(
+        vault.self_cap == 0 || vault.balance.saturating_add(amount) <= vault.self_cap,
+        ErrorCode::SelfCapExceeded
+    );
+    Ok(())
+}
+
+// ★入金額の match_ratio_bps の割合を MatchPool からVaultへ移す
+// プールの残高（レント免除額を除く）が足りなければある分だけ、空なら何もしない。
+// 上乗せ分は入金と同じく元本になり、ベスティング・リワード待機の対象にもなる
+fn apply_deposit_match(
+    vault: &mut Account<Vault>,
+    match_pool: &mut Account<MatchPool>,
+    config: &Config,
+    emission: &mut GlobalEmission,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    let wanted = (amount as u128 * config.match_ratio_bps as u128 / 10000) as u64;
+    let pool_info = match_pool.to_account_info();
+    let available = pool_info
+        .lamports()
+        .saturating_sub(Rent::get()?.minimum_balance(pool_info.data_len()));
+    let matched = wanted.min(available);
+    if matched == 0 {
+        return Ok(());
+    }
+
+    **pool_info.try_borrow_mut_lamports()? -= matched;
+    **vault.to_account_info().try_borrow_mut_lamports()? += matched;
+    match_pool.total_matched = match_pool.total_matched.saturating_add(matched);
+
+    credit_deposit(vault, matched)?;
+    record_deposit(emission, matched);
+    emit_balance_delta(vault, matched as i64);
+    add_vesting(vault, config, matched, now);
+    add_reward_delay(vault, config, matched, now);
+    emit!(DepositMatchedEvent {
+        vault: vault.key(),
+        match_pool: match_pool.key(),
+        amount: matched,
+    });
+    Ok(())
+}
+
+// ★定額リワード（flat_reward_per_deposit）を付ける
+fn credit_flat_reward(
+    vault: &mut Account<Vault>,
+    reward_box: &mut Account<RewardBox>,
+    config: &Config,
+    emission: &mut GlobalEmission,
+) {
+    credit_bonus_reward(vault, reward_box, emission, config.flat_reward_per_deposit);
+}
+
+// ★大口入金のボーナス（large_deposit_bonus_bps）を付ける（元本への入金を反映した後に呼ぶ）
+fn credit_large_deposit_bonus(
+    vault: &mut Account<Vault>,
+    reward_box: &mut Account<RewardBox>,
+    config: &Config,
+    emission: &mut GlobalEmission,
+    amount: u64,
+) {
+    let previous = vault.balance - amount;
+    if config.large_deposit_bonus_bps == 0 || previous == 0 || amount <= previous {
+        return;
+    }
+    let bonus = (amount as u128 * config.large_deposit_bonus_bps as u128 / 10000) as u64;
+    if bonus == 0 {
+        return;
+    }
+    credit_bonus_reward(vault, reward_box, emission, bonus);
+    emit!(LargeDepositBonusEvent {
+        vault: vault.key(),
+        deposit: amount,
+        previous_balance: previous,
+        bonus,
+    });
+}
+
+// ★経過時間によらないリワードを付ける。送り先（reward_destination）の扱いは update_rewards と同じ
+fn credit_bonus_reward(
+    vault: &mut Account<Vault>,
+    reward_box: &mut Account<RewardBox>,
+    emission: &mut GlobalEmission,
+    amount: u64,
+) {
+    if amount == 0 {
+        return;
+    }
+    record_liability(emission, reward_box, amount);
+    if vault.reward_destination != Pubkey::default() && reward_box.key() != vault.reward_destination {
+        vault.pending_destination_rewards = vault.pending_destination_rewards.saturating_add(amount);
+        return;
+    }
+    let before = reward_box.balance;
+    reward_box.balance = reward_box.balance.saturating_add(amount);
+    emit_reward_delta(vault, reward_box, amount as i64);
+    emit_threshold_crossed(vault, ThresholdKind::Rewards, before, reward_box.balance);
+}
+
+// ★reject_deposit_when_goal_locked が有効なら、貯金目標を達成していてロック中のVaultへの入金を拒否する
+fn require_not_goal_locked(config: &Config, vault: &Vault, now: i64) -> Result<()> {
+    let goal_reached = vault.goal_amount > 0 && vault.balance >= vault.goal_amount;
+    require!(
+        !(config.reject_deposit_when_goal_locked && goal_reached && now < vault.lock_until),
+        ErrorCode::GoalLockedDepositRejected
+    );
+    Ok(())
+}
+
+// ★ロック中・凍結中・寄付済みのVaultからの出金・送金を禁止する
+fn require_unlocked(vault: &Vault) -> Result<()> {
+    require!(!vault.frozen, ErrorCode::VaultFrozen);
+    require!(!vault.donated, ErrorCode::VaultDonated);
+    let clock = current_clock()?;
+    require!(clock.unix_timestamp >= vault.lock_until, ErrorCode::VaultLocked);
+    Ok(())
+}
+
+// --- ヘルパー関数: リワード計算ロジック ---
+
+// ★利率によるリワードが付かない状態か（全体停止中・設定により凍結中・定額リワード・元本が reward_min_balance 未満）
+// 止まっている間の経過時間は、後から再開してもさかのぼって付与しない
+fn rate_accrual_paused(config: &Config, vault: &Vault) -> bool {
+    config.shutdown
+        || (vault.frozen && !config.accrue_while_frozen)
+        || vault.flat_reward
+        || vault.balance < config.reward_min_balance
+}
+
+// 前回からの経過秒数ぶん、毎秒リワード対象の元本の0.01%をリワードBoxに加算する（REWARD_RATE_DENOMINATOR）
+fn update_rewards(
+    vault: &mut Account<Vault>,
+    reward_box: &mut Account<RewardBox>,
+    config: &Config,
+    emission: &mut GlobalEmission,
+) -> Result<()> {
+    // ★元本・リワードを動かす操作はすべてここを通るので、version の確認もここで行う
+    require!(
+        vault.version == VAULT_VERSION && reward_box.version == REWARD_BOX_VERSION,
+        ErrorCode::UnsupportedAccountVersion
+    );
+
+    let clock = current_clock()?;
+    let current_time = clock.unix_timestamp;
+    let last_update = vault.last_update_time;
+
+    // 経過秒数
+    let diff = current_time - last_update;
+
+    // ★利率によるリワードが止まっている間（rate_accrual_paused）は付与せず、時刻だけ進める
+    if rate_accrual_paused(config, vault) {
+        vault.last_update_time = current_time;
+        return Ok(());
+    }
+
+    // ★最小間隔に満たない場合は確定させない（last_update_time も進めず、次回にまとめて確定する）
+    // まとめて確定する期間は確定時点の残高で計算されるため、間隔は短め（数十秒程度）に設定すること
+    if diff < config.min_accrual_interval_secs {
+        return Ok(());
+    }
+
+    // ★リワード対象の元本（promo_balance を含む）がなければ付与額も0なので、計算せず時刻だけ進める
+    if vault.balance == 0 && vault.promo_balance == 0 {
+        vault.last_update_time = current_time;
+        return Ok(());
+    }
+
+    // ★単位期間が設定されている場合は満了した期間の終わりまでを確定し、端数の秒は次回へ繰り越す
+    // 繰り越した秒は次回の確定時点の残高で計算される（min_accrual_interval_secs と同じ）
+    let period = config.accrual_period_secs;
+    let current_time = if period > 0 {
+        last_update + diff / period * period
+    } else {
+        current_time
+    };
+    let diff = current_time - last_update;
+
+    // 1秒以上経過していたら計算
+    if diff >= 1 {
+        // リワード計算: 残高 * 0.01% * 経過秒数
+        // 0.01% = 0.0001 = 1 / 10000
+        
+        // ★max_accrual_per_call を超える場合は、確定する期間の終わり（current_time）が手前に下がる
+        let (reward_amount, reward_acc, current_time) = capped_accrued_reward(
+            config,
+            vault,
+            emission,
+            reward_box.reward_acc,
+            last_update,
+            current_time,
+        );
+        reward_box.reward_acc = reward_acc;
+        if current_time >= vault.lock_until {
+            vault.locked_amount = 0;
+        }
+        if current_time >= vault.reward_eligible_after {
+            vault.unearning_amount = 0;
+        }
+
+        // 未払いリワードの合計に加える
+        record_liability(emission, reward_box, reward_amount);
+
+        // 最終更新時刻を現在に更新
+        vault.last_update_time = current_time;
+
+        // ★送り先が別の RewardBox なのに渡されていない場合は、Vault に貯めておく
+        if vault.reward_destination != Pubkey::default() && reward_box.key() != vault.reward_destination {
+            vault.pending_destination_rewards =
+                vault.pending_destination_rewards.saturating_add(reward_amount);
+            return Ok(());
+        }
+
+        // リワード加算
+        reward_box.balance += reward_amount;
+
+        // ★元本の変更（VaultTouchedEvent）より必ず先に発行される
+        if reward_amount > 0 {
+            record_reward_history(reward_box, current_time, reward_amount)?;
+            // ★まとめて出す設定なら、出していない分の合計が accrued_event_threshold に達したときだけ出す
+            let unemitted = vault.unemitted_reward.saturating_add(reward_amount);
+            if unemitted >= config.accrued_event_threshold {
+                vault.unemitted_reward = 0;
+                emit!(RewardAccruedEvent {
+                    vault: vault.key(),
+                    amount: unemitted,
+                    reward_box_balance: reward_box.balance,
+                    last_update_time: current_time,
+                });
+            } else {
+                vault.unemitted_reward = unemitted;
+            }
+            emit_threshold_crossed(
+                vault,
+                ThresholdKind::Rewards,
+                reward_box.balance - reward_amount,
+                reward_box.balance,
+            );
+        }
+    }
+
+    Ok(())
+}
+
+// ★リワード履歴を有効にした RewardBox に確定を1件記録する（無効なら何もしない）
+// 履歴は RewardBox の構造体の外（末尾）にあるため、アカウントのデータへ直接書き込む
+fn record_reward_history(reward_box: &Account<RewardBox>, timestamp: i64, amount: u64) -> Result<()> {
+    let info = reward_box.to_account_info();
+    if info.data_len() < RewardBox::SPACE + RewardHistory::SPACE {
+        return Ok(());
+    }
+    let mut data = info.try_borrow_mut_data()?;
+    let history_data = &mut data[RewardBox::SPACE..];
+    let mut history = RewardHistory::deserialize(&mut &history_data[..])?;
+    let next = history.next as usize % REWARD_HISTORY_LEN;
+    history.entries[next] = RewardHistoryEntry { timestamp, amount };
+    history.next = ((next + 1) % REWARD_HISTORY_LEN) as u8;
+    history.len = (history.len as usize + 1).min(REWARD_HISTORY_LEN) as u8;
+    history.serialize(&mut &mut history_data[..])?;
+    Ok(())
+}
+
+// ★last_update〜current_time に付くリワード（ポイント）と、次に繰り越す端数（update_rewards / next_accrual_preview で共通）
+fn accrued_reward(
+    config: &Config,
+    vault: &Vault,
+    emission: &GlobalEmission,
+    reward_acc: u128,
+    last_update: i64,
+    current_time: i64,
+) -> (u64, u128) {
+    // ★ここから先は REWARD_ACC_SCALE 倍した値で計算し、ポイントへの切り捨ては最後の1回だけ行う
+    // ★rate_lock_until までは lock_rate で固定した利率、それ以降は現在の設定で計算する
+    let rate_lock_end = vault.rate_lock_until.clamp(last_update, current_time);
+    let reward_scaled = fixed_rate_reward_scaled(vault, last_update, rate_lock_end)
+        .saturating_add(configured_reward_scaled(config, vault, emission, rate_lock_end, current_time));
+
+    // ★リワードが付く時間帯が決まっていれば、期間のうち時間帯に入っている秒数の割合に縮める
+    let reward_scaled = scale_to_accrual_window(config, reward_scaled, last_update, current_time);
+
+    // ★前回までの端数を足してから整数ポイントに直し、1ポイント未満は再び繰り越す
+    let total_scaled = reward_scaled.saturating_add(reward_acc);
+    let reward_amount = (total_scaled / REWARD_ACC_SCALE).min(u64::MAX as u128) as u64;
+    // ★元本があるVaultには最低額を保証する（元本0のVaultには付けない）
+    let reward_amount = if vault.balance > 0 {
+        reward_amount.max(config.min_accrual_per_interval)
+    } else {
+        reward_amount
+    };
+    (reward_amount, total_scaled % REWARD_ACC_SCALE)
+}
+
+// ★start〜end のリワード（REWARD_ACC_SCALE 倍）を、リワードが付く時間帯に入っている秒数の割合に縮める
+// 期間中は元本が変わらないので、時間帯の外の秒数を除いて計算したのと同じになる
+// （待機・ロックの明けなど期間の途中で倍率が変わる場合は、期間全体で均した倍率になる）
+fn scale_to_accrual_window(config: &Config, reward_scaled: u128, start: i64, end: i64) -> u128 {
+    let total = (end - start) as u128;
+    if config.accrual_window_start_secs == config.accrual_window_end_secs || total == 0 {
+        return reward_scaled;
+    }
+    let in_window = (accrual_window_secs_until(config, end) - accrual_window_secs_until(config, start)) as u128;
+    (reward_scaled / total)
+        .saturating_mul(in_window)
+        .saturating_add(reward_scaled % total * in_window / total)
+}
+
+// ★1970-01-01 0時（UTC）から t までのうち、リワードが付く時間帯に入っている秒数
+// 差を取れば任意の期間の秒数になる（日をまたぐ期間も、日付をまたぐ時間帯もこれで数えられる）
+fn accrual_window_secs_until(config: &Config, t: i64) -> i64 {
+    let start = config.accrual_window_start_secs as i64;
+    let end = config.accrual_window_end_secs as i64;
+    let days = t.div_euclid(SECS_PER_DAY);
+    let secs_of_day = t.rem_euclid(SECS_PER_DAY);
+    if start < end {
+        days * (end - start) + secs_of_day.clamp(start, end) - start
+    } else {
+        // 0時〜end と start〜24時の2つに分かれる
+        days * (SECS_PER_DAY - start + end) + secs_of_day.min(end) + (secs_of_day - start).max(0)
+    }
+}
+
+// ★accrued_reward に max_accrual_per_call の上限を反映したもの（付くリワード、端数、確定する期間の終わり）
+// 上限を超える場合は、経過秒数を上限との比で縮めた時刻（単位期間があればその区切り）までを確定する。
+// 最短でも1秒（単位期間があれば1期間）は確定するので、その分だけで上限を超えることはある。
+// max_accrual_lookback_secs より古い経過秒数は、上限の計算の前に切り捨てる（確定する期間の終わりは変わらない）
+fn capped_accrued_reward(
+    config: &Config,
+    vault: &Vault,
+    emission: &GlobalEmission,
+    reward_acc: u128,
+    last_update: i64,
+    current_time: i64,
+) -> (u64, u128, i64) {
+    let lookback = config.max_accrual_lookback_secs;
+    let last_update = if lookback > 0 {
+        last_update.max(current_time - lookback)
+    } else {
+        last_update
+    };
+    let (amount, acc) = accrued_reward(config, vault, emission, reward_acc, last_update, current_time);
+    let cap = config.max_accrual_per_call;
+    if cap == 0 || amount <= cap {
+        return (amount, acc, current_time);
+    }
+
+    let diff = (current_time - last_update) as u128;
+    let secs = (diff * cap as u128 / amount as u128) as i64;
+    let period = config.accrual_period_secs.max(1);
+    let end = last_update + (secs / period * period).max(period);
+    let (amount, acc) = accrued_reward(config, vault, emission, reward_acc, last_update, end);
+    (amount, acc, end)
+}
+
+// ★現在の設定での実効利率の内訳（get_rate_info / lock_rate で共通、lock_rate による固定は含まない）
+fn rate_info(config: &Config, vault: &Vault, emission: &GlobalEmission, now: i64) -> RateInfo {
+    // ★promo_balance を含み、待機中の元本は除く（update_rewards と同じ）
+    let base = vault.balance.saturating_add(vault.promo_balance);
+    let earning_balance = if now >= vault.reward_eligible_after {
+        base
+    } else {
+        base - vault.unearning_amount.min(vault.balance)
+    };
+
+    // 方式による倍率は Linear との比（Tiered の超過分の倍率や Capped の上限を反映）
+    let strategy_bps = if earning_balance == 0 {
+        10000
+    } else {
+        calculate_reward(config.reward_strategy, earning_balance, 10000) as u128 * 10000
+            / earning_balance as u128
+    };
+    let decay_bps = decay_multiplier_bps(config, now - vault.created_at);
+    let rate_bps = rate_multiplier_bps(config, vault);
+    let utilization_bps = utilization_multiplier_bps(config, emission);
+    let goal_bps = goal_multiplier_bps(config, vault);
+    let withdrawal_bps = withdrawal_multiplier_bps(config, vault, now);
+
+    let accrues = !rate_accrual_paused(config, vault);
+    let effective_bps = if accrues {
+        strategy_bps * decay_bps / 10000 * rate_bps / 10000 * utilization_bps / 10000 * goal_bps / 10000
+            * withdrawal_bps / 10000
+    } else {
+        0
+    };
+
+    RateInfo {
+        earning_balance,
+        strategy_bps: strategy_bps as u64,
+        decay_bps: decay_bps as u64,
+        rate_bps: rate_bps as u64,
+        utilization_bps: utilization_bps as u64,
+        goal_bps: goal_bps as u64,
+        withdrawal_bps: withdrawal_bps as u64,
+        effective_bps: effective_bps as u64,
+    }
+}
+
+// ★start〜end の現在の設定でのリワード額（REWARD_ACC_SCALE 倍、端数を含む）
+// 方式・経年減衰・ロック中の元本の扱い・利用率・出金の頻度・目標達成ボーナスを反映する
+fn configured_reward_scaled(
+    config: &Config,
+    vault: &Vault,
+    emission: &GlobalEmission,
+    start: i64,
+    end: i64,
+) -> u128 {
+    // ※Solanaは整数演算なので、先に掛けてから割る
+    // ★待機中の元本はリワード対象外。待機が明けた時刻を境に前後で分けて計算する
+    // ★promo_balance（裏付けのない販促残高）もリワード対象に含める
+    let base = vault.balance.saturating_add(vault.promo_balance);
+    let delayed = vault.unearning_amount.min(vault.balance);
+    let earning = base - delayed;
+    let reward_scaled = if delayed > 0 && end >= vault.reward_eligible_after {
+        let split = vault.reward_eligible_after.max(start);
+        reward_between(config, vault, earning, start, split, end)
+            .saturating_add(reward_between(config, vault, base, split, end, end))
+    } else {
+        reward_between(config, vault, earning, start, end, end)
+    };
+    // ★ロック中の元本は locked_reward_mode に従って増減させる（ロックが明けた時刻までの分だけ）
+    // 調整額は locked_amount を単独で預けた場合のリワードを基準にする
+    let locked = vault.locked_amount.min(earning);
+    let lock_end = vault.lock_until.min(end);
+    let reward_scaled = if locked > 0 && lock_end > start {
+        let locked_reward = reward_between(config, vault, locked, start, lock_end, end);
+        match config.locked_reward_mode {
+            LockedRewardMode::Base => reward_scaled,
+            LockedRewardMode::Boosted => reward_scaled
+                .saturating_add(apply_bps(locked_reward, LOCK_BONUS_BPS - 10000)),
+            LockedRewardMode::None => reward_scaled.saturating_sub(locked_reward),
+        }
+    } else {
+        reward_scaled
+    };
+    // ★全体共通の利率に、Vaultごとの上乗せを足した倍率をかける
+    let reward_scaled = apply_bps(reward_scaled, rate_multiplier_bps(config, vault));
+    // ★全体の利用率が高いほど利率を下げる
+    let reward_scaled = apply_bps(reward_scaled, utilization_multiplier_bps(config, emission));
+    // ★最近よく出金しているVaultは利率を下げる（期間の終わりの withdrawal_score で判定する）
+    let reward_scaled = apply_bps(reward_scaled, withdrawal_multiplier_bps(config, vault, end));
+    // ★貯金目標を達成していれば上乗せする（元本は確定の後にしか変わらないため、期間中の残高で判定できる）
+    apply_bps(reward_scaled, goal_multiplier_bps(config, vault))
+}
+
+// ★REWARD_ACC_SCALE 倍の値に bps の倍率をかける
+// 値が大きくても溢れないよう、10000 の商と余りに分けて掛ける（余りの部分も切り捨てずに計算する）
+fn apply_bps(value: u128, bps: u128) -> u128 {
+    (value / 10000)
+        .saturating_mul(bps)
+        .saturating_add(value % 10000 * bps / 10000)
+}
+
+// ★start〜end の lock_rate で固定した利率でのリワード額（REWARD_ACC_SCALE 倍、端数を含む）
+// 固定中は方式や各倍率の変更を無視し、リワード対象の元本 × 0.01% × locked_rate_bps / 10000 で計算する
+fn fixed_rate_reward_scaled(vault: &Vault, start: i64, end: i64) -> u128 {
+    if end <= start {
+        return 0;
+    }
+    // 待機中の元本は待機が明けた時刻から対象にする（configured_reward_scaled と同じ）
+    let base = vault.balance.saturating_add(vault.promo_balance) as u128;
+    let delayed = vault.unearning_amount.min(vault.balance) as u128;
+    let split = if delayed > 0 {
+        vault.reward_eligible_after.clamp(start, end)
+    } else {
+        start
+    };
+    let weighted_secs = (base - delayed)
+        .saturating_mul((split - start) as u128)
+        .saturating_add(base.saturating_mul((end - split) as u128));
+    weighted_secs
+        .saturating_mul(vault.locked_rate_bps as u128)
+        .saturating_mul(REWARD_ACC_SCALE / (10000 * 10000))
+}
+
+// ★start〜end に balance を預けた場合のリワード額（REWARD_ACC_SCALE 倍、端数を含む）
+// 期間が方式の変更時刻（last_config_update）をまたぐ場合、変更前の部分は変更前の方式で計算する。
+// 残しているのは直前の方式だけなので、1回の期間中に2回以上変更された場合は、最後の変更より前を直前の方式で計算する
+fn reward_between(config: &Config, vault: &Vault, balance: u64, start: i64, end: i64, now: i64) -> u128 {
+    let boundary = config.last_config_update.clamp(start, end.max(start));
+    let reward = calculate_reward_scaled(config.prev_reward_strategy, balance, boundary - start)
+        .saturating_add(calculate_reward_scaled(config.reward_strategy, balance, end - boundary));
+    let multiplier = decay_multiplier_bps(config, now - vault.created_at);
+    apply_bps(reward, multiplier)
+}
+
+// ★方式と経年減衰を反映したリワード額（balance を diff 秒預けた場合）
+fn reward_for(config: &Config, vault: &Vault, balance: u64, diff: i64, now: i64) -> u64 {
+    let reward = calculate_reward(config.reward_strategy, balance, diff);
+    let multiplier = decay_multiplier_bps(config, now - vault.created_at);
+    (reward as u128 * multiplier / 10000) as u64
+}
+
+// ★Vaultの経過年数に応じた利率の倍率（10000 = 減衰なし）
+fn decay_multiplier_bps(config: &Config, age: i64) -> u128 {
+    if config.decay_period_secs <= 0 {
+        return 10000;
+    }
+    let periods = (age.max(0) / config.decay_period_secs) as u128;
+    let decayed = 10000u128.saturating_sub(periods.saturating_mul(config.decay_bps_per_period as u128));
+    decayed.max(config.decay_floor_bps as u128)
+}
+
+// ★利率の倍率（10000 = 基本の利率）= default_reward_rate_bps + rate_delta_bps（0で下げ止める）
+// 上乗せは差分で持つので、全体の利率を変えると上乗せしたVaultの利率も同じだけ動く。
+// リワードの運用先のVaultには growth_rate_bonus_bps も足す
+fn rate_multiplier_bps(config: &Config, vault: &Vault) -> u128 {
+    let growth_bps = if is_growth_vault(vault) { config.growth_rate_bonus_bps as i64 } else { 0 };
+    (config.default_reward_rate_bps as i64 + vault.rate_delta_bps as i64 + growth_bps).max(0) as u128
+}
+
+// ★リワードの運用先のVault（move_rewards_to_growth で作る名前付きVault）か
+fn is_growth_vault(vault: &Vault) -> bool {
+    vault.name_seed() == GROWTH_VAULT_NAME
+}
+
+// ★now 時点の withdrawal_score（withdrawal_score_decay_secs が経つごとに1ずつ減る）
+fn withdrawal_score(config: &Config, vault: &Vault, now: i64) -> u32 {
+    let decay = config.withdrawal_score_decay_secs;
+    if decay <= 0 {
+        return vault.withdrawal_score;
+    }
+    let steps = (now - vault.withdrawal_score_at).max(0) / decay;
+    vault.withdrawal_score.saturating_sub(steps.min(u32::MAX as i64) as u32)
+}
+
+// ★出金の頻度による利率の倍率（10000 = 引き下げなし、0で下げ止める）
+fn withdrawal_multiplier_bps(config: &Config, vault: &Vault, now: i64) -> u128 {
+    let penalty = withdrawal_score(config, vault, now) as u128 * config.withdrawal_score_penalty_bps as u128;
+    10000u128.saturating_sub(penalty)
+}
+
+// ★出金を1回数える（リワードを確定させた後に呼ぶ）
+// 減少の途中の端数の秒は持ち越し、スコアが0から増えるときは now から数え始める
+fn record_withdrawal_score(config: &Config, vault: &mut Vault, now: i64) {
+    let score = withdrawal_score(config, vault, now);
+    let decay = config.withdrawal_score_decay_secs;
+    if score == 0 || decay <= 0 {
+        vault.withdrawal_score_at = now;
+    } else {
+        vault.withdrawal_score_at += (now - vault.withdrawal_score_at).max(0) / decay * decay;
+    }
+    vault.withdrawal_score = score.saturating_add(1);
+}
+
+// ★貯金目標の達成による利率の倍率（10000 = 上乗せなし）
+fn goal_multiplier_bps(config: &Config, vault: &Vault) -> u128 {
+    if vault.goal_amount == 0 || vault.balance < vault.goal_amount {
+        return 10000;
+    }
+    10000 + config.goal_bonus_bps as u128
+}
+
+// ★全体の利用率に応じた利率の倍率（10000 = 調整なし）
+// half_point / (half_point + total_deposits): 元本合計が0なら等倍、half_point で半分になる
+fn utilization_multiplier_bps(config: &Config, emission: &GlobalEmission) -> u128 {
+    let half_point = config.utilization_half_point as u128;
+    if half_point == 0 {
+        return 10000;
+    }
+    10000 * half_point / (half_point + emission.total_deposits as u128)
+}
+
+// ★e^x - 1 の固定小数点近似（x・戻り値とも EXP_FIXED_SCALE 倍）
+// x を EXP_MAX_X ごとの区間に分けて expm1_segment で計算し、e^(a+b) - 1 = (e^a - 1)(e^b - 1) + (e^a - 1) + (e^b - 1) でつなぐ。
+// u128 に収まらなくなったら u128::MAX を返す（数区間で桁あふれするので、区間の数にも上限がある）
+fn expm1_fixed(x: u128) -> u128 {
+    let mut rest = x;
+    let mut sum = 0u128;
+    while rest > 0 {
+        let segment_x = rest.min(EXP_MAX_X);
+        rest -= segment_x;
+        let segment = expm1_segment(segment_x);
+        let combined = mul_fixed(sum, segment)
+            .and_then(|product| product.checked_add(sum))
+            .and_then(|product| product.checked_add(segment));
+        match combined {
+            Some(value) => sum = value,
+            None => return u128::MAX,
+        }
+    }
+    sum
+}
+
+// ★固定小数点どうしの積（EXP_FIXED_SCALE 倍）。a × b が u128 に収まらなければ先に a を単位で割る
+fn mul_fixed(a: u128, b: u128) -> Option<u128> {
+    match a.checked_mul(b) {
+        Some(product) => Some(product / EXP_FIXED_SCALE),
+        None => (a / EXP_FIXED_SCALE).checked_mul(b),
+    }
+}
+
+// ★EXP_MAX_X 以下の x についての e^x - 1
+// x を半分にし続けて 1/2 以下にしてからテイラー展開し、e^(2y) - 1 = (e^y - 1)(e^y - 1 + 2) で戻す。
+// x に上限があるので、項数・半分にする回数とも上限があり、計算量と桁あふれの心配がない
+fn expm1_segment(x: u128) -> u128 {
+    let mut y = x;
+    let mut halvings = 0;
+    while y > EXP_FIXED_SCALE / 2 {
+        y /= 2;
+        halvings += 1;
+    }
+
+    // y + y^2/2! + y^3/3! + ...
+    let mut term = y;
+    let mut sum = y;
+    for k in 2..=EXP_TAYLOR_TERMS {
+        term = term * y / EXP_FIXED_SCALE / k;
+        sum += term;
+    }
+
+    for _ in 0..halvings {
+        sum = sum * (sum + 2 * EXP_FIXED_SCALE) / EXP_FIXED_SCALE;
+    }
+    sum
+}
+
+// ★Configで選ばれた方式でリワード額を計算する
+fn calculate_reward(strategy: RewardStrategy, balance: u64, diff: i64) -> u64 {
+    (calculate_reward_scaled(strategy, balance, diff) / REWARD_ACC_SCALE).min(u64::MAX as u128) as u64
+}
+
+// ★calculate_reward の REWARD_ACC_SCALE 倍（1ポイント未満の端数を切り捨てない）
+fn calculate_reward_scaled(strategy: RewardStrategy, balance: u64, diff: i64) -> u128 {
+    let balance = balance as u128;
+    // ★負の経過秒数を `as u128` すると巨大な値になるため、0以上の場合だけ変換する
+    let diff = match u128::try_from(diff) {
+        Ok(diff) => diff,
+        Err(_) => return 0,
+    };
+
+    let weighted = match strategy {
+        // 残高全体に一律の利率
+        RewardStrategy::Linear => balance,
+        // しきい値を超えた部分だけ倍率をかける
+        RewardStrategy::Tiered => {
+            let base = balance.min(TIER_THRESHOLD as u128);
+            let excess = balance - base;
+            base + excess * TIER_MULTIPLIER
+        }
+        // 上限を超えた残高は報酬の対象外
+        RewardStrategy::Capped => balance.min(REWARD_BALANCE_CAP as u128),
+        // ★連続複利は経過秒数に比例しないため、ここで計算して返す
+        // （確定を分けると通しで確定した場合より少なくなる。その期間のリワードは元本に組み入れないため）
+        RewardStrategy::Continuous => {
+            let x = diff.saturating_mul(EXP_FIXED_SCALE) / REWARD_RATE_DENOMINATOR;
+            return balance
+                .saturating_mul(expm1_fixed(x))
+                .saturating_mul(REWARD_ACC_SCALE / EXP_FIXED_SCALE);
+        }
+    };
+
+    // 残高 × 0.01% × 経過秒数（REWARD_ACC_SCALE は REWARD_RATE_DENOMINATOR で割り切れる）
+    weighted
+        .saturating_mul(diff)
+        .saturating_mul(REWARD_ACC_SCALE / REWARD_RATE_DENOMINATOR)
+}
+
+
+// --- Account Structures ---
+//
+// ★PDAのbumpについて
+// - init するPDAは必ず `bump`（= ctx.bumps の正規bump）で作成し、その値をアカウントに保存する
+// - 以降のインストラクションでは `bump = <account>.bump` で保存済みの正規bumpを使って再検証する
+// - Mint / mint_authority などデータを持たない（またはbumpを保存しない）PDAは毎回 `bump` で正規bumpを導出する
+// 正規でないbumpから導出したアドレスは別アドレスになるため、どのコンテキストでも seeds 制約で拒否される。
+//
+// ★名前付きVaultについて
+// - Vault / RewardBox の seeds は [b"vault" | b"reward", user, name]。基本Vaultは name が空なので
+//   [b"vault", user] と同じアドレスになる（空のseedはアドレス導出に影響しない）
+// - name は Vault に保存し、以降は `vault.name_seed()` で seeds を再現する
+// - 定期入金（RecurringDeposit）と migrate_vault は基本Vaultのみが対象
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = Config::SPACE,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★Config変更用（管理者のみ）
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = Vault::SPACE,
+        seeds = [b"vault", user.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // ★追加: リワード専用Box
+    #[account(
+        init,
+        payer = user,
+        space = RewardBox::SPACE,
+        seeds = [b"reward", user.key().as_ref()], // seedを変えて別の箱にする
+        bump
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    // ★有効なVault数のカウント用
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★ユーザーごとのVault数のカウント用（最初のVault作成時に作る）
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserStats::SPACE,
+        seeds = [b"user_stats", user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★作成と最初の入金を同時に行う用（Initialize + 入金に必要なアカウント）
+#[derive(Accounts)]
+pub struct InitializeAndDeposit<'info> {
+    #[account(
+        init,
+        payer = user,
+        space = Vault::SPACE,
+        seeds = [b"vault", user.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init,
+        payer = user,
+        space = RewardBox::SPACE,
+        seeds = [b"reward", user.key().as_ref()],
+        bump
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    // ★有効なVault数のカウント・入金設定の参照用
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserStats::SPACE,
+        seeds = [b"user_stats", user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★手数料の受け取り先
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★スポンサー負担の初期化用（レントは payer、持ち主は user）
+#[derive(Accounts)]
+pub struct InitializeSponsored<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = Vault::SPACE,
+        seeds = [b"vault", user.key().as_ref()],
+        bump
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init,
+        payer = payer,
+        space = RewardBox::SPACE,
+        seeds = [b"reward", user.key().as_ref()],
+        bump
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    // ★有効なVault数のカウント用
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★ユーザーごとのVault数のカウント用（最初のVault作成時に作る）
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = UserStats::SPACE,
+        seeds = [b"user_stats", user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    // ★持ち主になるユーザー（lamportは動かないので mut は不要）
+    pub user: Signer<'info>,
+
+    // ★レントを支払うスポンサー
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Deposit<'info> {
+    // ★RewardBoxより先に検証されるので、Vaultがなければ RewardBox を作る前に AccountNotInitialized で失敗する
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // ★追加: リワード計算のために必要
+    // RewardBox導入前のVaultでも入金できるよう、なければここで作る（費用は user が負担）
+    // seeds に vault の名前を使うので、作られるのは検証済みのVaultに対応する RewardBox だけ
+    #[account(
+        init_if_needed,
+        payer = user,
+        // init_if_needed は既存アカウントの長さが space と一致することを求めるため、
+        // リワード履歴を有効にした（末尾を広げた）RewardBox では現在の長さを渡す
+        space = RewardBox::SPACE.max(reward_box.data_len()),
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    // ★報酬計算方式の参照用
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★手数料の受け取り先
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    // ★入金の上乗せ元（渡さなければ上乗せしない）
+    #[account(
+        mut,
+        seeds = [b"match_pool", match_pool.sponsor.as_ref()],
+        bump = match_pool.bump,
+    )]
+    pub match_pool: Option<Account<'info, MatchPool>>,
+
+    // ★入金元の記録（渡さなければ記録しない）
+    #[account(
+        mut,
+        seeds = [b"deposit_sources", vault.key().as_ref()],
+        bump = deposit_sources.bump,
+    )]
+    pub deposit_sources: Option<Account<'info, DepositSources>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★MatchPool の作成用
+#[derive(Accounts)]
+pub struct CreateMatchPool<'info> {
+    #[account(
+        init,
+        payer = sponsor,
+        space = MatchPool::SPACE,
+        seeds = [b"match_pool", sponsor.key().as_ref()],
+        bump
+    )]
+    pub match_pool: Account<'info, MatchPool>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★MatchPool への資金投入用
+#[derive(Accounts)]
+pub struct FundMatchPool<'info> {
+    #[account(
+        mut,
+        seeds = [b"match_pool", sponsor.key().as_ref()],
+        bump = match_pool.bump,
+        has_one = sponsor,
+    )]
+    pub match_pool: Account<'info, MatchPool>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★MatchPool を閉じる用
+#[derive(Accounts)]
+pub struct CloseMatchPool<'info> {
+    #[account(
+        mut,
+        seeds = [b"match_pool", sponsor.key().as_ref()],
+        bump = match_pool.bump,
+        has_one = sponsor,
+        close = sponsor,
+    )]
+    pub match_pool: Account<'info, MatchPool>,
+
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+}
+
+// ★RewardBoxの後付け作成用
+// Vault を RewardBox より先に置き、持ち主の既存のVaultがなければ作成前に拒否する
+#[derive(Accounts)]
+pub struct InitRewardBox<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init,
+        payer = user,
+        space = RewardBox::SPACE,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct Withdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // ★追加
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    // ★報酬計算方式の参照用
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★手数料の受け取り先
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    // ★出金先。PDAは署名できないので通常は起こりえないが、念のためVault自身でないことを確認する
+    #[account(
+        mut,
+        constraint = user.key() != vault.key() @ ErrorCode::DestinationIsVault,
+    )]
+    pub user: Signer<'info>,
+}
+
+// ★早期出金用（Withdraw + ペナルティの送り先）
+#[derive(Accounts)]
+pub struct EarlyWithdraw<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    #[account(seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    /// CHECK: Config の penalty_destination から決まるアドレスと一致することを early_withdraw 内で検証する
+    #[account(
+        mut,
+        constraint = penalty_recipient.key() != vault.key() @ ErrorCode::DestinationIsVault,
+    )]
+    pub penalty_recipient: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = user.key() != vault.key() @ ErrorCode::DestinationIsVault,
+    )]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + 1 + 8, // discriminator + bump + total_funded
+        seeds = [b"treasury"],
+        bump
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeLeaderboard<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = Leaderboard::SPACE,
+        seeds = [b"leaderboard"],
+        bump
+    )]
+    pub leaderboard: Account<'info, Leaderboard>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeGlobalEmission<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init,
+        payer = admin,
+        // discriminator + bump + outstanding_liabilities + total_deposits + total_deposit_fees
+        space = 8 + 1 + 8 + 8 + 8,
+        seeds = [b"global_emission"],
+        bump
+    )]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct DrainTreasury<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump,
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseTreasury<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump,
+        close = admin,
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundTreasury<'info> {
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump,
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(mut)]
+    pub funder: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★Treasuryへのリワードトークンの入金用
+#[derive(Accounts)]
+pub struct FundTreasuryToken<'info> {
+    #[account(seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(seeds = [b"reward_mint"], bump)]
+    pub reward_mint: Account<'info, Mint>,
+
+    // 受け取り先（Treasury PDA のATA、なければ作成）
+    #[account(
+        init_if_needed,
+        payer = funder,
+        associated_token::mint = reward_mint,
+        associated_token::authority = treasury,
+    )]
+    pub treasury_token_account: Account<'info, TokenAccount>,
+
+    // ★送り元のMintがリワードトークンと違えば拒否する
+    #[account(
+        mut,
+        constraint = funder_token_account.mint == reward_mint.key() @ ErrorCode::FundingMintMismatch,
+    )]
+    pub funder_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub funder: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★リワード → ロック付き元本の変換用
+#[derive(Accounts)]
+pub struct ConvertRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    #[account(
+        mut,
+        seeds = [b"treasury"],
+        bump = treasury.bump,
+    )]
+    pub treasury: Account<'info, Treasury>,
+
+    pub user: Signer<'info>,
+}
+
+// ★不変条件チェック用（署名不要）
+#[derive(Accounts)]
+pub struct AssertInvariants<'info> {
+    #[account(
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+}
+
+// ★Vault PDAの余剰SOLの回収用（Vaultの持ち主が署名）
+#[derive(Accounts)]
+pub struct SweepSurplus<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+// ★Vaultのトークンへの変換用（スワップに使うアカウントは remaining_accounts で渡す）
+#[derive(Accounts)]
+pub struct ConvertVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    // ★報酬計算方式・手数料の参照用
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）と預かり総額の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★出金手数料の受け取り先
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    /// CHECK: 呼び出し先のスワッププログラム（命令の中身は route と remaining_accounts で指定する）
+    #[account(executable)]
+    pub swap_program: UncheckedAccount<'info>,
+
+    pub output_mint: Account<'info, Mint>,
+
+    // ★スワップの出力を受け取るユーザーのトークンアカウント
+    #[account(
+        mut,
+        token::mint = output_mint,
+        token::authority = user,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    // ★トークン用のVault（Vault PDA が権限を持つ）
+    #[account(
+        init_if_needed,
+        payer = user,
+        seeds = [b"token_vault", vault.key().as_ref(), output_mint.key().as_ref()],
+        bump,
+        token::mint = output_mint,
+        token::authority = vault,
+    )]
+    pub token_vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★トークン用のVaultからの出金用
+#[derive(Accounts)]
+pub struct WithdrawTokenVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub output_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        seeds = [b"token_vault", vault.key().as_ref(), output_mint.key().as_ref()],
+        bump,
+    )]
+    pub token_vault: Account<'info, TokenAccount>,
+
+    #[account(mut, token::mint = output_mint)]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+// ★定期入金の登録用
+#[derive(Accounts)]
+pub struct AuthorizeRecurring<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + 32 + 8 + 8 + 8 + 1, // discriminator + user + amount + interval_secs + next_deposit_at + bump
+        seeds = [b"recurring", user.key().as_ref()],
+        bump
+    )]
+    pub recurring: Account<'info, RecurringDeposit>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★定期入金の実行用（cranker は誰でもよい）
+#[derive(Accounts)]
+pub struct CrankDeposit<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", recurring.user.as_ref()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", recurring.user.as_ref()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    #[account(
+        mut,
+        seeds = [b"recurring", recurring.user.as_ref()],
+        bump = recurring.bump,
+    )]
+    pub recurring: Account<'info, RecurringDeposit>,
+
+    // ★手数料の受け取り先
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    pub cranker: Signer<'info>,
+}
+
+// ★リワードの一括確定用（対象の Vault / RewardBox は remaining_accounts で渡す）
+#[derive(Accounts)]
+pub struct CrankAccrue<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★渡した場合のみリーダーボードを更新する
+    #[account(mut, seeds = [b"leaderboard"], bump = leaderboard.bump)]
+    pub leaderboard: Option<Account<'info, Leaderboard>>,
+
+    pub cranker: Signer<'info>,
+}
+
+// ★端数のリワードの一括整理用（Vault と RewardBox の組は remaining_accounts で渡す）
+#[derive(Accounts)]
+pub struct SweepDust<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    pub admin: Signer<'info>,
+}
+
+// ★Vaultの一括作成用（user, Vault, RewardBox, UserStats の組は remaining_accounts で渡す）
+#[derive(Accounts)]
+pub struct BatchInitialize<'info> {
+    // ★有効なVault数のカウント用
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★全員分のレントを負担するスポンサー
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★一括入金用（Vault と RewardBox の組は remaining_accounts で渡す）
+#[derive(Accounts)]
+pub struct BatchDepositToVaults<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）・元本合計の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★手数料の受け取り先
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    // ★全員分の入金額を負担する
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRecurring<'info> {
+    #[account(
+        mut,
+        seeds = [b"recurring", user.key().as_ref()],
+        bump = recurring.bump,
+        has_one = user,
+        close = user,
+    )]
+    pub recurring: Account<'info, RecurringDeposit>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+// ★Vaultの縮小用（返却されるレントは持ち主へ）
+#[derive(Accounts)]
+pub struct CompactVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+// ★旧レイアウトのVault拡張用
+#[derive(Accounts)]
+pub struct MigrateVault<'info> {
+    /// CHECK: 旧サイズのVaultは Account<Vault> として読めないため、seeds と所有者、discriminator で検証する
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref()],
+        bump,
+        owner = crate::ID,
+    )]
+    pub vault: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★残高の記録用（記録用のPDAがなければ作る）
+#[derive(Accounts)]
+pub struct SnapshotBalance<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = BalanceSnapshots::SPACE,
+        seeds = [b"balance_snapshots", vault.key().as_ref()],
+        bump
+    )]
+    pub balance_snapshots: Account<'info, BalanceSnapshots>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★リワード履歴の有効化・無効化用（レントは持ち主が支払い、持ち主へ返却される）
+#[derive(Accounts)]
+pub struct RewardHistoryAccounts<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★Vaultの存在確認用（読み取り専用、Vaultが未作成でもよい）
+#[derive(Accounts)]
+pub struct Peek<'info> {
+    /// CHECK: 存在しない場合もあるため生のアカウントとして受け取り、所有者と discriminator は peek で確認する
+    #[account(seeds = [b"vault", user.key().as_ref()], bump)]
+    pub vault: UncheckedAccount<'info>,
+
+    /// CHECK: seeds の導出にだけ使う
+    pub user: UncheckedAccount<'info>,
+}
+
+// ★持ち主か view_authority だけが呼べる閲覧用（読み取り専用）
+#[derive(Accounts)]
+pub struct GatedView<'info> {
+    #[account(
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        constraint = viewer.key() == vault.user || viewer.key() == vault.view_authority
+            @ ErrorCode::ViewerNotAuthorized,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    pub viewer: Signer<'info>,
+}
+
+// ★USD建て目標の確認用（読み取り専用）
+#[derive(Accounts)]
+pub struct GoalProgressUsd<'info> {
+    #[account(
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    /// CHECK: アドレス・所有者・中身は read_sol_usd_price で検証する
+    pub price_feed: UncheckedAccount<'info>,
+}
+
+// ★リワードトークンのMint作成用
+#[derive(Accounts)]
+#[instruction(decimals: u8)]
+pub struct InitializeRewardMint<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [b"reward_mint"],
+        bump,
+        mint::decimals = decimals, // 9 ならリワードポイント（lamport単位）と1:1
+        mint::authority = mint_authority,
+    )]
+    pub reward_mint: Account<'info, Mint>,
+
+    /// CHECK: Mintの発行権限を持つだけのPDA（データなし）
+    #[account(seeds = [b"mint_authority"], bump)]
+    pub mint_authority: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★リワードトークンの発行権限の移譲用（管理者のみ）
+#[derive(Accounts)]
+pub struct RotateMintAuthority<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = admin)]
+    pub config: Account<'info, Config>,
+
+    #[account(mut, seeds = [b"reward_mint"], bump, mint::authority = mint_authority)]
+    pub reward_mint: Account<'info, Mint>,
+
+    /// CHECK: Mintの発行権限を持つだけのPDA（データなし）
+    #[account(seeds = [b"mint_authority"], bump)]
+    pub mint_authority: UncheckedAccount<'info>,
+
+    pub admin: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+}
+
+// ★リワードのSOL受け取り用
+#[derive(Accounts)]
+pub struct ClaimReward<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    // ★受け取れるのはリワードの持ち主（reward_owner、未設定なら Vault の持ち主）
+    #[account(
+        mut,
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+        constraint = reward_owner(&vault, &reward_box) == user.key() @ ErrorCode::NotRewardOwner,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    // ★報酬計算方式・手数料率の参照用
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★リワードの支払い元
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    // ★渡した場合のみリーダーボードを更新する
+    #[account(mut, seeds = [b"leaderboard"], bump = leaderboard.bump)]
+    pub leaderboard: Option<Account<'info, Leaderboard>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+// ★リワードの持ち主の変更用（現在のリワードの持ち主が署名）
+#[derive(Accounts)]
+pub struct TransferRewardOwnership<'info> {
+    #[account(
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+        constraint = reward_owner(&vault, &reward_box) == owner.key() @ ErrorCode::NotRewardOwner,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    pub owner: Signer<'info>,
+}
+
+// ★リワードのまとめ受け取り用（対象の Vault / RewardBox は remaining_accounts で渡す）
+#[derive(Accounts)]
+pub struct BatchClaim<'info> {
+    // ★報酬計算方式・手数料率の参照用
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★リワードの支払い元
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+// ★リワードのトークン受け取り用
+#[derive(Accounts)]
+pub struct ClaimRewardToken<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★発行権限を rotate_mint_authority で移した後は発行できない
+    #[account(mut, seeds = [b"reward_mint"], bump, mint::authority = mint_authority)]
+    pub reward_mint: Account<'info, Mint>,
+
+    /// CHECK: Mintの発行権限を持つだけのPDA（データなし）
+    #[account(seeds = [b"mint_authority"], bump)]
+    pub mint_authority: UncheckedAccount<'info>,
+
+    // 受け取り先（ユーザーのATA、なければ作成）
+    #[account(
+        init_if_needed,
+        payer = user,
+        associated_token::mint = reward_mint,
+        associated_token::authority = user,
+    )]
+    pub user_token_account: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★Vaultの読み取り専用ゲッター用
+#[derive(Accounts)]
+pub struct ViewVault<'info> {
+    #[account(
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+// ★利率の固定用（Vaultの持ち主が署名）
+#[derive(Accounts)]
+pub struct LockRate<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    pub user: Signer<'info>,
+}
+
+// ★定額リワードの切り替え用（切り替え前にリワードを確定する）
+#[derive(Accounts)]
+pub struct SetFlatReward<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    pub user: Signer<'info>,
+}
+
+// ★実効利率の確認用（読み取り専用）
+#[derive(Accounts)]
+pub struct ViewRate<'info> {
+    #[account(
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+}
+
+// ★次の確定の試算用（読み取り専用）
+#[derive(Accounts)]
+pub struct ViewAccrual<'info> {
+    #[account(
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+}
+
+// ★全体の集計値の確認用（読み取り専用）
+#[derive(Accounts)]
+pub struct ViewEmission<'info> {
+    #[account(seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+}
+
+// ★Configの確認用（読み取り専用）
+#[derive(Accounts)]
+pub struct ViewConfig<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+// ★利用率の確認用（読み取り専用）
+#[derive(Accounts)]
+pub struct ViewUtilization<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+}
+
+// ★状態のエクスポート用（読み取り専用）
+#[derive(Accounts)]
+pub struct ExportState<'info> {
+    #[account(
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+}
+
+#[derive(Accounts)]
+pub struct CloseConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+        close = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+}
+
+// ★Vaultのクローズ用（レントは持ち主へ返却）
+#[derive(Accounts)]
+pub struct CloseVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+        close = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+        close = user,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★渡した場合のみユーザーごとのVault数を減らす
+    #[account(mut, seeds = [b"user_stats", user.key().as_ref()], bump = user_stats.bump)]
+    pub user_stats: Option<Account<'info, UserStats>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+// ★Vaultの統合用（source_vault → vault）
+#[derive(Accounts)]
+pub struct MergeVaults<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), source_vault.name_seed()],
+        bump = source_vault.bump,
+        has_one = user,
+        constraint = source_vault.key() != vault.key() @ ErrorCode::CannotMergeSameVault,
+        close = user,
+    )]
+    pub source_vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), source_vault.name_seed()],
+        bump = source_reward_box.bump,
+        close = user,
+    )]
+    pub source_reward_box: Account<'info, RewardBox>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    // ★報酬計算方式の参照・有効なVault数のカウント用
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★渡した場合のみユーザーごとのVault数を減らす
+    #[account(mut, seeds = [b"user_stats", user.key().as_ref()], bump = user_stats.bump)]
+    pub user_stats: Option<Account<'info, UserStats>>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+}
+
+// ★Vault間のリワードポイント移動用（どちらも user のVaultであること）
+#[derive(Accounts)]
+pub struct MoveRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), from_vault.name_seed()],
+        bump = from_vault.bump,
+        has_one = user,
+        constraint = from_vault.key() != to_vault.key() @ ErrorCode::CannotMoveToSameVault,
+    )]
+    pub from_vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), from_vault.name_seed()],
+        bump = from_reward_box.bump,
+    )]
+    pub from_reward_box: Account<'info, RewardBox>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), to_vault.name_seed()],
+        bump = to_vault.bump,
+        has_one = user,
+    )]
+    pub to_vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), to_vault.name_seed()],
+        bump = to_reward_box.bump,
+    )]
+    pub to_reward_box: Account<'info, RewardBox>,
+
+    // ★報酬計算方式の参照用
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    pub user: Signer<'info>,
+}
+
+// ★集計に含まれていないリワードの計上用（管理者のみ）
+#[derive(Accounts)]
+pub struct MigrateRewards<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★支払い能力の確認用（残高を読むだけ）
+    #[account(seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    pub admin: Signer<'info>,
+}
+
+// ★手数料の免除の購入用
+#[derive(Accounts)]
+pub struct BuyFeeExemption<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★リワードの運用先への振り替え用（運用先の Vault・RewardBox は初回に作る）
+#[derive(Accounts)]
+pub struct MoveRewardsToGrowth<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+        constraint = !is_growth_vault(&vault) @ ErrorCode::CannotMoveToSameVault,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = Vault::SPACE,
+        seeds = [b"vault", user.key().as_ref(), GROWTH_VAULT_NAME],
+        bump
+    )]
+    pub growth_vault: Account<'info, Vault>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = RewardBox::SPACE,
+        seeds = [b"reward", user.key().as_ref(), GROWTH_VAULT_NAME],
+        bump
+    )]
+    pub growth_reward_box: Account<'info, RewardBox>,
+
+    // ★報酬計算方式の参照・有効なVault数のカウント用
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）と入金の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    // ★ユーザーごとのVault数のカウント用（運用先を作るときに数える）
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserStats::SPACE,
+        seeds = [b"user_stats", user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★Vault間の元本移動用（どちらも user のVaultであること）
+#[derive(Accounts)]
+pub struct VaultToVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), from_vault.name_seed()],
+        bump = from_vault.bump,
+        has_one = user,
+        constraint = from_vault.key() != to_vault.key() @ ErrorCode::CannotMoveToSameVault,
+    )]
+    pub from_vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), from_vault.name_seed()],
+        bump = from_reward_box.bump,
+    )]
+    pub from_reward_box: Account<'info, RewardBox>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), to_vault.name_seed()],
+        bump = to_vault.bump,
+        has_one = user,
+    )]
+    pub to_vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), to_vault.name_seed()],
+        bump = to_reward_box.bump,
+    )]
+    pub to_reward_box: Account<'info, RewardBox>,
+
+    // ★報酬計算方式の参照用
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    pub user: Signer<'info>,
 }
 
+// ★リワードの販促残高への振り替え用
+#[derive(Accounts)]
+pub struct SweepRewards<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
 
-// --- Account Structures ---
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    pub user: Signer<'info>,
+}
 
+// ★管理者による created_at の補完用
 #[derive(Accounts)]
-pub struct Initialize<'info> {
+pub struct BackfillCreatedAt<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub admin: Signer<'info>,
+}
+
+// ★Vaultの凍結・解除用（管理者のみ）
+#[derive(Accounts)]
+pub struct SetVaultFrozen<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    pub admin: Signer<'info>,
+}
+
+// ★last_update_time の修復用（管理者のみ）
+#[derive(Accounts)]
+pub struct RepairTimestamp<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub admin: Signer<'info>,
+}
+
+// ★Vaultごとの利率の上乗せ用（管理者のみ、変更前にリワードを確定する）
+#[derive(Accounts)]
+pub struct SetRateDelta<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin,
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(
+        mut,
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    pub admin: Signer<'info>,
+}
+
+// ★Vaultの持ち主だけが行う設定変更用
+#[derive(Accounts)]
+pub struct UpdateVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    pub user: Signer<'info>,
+}
+
+// ★リワードを送り先の RewardBox に届ける用（署名不要）
+#[derive(Accounts)]
+pub struct AccrueToDestination<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        address = vault.reward_destination @ ErrorCode::InvalidRewardDestination,
+    )]
+    pub destination_reward_box: Account<'info, RewardBox>,
+
+    // ★報酬計算方式の参照用
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+}
+
+// ★出金の事前承認用（Vaultの持ち主が署名）
+#[derive(Accounts)]
+#[instruction(delegate: Pubkey)]
+pub struct ApproveWithdrawal<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + 32 + 32 + 8 + 1, // discriminator + vault + delegate + amount + bump
+        seeds = [b"allowance", vault.key().as_ref(), delegate.as_ref()],
+        bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★承認済みの出金用（delegate が署名）
+#[derive(Accounts)]
+pub struct WithdrawWithAllowance<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(
+        mut,
+        seeds = [b"allowance", vault.key().as_ref(), delegate.key().as_ref()],
+        bump = allowance.bump,
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★手数料の受け取り先
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    #[account(mut)]
+    pub delegate: Signer<'info>,
+}
+
+// ★入金元の記録の作成用
+#[derive(Accounts)]
+pub struct EnableDepositSources<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
     #[account(
         init,
         payer = user,
-        space = 8 + 32 + 8 + 1 + 8, // 容量拡張: last_update_time(8byte)を追加
-        seeds = [b"vault", user.key().as_ref()],
+        space = DepositSources::SPACE,
+        seeds = [b"deposit_sources", vault.key().as_ref()],
+        bump
+    )]
+    pub deposit_sources: Account<'info, DepositSources>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★入金元への出金用（Withdraw + 入金元の記録と出金先）
+#[derive(Accounts)]
+pub struct WithdrawToSource<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(
+        seeds = [b"deposit_sources", vault.key().as_ref()],
+        bump = deposit_sources.bump,
+    )]
+    pub deposit_sources: Account<'info, DepositSources>,
+
+    // ★報酬計算方式の参照用
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★手数料の受け取り先
+    #[account(mut, seeds = [b"treasury"], bump = treasury.bump)]
+    pub treasury: Account<'info, Treasury>,
+
+    // ★出金先（記録された入金元であること）
+    #[account(mut)]
+    pub destination: SystemAccount<'info>,
+
+    pub user: Signer<'info>,
+}
+
+// ★受取人の指定用（Vaultの持ち主が署名）
+#[derive(Accounts)]
+pub struct SetBeneficiary<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = 8 + 32 + 32 + 8 + 1, // discriminator + vault + beneficiary + inactivity_period_secs + bump
+        seeds = [b"beneficiary", vault.key().as_ref()],
         bump
     )]
+    pub beneficiary_record: Account<'info, Beneficiary>,
+
+    #[account(mut)]
+    pub user: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+// ★受取人による受け取り用（beneficiary が署名）
+#[derive(Accounts)]
+pub struct ClaimAsBeneficiary<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(
+        seeds = [b"beneficiary", vault.key().as_ref()],
+        bump = beneficiary_record.bump,
+        has_one = beneficiary,
+    )]
+    pub beneficiary_record: Account<'info, Beneficiary>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+}
+
+// ★寄付されたVaultの受け取り用（寄付先が署名）
+#[derive(Accounts)]
+pub struct CharityClaim<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        constraint = vault.donated && vault.donation_charity == charity.key()
+            @ ErrorCode::NotDonationCharity,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(
+        mut,
+        seeds = [b"reward", vault.user.as_ref(), vault.name_seed()],
+        bump = reward_box.bump,
+    )]
+    pub reward_box: Account<'info, RewardBox>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    #[account(mut)]
+    pub charity: Signer<'info>,
+}
+
+// ★紹介者の設定用（持ち主のみ）
+#[derive(Accounts)]
+pub struct SetReferrer<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        has_one = user,
+    )]
     pub vault: Account<'info, Vault>,
 
-    // ★追加: リワード専用Box
     #[account(
-        init,
-        payer = user,
-        space = 8 + 8 + 1, // discriminator + balance + bump
-        seeds = [b"reward", user.key().as_ref()], // seedを変えて別の箱にする
-        bump
+        seeds = [b"vault", referrer_vault.user.as_ref(), referrer_vault.name_seed()],
+        bump = referrer_vault.bump,
+        constraint = referrer_vault.key() != vault.key() @ ErrorCode::InvalidReferrer,
     )]
-    pub reward_box: Account<'info, RewardBox>,
+    pub referrer_vault: Account<'info, Vault>,
 
-    #[account(mut)]
     pub user: Signer<'info>,
-    pub system_program: Program<'info, System>,
 }
 
+// ★送金予約の取り消し用（持ち主のみ）
 #[derive(Accounts)]
-pub struct Deposit<'info> {
+pub struct CancelTransfer<'info> {
     #[account(
         mut,
-        seeds = [b"vault", user.key().as_ref()],
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
         bump = vault.bump,
+        has_one = user,
     )]
     pub vault: Account<'info, Vault>,
 
-    // ★追加: リワード計算のために必要
     #[account(
         mut,
-        seeds = [b"reward", user.key().as_ref()],
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
         bump = reward_box.bump,
     )]
     pub reward_box: Account<'info, RewardBox>,
 
-    #[account(mut)]
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）・元本合計の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
     pub user: Signer<'info>,
-    pub system_program: Program<'info, System>,
 }
 
+// ★送金予約の受け取り用（受取人のみ）
 #[derive(Accounts)]
-pub struct Withdraw<'info> {
+pub struct ClaimTransfer<'info> {
     #[account(
         mut,
-        seeds = [b"vault", user.key().as_ref()],
+        seeds = [b"vault", vault.user.as_ref(), vault.name_seed()],
+        bump = vault.bump,
+        constraint = vault.pending_transfer_recipient == recipient.key()
+            @ ErrorCode::NotTransferRecipient,
+    )]
+    pub vault: Account<'info, Vault>,
+
+    #[account(mut)]
+    pub recipient: Signer<'info>,
+}
+
+// ★追加: 送金用コンテキスト
+#[derive(Accounts)]
+pub struct TransferSol<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
         bump = vault.bump,
         has_one = user,
     )]
@@ -191,42 +6893,125 @@ pub struct Withdraw<'info> {
     // ★追加
     #[account(
         mut,
-        seeds = [b"reward", user.key().as_ref()],
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
         bump = reward_box.bump,
     )]
     pub reward_box: Account<'info, RewardBox>,
 
+    // ★報酬計算方式の参照用
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub user: Signer<'info>, // 実行者（Vaultの持ち主）
+    
+    /// CHECK: 任意の送金先アドレス。所有者がシステムプログラムであることは transfer 内で検証する
+    // ★Vault / RewardBox 自身を送金先にすると会計が壊れるため、所有者チェックより前に明示的に拒否する
+    #[account(
+        mut,
+        constraint = recipient.key() != vault.key() @ ErrorCode::DestinationIsVault,
+        constraint = recipient.key() != reward_box.key() @ ErrorCode::DestinationIsVault,
+    )]
+    pub recipient: UncheckedAccount<'info>, // ★送金先
+
+    // ★送金先の受け取り登録（require_recipient_opt_in が有効なときは必須）
+    #[account(seeds = [b"recipient_opt_in", recipient.key().as_ref()], bump = recipient_opt_in.bump)]
+    pub recipient_opt_in: Option<Account<'info, RecipientOptIn>>,
+}
+
+// ★transfer の受け取り登録用
+#[derive(Accounts)]
+pub struct OptInRecipient<'info> {
+    #[account(
+        init,
+        payer = recipient,
+        space = RecipientOptIn::SPACE,
+        seeds = [b"recipient_opt_in", recipient.key().as_ref()],
+        bump
+    )]
+    pub recipient_opt_in: Account<'info, RecipientOptIn>,
+
+    #[account(mut)]
+    pub recipient: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
-// ★追加: 送金用コンテキスト
+// ★受け取り登録の取り消し用
 #[derive(Accounts)]
-pub struct TransferSol<'info> {
+pub struct OptOutRecipient<'info> {
     #[account(
         mut,
-        seeds = [b"vault", user.key().as_ref()],
+        seeds = [b"recipient_opt_in", recipient.key().as_ref()],
+        bump = recipient_opt_in.bump,
+        close = recipient,
+    )]
+    pub recipient_opt_in: Account<'info, RecipientOptIn>,
+
+    #[account(mut)]
+    pub recipient: Signer<'info>,
+}
+
+// ★Vaultの分割用（新しい名前付きVaultを作る）
+#[derive(Accounts)]
+#[instruction(amount: u64, new_name: String)]
+pub struct SplitVault<'info> {
+    #[account(
+        mut,
+        seeds = [b"vault", user.key().as_ref(), vault.name_seed()],
         bump = vault.bump,
         has_one = user,
     )]
     pub vault: Account<'info, Vault>,
 
-    // ★追加
     #[account(
         mut,
-        seeds = [b"reward", user.key().as_ref()],
+        seeds = [b"reward", user.key().as_ref(), vault.name_seed()],
         bump = reward_box.bump,
     )]
     pub reward_box: Account<'info, RewardBox>,
 
+    #[account(
+        init,
+        payer = user,
+        space = Vault::SPACE,
+        seeds = [b"vault", user.key().as_ref(), new_name.as_bytes()],
+        bump
+    )]
+    pub new_vault: Account<'info, Vault>,
+
+    #[account(
+        init,
+        payer = user,
+        space = RewardBox::SPACE,
+        seeds = [b"reward", user.key().as_ref(), new_name.as_bytes()],
+        bump
+    )]
+    pub new_reward_box: Account<'info, RewardBox>,
+
+    // ★報酬計算方式の参照・有効なVault数のカウント用
+    #[account(mut, seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    // ★未払いリワード（負債）の集計用
+    #[account(mut, seeds = [b"global_emission"], bump = global_emission.bump)]
+    pub global_emission: Account<'info, GlobalEmission>,
+
+    // ★ユーザーごとのVault数のカウント用（最初のVault作成時に作る）
+    #[account(
+        init_if_needed,
+        payer = user,
+        space = UserStats::SPACE,
+        seeds = [b"user_stats", user.key().as_ref()],
+        bump
+    )]
+    pub user_stats: Account<'info, UserStats>,
+
     #[account(mut)]
-    pub user: Signer<'info>, // 実行者（Vaultの持ち主）
-    
-    /// CHECK: 任意の送金先アドレスなのでチェック不要だがSystemAccount推奨
-    #[account(mut)] 
-    pub recipient: SystemAccount<'info>, // ★送金先
-    
+    pub user: Signer<'info>,
     pub system_program: Program<'info, System>,
 }
 
@@ -236,6 +7021,415 @@ pub struct Vault {
     pub balance: u64,
     pub bump: u8,
     pub last_update_time: i64, // ★追加: 最終リワード更新時刻
+    pub pending_withdraw_amount: u64, // ★予約中の出金額（0 = 予約なし）
+    pub pending_withdraw_ready_at: i64, // ★予約出金が実行可能になる時刻
+    pub lock_until: i64, // ★この時刻まで出金・送金できない（0 = ロックなし）
+    pub goal_usd_cents: u64, // ★USD建ての貯金目標（セント、0 = 目標なし）
+    pub created_at: i64, // ★Vaultの作成時刻
+    pub transfer_day: i64, // ★送金回数を数えている日（unix_timestamp / 86400）
+    pub transfers_today: u32, // ★その日の送金回数
+    pub vesting_amount: u64, // ★ベスティング中のトランシェの額（vesting_start 時点）
+    pub vesting_start: i64,  // ★トランシェの解放開始時刻
+    pub vesting_end: i64,    // ★トランシェが全額解放される時刻
+    pub unearning_amount: u64,     // ★リワード対象になるのを待っている元本
+    pub reward_eligible_after: i64, // ★unearning_amount がリワード対象になる時刻
+    pub name: [u8; 32],  // ★名前付きVaultの名前（seedの一部、先頭 name_len バイトが有効）
+    pub name_len: u8,    // ★0 = 基本Vault
+    pub auto_compound: bool,         // ★入金時にリワードを元本へ自動で組み入れる
+    pub compound_interval_secs: i64, // ★自動組み入れの最短間隔（0 = 入金のたび）
+    pub last_compound_at: i64,       // ★最後に組み入れた時刻
+    pub promo_balance: u64, // ★リワードから振り替えた販促残高（lamportの裏付けなし、出金不可）
+    pub version: u8, // ★アカウントのレイアウトのバージョン（VAULT_VERSION 以外は migrate_vault が必要）
+    pub frozen: bool, // ★管理者による凍結（出金・送金不可）
+    pub label: [u8; 32], // ★UI表示用の名前（UTF-8、seeds の name とは別物）
+    pub label_len: u8,
+    pub goal_amount: u64, // ★lamport建ての貯金目標（0 = 目標なし、達成中は goal_bonus_bps の上乗せ）
+    pub reward_destination: Pubkey, // ★リワードの送り先の RewardBox（default = 自分の RewardBox）
+    pub pending_destination_rewards: u64, // ★送り先へ未転送のリワード（accrue_to_destination で届ける）
+    pub closing: bool, // ★request_close で閉鎖予定になったVault（入金不可）
+    pub locked_amount: u64, // ★convert_rewards_to_locked でロックして組み入れた元本（lock_until まで）
+    pub last_deposit_time: i64, // ★最後に deposit した時刻（min_deposit_interval_secs の判定用）
+    pub locked_rate_bps: u64, // ★lock_rate で固定した実効利率（10000 = 毎秒0.01%）
+    pub rate_lock_until: i64, // ★この時刻まで locked_rate_bps でリワードを計算する（0 = 固定なし）
+    pub auto_claim: bool, // ★入金時にリワードをSOLで自動的に受け取る
+    pub donated: bool, // ★donate_vault で寄付済み（持ち主は出金できず、donation_charity が受け取る）
+    pub donation_charity: Pubkey, // ★寄付先（donated のときだけ有効）
+    pub pending_transfer_amount: u64, // ★取り消し期間中の送金予約の額（元本には含まない、0 = 予約なし）
+    pub pending_transfer_recipient: Pubkey, // ★送金予約の受取人
+    pub pending_transfer_claimable_at: i64, // ★この時刻から受取人が受け取れる（それまでは取り消せる）
+    pub referrer: Pubkey, // ★紹介者のVault（default = 紹介者なし）
+    pub self_cap: u64, // ★set_self_cap で持ち主が決めた元本の上限（0 = 上限なし、下げることしかできない）
+    pub flat_reward: bool, // ★利率ではなく入金1回ごとの定額（Config.flat_reward_per_deposit）でリワードを受け取る
+    pub notify_threshold: u64, // ★元本・リワードがこの値に達したら ThresholdCrossedEvent を出す（0 = 通知しない）
+    pub view_authority: Pubkey, // ★持ち主の代わりに閲覧用の getter を呼べるアドレス（default = なし）
+    pub total_deposited: u64, // ★これまでの入金の合計（手数料を除く、get_realized_apy 用）
+    pub total_rewards_claimed: u64, // ★これまでにSOL・トークンで受け取ったリワードの合計（組み入れは含まない）
+    pub rate_delta_bps: i32, // ★Config.default_reward_rate_bps に対する上乗せ（負なら引き下げ、合計は0で下げ止める）
+    pub locked_fraction_bps: u16, // ★入金のうちロックする積立に回す割合（10000 = 全額、0 = 回さない）
+    pub locked_balance: u64, // ★ロック中の積立（balance に含まれ、locked_balance_until まで出金・送金できない）
+    pub locked_balance_until: i64, // ★ロックする積立が出金できるようになる時刻
+    pub total_fees_paid: u64, // ★これまでに払った入金・出金・受け取りの手数料の合計（get_fees_paid 用）
+    pub withdrawal_score: u32, // ★最近の出金回数（出金ごとに+1、withdrawal_score_decay_secs ごとに-1）
+    pub withdrawal_score_at: i64, // ★withdrawal_score の減少を数え始める時刻
+    pub fee_exempt: bool, // ★buy_fee_exemption で手数料の免除を買った（入金・出金・受け取りの手数料がかからない）
+    pub unemitted_reward: u64, // ★確定したがまだ RewardAccruedEvent に出していないリワード（accrued_event_threshold 用）
+    pub last_owner_activity: i64, // ★持ち主が署名した最後の操作の時刻（claim_as_beneficiary の無操作の判定用）
+    // ★将来のフィールド追加用の予備領域（追加時はここを減らしてサイズを保つ）
+    // 使い切るたびに拡張している。旧サイズのVaultは migrate_vault で広げる
+    pub _reserved: [u8; 3],
+}
+
+impl Vault {
+    // discriminator + user + balance + bump + last_update_time + 出金予約(16byte) + lock_until
+    // + goal_usd_cents + created_at + 送金回数(12byte) + ベスティング(24byte) + リワード待機(16byte)
+    // + 名前(33byte) + 自動組み入れ設定(17byte) + promo_balance + version + frozen + ラベル(33byte)
+    // + goal_amount + リワードの送り先(40byte) + closing + locked_amount + last_deposit_time
+    // + 利率の固定(16byte) + auto_claim + 寄付(33byte) + 送金予約(48byte) + referrer + self_cap
+    // + flat_reward + notify_threshold + view_authority + total_deposited + total_rewards_claimed
+    // + rate_delta_bps + ロックする積立(18byte) + total_fees_paid + 出金の頻度(12byte) + fee_exempt
+    // + unemitted_reward + last_owner_activity + 予備領域
+    pub const SPACE: usize =
+        8 + 32 + 8 + 1 + 8 + 16 + 8 + 8 + 8 + 12 + 24 + 16 + 33 + 17 + 8 + 1 + 1 + 33 + 8 + 40 + 1 + 8 + 8 + 16 + 1 + 33
+            + 48 + 32 + 8 + 1 + 8 + 32 + 8 + 8 + 4 + 18 + 8 + 12 + 1 + 8 + 8 + 3;
+
+    // ★PDAのseedに使う名前（基本Vaultは空）
+    pub fn name_seed(&self) -> &[u8] {
+        &self.name[..self.name_len as usize]
+    }
+}
+
+// ★全体設定（管理者と報酬計算方式）
+#[account]
+pub struct Config {
+    pub admin: Pubkey,
+    pub bump: u8,
+    pub reward_strategy: RewardStrategy,
+    pub large_withdraw_threshold: u64, // ★この額を超える出金は予約制（0 = 無効）
+    pub withdraw_delay_secs: i64,      // ★予約から実行までの待機秒数
+    pub deposit_granularity: u64,      // ★入金単位（0 = 制限なし）
+    pub deposit_rounding: DepositRounding, // ★入金単位に合わない場合の扱い
+    pub price_feed: Pubkey,            // ★SOL/USD価格フィード
+    pub max_price_age_secs: i64,       // ★これより古い価格は使わない
+    pub decay_period_secs: i64,        // ★利率が1段階下がる間隔（0 = 減衰なし）
+    pub decay_bps_per_period: u16,     // ★1段階あたりの低下幅
+    pub decay_floor_bps: u16,          // ★下げ止まりの倍率
+    pub max_transfers_per_day: u32,    // ★1日あたりの送金回数上限（0 = 無制限）
+    pub shutdown: bool,                // ★全体停止中（出金のみ可能）
+    pub vault_count: u64,              // ★有効なVaultの数
+    pub vesting_duration_secs: i64,    // ★入金が全額解放されるまでの期間（0 = 無効）
+    pub deposit_fee_bps: u16,          // ★入金手数料（0 = なし）
+    pub withdraw_fee_bps: u16,         // ★出金手数料（0 = なし）
+    pub fee_grace_period_secs: i64,    // ★Vault作成からこの秒数は手数料なし
+    pub reward_start_delay_secs: i64,  // ★入金した元本がリワード対象になるまでの秒数
+    pub early_withdraw_penalty_bps: u16, // ★ロック中の早期出金のペナルティ（0 = 早期出金不可）
+    pub penalty_destination: PenaltyDestination, // ★ペナルティの送り先
+    pub charity: Pubkey,               // ★Charity 指定時の送り先
+    pub min_reserve_ratio_bps: u16,    // ★Treasuryに残すべき原資（未払いリワードに対する比率）
+    pub utilization_half_point: u64,   // ★利率が半分になる元本合計（0 = 利用率による調整なし）
+    pub max_lock_duration_secs: i64,   // ★ロック期間の上限（0 = 上限なし）
+    pub accrue_while_frozen: bool,     // ★凍結中のVaultにもリワードを付与するか
+    pub claim_fee_bps: u16,            // ★リワード受け取り時の手数料（Treasuryに残る）
+    pub prev_reward_strategy: RewardStrategy, // ★直前の報酬計算方式（last_config_update より前の期間に使う）
+    pub last_config_update: i64,       // ★報酬計算方式を最後に変更した時刻
+    pub config_version: u32,           // ★報酬計算方式の変更回数
+    pub min_accrual_interval_secs: i64, // ★リワードを確定させる最小間隔（0 = 毎回）
+    pub goal_bonus_bps: u16,           // ★貯金目標を達成しているVaultへの上乗せ率
+    pub reward_token_decimals: u8,     // ★リワードトークンのMintの decimals
+    pub locked_reward_mode: LockedRewardMode, // ★ロック中の元本（locked_amount）に付けるリワード
+    pub min_deposit_interval_secs: i64, // ★同じVaultへの deposit の最小間隔（0 = 制限なし）
+    pub withdraw_dust_tolerance: u64,   // ★withdraw で全額として扱う残りの上限（0 = 無効）
+    pub accrual_period_secs: i64,       // ★リワードを確定させる単位期間（0 = 秒単位）
+    pub min_accrual_per_interval: u64,  // ★元本があるVaultに1回の確定で付くリワードの最低額
+    pub age_deposit_cap_count: u8,      // ★age_deposit_caps の有効な段階数（0 = 上限なし）
+    pub age_deposit_caps: [AgeDepositCap; MAX_AGE_DEPOSIT_CAPS], // ★Vaultの経過時間ごとの元本の上限
+    pub max_vaults_per_user: u32,       // ★1ユーザーが持てるVaultの数の上限（0 = 無制限）
+    pub transfer_cancel_window_secs: i64, // ★transfer を取り消せる秒数（0 = すぐに送金する）
+    pub referral_level1_bps: u16,       // ★紹介者に付くボーナス率（入金額に対する bps）
+    pub referral_level2_bps: u16,       // ★紹介者の紹介者に付くボーナス率
+    pub max_accrual_per_call: u64,      // ★1回の確定で付くリワードの上限（0 = 上限なし）
+    pub reject_deposit_when_goal_locked: bool, // ★目標達成済みでロック中のVaultへの入金を拒否する
+    pub flat_reward_per_deposit: u64,   // ★定額リワード（Vault.flat_reward）のVaultに入金1回ごとに付くポイント
+    pub reward_forfeit_on_withdraw_bps: u16, // ★withdraw のたびに没収する確定済みリワードの割合（10000 = 全額）
+    pub reward_min_balance: u64, // ★元本がこの額未満のVaultにはリワードが付かない（0 = 下限なし）
+    pub require_recipient_opt_in: bool, // ★transfer の送金先に RecipientOptIn を作ってあることを求める
+    pub default_reward_rate_bps: u16, // ★全Vault共通の利率の倍率（10000 = 基本の利率）。Vault.rate_delta_bps を足して使う
+    pub match_ratio_bps: u16, // ★入金額に対する MatchPool からの上乗せの割合（10000 = 1:1、0 = なし）
+    pub compact_events: bool, // ★VaultTouchedEvent の代わりに CompactVaultTouched のバイト列を出す
+    pub accrual_window_start_secs: u32, // ★リワードが付く時間帯の始まり（UTCの0時からの秒数）
+    pub accrual_window_end_secs: u32,   // ★リワードが付く時間帯の終わり（start == end なら制限なし）
+    pub large_deposit_bonus_bps: u16, // ★元本を2倍より大きくする入金に付くボーナス（入金額に対する割合）
+    pub growth_rate_bonus_bps: u16, // ★リワードの運用先のVaultの利率の上乗せ（rate_delta_bps に足す）
+    pub max_accrual_lookback_secs: i64, // ★1回の確定でさかのぼる経過秒数の上限（古い分は切り捨て、0 = 上限なし）
+    pub withdrawal_score_penalty_bps: u16, // ★Vault.withdrawal_score 1あたりの利率の引き下げ（0 = なし）
+    pub withdrawal_score_decay_secs: i64,  // ★この秒数ごとに withdrawal_score が1減る
+    pub fee_exemption_price: u64, // ★Vault.fee_exempt にするための一度きりの支払額（0 = 販売しない）
+    pub accrued_event_threshold: u64, // ★RewardAccruedEvent を出す、まとめた確定額の下限（0 = 毎回出す）
+}
+
+impl Config {
+    // discriminator + admin + bump + reward_strategy + 出金キュー設定(16byte) + 入金単位設定(9byte)
+    // + 価格フィード設定(40byte) + 減衰設定(12byte) + max_transfers_per_day + shutdown + vault_count
+    // + vesting_duration_secs + 手数料設定(12byte) + reward_start_delay_secs + 早期出金ペナルティ設定(35byte)
+    // + min_reserve_ratio_bps + utilization_half_point + max_lock_duration_secs + accrue_while_frozen
+    // + claim_fee_bps + 方式の変更履歴(13byte) + min_accrual_interval_secs + goal_bonus_bps
+    // + reward_token_decimals + locked_reward_mode + min_deposit_interval_secs + withdraw_dust_tolerance
+    // + accrual_period_secs + min_accrual_per_interval + 経過時間ごとの上限(1 + 16byte × MAX_AGE_DEPOSIT_CAPS)
+    // + max_vaults_per_user + transfer_cancel_window_secs + 紹介ボーナス率(4byte) + max_accrual_per_call
+    // + reject_deposit_when_goal_locked + flat_reward_per_deposit + reward_forfeit_on_withdraw_bps
+    // + reward_min_balance + require_recipient_opt_in + default_reward_rate_bps + match_ratio_bps
+    // + compact_events + リワードが付く時間帯(8byte) + large_deposit_bonus_bps + growth_rate_bonus_bps
+    // + max_accrual_lookback_secs + 出金の頻度による引き下げ(10byte) + fee_exemption_price
+    // + accrued_event_threshold
+    pub const SPACE: usize = 8 + 32 + 1 + 1 + 16 + 9 + 40 + 12 + 4 + 1 + 8 + 8 + 12 + 8 + 35 + 2 + 8 + 8 + 1
+        + 2 + 13 + 8 + 2 + 1 + 1 + 8 + 8 + 8 + 8 + 1 + 16 * MAX_AGE_DEPOSIT_CAPS + 4 + 8 + 4 + 8
+        + 1 + 8 + 2 + 8 + 1 + 2 + 2 + 1 + 8 + 2 + 2 + 8 + 10 + 8 + 8;
+}
+
+// ★Vaultの経過時間ごとの元本の上限の1段階
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct AgeDepositCap {
+    pub min_age_secs: i64, // 作成からこの秒数以上経ったVaultに適用する
+    pub max_balance: u64,  // 入金後の元本（balance）の上限
+}
+
+// ★報酬計算方式
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum RewardStrategy {
+    Linear, // 残高 × 利率
+    Tiered, // しきい値超過分は倍率アップ
+    Capped, // 報酬対象の残高に上限
+    Continuous, // 連続複利: 残高 × (e^(利率 × 経過秒数) - 1)
+}
+
+// ★定期入金のスケジュール（このアカウント自体が入金の原資を保管する）
+#[account]
+pub struct RecurringDeposit {
+    pub user: Pubkey,
+    pub amount: u64,          // 1回あたりの入金額
+    pub interval_secs: i64,   // 入金間隔
+    pub next_deposit_at: i64, // 次に入金できる時刻
+    pub bump: u8,
+}
+
+// ★出金の事前承認（Vault × delegate ごと）
+#[account]
+pub struct Allowance {
+    pub vault: Pubkey,
+    pub delegate: Pubkey,
+    pub amount: u64, // 残りの出金可能額
+    pub bump: u8,
+}
+
+// ★Vaultの受取人（Vaultごと）
+#[account]
+pub struct Beneficiary {
+    pub vault: Pubkey,
+    pub beneficiary: Pubkey,
+    pub inactivity_period_secs: i64, // 持ち主の操作がこの秒数を超えて途絶えたら受け取れる
+    pub bump: u8,
+}
+
+// ★goal_progress_usd の戻り値
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct GoalProgress {
+    pub balance_usd_cents: u64,
+    pub goal_usd_cents: u64,
+    pub progress_bps: u16, // 達成率（10000 = 100%）
+}
+
+// ★peek の戻り値
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct VaultPeek {
+    pub exists: bool,
+    pub balance: u64,
+    pub rent_exempt: bool,
+}
+
+// ★get_rate_info の戻り値（倍率はすべて 10000 = 等倍）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct RateInfo {
+    pub earning_balance: u64, // リワード対象の元本（promo_balance を含み、待機中の元本を除く）
+    pub strategy_bps: u64,
+    pub decay_bps: u64,
+    pub rate_bps: u64, // default_reward_rate_bps + rate_delta_bps（運用先のVaultは + growth_rate_bonus_bps）
+    pub utilization_bps: u64,
+    pub goal_bps: u64,
+    pub withdrawal_bps: u64, // 出金の頻度による引き下げ（withdrawal_score_penalty_bps）
+    pub effective_bps: u64, // 上の倍率の積（停止中・凍結中は0）
+}
+
+// ★next_accrual_preview の戻り値
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AccrualPreview {
+    pub amount: u64,          // 今確定した場合に付くリワード（ポイント）
+    pub secs_until_next: i64, // 次に確定額が増えるまでの秒数
+}
+
+// ★get_vault_summary の戻り値
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct VaultSummary {
+    pub balance: u64,
+    pub reward_balance: u64, // RewardBox の残高（確定済みのリワード）
+    pub locked_amount: u64,
+    pub lock_until: i64,
+    pub goal_amount: u64,
+    pub last_update_time: i64,
+}
+
+// ★audit_liabilities の戻り値
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LiabilityAudit {
+    pub reward_box_total: u64,        // 渡した RewardBox の残高の合計（carried_total を含む）
+    pub outstanding_liabilities: u64, // GlobalEmission.outstanding_liabilities
+    pub discrepancy: i64,             // outstanding_liabilities - reward_box_total（0 = 一致）
+}
+
+// ★get_fee_schedule の戻り値（bps はすべて 10000 = 100%）
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FeeSchedule {
+    pub deposit_fee_bps: u16,
+    pub withdraw_fee_bps: u16, // 委任先の出金にもかかる
+    pub claim_fee_bps: u16,
+    pub early_withdraw_penalty_bps: u16, // ロック中の早期出金（0 = 早期出金不可）
+    pub fee_grace_period_secs: i64,      // Vault作成からこの秒数は入金・出金手数料なし
+}
+
+// ★年率の換算に使う1年の秒数（365日）
+const SECS_PER_YEAR: u128 = 365 * 86400;
+
+// ★リワードが付く時間帯（Config.accrual_window_*）の1日の秒数
+const SECS_PER_DAY: i64 = 86400;
+
+// ★将来残高の試算で扱える最大日数（約10年）
+const MAX_PROJECTION_DAYS: u64 = 3650;
+
+// ★Pyth PriceUpdateV2 アカウントのレイアウト（先頭8byteはdiscriminator）
+const PRICE_UPDATE_V2_DISCRIMINATOR: [u8; 8] = [34, 241, 35, 99, 157, 126, 244, 205];
+
+#[derive(AnchorDeserialize)]
+pub struct PriceUpdateV2 {
+    pub write_authority: Pubkey,
+    pub verification_level: VerificationLevel,
+    pub price_message: PriceFeedMessage,
+    pub posted_slot: u64,
+}
+
+#[derive(AnchorDeserialize)]
+pub enum VerificationLevel {
+    Partial { num_signatures: u8 },
+    Full,
+}
+
+#[derive(AnchorDeserialize)]
+pub struct PriceFeedMessage {
+    pub feed_id: [u8; 32],
+    pub price: i64,
+    pub conf: u64,
+    pub exponent: i32,
+    pub publish_time: i64,
+    pub prev_publish_time: i64,
+    pub ema_price: i64,
+    pub ema_conf: u64,
+}
+
+// ★ThresholdCrossedEvent で何がしきい値に達したか
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ThresholdKind {
+    Balance, // 元本（入金）
+    Rewards, // リワード残高（確定・定額リワード）
+}
+
+// ★入金単位に合わない入金の扱い
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum DepositRounding {
+    Reject,    // 拒否する
+    RoundDown, // 端数を切り捨てて入金する
+}
+
+// ★ロック中の元本（locked_amount）へのリワードの付け方
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum LockedRewardMode {
+    Base,    // 他の元本と同じ利率
+    Boosted, // LOCK_BONUS_BPS 倍の利率
+    None,    // ロック中はリワードなし
+}
+
+// ★早期出金ペナルティの送り先
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PenaltyDestination {
+    Treasury, // Treasury（リワード原資）へ
+    Burn,     // 焼却アドレスへ送って消滅させる
+    Charity,  // Config.charity へ寄付
+}
+
+// ★リワードの原資となるSOLを保管するアカウント
+#[account]
+pub struct Treasury {
+    pub bump: u8,
+    pub total_funded: u64, // これまでに投入された合計額
+}
+
+// ★プロトコル全体のリワード集計
+#[account]
+pub struct GlobalEmission {
+    pub bump: u8,
+    pub outstanding_liabilities: u64, // 全RewardBoxの未払いリワードの合計
+    pub total_deposits: u64,          // ★全Vaultの元本（balance）の合計
+    pub total_deposit_fees: u64,      // ★入金手数料としてTreasuryに入った合計
+}
+
+// ★リワードの多いVaultのリーダーボード（entries はリワードの多い順、空きは vault = default）
+#[account]
+pub struct Leaderboard {
+    pub bump: u8,
+    pub entries: [LeaderboardEntry; LEADERBOARD_SIZE],
+}
+
+impl Leaderboard {
+    // discriminator + bump + entries（vault + rewards）
+    pub const SPACE: usize = 8 + 1 + (32 + 8) * LEADERBOARD_SIZE;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct LeaderboardEntry {
+    pub vault: Pubkey,
+    pub rewards: u64, // 最後に記録したときの RewardBox.balance
+}
+
+// ★transfer の受け取り登録（ウォレットごとに1つ、存在すること自体が登録の印）
+#[account]
+pub struct RecipientOptIn {
+    pub bump: u8,
+}
+
+impl RecipientOptIn {
+    // discriminator + bump
+    pub const SPACE: usize = 8 + 1;
+}
+
+// ★入金の上乗せ（マッチング）用のプール（スポンサーごとに1つ、レント免除額を超える lamports が原資）
+#[account]
+pub struct MatchPool {
+    pub bump: u8,
+    pub sponsor: Pubkey,
+    pub total_matched: u64, // これまでにVaultへ上乗せした合計
+}
+
+impl MatchPool {
+    // discriminator + bump + sponsor + total_matched
+    pub const SPACE: usize = 8 + 1 + 32 + 8;
+}
+
+// ★ユーザーごとの集計（ユーザーごとに1つ）
+#[account]
+pub struct UserStats {
+    pub bump: u8,
+    pub vault_count: u32, // 持っているVaultの数（UserStats 導入前に作ったVaultは数えない）
+}
+
+impl UserStats {
+    // discriminator + bump + vault_count
+    pub const SPACE: usize = 8 + 1 + 4;
 }
 
 // ★追加: リワードBOXのアカウント構造
@@ -243,4 +7437,523 @@ pub struct Vault {
 pub struct RewardBox {
     pub balance: u64, // 貯まったリワードポイント
     pub bump: u8,
-}
\ No newline at end of file
+    pub version: u8, // ★アカウントのレイアウトのバージョン
+    pub reward_acc: u128, // ★1ポイント未満の端数（1/REWARD_ACC_SCALE ポイント単位、次の確定に繰り越す）
+    pub reward_owner: Pubkey, // ★リワードの持ち主（default = Vaultの持ち主）
+    pub liability_recorded: bool, // ★作成時から（または migrate_rewards で）残高を未払いリワードの集計に含めている
+    // ★将来のフィールド追加用の予備領域（追加時はここを減らしてサイズを保つ）
+    pub _reserved: [u8; 14],
+}
+
+impl RewardBox {
+    // discriminator + balance + bump + version + reward_acc + reward_owner + liability_recorded + 予備領域
+    // ★リワード履歴を有効にした RewardBox は、この後ろに RewardHistory が続く
+    pub const SPACE: usize = 8 + 8 + 1 + 1 + 16 + 32 + 1 + 14;
+}
+
+// ★リワード履歴（直近 REWARD_HISTORY_LEN 件の確定のリングバッファ）
+// enable_reward_history で RewardBox の末尾（RewardBox::SPACE 以降）に追加する。
+// 既存の RewardBox のレイアウトには含めず、末尾のデータの有無で有効かどうかを判定する
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct RewardHistory {
+    pub next: u8, // 次に書き込む位置
+    pub len: u8,  // 記録済みの件数（最大 REWARD_HISTORY_LEN）
+    pub entries: [RewardHistoryEntry; REWARD_HISTORY_LEN],
+}
+
+impl RewardHistory {
+    // next + len + entries（timestamp + amount）
+    pub const SPACE: usize = 1 + 1 + (8 + 8) * REWARD_HISTORY_LEN;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RewardHistoryEntry {
+    pub timestamp: i64, // 確定した時刻（last_update_time）
+    pub amount: u64,    // 付いたリワードポイント
+}
+
+// ★入金元の記録（Vaultごとに1つ、直近 MAX_DEPOSIT_SOURCES 件のリングバッファ）
+#[account]
+pub struct DepositSources {
+    pub bump: u8,
+    pub vault: Pubkey,
+    pub next: u8, // 次に書き込む位置
+    pub len: u8,  // 記録済みの件数（最大 MAX_DEPOSIT_SOURCES）
+    pub sources: [Pubkey; MAX_DEPOSIT_SOURCES],
+}
+
+impl DepositSources {
+    // discriminator + bump + vault + next + len + sources
+    pub const SPACE: usize = 8 + 1 + 32 + 1 + 1 + 32 * MAX_DEPOSIT_SOURCES;
+
+    pub fn contains(&self, source: &Pubkey) -> bool {
+        self.sources[..self.len as usize].contains(source)
+    }
+}
+
+// ★残高の記録（Vaultごとに1つ、直近 BALANCE_SNAPSHOT_LEN 件のリングバッファ）
+#[account]
+pub struct BalanceSnapshots {
+    pub bump: u8,
+    pub next: u8, // 次に書き込む位置
+    pub len: u8,  // 記録済みの件数（最大 BALANCE_SNAPSHOT_LEN）
+    pub entries: [BalanceSnapshot; BALANCE_SNAPSHOT_LEN],
+}
+
+impl BalanceSnapshots {
+    // discriminator + bump + next + len + entries（timestamp + balance + reward_box_balance）
+    pub const SPACE: usize = 8 + 1 + 1 + 1 + (8 + 8 + 8) * BALANCE_SNAPSHOT_LEN;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct BalanceSnapshot {
+    pub timestamp: i64, // 記録した時刻
+    pub balance: u64,
+    pub reward_box_balance: u64,
+}
+
+// --- Events ---
+//
+// ★発行順序について
+// 元本を動かすインストラクションは、必ず最初に update_rewards でリワードを確定させてから元本を変更する。
+// そのため1つのインストラクション内では RewardAccruedEvent（あれば）が常に VaultTouchedEvent より先に出る。
+// インデクサはログの順に適用すれば、リワード確定時点の残高 → 変更後の残高の順に状態を再現できる。
+//
+// ★イベントだけでの状態の再現について
+// - vault.balance      = BalanceDeltaEvent.delta の合計
+// - reward_box.balance = RewardAccruedEvent.amount + RewardDeltaEvent.delta の合計 + vault.unemitted_reward
+// （Vault作成時はどちらも0。イベントを発行しない変更を加えるときは、ここも必ず更新すること）
+// - accrued_event_threshold でまとめて出す設定では、出していない確定額（unemitted_reward）の分だけ
+//   イベントの合計が足りない。close_vault・merge_vaults（閉じる側）と、閾値を下げるときに渡したVaultでは
+//   その場で RewardAccruedEvent を出して0に戻す
+// - reward_destination を設定したVaultでは、イベントの vault は送り元、reward_box_balance は送り先の残高になる。
+//   送り先への未転送分（pending_destination_rewards）はイベントを出さず、届けたときに RewardDeltaEvent が出る
+
+// ★元本またはリワード残高が notify_threshold に達したときに発行される（状態の再現には使わない）
+#[event]
+pub struct ThresholdCrossedEvent {
+    pub vault: Pubkey,
+    pub kind: ThresholdKind,
+    pub threshold: u64,
+    pub value: u64, // 達した後の値
+}
+
+// ★sweep_dust で端数のリワードを整理したときに発行される（各 RewardBox の増減は RewardDeltaEvent で出る）
+#[event]
+pub struct DustSweptEvent {
+    pub count: u32, // 0にした RewardBox の数
+    pub total: u64, // 未払いリワードの合計から除いたポイント
+}
+
+// ★入金に MatchPool からの上乗せが付いたときに発行される（元本の増加は BalanceDeltaEvent でも出る）
+#[event]
+pub struct DepositMatchedEvent {
+    pub vault: Pubkey,
+    pub match_pool: Pubkey,
+    pub amount: u64,
+}
+
+// ★大口入金のボーナスが付いたときに発行される（RewardBox の増加は RewardDeltaEvent でも出る）
+#[event]
+pub struct LargeDepositBonusEvent {
+    pub vault: Pubkey,
+    pub deposit: u64,          // 手数料を引いた入金額
+    pub previous_balance: u64, // 入金前の元本
+    pub bonus: u64,
+}
+
+// ★migrate_rewards で RewardBox のリワードを未払いリワードの集計に計上したときに発行される
+#[event]
+pub struct RewardsMigratedEvent {
+    pub reward_box: Pubkey,
+    pub points: u64,                  // 計上したポイント（= RewardBox の残高）
+    pub outstanding_liabilities: u64, // 計上後の未払いリワードの合計
+}
+
+// ★buy_fee_exemption で手数料の免除を買ったときに発行される
+#[event]
+pub struct FeeExemptionPurchasedEvent {
+    pub vault: Pubkey,
+    pub price: u64, // Treasuryへ払った額
+}
+
+// ★move_rewards_to_growth でリワードを運用先のVaultへ移したときに発行される
+#[event]
+pub struct RewardsMovedToGrowthEvent {
+    pub vault: Pubkey,        // リワードの移動元
+    pub growth_vault: Pubkey, // 運用先
+    pub amount: u64,          // 移したリワード（= 運用先の元本の増加）
+}
+
+// ★repair_timestamp で未来の last_update_time を戻したときに発行される
+#[event]
+pub struct TimestampRepairedEvent {
+    pub vault: Pubkey,
+    pub previous: i64, // 戻す前の last_update_time
+    pub repaired: i64, // 戻した後の last_update_time（その時点の時刻）
+}
+
+// ★Vault PDAの余剰SOLを持ち主に返したときに発行される
+#[event]
+pub struct SurplusSweptEvent {
+    pub vault: Pubkey,
+    pub amount: u64,
+}
+
+// ★donate_vault でVaultが寄付になったときに発行される
+#[event]
+pub struct VaultDonatedEvent {
+    pub vault: Pubkey,
+    pub charity: Pubkey,
+}
+
+// ★transfer_reward_ownership でリワードの持ち主が変わったときに発行される
+#[event]
+pub struct RewardOwnershipTransferredEvent {
+    pub vault: Pubkey,
+    pub previous_owner: Pubkey,
+    pub new_owner: Pubkey,
+}
+
+// ★convert_vault で元本をトークンに替えたときに発行される
+#[event]
+pub struct VaultConvertedEvent {
+    pub vault: Pubkey,
+    pub sol_amount: u64, // 引き出した元本（手数料を含む）
+    pub fee: u64,
+    pub mint: Pubkey,
+    pub amount_out: u64, // token_vault に入れたトークンの量
+}
+
+// ★リワードが確定したときに発行される（付与額が0のときは発行しない）
+// accrued_event_threshold があれば、出していない確定額の合計が閾値に達したときだけ合計額で発行する
+#[event]
+pub struct RewardAccruedEvent {
+    pub vault: Pubkey,
+    pub amount: u64, // 前回の発行からの確定額の合計
+    pub reward_box_balance: u64,
+    pub last_update_time: i64,
+}
+
+// ★リワードをSOLで受け取ったときに発行される（amount = 受け取ったポイント、fee = Treasuryに残った手数料）
+#[event]
+pub struct RewardClaimedEvent {
+    pub vault: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+}
+
+// ★Treasuryの原資不足で一部だけ支払ったときに発行される（remaining = RewardBoxに残った未払いポイント）
+#[event]
+pub struct PartialClaimEvent {
+    pub vault: Pubkey,
+    pub paid: u64,
+    pub remaining: u64,
+}
+
+// ★元本が増減するたびに発行される
+#[event]
+pub struct BalanceDeltaEvent {
+    pub vault: Pubkey,
+    pub delta: i64,
+    pub balance: u64,
+}
+
+// ★リワード確定以外（変換・組み入れ・統合など）でリワードポイントが増減するたびに発行される
+#[event]
+pub struct RewardDeltaEvent {
+    pub vault: Pubkey,
+    pub delta: i64,
+    pub reward_box_balance: u64,
+}
+
+// ★入金・出金・送金のたびに発行される（リワードが0でも発行）
+#[event]
+pub struct VaultTouchedEvent {
+    pub vault: Pubkey,
+    pub balance: u64,
+    pub reward_box_balance: u64,
+    pub last_update_time: i64,
+}
+
+// ★VaultTouchedEvent の固定レイアウト版（Config.compact_events が有効なときに sol_log_data で出す）
+// tag(1) + vault(32) + balance(8) + reward_box_balance(8) + last_update_time(8) の57byte、数値はリトルエンディアン。
+// Anchor イベントの discriminator（8byte）と Borsh の代わりに1byteの tag で見分ける
+pub struct CompactVaultTouched {
+    pub vault: Pubkey,
+    pub balance: u64,
+    pub reward_box_balance: u64,
+    pub last_update_time: i64,
+}
+
+impl CompactVaultTouched {
+    pub const LEN: usize = 1 + 32 + 8 + 8 + 8;
+
+    pub fn encode(&self) -> [u8; Self::LEN] {
+        let mut data = [0u8; Self::LEN];
+        data[0] = COMPACT_VAULT_TOUCHED_TAG;
+        data[1..33].copy_from_slice(self.vault.as_ref());
+        data[33..41].copy_from_slice(&self.balance.to_le_bytes());
+        data[41..49].copy_from_slice(&self.reward_box_balance.to_le_bytes());
+        data[49..57].copy_from_slice(&self.last_update_time.to_le_bytes());
+        data
+    }
+}
+
+// --- Errors ---
+
+#[error_code]
+pub enum ErrorCode {
+    #[msg("送金先の残高がレント免除額に届きません")]
+    RecipientNotRentExempt,
+    #[msg("残高が不足しています")]
+    InsufficientBalance,
+    #[msg("高額出金は request_withdraw で予約してください")]
+    WithdrawRequiresQueue,
+    #[msg("すでに出金予約があります")]
+    PendingWithdrawExists,
+    #[msg("出金予約がありません")]
+    NoPendingWithdraw,
+    #[msg("予約出金はまだ実行できません")]
+    WithdrawNotReady,
+    #[msg("待機秒数が不正です")]
+    InvalidWithdrawDelay,
+    #[msg("Vaultはロック中です")]
+    VaultLocked,
+    #[msg("ロック期間が不正です")]
+    InvalidLockDuration,
+    #[msg("リワードがありません")]
+    NoRewards,
+    #[msg("Treasuryの残高が不足しています")]
+    InsufficientTreasury,
+    #[msg("Vaultのlamportsと残高が一致しません")]
+    VaultBalanceMismatch,
+    #[msg("最終更新時刻が未来になっています")]
+    FutureUpdateTime,
+    #[msg("RewardBoxがレント免除を満たしていません")]
+    RewardBoxNotRentExempt,
+    #[msg("入金額が入金単位の倍数ではありません")]
+    DepositNotMultipleOfGranularity,
+    #[msg("入金額が小さすぎます")]
+    DepositTooSmall,
+    #[msg("定期入金の間隔が不正です")]
+    InvalidRecurringInterval,
+    #[msg("登録された定期入金額と一致しません")]
+    RecurringAmountMismatch,
+    #[msg("まだ定期入金の時刻ではありません")]
+    CrankTooEarly,
+    #[msg("定期入金の原資が不足しています")]
+    InsufficientRecurringFunds,
+    #[msg("Vaultはすでに最小サイズです")]
+    NothingToCompact,
+    #[msg("価格フィードが不正です")]
+    InvalidPriceFeed,
+    #[msg("価格が古すぎます")]
+    StalePrice,
+    #[msg("価格の有効期限が不正です")]
+    InvalidPriceAge,
+    #[msg("減衰パラメータが不正です")]
+    InvalidDecayParams,
+    #[msg("送金先はシステムプログラム所有のアカウントである必要があります")]
+    InvalidRecipientOwner,
+    #[msg("本日の送金回数の上限に達しました")]
+    TransferLimitExceeded,
+    #[msg("正規（canonical）でないbumpが保存されています")]
+    NonCanonicalBump,
+    #[msg("プロトコルは停止中です（出金のみ可能）")]
+    ProtocolShutdown,
+    #[msg("試算できる日数を超えています")]
+    InvalidProjectionDays,
+    #[msg("有効なVaultが残っています")]
+    VaultsStillActive,
+    #[msg("Vaultに残高があります")]
+    VaultNotEmpty,
+    #[msg("未受け取りのリワードがあります")]
+    UnclaimedRewards,
+    #[msg("まだ解放されていない額が含まれています")]
+    AmountNotVested,
+    #[msg("手数料の設定が不正です")]
+    InvalidFee,
+    #[msg("出金・送金先にVault自身は指定できません")]
+    DestinationIsVault,
+    #[msg("Vaultはすでに最新のサイズです")]
+    NothingToMigrate,
+    #[msg("Vaultの名前は1〜32バイトで指定してください")]
+    InvalidVaultName,
+    #[msg("同じVault同士は統合できません")]
+    CannotMergeSameVault,
+    #[msg("計算がオーバーフローしました")]
+    MathOverflow,
+    #[msg("早期出金は無効です")]
+    EarlyWithdrawDisabled,
+    #[msg("Vaultはロックされていません（通常の出金を使ってください）")]
+    VaultNotLocked,
+    #[msg("ペナルティの送り先が不正です")]
+    InvalidPenaltyDestination,
+    #[msg("Treasuryの原資が未払いリワードを下回るため引き出せません")]
+    InsufficientReserve,
+    #[msg("準備率は10000（100%）以上で指定してください")]
+    InvalidReserveRatio,
+    #[msg("時刻の指定が不正です")]
+    InvalidTimestamp,
+    #[msg("created_at はすでに設定されています")]
+    CreatedAtAlreadySet,
+    #[msg("組み入れ間隔の指定が不正です")]
+    InvalidCompoundInterval,
+    #[msg("未対応のアカウントバージョンです（migrate_vault を実行してください）")]
+    UnsupportedAccountVersion,
+    #[msg("承認された出金額を超えています")]
+    AllowanceExceeded,
+    #[msg("ロック期間が上限を超えています")]
+    LockDurationTooLong,
+    #[msg("Vaultの持ち主ではありません")]
+    NotVaultOwner,
+    #[msg("Vaultは凍結中です")]
+    VaultFrozen,
+    #[msg("リワードポイントが足りません")]
+    InsufficientRewards,
+    #[msg("移動元と移動先が同じVaultです")]
+    CannotMoveToSameVault,
+    #[msg("ラベルは32byte以内にしてください")]
+    InvalidLabel,
+    #[msg("リワード確定の間隔が不正です")]
+    InvalidAccrualInterval,
+    #[msg("目標達成ボーナスは10000bps以下にしてください")]
+    InvalidGoalBonus,
+    #[msg("リワードトークンの decimals は9以下にしてください")]
+    InvalidTokenDecimals,
+    #[msg("現在時刻（Clock sysvar）を取得できませんでした")]
+    ClockUnavailable,
+    #[msg("remaining_accounts は (Vault, RewardBox) の組で渡してください")]
+    InvalidVaultPairs,
+    #[msg("一度に処理できる (Vault, RewardBox) の組数を超えています")]
+    TooManyVaultPairs,
+    #[msg("送り先へ未転送のリワードがあります（先に accrue_to_destination を呼んでください）")]
+    PendingDestinationRewards,
+    #[msg("リワードの送り先の RewardBox が一致しません")]
+    InvalidRewardDestination,
+    #[msg("閉鎖予定のVaultには入金できません（cancel_close で取り消せます）")]
+    VaultClosing,
+    #[msg("Vault PDAに余剰のSOLはありません")]
+    NoSurplus,
+    #[msg("無操作期間は1秒以上で指定してください")]
+    InvalidInactivityPeriod,
+    #[msg("持ち主の無操作期間がまだ経過していません")]
+    OwnerStillActive,
+    #[msg("入金の最小間隔は0以上で指定してください")]
+    InvalidDepositInterval,
+    #[msg("前回の入金から最小間隔が経っていません")]
+    DepositTooFrequent,
+    #[msg("指定された期限を過ぎています")]
+    DeadlineExceeded,
+    #[msg("未払いのリワードが残っています")]
+    OutstandingLiabilities,
+    #[msg("端数として扱える額が上限を超えています")]
+    InvalidDustTolerance,
+    #[msg("スワッププログラムが不正です")]
+    InvalidSwapProgram,
+    #[msg("スワップの出力が min_out に届きません")]
+    SlippageExceeded,
+    #[msg("スワップの命令データが長すぎます")]
+    SwapRouteTooLong,
+    #[msg("スワップに渡すアカウントが多すぎます")]
+    TooManySwapAccounts,
+    #[msg("リワードの持ち主ではありません")]
+    NotRewardOwner,
+    #[msg("リワードの持ち主が別のアカウントです")]
+    RewardsOwnedByOther,
+    #[msg("経過時間ごとの上限の設定が不正です")]
+    InvalidAgeDepositCaps,
+    #[msg("Vaultの経過時間に応じた元本の上限を超えます")]
+    AgeDepositCapExceeded,
+    #[msg("寄付済みのVaultです")]
+    VaultDonated,
+    #[msg("寄付先が不正です")]
+    InvalidCharity,
+    #[msg("このVaultの寄付先ではありません")]
+    NotDonationCharity,
+    #[msg("1ユーザーが持てるVaultの数の上限に達しています")]
+    VaultLimitExceeded,
+    #[msg("入金額の数とVaultの組数が一致しません")]
+    DepositAmountsMismatch,
+    #[msg("取り消し期間の設定が不正です")]
+    InvalidCancelWindow,
+    #[msg("送金予約が既にあります")]
+    PendingTransferExists,
+    #[msg("送金予約がありません")]
+    NoPendingTransfer,
+    #[msg("送金予約の取り消し期間が過ぎています")]
+    TransferCancelWindowClosed,
+    #[msg("送金予約はまだ受け取れません")]
+    TransferNotClaimable,
+    #[msg("送金予約の受取人ではありません")]
+    NotTransferRecipient,
+    #[msg("紹介ボーナス率が上限を超えています")]
+    InvalidReferralBonus,
+    #[msg("紹介者は既に設定されています")]
+    ReferrerAlreadySet,
+    #[msg("紹介者には他のユーザーのVaultを指定してください")]
+    InvalidReferrer,
+    #[msg("紹介が循環しています")]
+    ReferralCycle,
+    #[msg("紹介者の Vault / RewardBox が渡されていません")]
+    MissingReferrerAccounts,
+    #[msg("元本の上限は今より低い値にしか変更できません")]
+    SelfCapNotLowered,
+    #[msg("自分で決めた元本の上限を超えます")]
+    SelfCapExceeded,
+    #[msg("リワード履歴は既に有効です")]
+    RewardHistoryAlreadyEnabled,
+    #[msg("リワード履歴は有効になっていません")]
+    RewardHistoryNotEnabled,
+    #[msg("Treasuryへは送金できません（fund_treasury を使ってください）")]
+    RecipientIsTreasury,
+    #[msg("入金するトークンのMintがリワードトークンと一致しません")]
+    FundingMintMismatch,
+    #[msg("貯金目標を達成していてロック中のVaultには入金できません")]
+    GoalLockedDepositRejected,
+    #[msg("同じ RewardBox が重複して渡されています")]
+    DuplicateAuditAccount,
+    #[msg("このVaultを閲覧する権限がありません")]
+    ViewerNotAuthorized,
+    #[msg("整理できる端数のリワードがありません")]
+    NoDust,
+    #[msg("送金先が受け取りを登録していません")]
+    RecipientNotOptedIn,
+    #[msg("上乗せの割合が範囲外です")]
+    InvalidMatchRatio,
+    #[msg("ロックする積立の割合が範囲外です")]
+    InvalidLockedFraction,
+    #[msg("ロックする積立の期限が不正です（ロック中は早められません）")]
+    InvalidLockedSavingsTerm,
+    #[msg("ロック中の積立は期限まで出金できません")]
+    LockedSavingsNotWithdrawable,
+    #[msg("last_update_time は未来になっていません")]
+    TimestampNotInFuture,
+    #[msg("リワードが付く時間帯は0時からの秒数（86400未満）で指定してください")]
+    InvalidAccrualWindow,
+    #[msg("一括作成には (user, Vault, RewardBox, UserStats) の組を上限数まで渡してください")]
+    InvalidBatchInitAccounts,
+    #[msg("大口入金のボーナスの割合が範囲外です")]
+    InvalidDepositBonus,
+    #[msg("リワードの運用先のVaultには入金できません")]
+    GrowthVaultDepositRejected,
+    #[msg("出金の頻度による引き下げの設定が不正です")]
+    InvalidWithdrawalScorePenalty,
+    #[msg("手数料の免除は販売されていません")]
+    FeeExemptionNotForSale,
+    #[msg("このVaultはすでに手数料が免除されています")]
+    AlreadyFeeExempt,
+    #[msg("この RewardBox のリワードはすでに未払いリワードの集計に含まれています")]
+    RewardsAlreadyRecorded,
+    #[msg("出金先が記録された入金元ではありません")]
+    NotDepositSource,
+    #[msg("利率の倍率が範囲外です")]
+    InvalidRewardRate,
+    #[msg("利率の上乗せの幅が範囲外です")]
+    InvalidRateDelta,
+    #[msg("運用先の利率の上乗せが範囲外です")]
+    InvalidGrowthRateBonus,
+    #[msg("未払いリワードの集計の扱いが異なる RewardBox 間ではポイントを移せません")]
+    LiabilityTrackingMismatch,
+}
@@ -1,7 +1,251 @@
 const anchor = require("@coral-xyz/anchor");
-const { SystemProgram } = anchor.web3;
+const { SystemProgram, Keypair, LAMPORTS_PER_SOL } = anchor.web3;
 const assert = require("assert");
 
+// --- テスト用ヘルパー ---
+
+// SOLをエアドロップした新しいユーザーを作る
+async function createUser(provider, sol = 10) {
+  const user = Keypair.generate();
+  const sig = await provider.connection.requestAirdrop(
+    user.publicKey,
+    sol * LAMPORTS_PER_SOL
+  );
+  await provider.connection.confirmTransaction(sig);
+  return user;
+}
+
+// name を指定すると名前付きVaultのアドレスになる（空 = 基本Vault）
+function findVaultPda(program, user, name = "") {
+  return anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("vault"), user.toBuffer(), Buffer.from(name)],
+    program.programId
+  )[0];
+}
+
+function findRewardPda(program, user, name = "") {
+  return anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("reward"), user.toBuffer(), Buffer.from(name)],
+    program.programId
+  )[0];
+}
+
+// Vault と RewardBox を作成する
+async function initVault(program, user) {
+  await program.methods
+    .initialize()
+    .accounts({
+      vault: findVaultPda(program, user.publicKey),
+      rewardBox: findRewardPda(program, user.publicKey),
+      user: user.publicKey,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([user])
+    .rpc();
+}
+
+async function deposit(program, user, amount) {
+  return program.methods
+    .deposit(new anchor.BN(amount), null)
+    .accounts({
+      vault: findVaultPda(program, user.publicKey),
+      rewardBox: findRewardPda(program, user.publicKey),
+      matchPool: null,
+      depositSources: null,
+      user: user.publicKey,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([user])
+    .rpc();
+}
+
+async function withdraw(program, user, amount) {
+  return program.methods
+    .withdraw(new anchor.BN(amount))
+    .accounts({
+      vault: findVaultPda(program, user.publicKey),
+      rewardBox: findRewardPda(program, user.publicKey),
+      user: user.publicKey,
+    })
+    .signers([user])
+    .rpc();
+}
+
+async function fetchVault(program, user) {
+  return program.account.vault.fetch(findVaultPda(program, user.publicKey));
+}
+
+function findConfigPda(program) {
+  return anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  )[0];
+}
+
+// Config が未作成なら作成する（実行者 = provider.wallet が管理者）
+async function ensureConfig(program) {
+  const configPda = findConfigPda(program);
+  const existing = await program.account.config.fetchNullable(configPda);
+  if (!existing) {
+    await program.methods.initializeConfig().rpc();
+  }
+  return configPda;
+}
+
+function findTreasuryPda(program) {
+  return anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("treasury")],
+    program.programId
+  )[0];
+}
+
+// Treasury が未作成なら作成する
+async function ensureTreasury(program) {
+  const treasuryPda = findTreasuryPda(program);
+  const existing = await program.account.treasury.fetchNullable(treasuryPda);
+  if (!existing) {
+    await program.methods.initializeTreasury().rpc();
+  }
+  return treasuryPda;
+}
+
+function findLeaderboardPda(program) {
+  return anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("leaderboard")],
+    program.programId
+  )[0];
+}
+
+function findUserStatsPda(program, user) {
+  return anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("user_stats"), user.toBuffer()],
+    program.programId
+  )[0];
+}
+
+function findGlobalEmissionPda(program) {
+  return anchor.web3.PublicKey.findProgramAddressSync(
+    [Buffer.from("global_emission")],
+    program.programId
+  )[0];
+}
+
+async function ensureGlobalEmission(program) {
+  const pda = findGlobalEmissionPda(program);
+  const existing = await program.account.globalEmission.fetchNullable(pda);
+  if (!existing) {
+    await program.methods.initializeGlobalEmission().rpc();
+  }
+  return pda;
+}
+
+async function fundTreasury(program, amount) {
+  await program.methods.fundTreasury(new anchor.BN(amount)).rpc();
+}
+
+// 新しいアドレスへSOLを直接送る（PDAへの入金など）
+// Treasuryから支払える額（レント免除額を除いた残高）
+async function treasuryAvailable(program, provider) {
+  const info = await provider.connection.getAccountInfo(
+    findTreasuryPda(program)
+  );
+  const rent = await provider.connection.getMinimumBalanceForRentExemption(
+    info.data.length
+  );
+  return info.lamports - rent;
+}
+
+// SPLトークンアカウントの残高（最小単位）
+async function tokenAmount(provider, key) {
+  const { value } = await provider.connection.getTokenAccountBalance(key);
+  return Number(value.amount);
+}
+
+async function sendLamports(provider, to, lamports) {
+  const tx = new anchor.web3.Transaction().add(
+    SystemProgram.transfer({
+      fromPubkey: provider.wallet.publicKey,
+      toPubkey: to,
+      lamports,
+    })
+  );
+  await provider.sendAndConfirm(tx);
+}
+
+async function fetchRewardBox(program, user) {
+  return program.account.rewardBox.fetch(
+    findRewardPda(program, user.publicKey)
+  );
+}
+
+// 待機してから1 lamport入金し、その間に付いたリワードと、直前の元本・ロック分・経過秒数、
+// 基本の利率（0.01%/秒）での期待値を返す
+async function accrueOnce(program, user) {
+  const before = await fetchVault(program, user);
+  const rewardBefore = await fetchRewardBox(program, user);
+  await sleep(1500);
+  await deposit(program, user, 1);
+  const after = await fetchVault(program, user);
+  const rewardAfter = await fetchRewardBox(program, user);
+  const elapsed = after.lastUpdateTime.sub(before.lastUpdateTime);
+  return {
+    reward: rewardAfter.balance.sub(rewardBefore.balance),
+    base: before.balance.mul(elapsed).divn(10000),
+    balance: before.balance,
+    locked: before.lockedAmount,
+    elapsed,
+  };
+}
+
+// RewardBox を20個ずつ audit_liabilities に渡し、前回までの合計を引き継いで最後の結果を返す
+async function auditRewardBoxes(program, keys) {
+  const CHUNK = 20;
+  let result;
+  let carried = new anchor.BN(0);
+  for (let i = 0; i === 0 || i < keys.length; i += CHUNK) {
+    result = await program.methods
+      .auditLiabilities(carried)
+      .remainingAccounts(
+        keys.slice(i, i + CHUNK).map((pubkey) => ({
+          pubkey,
+          isWritable: false,
+          isSigner: false,
+        }))
+      )
+      .view();
+    carried = result.rewardBoxTotal;
+  }
+  return result;
+}
+
+// トランザクションのログからイベントを取り出す
+async function eventsOf(program, provider, signature) {
+  await provider.connection.confirmTransaction(signature, "confirmed");
+  const tx = await provider.connection.getTransaction(signature, {
+    commitment: "confirmed",
+    maxSupportedTransactionVersion: 0,
+  });
+  const parser = new anchor.EventParser(program.programId, program.coder);
+  return Array.from(parser.parseLogs(tx.meta.logMessages));
+}
+
+const sleep = (ms) => new Promise((resolve) => setTimeout(resolve, ms));
+
+// 指定したエラーコードで失敗することを確認する
+// （.view() のシミュレーション失敗はログから AnchorError を復元する）
+async function expectError(promise, code) {
+  await assert.rejects(promise, (err) => {
+    const anchorError =
+      err instanceof anchor.AnchorError
+        ? err
+        : anchor.AnchorError.parse(
+            err.logs ?? err.simulationResponse?.logs ?? []
+          );
+    assert.strictEqual(anchorError?.error.errorCode.code, code, err.toString());
+    return true;
+  });
+}
+
 describe("save-to-grow", () => {
   // 環境設定を読み込む
   const provider = anchor.AnchorProvider.env();
@@ -12,6 +256,12 @@ describe("save-to-grow", () => {
   let vaultPda; // 貯金箱のアドレス(PDA)
   let vaultBump;
 
+  before(async () => {
+    await ensureConfig(program);
+    await ensureTreasury(program);
+    await ensureGlobalEmission(program);
+  });
+
   it("1. 貯金箱（Vault）を作成できる", async () => {
     // PDA（貯金箱のアドレス）を計算する
     // ルール: "vault" + ユーザーの公開鍵 = その人の貯金箱アドレス
@@ -32,10 +282,10 @@ describe("save-to-grow", () => {
 
     // 正しくデータが作られたか確認
     const account = await program.account.vault.fetch(vaultPda);
-    console.log("  Owner:", account.owner.toString());
+    console.log("  Owner:", account.user.toString());
     console.log("  Balance:", account.balance.toString());
     
-    assert.ok(account.owner.equals(provider.wallet.publicKey));
+    assert.ok(account.user.equals(provider.wallet.publicKey));
   });
 
   it("2. お金を入金（Deposit）できる", async () => {
@@ -43,9 +293,12 @@ describe("save-to-grow", () => {
 
     // deposit関数を呼び出す
     await program.methods
-      .deposit(depositAmount)
+      .deposit(depositAmount, null)
       .accounts({
         vault: vaultPda,
+        rewardBox: findRewardPda(program, provider.wallet.publicKey),
+        matchPool: null,
+        depositSources: null,
         user: provider.wallet.publicKey,
         systemProgram: SystemProgram.programId,
       })
@@ -57,4 +310,8646 @@ describe("save-to-grow", () => {
 
     assert.ok(account.balance.eq(depositAmount));
   });
-});
\ No newline at end of file
+
+  describe("送金（Transfer）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+    });
+
+    async function transfer(recipient, amount) {
+      await program.methods
+        .transfer(new anchor.BN(amount))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          recipient,
+          recipientOptIn: null,
+        })
+        .signers([user])
+        .rpc();
+    }
+
+    it("空のアカウントへのレント免除額未満の送金は拒否される", async () => {
+      const recipient = Keypair.generate().publicKey;
+      await expectError(transfer(recipient, 1000), "RecipientNotRentExempt");
+    });
+
+    it("空のアカウントでもレント免除額以上なら送金できる", async () => {
+      const recipient = Keypair.generate().publicKey;
+      const rentExempt =
+        await provider.connection.getMinimumBalanceForRentExemption(0);
+      await transfer(recipient, rentExempt);
+
+      const balance = await provider.connection.getBalance(recipient);
+      assert.strictEqual(balance, rentExempt);
+    });
+
+    it("取り消し期間がなくても元本を超える送金は拒否される", async () => {
+      const { balance } = await fetchVault(program, user);
+      await expectError(
+        transfer(Keypair.generate().publicKey, balance.toNumber() + 1),
+        "InsufficientBalance"
+      );
+    });
+
+    it("1日の送金回数の上限を超えると拒否される", async () => {
+      const rentExempt =
+        await provider.connection.getMinimumBalanceForRentExemption(0);
+      const before = await fetchVault(program, user);
+      // 今日すでに行った回数 + 2回を上限にする
+      const max = before.transfersToday + 2;
+      await program.methods.setMaxTransfersPerDay(max).rpc();
+
+      try {
+        await transfer(Keypair.generate().publicKey, rentExempt);
+        await transfer(Keypair.generate().publicKey, rentExempt);
+        await expectError(
+          transfer(Keypair.generate().publicKey, rentExempt),
+          "TransferLimitExceeded"
+        );
+
+        const vault = await fetchVault(program, user);
+        assert.strictEqual(vault.transfersToday, max);
+        const today = Math.floor(vault.lastUpdateTime.toNumber() / 86400);
+        assert.strictEqual(vault.transferDay.toNumber(), today);
+      } finally {
+        await program.methods.setMaxTransfersPerDay(0).rpc();
+      }
+    });
+
+    it("プログラム所有のアカウントへの送金は拒否される", async () => {
+      // Config PDA はこのプログラムが所有している
+      await expectError(
+        transfer(findConfigPda(program), LAMPORTS_PER_SOL),
+        "InvalidRecipientOwner"
+      );
+    });
+
+    it("Vault / RewardBox 自身を送金先にはできない", async () => {
+      await expectError(
+        transfer(findVaultPda(program, user.publicKey), LAMPORTS_PER_SOL),
+        "DestinationIsVault"
+      );
+      await expectError(
+        transfer(findRewardPda(program, user.publicKey), LAMPORTS_PER_SOL),
+        "DestinationIsVault"
+      );
+    });
+
+    it("Treasury を送金先にすると拒否され、Treasury の会計は変わらない", async () => {
+      const treasuryPda = await ensureTreasury(program);
+      const treasuryBefore = await program.account.treasury.fetch(treasuryPda);
+      const lamportsBefore = await provider.connection.getBalance(treasuryPda);
+      const vaultBefore = await fetchVault(program, user);
+
+      await expectError(
+        transfer(treasuryPda, LAMPORTS_PER_SOL),
+        "RecipientIsTreasury"
+      );
+
+      const treasuryAfter = await program.account.treasury.fetch(treasuryPda);
+      assert.strictEqual(
+        treasuryAfter.totalFunded.toString(),
+        treasuryBefore.totalFunded.toString()
+      );
+      assert.strictEqual(
+        await provider.connection.getBalance(treasuryPda),
+        lamportsBefore
+      );
+      const vaultAfter = await fetchVault(program, user);
+      assert.strictEqual(
+        vaultAfter.balance.toString(),
+        vaultBefore.balance.toString()
+      );
+    });
+
+    it("他のプログラム所有PDAも送金先にはできない", async () => {
+      await expectError(
+        transfer(findConfigPda(program), LAMPORTS_PER_SOL),
+        "InvalidRecipientOwner"
+      );
+    });
+  });
+
+  describe("報酬計算方式（RewardStrategy）", () => {
+    const depositAmount = 10 * LAMPORTS_PER_SOL;
+
+    after(async () => {
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+    });
+
+    // 指定した方式で入金→待機→再入金し、1秒あたりの報酬額を返す
+    async function rewardPerSecond(strategy) {
+      await program.methods.setRewardStrategy(strategy).rpc();
+
+      const user = await createUser(provider, 20);
+      await initVault(program, user);
+      await deposit(program, user, depositAmount);
+      const before = await program.account.vault.fetch(
+        findVaultPda(program, user.publicKey)
+      );
+
+      await sleep(3000);
+      await deposit(program, user, 1);
+
+      const after = await program.account.vault.fetch(
+        findVaultPda(program, user.publicKey)
+      );
+      const reward = await program.account.rewardBox.fetch(
+        findRewardPda(program, user.publicKey)
+      );
+      const elapsed = after.lastUpdateTime
+        .sub(before.lastUpdateTime)
+        .toNumber();
+      assert.ok(elapsed > 0);
+      return reward.balance.toNumber() / elapsed;
+    }
+
+    it("Linear / Tiered / Capped でそれぞれ異なる報酬が付く", async () => {
+      const linear = await rewardPerSecond({ linear: {} });
+      const tiered = await rewardPerSecond({ tiered: {} });
+      const capped = await rewardPerSecond({ capped: {} });
+
+      // 10 SOL預けた場合: Linear = 10 SOL分, Tiered = 5 + 5×2 SOL分, Capped = 5 SOL分
+      assert.strictEqual(linear, depositAmount / 10000);
+      assert.strictEqual(tiered, (depositAmount * 1.5) / 10000);
+      assert.strictEqual(capped, depositAmount / 2 / 10000);
+    });
+
+    it("管理者以外は方式を変更できない", async () => {
+      const other = await createUser(provider);
+      await expectError(
+        program.methods
+          .setRewardStrategy({ capped: {} })
+          .accounts({ admin: other.publicKey })
+          .signers([other])
+          .rpc(),
+        "ConstraintHasOne"
+      );
+    });
+  });
+
+  describe("出金キュー（高額出金の予約）", () => {
+    let user;
+
+    before(async () => {
+      // 1 SOLを超える出金は3秒待ちの予約制にする
+      await program.methods
+        .setWithdrawDelay(new anchor.BN(LAMPORTS_PER_SOL), new anchor.BN(3))
+        .rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 3 * LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await program.methods
+        .setWithdrawDelay(new anchor.BN(0), new anchor.BN(0))
+        .rpc();
+    });
+
+    const accounts = () => ({
+      vault: findVaultPda(program, user.publicKey),
+      rewardBox: findRewardPda(program, user.publicKey),
+      user: user.publicKey,
+    });
+
+    const executeWithdraw = () =>
+      program.methods
+        .executeWithdraw()
+        .accounts(accounts())
+        .signers([user])
+        .rpc();
+
+    it("しきい値を超える出金は直接できない", async () => {
+      await expectError(
+        withdraw(program, user, 2 * LAMPORTS_PER_SOL),
+        "WithdrawRequiresQueue"
+      );
+    });
+
+    it("ready_at より前の実行は失敗し、経過後は成功する", async () => {
+      await program.methods
+        .requestWithdraw(new anchor.BN(2 * LAMPORTS_PER_SOL))
+        .accounts(accounts())
+        .signers([user])
+        .rpc();
+
+      await expectError(executeWithdraw(), "WithdrawNotReady");
+
+      await sleep(4000);
+      await executeWithdraw();
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL);
+      assert.strictEqual(vault.pendingWithdrawAmount.toNumber(), 0);
+    });
+
+    it("予約を取り消すとクリアされる", async () => {
+      await program.methods
+        .requestWithdraw(new anchor.BN(LAMPORTS_PER_SOL))
+        .accounts(accounts())
+        .signers([user])
+        .rpc();
+      await program.methods
+        .cancelWithdraw()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.pendingWithdrawAmount.toNumber(), 0);
+      assert.strictEqual(vault.pendingWithdrawReadyAt.toNumber(), 0);
+      await expectError(executeWithdraw(), "NoPendingWithdraw");
+    });
+  });
+
+  describe("予備領域（_reserved）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    // アカウントデータ末尾の予備領域がすべて0であることを確認する
+    async function assertReservedZero(pda, expectedSize, reservedSize) {
+      const info = await provider.connection.getAccountInfo(pda);
+      assert.strictEqual(info.data.length, expectedSize);
+      const reserved = info.data.subarray(info.data.length - reservedSize);
+      assert.ok(reserved.every((b) => b === 0));
+    }
+
+    it("初期化後の予備領域は0で、サイズも拡張されている", async () => {
+      const vaultPda = findVaultPda(program, user.publicKey);
+      const rewardPda = findRewardPda(program, user.publicKey);
+      await assertReservedZero(vaultPda, program.account.vault.size, 3);
+      await assertReservedZero(rewardPda, 8 + 8 + 1 + 1 + 16 + 32 + 1 + 14, 14);
+    });
+
+    it("既存フィールドは正しく読み書きできる", async () => {
+      const vault = await fetchVault(program, user);
+      assert.ok(vault.user.equals(user.publicKey));
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL);
+      assert.strictEqual(vault.pendingWithdrawAmount.toNumber(), 0);
+
+      const reward = await program.account.rewardBox.fetch(
+        findRewardPda(program, user.publicKey)
+      );
+      assert.strictEqual(reward.balance.toNumber(), 0);
+    });
+  });
+
+  describe("リワードのロック付き元本への変換", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 5 * LAMPORTS_PER_SOL);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+    });
+
+    it("ボーナス1.1倍で元本に加算され、ロックされてリワードBoxは0になる", async () => {
+      await sleep(2000);
+      const before = await fetchVault(program, user);
+      const rewardBefore = await fetchRewardBox(program, user);
+
+      await program.methods
+        .convertRewardsToLocked(new anchor.BN(3600))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      const after = await fetchVault(program, user);
+      const elapsed = after.lastUpdateTime
+        .sub(before.lastUpdateTime)
+        .toNumber();
+      const rewards =
+        rewardBefore.balance.toNumber() +
+        (before.balance.toNumber() * elapsed) / 10000;
+      const bonus = Math.floor((rewards * 11000) / 10000);
+
+      assert.strictEqual(after.balance.sub(before.balance).toNumber(), bonus);
+      assert.strictEqual(
+        after.lockUntil.toNumber(),
+        after.lastUpdateTime.toNumber() + 3600
+      );
+      assert.strictEqual(
+        (await fetchRewardBox(program, user)).balance.toNumber(),
+        0
+      );
+    });
+
+    it("ロック中は出金できない", async () => {
+      await expectError(withdraw(program, user, 1), "VaultLocked");
+    });
+  });
+
+  describe("不変条件チェック（assert_invariants）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    const assertInvariants = () =>
+      program.methods
+        .assertInvariants()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+
+    it("正常なVaultではチェックが通る", async () => {
+      await assertInvariants();
+    });
+
+    it("Vault PDAへ直接SOLを送ると不一致として検出される", async () => {
+      await sendLamports(provider, findVaultPda(program, user.publicKey), 1000);
+
+      await expectError(assertInvariants(), "VaultBalanceMismatch");
+    });
+  });
+
+  describe("入金単位（deposit_granularity）", () => {
+    const granularity = LAMPORTS_PER_SOL / 10; // 0.1 SOL単位
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    after(async () => {
+      await program.methods
+        .setDepositGranularity(new anchor.BN(0), { reject: {} })
+        .rpc();
+    });
+
+    it("Reject: 倍数の入金は通り、端数のある入金は拒否される", async () => {
+      await program.methods
+        .setDepositGranularity(new anchor.BN(granularity), { reject: {} })
+        .rpc();
+
+      await deposit(program, user, granularity);
+      await expectError(
+        deposit(program, user, granularity * 1.5),
+        "DepositNotMultipleOfGranularity"
+      );
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), granularity);
+    });
+
+    it("RoundDown: 端数は切り捨てられて手元に残る", async () => {
+      await program.methods
+        .setDepositGranularity(new anchor.BN(granularity), { roundDown: {} })
+        .rpc();
+
+      const before = await fetchVault(program, user);
+      await deposit(program, user, granularity * 2.5);
+      const after = await fetchVault(program, user);
+
+      assert.strictEqual(
+        after.balance.sub(before.balance).toNumber(),
+        granularity * 2
+      );
+      await expectError(
+        deposit(program, user, granularity / 2),
+        "DepositTooSmall"
+      );
+    });
+  });
+
+  describe("定期入金（authorize_recurring / crank_deposit）", () => {
+    const amount = LAMPORTS_PER_SOL / 10;
+    let user;
+    let cranker;
+    let recurringPda;
+
+    before(async () => {
+      user = await createUser(provider);
+      cranker = await createUser(provider, 1);
+      await initVault(program, user);
+      [recurringPda] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("recurring"), user.publicKey.toBuffer()],
+        program.programId
+      );
+
+      await program.methods
+        .authorizeRecurring(new anchor.BN(amount), new anchor.BN(3))
+        .accounts({ user: user.publicKey })
+        .signers([user])
+        .rpc();
+      // 原資をスケジュールPDAへ送っておく
+      await sendLamports(provider, recurringPda, LAMPORTS_PER_SOL);
+    });
+
+    const crank = () =>
+      program.methods
+        .crankDeposit(new anchor.BN(amount))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          recurring: recurringPda,
+          cranker: cranker.publicKey,
+        })
+        .signers([cranker])
+        .rpc();
+
+    it("スケジュール通りのcrankは成功し、早すぎるcrankは拒否される", async () => {
+      await crank();
+      await expectError(crank(), "CrankTooEarly");
+
+      await sleep(4000);
+      await crank();
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), amount * 2);
+    });
+
+    it("解除すると原資が持ち主に戻る", async () => {
+      await program.methods
+        .cancelRecurring()
+        .accounts({ recurring: recurringPda, user: user.publicKey })
+        .signers([user])
+        .rpc();
+      assert.strictEqual(
+        await provider.connection.getAccountInfo(recurringPda),
+        null
+      );
+    });
+  });
+
+  describe("Vaultの縮小（compact_vault）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    it("最小サイズのVaultは縮小できず、状態も変わらない", async () => {
+      const vaultPda = findVaultPda(program, user.publicKey);
+      const before = await provider.connection.getAccountInfo(vaultPda);
+
+      await expectError(
+        program.methods
+          .compactVault()
+          .accounts({ vault: vaultPda, user: user.publicKey })
+          .signers([user])
+          .rpc(),
+        "NothingToCompact"
+      );
+
+      const after = await provider.connection.getAccountInfo(vaultPda);
+      assert.strictEqual(after.data.length, before.data.length);
+      assert.strictEqual(after.lamports, before.lamports);
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL);
+    });
+  });
+
+  describe("USD建ての貯金目標（set_goal_usd / goal_progress_usd）", () => {
+    // tests/fixtures のモック価格フィード（SOL/USD = $150）
+    const priceFeed = new anchor.web3.PublicKey(
+      "9vioLRinypv2fNJfnFRCZVvmcwwbtXz4tqTNph8PxDJv"
+    );
+    const stalePriceFeed = new anchor.web3.PublicKey(
+      "FoChQmCtcmpkhHSB1CM5QsgHXRFWy1ZLidfyz4VsJPbM"
+    );
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await program.methods
+        .setGoalUsd(new anchor.BN(30000)) // $300
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    const goalProgress = (feed) =>
+      program.methods
+        .goalProgressUsd()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          priceFeed: feed,
+        })
+        .view();
+
+    it("価格フィードで残高をUSDに換算して達成率を返す", async () => {
+      await program.methods
+        .setPriceFeed(priceFeed, new anchor.BN(60))
+        .rpc();
+
+      const progress = await goalProgress(priceFeed);
+      assert.strictEqual(progress.balanceUsdCents.toNumber(), 15000);
+      assert.strictEqual(progress.goalUsdCents.toNumber(), 30000);
+      assert.strictEqual(progress.progressBps, 5000);
+    });
+
+    it("古い価格は拒否される", async () => {
+      await program.methods
+        .setPriceFeed(stalePriceFeed, new anchor.BN(60))
+        .rpc();
+      await expectError(goalProgress(stalePriceFeed), "StalePrice");
+    });
+
+    it("設定と異なる価格アカウントは拒否される", async () => {
+      await expectError(goalProgress(priceFeed), "InvalidPriceFeed");
+    });
+  });
+
+  describe("リワード率の経年減衰", () => {
+    const depositAmount = 10 * LAMPORTS_PER_SOL;
+    let user;
+
+    before(async () => {
+      // 2秒ごとに25%ずつ下がり、50%で下げ止まる（"月"の代わりに秒で短縮）
+      await program.methods
+        .setRewardDecay(new anchor.BN(2), 2500, 5000)
+        .rpc();
+      user = await createUser(provider, 20);
+      await initVault(program, user);
+      await deposit(program, user, depositAmount);
+    });
+
+    after(async () => {
+      await program.methods.setRewardDecay(new anchor.BN(0), 0, 10000).rpc();
+    });
+
+    it("経過時間とともに利率が下がり、下限で止まる", async () => {
+      const multipliers = [];
+      for (let i = 0; i < 4; i++) {
+        await sleep(2000);
+        const before = await fetchVault(program, user);
+        const rewardBefore = await fetchRewardBox(program, user);
+        await deposit(program, user, 1);
+        const after = await fetchVault(program, user);
+        const rewardAfter = await fetchRewardBox(program, user);
+
+        const elapsed = after.lastUpdateTime
+          .sub(before.lastUpdateTime)
+          .toNumber();
+        const age = after.lastUpdateTime.sub(after.createdAt).toNumber();
+        const multiplier = Math.max(5000, 10000 - Math.floor(age / 2) * 2500);
+        const base = Math.floor((before.balance.toNumber() * elapsed) / 10000);
+
+        assert.strictEqual(
+          rewardAfter.balance.sub(rewardBefore.balance).toNumber(),
+          Math.floor((base * multiplier) / 10000)
+        );
+        multipliers.push(multiplier);
+      }
+
+      for (let i = 1; i < multipliers.length; i++) {
+        assert.ok(multipliers[i] <= multipliers[i - 1]);
+      }
+      assert.strictEqual(multipliers[multipliers.length - 1], 5000);
+    });
+  });
+
+  describe("リワードトークンでの受け取り（claim_reward_token）", () => {
+    let user;
+    let rewardMint;
+
+    before(async () => {
+      [rewardMint] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("reward_mint")],
+        program.programId
+      );
+      if (!(await provider.connection.getAccountInfo(rewardMint))) {
+        await program.methods.initializeRewardMint(6).rpc();
+      }
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 5 * LAMPORTS_PER_SOL);
+    });
+
+    it("6桁のMintではポイントを1000で割った量が発行され、端数はBoxに残る", async () => {
+      await sleep(2000);
+      const before = await fetchVault(program, user);
+      const rewardBefore = await fetchRewardBox(program, user);
+
+      await program.methods
+        .claimRewardToken()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      const after = await fetchVault(program, user);
+      const elapsed = after.lastUpdateTime
+        .sub(before.lastUpdateTime)
+        .toNumber();
+      const expected =
+        rewardBefore.balance.toNumber() +
+        (before.balance.toNumber() * elapsed) / 10000;
+
+      const ata = anchor.utils.token.associatedAddress({
+        mint: rewardMint,
+        owner: user.publicKey,
+      });
+      const { value } = await provider.connection.getTokenAccountBalance(ata);
+      assert.strictEqual(value.decimals, 6);
+      assert.strictEqual(Number(value.amount), Math.floor(expected / 1000));
+      assert.strictEqual(
+        (await fetchRewardBox(program, user)).balance.toNumber(),
+        expected % 1000
+      );
+    });
+  });
+
+  describe("Treasuryへのリワードトークンの入金（fund_treasury_token）", () => {
+    let user;
+    let rewardMint;
+    let userAta;
+
+    const treasuryAta = () =>
+      anchor.utils.token.associatedAddress({
+        mint: rewardMint,
+        owner: findTreasuryPda(program),
+      });
+
+    const fundTreasuryToken = (funderTokenAccount, amount) =>
+      program.methods
+        .fundTreasuryToken(new anchor.BN(amount))
+        .accounts({
+          treasuryTokenAccount: treasuryAta(),
+          funderTokenAccount,
+          funder: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureTreasury(program);
+      await ensureGlobalEmission(program);
+      [rewardMint] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("reward_mint")],
+        program.programId
+      );
+      if (!(await provider.connection.getAccountInfo(rewardMint))) {
+        await program.methods.initializeRewardMint(6).rpc();
+      }
+
+      // リワードトークンを受け取っておく
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 5 * LAMPORTS_PER_SOL);
+      await sleep(2000);
+      await program.methods
+        .claimRewardToken()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+      userAta = anchor.utils.token.associatedAddress({
+        mint: rewardMint,
+        owner: user.publicKey,
+      });
+    });
+
+    it("リワードトークンのMintなら Treasury のATAに入る", async () => {
+      const amount = Math.floor((await tokenAmount(provider, userAta)) / 2);
+      assert.ok(amount > 0);
+      const before = await tokenAmount(provider, userAta);
+
+      // Treasury のATAはこの入金で作られる
+      await fundTreasuryToken(userAta, amount);
+
+      assert.strictEqual(await tokenAmount(provider, userAta), before - amount);
+      assert.strictEqual(await tokenAmount(provider, treasuryAta()), amount);
+    });
+
+    it("別のMintのトークンアカウントからは入金できない", async () => {
+      // モックスワップのMintのATA（空）を送り元にする
+      const mockSwap = anchor.workspace.MockSwap;
+      const [otherMint] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("mint")],
+        mockSwap.programId
+      );
+      if (!(await provider.connection.getAccountInfo(otherMint))) {
+        await mockSwap.methods.initializeMint().rpc();
+      }
+      const otherAta = anchor.utils.token.associatedAddress({
+        mint: otherMint,
+        owner: user.publicKey,
+      });
+      const createAta = new anchor.web3.TransactionInstruction({
+        programId: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+        keys: [
+          { pubkey: user.publicKey, isSigner: true, isWritable: true },
+          { pubkey: otherAta, isSigner: false, isWritable: true },
+          { pubkey: user.publicKey, isSigner: false, isWritable: false },
+          { pubkey: otherMint, isSigner: false, isWritable: false },
+          {
+            pubkey: SystemProgram.programId,
+            isSigner: false,
+            isWritable: false,
+          },
+          {
+            pubkey: anchor.utils.token.TOKEN_PROGRAM_ID,
+            isSigner: false,
+            isWritable: false,
+          },
+        ],
+        data: Buffer.from([1]),
+      });
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(createAta),
+        [user]
+      );
+
+      const treasuryBefore = await tokenAmount(provider, treasuryAta());
+      await expectError(fundTreasuryToken(otherAta, 0), "FundingMintMismatch");
+      assert.strictEqual(
+        await tokenAmount(provider, treasuryAta()),
+        treasuryBefore
+      );
+    });
+  });
+
+  describe("PDAのbump（正規bumpのみ受け付ける）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    it("保存されたbumpは正規bumpと一致する", async () => {
+      const [, canonicalBump] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), user.publicKey.toBuffer()],
+        program.programId
+      );
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.bump, canonicalBump);
+
+      await program.methods
+        .assertInvariants()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+    });
+
+    it("正規でないbumpから導出したアドレスはVaultとして使えない", async () => {
+      const [, canonicalBump] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("vault"), user.publicKey.toBuffer()],
+        program.programId
+      );
+      // 正規bumpより小さいbumpで、曲線外（PDAとして有効）なアドレスを探す
+      let nonCanonical = null;
+      for (let bump = canonicalBump - 1; bump >= 0 && !nonCanonical; bump--) {
+        try {
+          nonCanonical = anchor.web3.PublicKey.createProgramAddressSync(
+            [
+              Buffer.from("vault"),
+              user.publicKey.toBuffer(),
+              Buffer.from([bump]),
+            ],
+            program.programId
+          );
+        } catch (_) {
+          // 曲線上のアドレスはPDAにならないので次へ
+        }
+      }
+      assert.ok(nonCanonical);
+
+      await assert.rejects(
+        program.methods
+          .deposit(new anchor.BN(LAMPORTS_PER_SOL), null)
+          .accounts({
+            vault: nonCanonical,
+            rewardBox: findRewardPda(program, user.publicKey),
+            matchPool: null,
+            depositSources: null,
+            user: user.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc()
+      );
+    });
+  });
+
+  describe("緊急の全体停止（shutdown）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+      await program.methods.setShutdown(true).rpc();
+    });
+
+    after(async () => {
+      await program.methods.setShutdown(false).rpc();
+    });
+
+    it("停止中は入金と送金が拒否される", async () => {
+      await expectError(
+        deposit(program, user, LAMPORTS_PER_SOL),
+        "ProtocolShutdown"
+      );
+      await expectError(
+        program.methods
+          .transfer(new anchor.BN(LAMPORTS_PER_SOL))
+          .accounts({
+            vault: findVaultPda(program, user.publicKey),
+            rewardBox: findRewardPda(program, user.publicKey),
+            user: user.publicKey,
+            recipient: provider.wallet.publicKey,
+            recipientOptIn: null,
+          })
+          .signers([user])
+          .rpc(),
+        "ProtocolShutdown"
+      );
+    });
+
+    it("停止中も出金はでき、リワードは付与されない", async () => {
+      const rewardBefore = await fetchRewardBox(program, user);
+      await sleep(2000);
+      await withdraw(program, user, LAMPORTS_PER_SOL);
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL);
+      const rewardAfter = await fetchRewardBox(program, user);
+      assert.ok(rewardAfter.balance.eq(rewardBefore.balance));
+    });
+  });
+
+  describe("将来残高の試算（project_balance）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    const project = (days, compound) =>
+      program.methods
+        .projectBalance(new anchor.BN(days), compound)
+        .accounts({ vault: findVaultPda(program, user.publicKey) })
+        .view();
+
+    it("単利の試算は 残高 + 残高 × 0.01% × 秒数 になる", async () => {
+      const projected = await project(365, false);
+      const expected =
+        LAMPORTS_PER_SOL + (LAMPORTS_PER_SOL / 10000) * 365 * 86400;
+      assert.strictEqual(projected.toString(), expected.toString());
+    });
+
+    it("複利の試算は単利より大きく、桁あふれしても失敗しない", async () => {
+      const simple = await project(365, false);
+      const compound = await project(365, true);
+      assert.ok(compound.gt(simple));
+      // 1年分の日次複利は u64 の上限で頭打ちになる
+      assert.strictEqual(compound.toString(), "18446744073709551615");
+    });
+
+    it("上限を超える日数は拒否される", async () => {
+      await expectError(project(3651, false), "InvalidProjectionDays");
+    });
+  });
+
+  describe("Vault / Config のクローズ", () => {
+    it("空のVaultは閉じられ、有効Vault数が減る", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      const before = await program.account.config.fetch(findConfigPda(program));
+
+      await program.methods
+        .closeVault()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      const after = await program.account.config.fetch(findConfigPda(program));
+      assert.strictEqual(
+        after.vaultCount.toNumber(),
+        before.vaultCount.toNumber() - 1
+      );
+      assert.strictEqual(
+        await program.account.vault.fetchNullable(
+          findVaultPda(program, user.publicKey)
+        ),
+        null
+      );
+    });
+
+    it("残高のあるVaultは閉じられない", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await expectError(
+        program.methods
+          .closeVault()
+          .accounts({
+            vault: findVaultPda(program, user.publicKey),
+            rewardBox: findRewardPda(program, user.publicKey),
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc(),
+        "VaultNotEmpty"
+      );
+    });
+
+    it("有効なVaultがあるとConfigは閉じられず、forceなら閉じられる", async () => {
+      await expectError(
+        program.methods.closeConfig(false).rpc(),
+        "VaultsStillActive"
+      );
+
+      await program.methods.closeConfig(true).rpc();
+      assert.strictEqual(
+        await program.account.config.fetchNullable(findConfigPda(program)),
+        null
+      );
+
+      // 以降のテストのために作り直す
+      await ensureConfig(program);
+    });
+  });
+
+  describe("入金のリニアベスティング", () => {
+    let user;
+
+    before(async () => {
+      // 入金は10秒かけて線形に解放される
+      await program.methods.setVestingDuration(new anchor.BN(10)).rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await program.methods.setVestingDuration(new anchor.BN(0)).rpc();
+    });
+
+    it("入金直後は引き出せない", async () => {
+      await expectError(
+        withdraw(program, user, LAMPORTS_PER_SOL / 5),
+        "AmountNotVested"
+      );
+    });
+
+    it("途中では解放済みの分だけ引き出せる", async () => {
+      await sleep(5000);
+      // 約50%が解放済み
+      await expectError(
+        withdraw(program, user, (LAMPORTS_PER_SOL * 9) / 10),
+        "AmountNotVested"
+      );
+      await withdraw(program, user, (LAMPORTS_PER_SOL * 3) / 10);
+    });
+
+    it("期間が終われば残りをすべて引き出せる", async () => {
+      await sleep(6000);
+      await withdraw(program, user, (LAMPORTS_PER_SOL * 7) / 10);
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), 0);
+    });
+  });
+
+  describe("VaultTouchedEvent", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    async function assertTouched(signature) {
+      const events = await eventsOf(program, provider, signature);
+      const touched = events.filter((e) => e.name === "vaultTouchedEvent");
+      assert.strictEqual(touched.length, 1);
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      const data = touched[0].data;
+      assert.ok(data.vault.equals(findVaultPda(program, user.publicKey)));
+      assert.ok(data.balance.eq(vault.balance));
+      assert.ok(data.rewardBoxBalance.eq(reward.balance));
+      assert.ok(data.lastUpdateTime.eq(vault.lastUpdateTime));
+    }
+
+    it("入金・出金・送金のそれぞれで発行される", async () => {
+      await assertTouched(await deposit(program, user, LAMPORTS_PER_SOL));
+      await assertTouched(await withdraw(program, user, LAMPORTS_PER_SOL / 4));
+      await assertTouched(
+        await program.methods
+          .transfer(new anchor.BN(LAMPORTS_PER_SOL / 4))
+          .accounts({
+            vault: findVaultPda(program, user.publicKey),
+            rewardBox: findRewardPda(program, user.publicKey),
+            user: user.publicKey,
+            recipient: provider.wallet.publicKey,
+            recipientOptIn: null,
+          })
+          .signers([user])
+          .rpc()
+      );
+    });
+
+    it("リワード確定のイベントは元本変更のイベントより先に出る", async () => {
+      await sleep(1000);
+      const events = await eventsOf(
+        program,
+        provider,
+        await deposit(program, user, LAMPORTS_PER_SOL)
+      );
+      const names = events.map((e) => e.name);
+      assert.deepStrictEqual(names, [
+        "rewardAccruedEvent",
+        "balanceDeltaEvent",
+        "vaultTouchedEvent",
+      ]);
+
+      const [accrued, delta, touched] = events.map((e) => e.data);
+      assert.ok(accrued.amount.gtn(0));
+      assert.strictEqual(delta.delta.toNumber(), LAMPORTS_PER_SOL);
+      assert.ok(delta.balance.eq(touched.balance));
+      assert.ok(accrued.rewardBoxBalance.eq(touched.rewardBoxBalance));
+      assert.ok(accrued.lastUpdateTime.eq(touched.lastUpdateTime));
+    });
+  });
+
+  describe("経過秒数が負の場合", () => {
+    // tests/fixtures/future_vault.json のVaultは last_update_time が
+    // 2100年に設定されており、このシードのユーザーが所有している
+    const user = Keypair.fromSeed(new Uint8Array(32).fill(121));
+
+    before(async () => {
+      const sig = await provider.connection.requestAirdrop(
+        user.publicKey,
+        10 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    it("リワードが過剰に付与されない", async () => {
+      const before = await fetchVault(program, user);
+      assert.ok(before.lastUpdateTime.toNumber() > Date.now() / 1000);
+
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(reward.balance.toNumber(), 0);
+      assert.strictEqual(vault.balance.toNumber(), 2 * LAMPORTS_PER_SOL);
+      assert.ok(vault.lastUpdateTime.eq(before.lastUpdateTime));
+    });
+  });
+
+  describe("手数料と新規Vaultの猶予期間", () => {
+    const FEE_BPS = 100; // 1%
+    const GRACE_SECS = 5;
+    let user;
+
+    async function setFees(depositBps, withdrawBps, graceSecs) {
+      await program.methods
+        .setFees(depositBps, withdrawBps, new anchor.BN(graceSecs))
+        .rpc();
+    }
+
+    async function treasuryLamports() {
+      return provider.connection.getBalance(findTreasuryPda(program));
+    }
+
+    before(async () => {
+      await setFees(FEE_BPS, FEE_BPS, GRACE_SECS);
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    after(async () => {
+      await setFees(0, 0, 0);
+    });
+
+    it("猶予期間中は入金・出金とも手数料がかからない", async () => {
+      const treasuryBefore = await treasuryLamports();
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await withdraw(program, user, LAMPORTS_PER_SOL / 2);
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL / 2);
+      assert.strictEqual(await treasuryLamports(), treasuryBefore);
+    });
+
+    it("猶予期間後は手数料がTreasuryに入る", async () => {
+      await sleep((GRACE_SECS + 1) * 1000);
+      const fee = (LAMPORTS_PER_SOL * FEE_BPS) / 10000;
+
+      const treasuryBefore = await treasuryLamports();
+      const vaultBefore = await fetchVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const afterDeposit = await fetchVault(program, user);
+      assert.strictEqual(
+        afterDeposit.balance.sub(vaultBefore.balance).toNumber(),
+        LAMPORTS_PER_SOL - fee
+      );
+      assert.strictEqual(await treasuryLamports(), treasuryBefore + fee);
+
+      const userBefore = await provider.connection.getBalance(user.publicKey);
+      await withdraw(program, user, LAMPORTS_PER_SOL);
+      const afterWithdraw = await fetchVault(program, user);
+      assert.strictEqual(
+        afterDeposit.balance.sub(afterWithdraw.balance).toNumber(),
+        LAMPORTS_PER_SOL
+      );
+      // ユーザーには手数料（と送信手数料）を引いた額が届く
+      const received =
+        (await provider.connection.getBalance(user.publicKey)) - userBefore;
+      assert.ok(received <= LAMPORTS_PER_SOL - fee);
+      assert.ok(received > LAMPORTS_PER_SOL - fee - 10000);
+      assert.strictEqual(await treasuryLamports(), treasuryBefore + 2 * fee);
+    });
+  });
+
+  describe("状態のエクスポート（export_state）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(1000);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    // discriminatorを付け直してAnchorのデコーダで読む
+    function decode(name, data) {
+      const account = program.idl.accounts.find((a) => a.name === name);
+      const discriminator = Buffer.from(account.discriminator);
+      return program.coder.accounts.decode(
+        name,
+        Buffer.concat([discriminator, data])
+      );
+    }
+
+    it("返されたデータがオンチェーンのアカウントと一致する", async () => {
+      const vaultPda = findVaultPda(program, user.publicKey);
+      const rewardPda = findRewardPda(program, user.publicKey);
+      const blob = await program.methods
+        .exportState()
+        .accounts({ vault: vaultPda, rewardBox: rewardPda })
+        .view();
+
+      const vaultInfo = await provider.connection.getAccountInfo(vaultPda);
+      const rewardInfo = await provider.connection.getAccountInfo(rewardPda);
+      const vaultLen = vaultInfo.data.length - 8;
+      assert.strictEqual(blob.length, vaultLen + rewardInfo.data.length - 8);
+      assert.ok(blob.subarray(0, vaultLen).equals(vaultInfo.data.subarray(8)));
+      assert.ok(blob.subarray(vaultLen).equals(rewardInfo.data.subarray(8)));
+
+      const vault = decode("vault", blob.subarray(0, vaultLen));
+      const reward = decode("rewardBox", blob.subarray(vaultLen));
+      const onchainVault = await fetchVault(program, user);
+      const onchainReward = await fetchRewardBox(program, user);
+      assert.ok(vault.user.equals(user.publicKey));
+      assert.ok(vault.balance.eq(onchainVault.balance));
+      assert.ok(vault.lastUpdateTime.eq(onchainVault.lastUpdateTime));
+      assert.ok(reward.balance.gtn(0));
+      assert.ok(reward.balance.eq(onchainReward.balance));
+    });
+  });
+
+  describe("リワード対象になるまでの待機（reward_start_delay）", () => {
+    const DELAY_SECS = 3;
+    let user;
+
+    // 少額の出金でリワードを確定させる
+    const touch = () => withdraw(program, user, 1000);
+
+    before(async () => {
+      await program.methods
+        .setRewardStartDelay(new anchor.BN(DELAY_SECS))
+        .rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await program.methods.setRewardStartDelay(new anchor.BN(0)).rpc();
+    });
+
+    it("待機中はリワードが付かない", async () => {
+      await sleep(1000);
+      await touch();
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(reward.balance.toNumber(), 0);
+      assert.strictEqual(vault.unearningAmount.toNumber(), LAMPORTS_PER_SOL);
+    });
+
+    it("待機が明けた時刻から全額にリワードが付く", async () => {
+      const before = await fetchVault(program, user);
+      await sleep((DELAY_SECS + 1) * 1000);
+      await touch();
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      // 待機明けから最終更新までの秒数だけ、残高全額に 0.01%/秒
+      const secs = vault.lastUpdateTime.sub(before.rewardEligibleAfter);
+      const expected = before.balance.mul(secs).divn(10000);
+      assert.ok(secs.gtn(0));
+      assert.strictEqual(reward.balance.toString(), expected.toString());
+      assert.strictEqual(vault.unearningAmount.toNumber(), 0);
+    });
+  });
+
+  describe("旧レイアウトのVaultの拡張（migrate_vault）", () => {
+    // tests/fixtures/legacy_vault.json は拡張前（137byte、残高0.5 SOL）のVault
+    const user = Keypair.fromSeed(new Uint8Array(32).fill(125));
+    const vaultPda = findVaultPda(program, user.publicKey);
+
+    const migrate = () =>
+      program.methods
+        .migrateVault()
+        .accounts({ vault: vaultPda, user: user.publicKey })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      const sig = await provider.connection.requestAirdrop(
+        user.publicKey,
+        LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    it("拡張後は現在のレイアウトで読め、残高とレントの整合も保たれる", async () => {
+      const before = await provider.connection.getAccountInfo(vaultPda);
+      assert.strictEqual(before.data.length, 137);
+
+      await migrate();
+
+      const after = await provider.connection.getAccountInfo(vaultPda);
+      const space = program.account.vault.size;
+      assert.strictEqual(after.data.length, space);
+      const vault = await fetchVault(program, user);
+      assert.ok(vault.user.equals(user.publicKey));
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL / 2);
+      assert.strictEqual(vault.unearningAmount.toNumber(), 0);
+      assert.strictEqual(vault.version, 1);
+      const reward = await program.account.rewardBox.fetch(
+        findRewardPda(program, user.publicKey)
+      );
+      assert.strictEqual(reward.version, 2);
+      // version 0 は集計に含まれているか分からないので、集計済みにはしない
+      assert.strictEqual(reward.liabilityRecorded, false);
+
+      await program.methods
+        .assertInvariants()
+        .accounts({
+          vault: vaultPda,
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+    });
+
+    it("最新サイズのVaultは拡張できない", async () => {
+      await expectError(migrate(), "NothingToMigrate");
+    });
+  });
+
+  describe("拡張の前後でのリワードの確定", () => {
+    // tests/fixtures/legacy_reward_vault.json は拡張前（137byte、残高0.5 SOL）で、
+    // 12345 ポイントを確定済みのVault
+    const user = Keypair.fromSeed(new Uint8Array(32).fill(182));
+    const vaultPda = findVaultPda(program, user.publicKey);
+    const LEGACY_LAST_UPDATE = 1700000000;
+    const LEGACY_REWARD = 12345;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      const sig = await provider.connection.requestAirdrop(
+        user.publicKey,
+        LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    it("拡張しても last_update_time と確定済みのリワードは変わらない", async () => {
+      const before = await provider.connection.getAccountInfo(vaultPda);
+      // discriminator + user + balance + bump の後ろが last_update_time
+      const offset = 8 + 32 + 8 + 1;
+      assert.strictEqual(
+        Number(before.data.readBigInt64LE(offset)),
+        LEGACY_LAST_UPDATE
+      );
+
+      await program.methods
+        .migrateVault()
+        .accounts({ vault: vaultPda, user: user.publicKey })
+        .signers([user])
+        .rpc();
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(vault.lastUpdateTime.toNumber(), LEGACY_LAST_UPDATE);
+      assert.strictEqual(reward.balance.toNumber(), LEGACY_REWARD);
+    });
+
+    it("拡張後の確定は last_update_time から続けて計算される", async () => {
+      await deposit(program, user, 1);
+      const first = await fetchVault(program, user);
+      const firstReward = await fetchRewardBox(program, user);
+      // 拡張前の last_update_time から今までの分が、確定済みの分に加わる
+      assert.ok(first.lastUpdateTime.gtn(LEGACY_LAST_UPDATE));
+      assert.ok(firstReward.balance.gtn(LEGACY_REWARD));
+
+      await sleep(2000);
+      await deposit(program, user, 1);
+      const second = await fetchVault(program, user);
+      const secondReward = await fetchRewardBox(program, user);
+      // 2回目は1回目の確定時刻からの分だけが付く（抜けも二重計上もない）
+      const elapsed = second.lastUpdateTime.sub(first.lastUpdateTime);
+      assert.ok(elapsed.gtn(0));
+      assert.strictEqual(
+        secondReward.balance.sub(firstReward.balance).toString(),
+        first.balance.mul(elapsed).divn(10000).toString()
+      );
+    });
+  });
+
+  describe("Vaultの分割（split_vault）", () => {
+    const NAME = "travel";
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 3 * LAMPORTS_PER_SOL);
+    });
+
+    const split = (amount, name) =>
+      program.methods
+        .splitVault(new anchor.BN(amount), name)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          newVault: findVaultPda(program, user.publicKey, name),
+          newRewardBox: findRewardPda(program, user.publicKey, name),
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    it("元本が新しいVaultへ移り、元のVaultのリワードは確定される", async () => {
+      const before = await fetchVault(program, user);
+      await sleep(2000);
+      await split(LAMPORTS_PER_SOL, NAME);
+
+      const source = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(source.balance.toNumber(), 2 * LAMPORTS_PER_SOL);
+      // 分割前の残高で、経過秒数分のリワードが確定している
+      const secs = source.lastUpdateTime.sub(before.lastUpdateTime);
+      assert.ok(secs.gtn(0));
+      assert.strictEqual(
+        reward.balance.toString(),
+        before.balance.mul(secs).divn(10000).toString()
+      );
+
+      const newVaultPda = findVaultPda(program, user.publicKey, NAME);
+      const created = await program.account.vault.fetch(newVaultPda);
+      assert.ok(created.user.equals(user.publicKey));
+      assert.strictEqual(created.balance.toNumber(), LAMPORTS_PER_SOL);
+      assert.strictEqual(
+        Buffer.from(created.name.slice(0, created.nameLen)).toString(),
+        NAME
+      );
+      const newReward = await program.account.rewardBox.fetch(
+        findRewardPda(program, user.publicKey, NAME)
+      );
+      assert.strictEqual(newReward.balance.toNumber(), 0);
+
+      // 両方のVaultで lamports = 残高 + レント が保たれている
+      for (const name of ["", NAME]) {
+        await program.methods
+          .assertInvariants()
+          .accounts({
+            vault: findVaultPda(program, user.publicKey, name),
+            rewardBox: findRewardPda(program, user.publicKey, name),
+          })
+          .rpc();
+      }
+    });
+
+    it("名前付きVaultにも入金できる", async () => {
+      await program.methods
+        .deposit(new anchor.BN(LAMPORTS_PER_SOL), null)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey, NAME),
+          rewardBox: findRewardPda(program, user.publicKey, NAME),
+          matchPool: null,
+          depositSources: null,
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      const vault = await program.account.vault.fetch(
+        findVaultPda(program, user.publicKey, NAME)
+      );
+      assert.strictEqual(vault.balance.toNumber(), 2 * LAMPORTS_PER_SOL);
+    });
+
+    it("残高を超える分割は拒否される", async () => {
+      await expectError(
+        split(10 * LAMPORTS_PER_SOL, "too-much"),
+        "InsufficientBalance"
+      );
+    });
+  });
+
+  describe("Vaultの統合（merge_vaults）", () => {
+    const NAME = "merge-me";
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 3 * LAMPORTS_PER_SOL);
+      await program.methods
+        .splitVault(new anchor.BN(LAMPORTS_PER_SOL), NAME)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          newVault: findVaultPda(program, user.publicKey, NAME),
+          newRewardBox: findRewardPda(program, user.publicKey, NAME),
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    const merge = (sourceName, name) =>
+      program.methods
+        .mergeVaults()
+        .accounts({
+          sourceVault: findVaultPda(program, user.publicKey, sourceName),
+          sourceRewardBox: findRewardPda(program, user.publicKey, sourceName),
+          vault: findVaultPda(program, user.publicKey, name),
+          rewardBox: findRewardPda(program, user.publicKey, name),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    it("同じVault同士は統合できない", async () => {
+      await expectError(merge(NAME, NAME), "CannotMergeSameVault");
+    });
+
+    it("元本とリワードが合算され、統合元は閉じられる", async () => {
+      const sourcePda = findVaultPda(program, user.publicKey, NAME);
+      await sleep(2000);
+      const source = await program.account.vault.fetch(sourcePda);
+      const sourceReward = await program.account.rewardBox.fetch(
+        findRewardPda(program, user.publicKey, NAME)
+      );
+      const dest = await fetchVault(program, user);
+      const destReward = await fetchRewardBox(program, user);
+
+      await merge(NAME, "");
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(vault.balance.toNumber(), 3 * LAMPORTS_PER_SOL);
+
+      // 統合時点で両方のリワードが確定してから合算されている
+      const now = vault.lastUpdateTime;
+      const expected = destReward.balance
+        .add(sourceReward.balance)
+        .add(dest.balance.mul(now.sub(dest.lastUpdateTime)).divn(10000))
+        .add(source.balance.mul(now.sub(source.lastUpdateTime)).divn(10000));
+      assert.strictEqual(reward.balance.toString(), expected.toString());
+
+      assert.strictEqual(
+        await provider.connection.getAccountInfo(sourcePda),
+        null
+      );
+      assert.strictEqual(
+        await provider.connection.getAccountInfo(
+          findRewardPda(program, user.publicKey, NAME)
+        ),
+        null
+      );
+    });
+
+    it("累計と手数料の免除が統合先に引き継がれる", async () => {
+      const DEST = "merge-totals";
+      await ensureTreasury(program);
+      await program.methods
+        .splitVault(new anchor.BN(LAMPORTS_PER_SOL), DEST)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          newVault: findVaultPda(program, user.publicKey, DEST),
+          newRewardBox: findRewardPda(program, user.publicKey, DEST),
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      await program.methods
+        .setFeeExemptionPrice(new anchor.BN(LAMPORTS_PER_SOL / 100))
+        .rpc();
+      try {
+        await program.methods
+          .buyFeeExemption()
+          .accounts({
+            vault: findVaultPda(program, user.publicKey),
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+      } finally {
+        await program.methods.setFeeExemptionPrice(new anchor.BN(0)).rpc();
+      }
+
+      const destPda = findVaultPda(program, user.publicKey, DEST);
+      const source = await fetchVault(program, user);
+      const dest = await program.account.vault.fetch(destPda);
+      assert.strictEqual(source.feeExempt, true);
+      assert.strictEqual(dest.feeExempt, false);
+
+      await merge("", DEST);
+
+      const merged = await program.account.vault.fetch(destPda);
+      assert.ok(
+        merged.totalDeposited.eq(
+          source.totalDeposited.add(dest.totalDeposited)
+        )
+      );
+      assert.ok(
+        merged.totalRewardsClaimed.eq(
+          source.totalRewardsClaimed.add(dest.totalRewardsClaimed)
+        )
+      );
+      assert.ok(
+        merged.totalFeesPaid.eq(source.totalFeesPaid.add(dest.totalFeesPaid))
+      );
+      assert.strictEqual(merged.feeExempt, true);
+    });
+  });
+
+  describe("早期出金ペナルティの送り先", () => {
+    const PENALTY_BPS = 1000; // 10%
+    const AMOUNT = LAMPORTS_PER_SOL / 10;
+    const PENALTY = (AMOUNT * PENALTY_BPS) / 10000;
+    const incinerator = new anchor.web3.PublicKey(
+      "1nc1nerator11111111111111111111111111111111"
+    );
+    let user;
+    let charity;
+
+    async function setPenalty(bps, destination, charityKey) {
+      await program.methods
+        .setEarlyWithdrawPenalty(bps, destination, charityKey)
+        .rpc();
+    }
+
+    const earlyWithdraw = (penaltyRecipient) =>
+      program.methods
+        .earlyWithdraw(new anchor.BN(AMOUNT))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          penaltyRecipient,
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    // トランザクション前後の残高差（焼却アドレスは後から残高を読めないため）
+    async function balanceChange(signature, pubkey) {
+      await provider.connection.confirmTransaction(signature, "confirmed");
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const keys = tx.transaction.message.getAccountKeys().staticAccountKeys;
+      const i = keys.findIndex((k) => k.equals(pubkey));
+      return tx.meta.postBalances[i] - tx.meta.preBalances[i];
+    }
+
+    before(async () => {
+      user = await createUser(provider);
+      charity = await createUser(provider, 1);
+      await initVault(program, user);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      await sleep(1000);
+      // リワードをロック付き元本に変換してVaultをロックする
+      await program.methods
+        .convertRewardsToLocked(new anchor.BN(3600))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    after(async () => {
+      await setPenalty(0, { treasury: {} }, anchor.web3.PublicKey.default);
+    });
+
+    it("ペナルティ未設定ならロック中は出金できない", async () => {
+      await expectError(
+        earlyWithdraw(findTreasuryPda(program)),
+        "EarlyWithdrawDisabled"
+      );
+    });
+
+    const modes = [
+      ["Treasury", { treasury: {} }, () => findTreasuryPda(program)],
+      ["Burn（焼却アドレス）", { burn: {} }, () => incinerator],
+      ["Charity", { charity: {} }, () => charity.publicKey],
+    ];
+
+    for (const [label, destination, recipientOf] of modes) {
+      it(`${label} モードではペナルティがその送り先に届く`, async () => {
+        await setPenalty(PENALTY_BPS, destination, charity.publicKey);
+        const recipient = recipientOf();
+        const before = await fetchVault(program, user);
+
+        const sig = await earlyWithdraw(recipient);
+
+        assert.strictEqual(await balanceChange(sig, recipient), PENALTY);
+        const vault = await fetchVault(program, user);
+        assert.strictEqual(
+          before.balance.sub(vault.balance).toNumber(),
+          AMOUNT
+        );
+      });
+    }
+
+    it("設定と異なる送り先は拒否される", async () => {
+      await setPenalty(PENALTY_BPS, { treasury: {} }, charity.publicKey);
+      await expectError(
+        earlyWithdraw(charity.publicKey),
+        "InvalidPenaltyDestination"
+      );
+    });
+  });
+
+  describe("Treasuryの準備金（admin_drain_treasury）", () => {
+    let user;
+
+    const liabilities = async () =>
+      (
+        await program.account.globalEmission.fetch(
+          findGlobalEmissionPda(program)
+        )
+      ).outstandingLiabilities.toNumber();
+
+    const drain = (amount) =>
+      program.methods.adminDrainTreasury(new anchor.BN(amount)).rpc();
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    it("付与されたリワードが未払いリワードとして集計される", async () => {
+      const before = await liabilities();
+      await sleep(1000);
+      await withdraw(program, user, 1000);
+
+      const reward = await fetchRewardBox(program, user);
+      assert.ok(reward.balance.gtn(0));
+      assert.strictEqual(
+        await liabilities(),
+        before + reward.balance.toNumber()
+      );
+    });
+
+    it("未払いリワードを下回る引き出しは拒否され、余剰分は引き出せる", async () => {
+      await fundTreasury(program, (await liabilities()) + 2 * LAMPORTS_PER_SOL);
+      const surplus =
+        (await treasuryAvailable(program, provider)) - (await liabilities());
+
+      await expectError(drain(surplus + 1), "InsufficientReserve");
+
+      const before = await treasuryAvailable(program, provider);
+      await drain(LAMPORTS_PER_SOL);
+      assert.strictEqual(
+        await treasuryAvailable(program, provider),
+        before - LAMPORTS_PER_SOL
+      );
+    });
+  });
+
+  describe("created_at の補完（backfill_created_at）", () => {
+    // tests/fixtures/future_vault.json のVaultは created_at = 0（導入前の状態）
+    const legacyUser = Keypair.fromSeed(new Uint8Array(32).fill(121));
+    const CREATED_AT = 1700000000;
+
+    const backfill = (vault, timestamp) =>
+      program.methods
+        .backfillCreatedAt(new anchor.BN(timestamp))
+        .accounts({ vault })
+        .rpc();
+
+    it("created_at が0のVaultだけ補完でき、2回目は拒否される", async () => {
+      const vaultPda = findVaultPda(program, legacyUser.publicKey);
+      const before = await fetchVault(program, legacyUser);
+      assert.strictEqual(before.createdAt.toNumber(), 0);
+
+      await backfill(vaultPda, CREATED_AT);
+      const vault = await fetchVault(program, legacyUser);
+      assert.strictEqual(vault.createdAt.toNumber(), CREATED_AT);
+
+      await expectError(
+        backfill(vaultPda, CREATED_AT + 1),
+        "CreatedAtAlreadySet"
+      );
+    });
+
+    it("作成時に created_at が入っているVaultは変更できない", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await expectError(
+        backfill(findVaultPda(program, user.publicKey), CREATED_AT),
+        "CreatedAtAlreadySet"
+      );
+    });
+  });
+
+  describe("リワードの自動組み入れ（set_auto_compound）", () => {
+    const INTERVAL_SECS = 3;
+    let user;
+
+    before(async () => {
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await program.methods
+        .setAutoCompound(true, new anchor.BN(INTERVAL_SECS))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    it("間隔内の入金ではリワードは組み入れられない", async () => {
+      await sleep(1000);
+      await deposit(program, user, 1000);
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.ok(reward.balance.gtn(0));
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL + 1000);
+    });
+
+    it("間隔が過ぎた後の入金でリワードが元本に組み入れられる", async () => {
+      await sleep(INTERVAL_SECS * 1000);
+      const before = await fetchVault(program, user);
+      const rewardBefore = await fetchRewardBox(program, user);
+      await deposit(program, user, 1000);
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(reward.balance.toNumber(), 0);
+      // 貯まっていたリワード + 今回確定した分がすべて元本に入っている
+      const secs = vault.lastUpdateTime.sub(before.lastUpdateTime);
+      const accrued = before.balance.mul(secs).divn(10000);
+      const compounded = vault.balance.sub(before.balance).subn(1000);
+      assert.strictEqual(
+        compounded.toString(),
+        rewardBefore.balance.add(accrued).toString()
+      );
+      assert.ok(vault.lastCompoundAt.eq(vault.lastUpdateTime));
+    });
+  });
+
+  describe("販促残高への振り替え（sweep_rewards_to_promo）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(1000);
+    });
+
+    const sweep = () =>
+      program.methods
+        .sweepRewardsToPromo()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    it("リワードは promo_balance に入り、元本とlamportは変わらない", async () => {
+      const vaultPda = findVaultPda(program, user.publicKey);
+      const lamportsBefore = await provider.connection.getBalance(vaultPda);
+
+      await sweep();
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(reward.balance.toNumber(), 0);
+      assert.ok(vault.promoBalance.gtn(0));
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL);
+      assert.strictEqual(
+        await provider.connection.getBalance(vaultPda),
+        lamportsBefore
+      );
+    });
+
+    it("promo_balance は出金できない", async () => {
+      const vault = await fetchVault(program, user);
+      await expectError(
+        withdraw(program, user, vault.balance.add(vault.promoBalance)),
+        "InsufficientBalance"
+      );
+    });
+
+    it("promo_balance もリワードの対象になる", async () => {
+      const before = await fetchVault(program, user);
+      await sleep(1000);
+      await withdraw(program, user, 1000);
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      const secs = vault.lastUpdateTime.sub(before.lastUpdateTime);
+      const expected = before.balance
+        .add(before.promoBalance)
+        .mul(secs)
+        .divn(10000);
+      assert.strictEqual(reward.balance.toString(), expected.toString());
+    });
+  });
+
+  describe("組み入れてから出金（withdraw_after_compound）", () => {
+    let user;
+
+    before(async () => {
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(1000);
+      // 一度リワードを確定させておく
+      await deposit(program, user, 1000);
+    });
+
+    it("リワードを組み入れた後の残高から出金できる", async () => {
+      const before = await fetchVault(program, user);
+      const rewardBefore = await fetchRewardBox(program, user);
+      assert.ok(rewardBefore.balance.gtn(0));
+      // 元本 + 確定済みリワード は通常の出金では引き出せない
+      const amount = before.balance.add(rewardBefore.balance);
+      await expectError(withdraw(program, user, amount), "InsufficientBalance");
+
+      const userBefore = await provider.connection.getBalance(user.publicKey);
+      await program.methods
+        .withdrawAfterCompound(amount)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(reward.balance.toNumber(), 0);
+      // 残るのは今回確定した分のリワードだけ
+      const secs = vault.lastUpdateTime.sub(before.lastUpdateTime);
+      assert.strictEqual(
+        vault.balance.toString(),
+        before.balance.mul(secs).divn(10000).toString()
+      );
+      const received =
+        (await provider.connection.getBalance(user.publicKey)) - userBefore;
+      assert.ok(received > amount.toNumber() - 10000);
+    });
+  });
+
+  describe("アカウントのバージョン", () => {
+    // tests/fixtures/v0_vault.json は最新サイズだが version 0（残高0.5 SOL）のVault
+    const user = Keypair.fromSeed(new Uint8Array(32).fill(135));
+
+    before(async () => {
+      const sig = await provider.connection.requestAirdrop(
+        user.publicKey,
+        LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    it("新規作成したVaultとRewardBoxは最新バージョンになる", async () => {
+      const fresh = await createUser(provider);
+      await initVault(program, fresh);
+      const vault = await fetchVault(program, fresh);
+      const reward = await program.account.rewardBox.fetch(
+        findRewardPda(program, fresh.publicKey)
+      );
+      assert.strictEqual(vault.version, 1);
+      assert.strictEqual(reward.version, 2);
+    });
+
+    it("旧バージョンのVaultは migrate_vault するまで操作できない", async () => {
+      await expectError(
+        deposit(program, user, LAMPORTS_PER_SOL / 10),
+        "UnsupportedAccountVersion"
+      );
+
+      await program.methods
+        .migrateVault()
+        .accounts({ user: user.publicKey })
+        .signers([user])
+        .rpc();
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.version, 1);
+      // サイズは最新のままで、残高も変わらない
+      const info = await provider.connection.getAccountInfo(
+        findVaultPda(program, user.publicKey)
+      );
+      assert.strictEqual(info.data.length, program.account.vault.size);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL / 2);
+
+      await deposit(program, user, LAMPORTS_PER_SOL / 10);
+      const after = await fetchVault(program, user);
+      assert.strictEqual(
+        after.balance.toNumber(),
+        LAMPORTS_PER_SOL / 2 + LAMPORTS_PER_SOL / 10
+      );
+    });
+  });
+
+  describe("利用率による利率調整", () => {
+    const HALF_POINT = 100 * LAMPORTS_PER_SOL;
+    const emissionPda = findGlobalEmissionPda(program);
+    let user;
+
+    const multiplier = async () =>
+      (await program.methods.utilizationMultiplier().view()).toNumber();
+    const totalDeposits = async () =>
+      (
+        await program.account.globalEmission.fetch(emissionPda)
+      ).totalDeposits.toNumber();
+
+    before(async () => {
+      await program.methods
+        .setUtilizationHalfPoint(new anchor.BN(HALF_POINT))
+        .rpc();
+      user = await createUser(provider, 30);
+      await initVault(program, user);
+    });
+
+    after(async () => {
+      await program.methods.setUtilizationHalfPoint(new anchor.BN(0)).rpc();
+    });
+
+    it("入金・出金で元本合計（total_deposits）が増減する", async () => {
+      const before = await totalDeposits();
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+      assert.strictEqual(await totalDeposits(), before + 2 * LAMPORTS_PER_SOL);
+      await withdraw(program, user, LAMPORTS_PER_SOL);
+      assert.strictEqual(await totalDeposits(), before + LAMPORTS_PER_SOL);
+    });
+
+    it("元本合計が増えるほど利率の倍率が下がる", async () => {
+      const before = await multiplier();
+      const expected = (total) =>
+        Math.floor((10000 * HALF_POINT) / (HALF_POINT + total));
+      assert.strictEqual(before, expected(await totalDeposits()));
+
+      await deposit(program, user, 20 * LAMPORTS_PER_SOL);
+      const after = await multiplier();
+      assert.strictEqual(after, expected(await totalDeposits()));
+      assert.ok(after < before);
+    });
+
+    it("調整を無効にすると等倍に戻る", async () => {
+      await program.methods.setUtilizationHalfPoint(new anchor.BN(0)).rpc();
+      assert.strictEqual(await multiplier(), 10000);
+    });
+  });
+
+  describe("出金の事前承認（approve_withdrawal）", () => {
+    let owner;
+    let delegate;
+    let vaultPda;
+    let allowancePda;
+
+    const withdrawAsDelegate = (amount) =>
+      program.methods
+        .withdrawWithAllowance(new anchor.BN(amount))
+        .accounts({
+          vault: vaultPda,
+          rewardBox: findRewardPda(program, owner.publicKey),
+          allowance: allowancePda,
+          delegate: delegate.publicKey,
+        })
+        .signers([delegate])
+        .rpc();
+
+    before(async () => {
+      owner = await createUser(provider);
+      delegate = await createUser(provider, 1);
+      await initVault(program, owner);
+      await deposit(program, owner, 2 * LAMPORTS_PER_SOL);
+      vaultPda = findVaultPda(program, owner.publicKey);
+      allowancePda = anchor.web3.PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("allowance"),
+          vaultPda.toBuffer(),
+          delegate.publicKey.toBuffer(),
+        ],
+        program.programId
+      )[0];
+
+      await program.methods
+        .approveWithdrawal(delegate.publicKey, new anchor.BN(LAMPORTS_PER_SOL))
+        .accounts({
+          vault: vaultPda,
+          allowance: allowancePda,
+          user: owner.publicKey,
+        })
+        .signers([owner])
+        .rpc();
+    });
+
+    it("承認額の範囲内なら delegate が出金でき、承認額が減る", async () => {
+      const before = await provider.connection.getBalance(delegate.publicKey);
+      await withdrawAsDelegate(LAMPORTS_PER_SOL / 2);
+
+      const after = await provider.connection.getBalance(delegate.publicKey);
+      // トランザクション手数料は delegate が払うため、その分の誤差を許容する
+      assert.ok(after > before + LAMPORTS_PER_SOL / 2 - 10000);
+      const allowance = await program.account.allowance.fetch(allowancePda);
+      assert.strictEqual(allowance.amount.toNumber(), LAMPORTS_PER_SOL / 2);
+      const vault = await fetchVault(program, owner);
+      assert.strictEqual(vault.balance.toNumber(), 1.5 * LAMPORTS_PER_SOL);
+    });
+
+    it("承認額を超える出金は拒否される", async () => {
+      await expectError(
+        withdrawAsDelegate(LAMPORTS_PER_SOL),
+        "AllowanceExceeded"
+      );
+      const vault = await fetchVault(program, owner);
+      assert.strictEqual(vault.balance.toNumber(), 1.5 * LAMPORTS_PER_SOL);
+    });
+  });
+
+  describe("RewardBoxがないVault", () => {
+    // tests/fixtures/no_reward_box_vault(2).json はRewardBoxを持たない（残高0.5 SOL）のVault
+    const userA = Keypair.fromSeed(new Uint8Array(32).fill(138));
+    const userB = Keypair.fromSeed(new Uint8Array(32).fill(139));
+
+    before(async () => {
+      for (const user of [userA, userB]) {
+        const sig = await provider.connection.requestAirdrop(
+          user.publicKey,
+          LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(sig);
+      }
+    });
+
+    const assertDeposited = async (user) => {
+      const reward = await program.account.rewardBox.fetch(
+        findRewardPda(program, user.publicKey)
+      );
+      assert.strictEqual(reward.version, 2);
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(
+        vault.balance.toNumber(),
+        LAMPORTS_PER_SOL / 2 + LAMPORTS_PER_SOL / 10
+      );
+      await program.methods
+        .assertInvariants()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+    };
+
+    it("init_reward_box で後からRewardBoxを作ると入金できる", async () => {
+      const rewardPda = findRewardPda(program, userA.publicKey);
+      const info = await provider.connection.getAccountInfo(rewardPda);
+      assert.strictEqual(info, null);
+
+      await program.methods
+        .initRewardBox()
+        .accounts({
+          vault: findVaultPda(program, userA.publicKey),
+          rewardBox: rewardPda,
+          user: userA.publicKey,
+        })
+        .signers([userA])
+        .rpc();
+
+      await deposit(program, userA, LAMPORTS_PER_SOL / 10);
+      await assertDeposited(userA);
+    });
+
+    it("入金時にRewardBoxがなければ自動で作られる", async () => {
+      const rewardPda = findRewardPda(program, userB.publicKey);
+      const info = await provider.connection.getAccountInfo(rewardPda);
+      assert.strictEqual(info, null);
+
+      await deposit(program, userB, LAMPORTS_PER_SOL / 10);
+      await assertDeposited(userB);
+    });
+
+    it("Vaultがなければ init_reward_box も入金もRewardBoxを作らない", async () => {
+      const stranger = await createUser(provider);
+      const rewardPda = findRewardPda(program, stranger.publicKey);
+
+      await expectError(
+        program.methods
+          .initRewardBox()
+          .accounts({
+            vault: findVaultPda(program, stranger.publicKey),
+            rewardBox: rewardPda,
+            user: stranger.publicKey,
+          })
+          .signers([stranger])
+          .rpc(),
+        "AccountNotInitialized"
+      );
+      await expectError(
+        deposit(program, stranger, LAMPORTS_PER_SOL / 10),
+        "AccountNotInitialized"
+      );
+      assert.strictEqual(
+        await provider.connection.getAccountInfo(rewardPda),
+        null
+      );
+    });
+
+    it("他人のVaultに対応するRewardBoxは作れない", async () => {
+      const stranger = await createUser(provider);
+      await expectError(
+        program.methods
+          .initRewardBox()
+          .accounts({
+            vault: findVaultPda(program, userA.publicKey),
+            rewardBox: findRewardPda(program, stranger.publicKey),
+            user: stranger.publicKey,
+          })
+          .signers([stranger])
+          .rpc(),
+        "ConstraintSeeds"
+      );
+    });
+  });
+
+  describe("イベントからの状態の再現", () => {
+    const NAME = "replay";
+    const setThreshold = (threshold, pairs = []) =>
+      program.methods
+        .setAccruedEventThreshold(new anchor.BN(threshold))
+        .remainingAccounts(
+          pairs.map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }))
+        )
+        .rpc();
+
+    // 一連の操作（名前付きVaultへの分割と統合を含む）をして、署名を返す
+    async function runOperations(user) {
+      const vaultPda = findVaultPda(program, user.publicKey);
+      const rewardPda = findRewardPda(program, user.publicKey);
+      const signatures = [];
+
+      signatures.push(await deposit(program, user, 2 * LAMPORTS_PER_SOL));
+      signatures.push(
+        await program.methods
+          .splitVault(new anchor.BN(LAMPORTS_PER_SOL / 2), NAME)
+          .accounts({
+            vault: vaultPda,
+            rewardBox: rewardPda,
+            newVault: findVaultPda(program, user.publicKey, NAME),
+            newRewardBox: findRewardPda(program, user.publicKey, NAME),
+            user: user.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc()
+      );
+      await sleep(1500);
+      signatures.push(await deposit(program, user, LAMPORTS_PER_SOL));
+      await sleep(1000);
+      signatures.push(await withdraw(program, user, LAMPORTS_PER_SOL / 2));
+      signatures.push(
+        await program.methods
+          .transfer(new anchor.BN(LAMPORTS_PER_SOL / 4))
+          .accounts({
+            vault: vaultPda,
+            rewardBox: rewardPda,
+            user: user.publicKey,
+            recipient: provider.wallet.publicKey,
+            recipientOptIn: null,
+          })
+          .signers([user])
+          .rpc()
+      );
+      await sleep(1000);
+      signatures.push(
+        await program.methods
+          .sweepRewardsToPromo()
+          .accounts({
+            vault: vaultPda,
+            rewardBox: rewardPda,
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc()
+      );
+      await sleep(1000);
+      signatures.push(await deposit(program, user, LAMPORTS_PER_SOL / 10));
+      signatures.push(
+        await program.methods
+          .mergeVaults()
+          .accounts({
+            sourceVault: findVaultPda(program, user.publicKey, NAME),
+            sourceRewardBox: findRewardPda(program, user.publicKey, NAME),
+            vault: vaultPda,
+            rewardBox: rewardPda,
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc()
+      );
+      return signatures;
+    }
+
+    // イベントだけを順に適用し、Vaultごとの残高を返す（作成時はどちらも0）
+    async function replay(signatures) {
+      const states = new Map();
+      const stateOf = (vault) => {
+        const key = vault.toBase58();
+        if (!states.has(key)) {
+          states.set(key, {
+            balance: new anchor.BN(0),
+            rewards: new anchor.BN(0),
+          });
+        }
+        return states.get(key);
+      };
+      for (const signature of signatures) {
+        for (const event of await eventsOf(program, provider, signature)) {
+          if (event.name === "balanceDeltaEvent") {
+            const state = stateOf(event.data.vault);
+            state.balance = state.balance.add(event.data.delta);
+          } else if (event.name === "rewardAccruedEvent") {
+            const state = stateOf(event.data.vault);
+            state.rewards = state.rewards.add(event.data.amount);
+          } else if (event.name === "rewardDeltaEvent") {
+            const state = stateOf(event.data.vault);
+            state.rewards = state.rewards.add(event.data.delta);
+          }
+        }
+      }
+      return stateOf;
+    }
+
+    after(async () => {
+      await setThreshold(0);
+    });
+
+    it("一連の操作のイベントだけで balance と reward_box.balance を再現できる", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      const signatures = await runOperations(user);
+
+      const stateOf = await replay(signatures);
+      const main = stateOf(findVaultPda(program, user.publicKey));
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.ok(main.balance.eq(vault.balance));
+      assert.ok(main.rewards.eq(reward.balance));
+      assert.ok(vault.promoBalance.gtn(0));
+
+      // 統合して閉じたVaultはどちらも0に戻る
+      const merged = stateOf(findVaultPda(program, user.publicKey, NAME));
+      assert.ok(merged.balance.isZero());
+      assert.ok(merged.rewards.isZero());
+    });
+
+    it("まとめて出す設定でも、統合・閾値の引き下げで出していない分が出て再現できる", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      // どの操作でも閾値に届かない値にする
+      await setThreshold("1000000000000000000");
+      const signatures = await runOperations(user);
+
+      // 出していない分（unemitted_reward）だけイベントの合計が足りない
+      const vaultPda = findVaultPda(program, user.publicKey);
+      const rewardPda = findRewardPda(program, user.publicKey);
+      let stateOf = await replay(signatures);
+      let vault = await fetchVault(program, user);
+      let reward = await fetchRewardBox(program, user);
+      assert.ok(vault.unemittedReward.gtn(0));
+      assert.ok(
+        stateOf(vaultPda).rewards.add(vault.unemittedReward).eq(reward.balance)
+      );
+
+      // 統合したVaultの分は merge_vaults で出ているので0に戻る
+      const merged = stateOf(findVaultPda(program, user.publicKey, NAME));
+      assert.ok(merged.balance.isZero());
+      assert.ok(merged.rewards.isZero());
+
+      // 閾値を下げるときに渡したVaultの分も出る
+      signatures.push(await setThreshold(0, [vaultPda, rewardPda]));
+      stateOf = await replay(signatures);
+      vault = await fetchVault(program, user);
+      reward = await fetchRewardBox(program, user);
+      assert.ok(vault.unemittedReward.isZero());
+      assert.ok(stateOf(vaultPda).balance.eq(vault.balance));
+      assert.ok(stateOf(vaultPda).rewards.eq(reward.balance));
+    });
+  });
+
+  describe("ロック期間の上限", () => {
+    const MAX_LOCK_SECS = 3600;
+    let user;
+
+    const convert = (lockSecs) =>
+      program.methods
+        .convertRewardsToLocked(new anchor.BN(lockSecs))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      await program.methods
+        .setMaxLockDuration(new anchor.BN(MAX_LOCK_SECS))
+        .rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      await sleep(1000);
+    });
+
+    after(async () => {
+      await program.methods.setMaxLockDuration(new anchor.BN(0)).rpc();
+    });
+
+    it("上限を超えるロックは拒否される", async () => {
+      await expectError(convert(MAX_LOCK_SECS + 1), "LockDurationTooLong");
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.lockUntil.toNumber(), 0);
+    });
+
+    it("上限ちょうどのロックは受け付けられる", async () => {
+      await convert(MAX_LOCK_SECS);
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(
+        vault.lockUntil.toNumber(),
+        vault.lastUpdateTime.toNumber() + MAX_LOCK_SECS
+      );
+    });
+  });
+
+  describe("Vaultの存在確認（peek）", () => {
+    const peek = (user) =>
+      program.methods.peek().accounts({ user: user.publicKey }).view();
+
+    it("作成済みのVaultは残高とレント免除の状態を返す", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+
+      const result = await peek(user);
+      assert.strictEqual(result.exists, true);
+      assert.strictEqual(result.balance.toNumber(), LAMPORTS_PER_SOL);
+      assert.strictEqual(result.rentExempt, true);
+    });
+
+    it("未作成のVaultでもエラーにならず exists = false を返す", async () => {
+      const result = await peek(Keypair.generate());
+      assert.strictEqual(result.exists, false);
+      assert.strictEqual(result.balance.toNumber(), 0);
+    });
+  });
+
+  describe("他人のVaultからの出金", () => {
+    let owner;
+    let attacker;
+
+    before(async () => {
+      owner = await createUser(provider);
+      attacker = await createUser(provider);
+      await initVault(program, owner);
+      await initVault(program, attacker);
+      await deposit(program, owner, LAMPORTS_PER_SOL);
+    });
+
+    // アドレスを直接指定して、他人のVault・RewardBoxで出金を試みる
+    const withdrawFrom = (vaultOwner) =>
+      program.methods
+        .withdraw(new anchor.BN(LAMPORTS_PER_SOL / 2))
+        .accounts({
+          vault: findVaultPda(program, vaultOwner.publicKey),
+          rewardBox: findRewardPda(program, vaultOwner.publicKey),
+          user: attacker.publicKey,
+        })
+        .signers([attacker])
+        .rpc();
+
+    it("他人のVaultを指定した出金は制約で拒否され、残高は変わらない", async () => {
+      await expectError(withdrawFrom(owner), "ConstraintSeeds");
+      const vault = await fetchVault(program, owner);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL);
+    });
+
+    it("自分のVaultからは出金できる（明示的な持ち主チェックを通過する）", async () => {
+      await deposit(program, attacker, LAMPORTS_PER_SOL);
+      await withdrawFrom(attacker);
+      const vault = await fetchVault(program, attacker);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL / 2);
+    });
+  });
+
+  describe("Vaultの凍結", () => {
+    let user;
+
+    const setFrozen = (frozen) =>
+      program.methods
+        .setVaultFrozen(frozen)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    it("凍結中はリワードが付かず、出金もできない", async () => {
+      await setFrozen(true);
+      const frozenAt = await fetchRewardBox(program, user);
+
+      await sleep(2000);
+      await expectError(
+        withdraw(program, user, LAMPORTS_PER_SOL / 2),
+        "VaultFrozen"
+      );
+      await setFrozen(false);
+
+      // 凍結期間の分は付与されない
+      const reward = await fetchRewardBox(program, user);
+      assert.ok(reward.balance.eq(frozenAt.balance));
+    });
+
+    it("解除後はリワードの付与が再開する", async () => {
+      const before = await fetchRewardBox(program, user);
+      await sleep(1000);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const after = await fetchRewardBox(program, user);
+      assert.ok(after.balance.gt(before.balance));
+    });
+  });
+
+  describe("Vault間のリワード移動（move_rewards）", () => {
+    const NAME = "gift";
+    let user;
+    let other;
+
+    const moveRewards = (amount, toOwner, toName = "") =>
+      program.methods
+        .moveRewards(new anchor.BN(amount))
+        .accounts({
+          fromVault: findVaultPda(program, user.publicKey),
+          fromRewardBox: findRewardPda(program, user.publicKey),
+          toVault: findVaultPda(program, toOwner.publicKey, toName),
+          toRewardBox: findRewardPda(program, toOwner.publicKey, toName),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      user = await createUser(provider);
+      other = await createUser(provider);
+      await initVault(program, user);
+      await initVault(program, other);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+      // 移動先は1 lamportだけの名前付きVault（自身のリワードはほぼ付かない）
+      await program.methods
+        .splitVault(new anchor.BN(1), NAME)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          newVault: findVaultPda(program, user.publicKey, NAME),
+          newRewardBox: findRewardPda(program, user.publicKey, NAME),
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      await sleep(2000);
+    });
+
+    it("同じ持ち主のVault間でリワードポイントを移せる", async () => {
+      const before = await fetchRewardBox(program, user);
+      const amount = before.balance.toNumber();
+      assert.ok(amount > 0);
+
+      const events = await eventsOf(
+        program,
+        provider,
+        await moveRewards(amount, user, NAME)
+      );
+
+      const from = await fetchRewardBox(program, user);
+      const to = await program.account.rewardBox.fetch(
+        findRewardPda(program, user.publicKey, NAME)
+      );
+      assert.strictEqual(to.balance.toNumber(), amount);
+      // 移動元には、移動の直前に確定した分から amount を引いた残りがある
+      const accrued = events.find((e) => e.name === "rewardAccruedEvent");
+      assert.strictEqual(
+        from.balance.toNumber(),
+        accrued.data.rewardBoxBalance.toNumber() - amount
+      );
+    });
+
+    it("持ち主の異なるVaultへは移せない", async () => {
+      await expectError(moveRewards(1, other), "ConstraintSeeds");
+    });
+
+    it("残高を超えるポイントは移せない", async () => {
+      await expectError(
+        moveRewards(LAMPORTS_PER_SOL, user, NAME),
+        "InsufficientRewards"
+      );
+    });
+  });
+
+  describe("リワードのSOL受け取り（claim_reward）", () => {
+    let user;
+
+    // 受け取り前後の残高差（トランザクション手数料はプロバイダが払うので受取額だけが出る）
+    async function claimAndMeasure() {
+      const signature = await program.methods
+        .claimReward()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          leaderboard: null,
+        })
+        .signers([user])
+        .rpc();
+      const events = await eventsOf(program, provider, signature);
+      const claimed = events.find((e) => e.name === "rewardClaimedEvent");
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const keys = tx.transaction.message.getAccountKeys().staticAccountKeys;
+      const i = keys.findIndex((k) => k.equals(user.publicKey));
+      return {
+        amount: claimed.data.amount.toNumber(),
+        fee: claimed.data.fee.toNumber(),
+        received: tx.meta.postBalances[i] - tx.meta.preBalances[i],
+      };
+    }
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await program.methods.setClaimFee(0).rpc();
+    });
+
+    it("手数料0ならリワード全額を受け取れる", async () => {
+      await sleep(1000);
+      const { amount, fee, received } = await claimAndMeasure();
+      assert.ok(amount > 0);
+      assert.strictEqual(fee, 0);
+      assert.strictEqual(received, amount);
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(reward.balance.toNumber(), 0);
+    });
+
+    it("手数料を差し引いた額を受け取り、手数料はTreasuryに残る", async () => {
+      await program.methods.setClaimFee(1000).rpc(); // 10%
+      await sleep(1000);
+      const { amount, fee, received } = await claimAndMeasure();
+      assert.strictEqual(fee, Math.floor((amount * 1000) / 10000));
+      assert.ok(fee > 0);
+      assert.strictEqual(received, amount - fee);
+    });
+  });
+
+  describe("Vaultのラベル（set_label）", () => {
+    let user;
+
+    const setLabel = (label) =>
+      program.methods
+        .setLabel(label)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    const readLabel = async () => {
+      const vault = await fetchVault(program, user);
+      return Buffer.from(vault.label.slice(0, vault.labelLen)).toString("utf8");
+    };
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    it("設定したラベルを読み戻せる（seeds の name は変わらない）", async () => {
+      await setLabel("旅行の貯金");
+      assert.strictEqual(await readLabel(), "旅行の貯金");
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.nameLen, 0);
+
+      // 短いラベルで上書きしても前の値が残らない
+      await setLabel("trip");
+      assert.strictEqual(await readLabel(), "trip");
+      const after = await fetchVault(program, user);
+      assert.ok(after.label.slice(4).every((b) => b === 0));
+    });
+
+    it("32byteを超えるラベルは拒否される", async () => {
+      await expectError(setLabel("a".repeat(33)), "InvalidLabel");
+      assert.strictEqual(await readLabel(), "trip");
+    });
+  });
+
+  describe("報酬計算方式の変更をまたぐ期間", () => {
+    const depositAmount = 10 * LAMPORTS_PER_SOL;
+    const CAP = new anchor.BN(5 * LAMPORTS_PER_SOL);
+    let user;
+
+    before(async () => {
+      user = await createUser(provider, 20);
+      await initVault(program, user);
+      await deposit(program, user, depositAmount);
+    });
+
+    after(async () => {
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+    });
+
+    it("変更前の部分は旧方式、変更後の部分は新方式で計算される", async () => {
+      const before = await fetchVault(program, user);
+      await sleep(2000);
+      await program.methods.setRewardStrategy({ capped: {} }).rpc();
+      const config = await program.account.config.fetch(findConfigPda(program));
+      await sleep(2000);
+      await deposit(program, user, 1);
+
+      const after = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      const boundary = config.lastConfigUpdate;
+      const beforeSecs = boundary.sub(before.lastUpdateTime);
+      const afterSecs = after.lastUpdateTime.sub(boundary);
+      assert.ok(beforeSecs.gtn(0) && afterSecs.gtn(0));
+
+      // Linear: 残高 × 秒 / 10000、Capped: min(残高, 5 SOL) × 秒 / 10000
+      const balance = new anchor.BN(depositAmount);
+      const expected = balance
+        .mul(beforeSecs)
+        .divn(10000)
+        .add(anchor.BN.min(balance, CAP).mul(afterSecs).divn(10000));
+      assert.ok(reward.balance.eq(expected));
+      assert.ok(config.configVersion > 0);
+    });
+  });
+
+  describe("スポンサー負担の初期化（initialize_sponsored）", () => {
+    it("スポンサーがレントを払い、Vaultの持ち主はユーザーになる", async () => {
+      const sponsor = await createUser(provider);
+      const user = Keypair.generate(); // SOLを持たないユーザー
+
+      await program.methods
+        .initializeSponsored()
+        .accounts({ user: user.publicKey, payer: sponsor.publicKey })
+        .signers([user, sponsor])
+        .rpc();
+
+      const vaultPda = findVaultPda(program, user.publicKey);
+      const vault = await fetchVault(program, user);
+      assert.ok(vault.user.equals(user.publicKey));
+      assert.strictEqual(vault.balance.toNumber(), 0);
+      assert.strictEqual(vault.version, 1);
+      assert.strictEqual(
+        await provider.connection.getBalance(user.publicKey),
+        0
+      );
+
+      // スポンサーの残高は2つのアカウントのレント分だけ減る
+      const rewardInfo = await provider.connection.getAccountInfo(
+        findRewardPda(program, user.publicKey)
+      );
+      const vaultInfo = await provider.connection.getAccountInfo(vaultPda);
+      const sponsorBalance = await provider.connection.getBalance(
+        sponsor.publicKey
+      );
+      assert.strictEqual(
+        sponsorBalance,
+        10 * LAMPORTS_PER_SOL - vaultInfo.lamports - rewardInfo.lamports
+      );
+
+      await program.methods
+        .assertInvariants()
+        .accounts({
+          vault: vaultPda,
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+    });
+  });
+
+  describe("リワード確定の最小間隔", () => {
+    const INTERVAL_SECS = 3;
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await program.methods
+        .setMinAccrualInterval(new anchor.BN(INTERVAL_SECS))
+        .rpc();
+    });
+
+    after(async () => {
+      await program.methods.setMinAccrualInterval(new anchor.BN(0)).rpc();
+    });
+
+    it("間隔内は確定が見送られ、経過後にまとめて確定される", async () => {
+      const start = await fetchVault(program, user);
+
+      // 間隔内: リワードも last_update_time も変わらない
+      await deposit(program, user, 1);
+      const deferred = await fetchVault(program, user);
+      assert.ok(deferred.lastUpdateTime.eq(start.lastUpdateTime));
+      const rewardDeferred = await fetchRewardBox(program, user);
+      assert.strictEqual(rewardDeferred.balance.toNumber(), 0);
+
+      // 経過後: 最初からの期間全体のリワードが確定する（取りこぼしなし）
+      await sleep((INTERVAL_SECS + 1) * 1000);
+      const beforeFinal = await fetchVault(program, user);
+      await deposit(program, user, 1);
+      const after = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      const elapsed = after.lastUpdateTime.sub(start.lastUpdateTime);
+      assert.ok(elapsed.gten(INTERVAL_SECS));
+      assert.ok(
+        reward.balance.eq(beforeFinal.balance.mul(elapsed).divn(10000))
+      );
+    });
+  });
+
+  describe("Vault間の元本移動（vault_to_vault）", () => {
+    const NAME = "house";
+    let user;
+
+    const vaultToVault = (amount) =>
+      program.methods
+        .vaultToVault(new anchor.BN(amount))
+        .accounts({
+          fromVault: findVaultPda(program, user.publicKey),
+          fromRewardBox: findRewardPda(program, user.publicKey),
+          toVault: findVaultPda(program, user.publicKey, NAME),
+          toRewardBox: findRewardPda(program, user.publicKey, NAME),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 3 * LAMPORTS_PER_SOL);
+      await program.methods
+        .splitVault(new anchor.BN(LAMPORTS_PER_SOL), NAME)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          newVault: findVaultPda(program, user.publicKey, NAME),
+          newRewardBox: findRewardPda(program, user.publicKey, NAME),
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    it("両方の残高が移動額だけ変わり、ウォレットの残高は変わらない", async () => {
+      const walletBefore = await provider.connection.getBalance(user.publicKey);
+      await vaultToVault(LAMPORTS_PER_SOL / 2);
+
+      const from = await fetchVault(program, user);
+      const to = await program.account.vault.fetch(
+        findVaultPda(program, user.publicKey, NAME)
+      );
+      assert.strictEqual(from.balance.toNumber(), 1.5 * LAMPORTS_PER_SOL);
+      assert.strictEqual(to.balance.toNumber(), 1.5 * LAMPORTS_PER_SOL);
+      assert.strictEqual(
+        await provider.connection.getBalance(user.publicKey),
+        walletBefore
+      );
+
+      // lamports と balance の整合も保たれている
+      for (const name of ["", NAME]) {
+        await program.methods
+          .assertInvariants()
+          .accounts({
+            vault: findVaultPda(program, user.publicKey, name),
+            rewardBox: findRewardPda(program, user.publicKey, name),
+          })
+          .rpc();
+      }
+    });
+
+    it("残高を超える移動は拒否される", async () => {
+      await expectError(
+        vaultToVault(10 * LAMPORTS_PER_SOL),
+        "InsufficientBalance"
+      );
+    });
+  });
+
+  describe("Treasuryの原資不足時の一部受け取り", () => {
+    // tests/fixtures/rich_reward_vault(_reward).json は100 SOL分のリワードポイントを持つVault
+    const user = Keypair.fromSeed(new Uint8Array(32).fill(151));
+    const POINTS = 100 * LAMPORTS_PER_SOL;
+
+    const claim = async () => {
+      const signature = await program.methods
+        .claimReward()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          leaderboard: null,
+        })
+        .signers([user])
+        .rpc();
+      return eventsOf(program, provider, signature);
+    };
+
+    it("原資が足りなければ払える分だけ支払い、残りはRewardBoxに残る", async () => {
+      const available = await treasuryAvailable(program, provider);
+      assert.ok(available > 0 && available < POINTS);
+
+      const events = await claim();
+      const partial = events.find((e) => e.name === "partialClaimEvent");
+      assert.ok(partial);
+      assert.strictEqual(partial.data.paid.toNumber(), available);
+      assert.strictEqual(
+        partial.data.remaining.toNumber(),
+        POINTS - available
+      );
+
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(reward.balance.toNumber(), POINTS - available);
+      assert.strictEqual(await treasuryAvailable(program, provider), 0);
+      assert.strictEqual(
+        await provider.connection.getBalance(user.publicKey),
+        available
+      );
+    });
+
+    it("補充後は残りを全額受け取れる", async () => {
+      const remaining = (await fetchRewardBox(program, user)).balance;
+      await fundTreasury(program, 2 * POINTS);
+
+      const events = await claim();
+      assert.ok(!events.some((e) => e.name === "partialClaimEvent"));
+      const claimed = events.find((e) => e.name === "rewardClaimedEvent");
+      assert.ok(claimed.data.amount.eq(remaining));
+
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(reward.balance.toNumber(), 0);
+      assert.strictEqual(
+        await provider.connection.getBalance(user.publicKey),
+        POINTS
+      );
+    });
+  });
+
+  describe("貯金目標の達成ボーナス", () => {
+    const GOAL = 2 * LAMPORTS_PER_SOL;
+    let user;
+
+    before(async () => {
+      await program.methods.setGoalBonus(10000).rpc(); // 達成中は2倍
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await program.methods
+        .setGoalAmount(new anchor.BN(GOAL))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    after(async () => {
+      await program.methods.setGoalBonus(0).rpc();
+    });
+
+    it("目標未達のうちは基本の利率", async () => {
+      const { reward, base } = await accrueOnce(program, user);
+      assert.ok(reward.eq(base));
+    });
+
+    it("目標に達すると上乗せされる", async () => {
+      await deposit(program, user, GOAL);
+      const { reward, base } = await accrueOnce(program, user);
+      assert.ok(reward.eq(base.muln(2)));
+    });
+
+    it("目標を下回ると基本の利率に戻る", async () => {
+      await withdraw(program, user, 2 * LAMPORTS_PER_SOL);
+      const { reward, base } = await accrueOnce(program, user);
+      assert.ok(reward.eq(base));
+    });
+  });
+
+  describe("リワードの一括確定（crank_accrue）", () => {
+    const MAX_VAULT_PAIRS = 8;
+    let users;
+    let cranker;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      cranker = await createUser(provider, 1);
+      users = [];
+      for (let i = 0; i < MAX_VAULT_PAIRS + 1; i++) {
+        const user = await createUser(provider, 2);
+        await initVault(program, user);
+        await deposit(program, user, LAMPORTS_PER_SOL);
+        users.push(user);
+      }
+    });
+
+    const crankAccrue = (targets) =>
+      program.methods
+        .crankAccrue()
+        .accounts({ cranker: cranker.publicKey, leaderboard: null })
+        .remainingAccounts(
+          targets.flatMap((user) => [
+            {
+              pubkey: findVaultPda(program, user.publicKey),
+              isWritable: true,
+              isSigner: false,
+            },
+            {
+              pubkey: findRewardPda(program, user.publicKey),
+              isWritable: true,
+              isSigner: false,
+            },
+          ])
+        )
+        .signers([cranker])
+        .rpc();
+
+    it("上限ちょうどの組数なら全Vaultのリワードが確定する", async () => {
+      await sleep(2000);
+      const targets = users.slice(0, MAX_VAULT_PAIRS);
+      const before = await Promise.all(
+        targets.map((u) => fetchVault(program, u))
+      );
+
+      await crankAccrue(targets);
+
+      for (let i = 0; i < targets.length; i++) {
+        const after = await fetchVault(program, targets[i]);
+        assert.ok(after.lastUpdateTime.gt(before[i].lastUpdateTime));
+        assert.ok(
+          (await fetchRewardBox(program, targets[i])).balance.toNumber() > 0
+        );
+      }
+    });
+
+    it("上限を1組でも超えると拒否される", async () => {
+      await expectError(crankAccrue(users), "TooManyVaultPairs");
+    });
+  });
+
+  describe("リワードの送り先（set_reward_destination / accrue_to_destination）", () => {
+    let owner;
+    let poolOwner;
+    let ownerVault;
+    let ownerRewardBox;
+    let poolRewardBox;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      owner = await createUser(provider);
+      poolOwner = await createUser(provider);
+      await initVault(program, owner);
+      await initVault(program, poolOwner);
+      ownerVault = findVaultPda(program, owner.publicKey);
+      ownerRewardBox = findRewardPda(program, owner.publicKey);
+      poolRewardBox = findRewardPda(program, poolOwner.publicKey);
+
+      await deposit(program, owner, 5 * LAMPORTS_PER_SOL);
+      await program.methods
+        .setRewardDestination(poolRewardBox)
+        .accounts({ vault: ownerVault, user: owner.publicKey })
+        .signers([owner])
+        .rpc();
+    });
+
+    const accrueToDestination = (destinationRewardBox) =>
+      program.methods
+        .accrueToDestination()
+        .accounts({ vault: ownerVault, destinationRewardBox })
+        .rpc();
+
+    it("送り先の設定中に確定したリワードは自分のBoxに入らず、送り先に届く", async () => {
+      const ownBefore = (await fetchRewardBox(program, owner)).balance;
+      await sleep(2000);
+      await deposit(program, owner, LAMPORTS_PER_SOL);
+
+      const pending = (await fetchVault(program, owner))
+        .pendingDestinationRewards;
+      assert.ok(pending.toNumber() > 0);
+      assert.ok((await fetchRewardBox(program, owner)).balance.eq(ownBefore));
+
+      const poolBefore = (await fetchRewardBox(program, poolOwner)).balance;
+      await accrueToDestination(poolRewardBox);
+
+      const poolAfter = (await fetchRewardBox(program, poolOwner)).balance;
+      assert.ok(poolAfter.sub(poolBefore).gte(pending));
+      assert.strictEqual(
+        (await fetchVault(program, owner)).pendingDestinationRewards.toNumber(),
+        0
+      );
+      assert.ok((await fetchRewardBox(program, owner)).balance.eq(ownBefore));
+    });
+
+    it("設定と異なる RewardBox には届けられない", async () => {
+      await expectError(
+        accrueToDestination(ownerRewardBox),
+        "InvalidRewardDestination"
+      );
+    });
+
+    it("送り先をdefaultに戻すと自分のBoxに貯まる", async () => {
+      await program.methods
+        .setRewardDestination(anchor.web3.PublicKey.default)
+        .accounts({ vault: ownerVault, user: owner.publicKey })
+        .signers([owner])
+        .rpc();
+      const ownBefore = (await fetchRewardBox(program, owner)).balance;
+      await sleep(2000);
+      await deposit(program, owner, LAMPORTS_PER_SOL);
+      assert.ok((await fetchRewardBox(program, owner)).balance.gt(ownBefore));
+    });
+  });
+
+  describe("複数Vaultのまとめ受け取り（batch_claim）", () => {
+    const NAMES = ["", "batch-a", "batch-b"];
+    let user;
+    let other;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      user = await createUser(provider);
+      other = await createUser(provider);
+      await initVault(program, user);
+      await initVault(program, other);
+      await deposit(program, user, 6 * LAMPORTS_PER_SOL);
+      await deposit(program, other, LAMPORTS_PER_SOL);
+      for (const name of NAMES.slice(1)) {
+        await program.methods
+          .splitVault(new anchor.BN(2 * LAMPORTS_PER_SOL), name)
+          .accounts({
+            vault: findVaultPda(program, user.publicKey),
+            rewardBox: findRewardPda(program, user.publicKey),
+            newVault: findVaultPda(program, user.publicKey, name),
+            newRewardBox: findRewardPda(program, user.publicKey, name),
+            user: user.publicKey,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([user])
+          .rpc();
+      }
+    });
+
+    const pairsOf = (owner, names) =>
+      names.flatMap((name) => [
+        {
+          pubkey: findVaultPda(program, owner.publicKey, name),
+          isWritable: true,
+          isSigner: false,
+        },
+        {
+          pubkey: findRewardPda(program, owner.publicKey, name),
+          isWritable: true,
+          isSigner: false,
+        },
+      ]);
+
+    const batchClaim = (accounts) =>
+      program.methods
+        .batchClaim()
+        .accounts({ user: user.publicKey })
+        .remainingAccounts(accounts)
+        .signers([user])
+        .rpc();
+
+    it("3つのVaultのリワードが1回でSOLとして支払われる", async () => {
+      await sleep(2000);
+      const lamportsBefore = await provider.connection.getBalance(
+        user.publicKey
+      );
+      const signature = await batchClaim(pairsOf(user, NAMES));
+
+      const claimed = (await eventsOf(program, provider, signature)).filter(
+        (e) => e.name === "rewardClaimedEvent"
+      );
+      assert.strictEqual(claimed.length, NAMES.length);
+      const paid = claimed.reduce(
+        (sum, e) => sum + e.data.amount.toNumber() - e.data.fee.toNumber(),
+        0
+      );
+      assert.ok(paid > 0);
+      const lamportsAfter = await provider.connection.getBalance(
+        user.publicKey
+      );
+      assert.strictEqual(lamportsAfter - lamportsBefore, paid);
+
+      for (const name of NAMES) {
+        const box = await program.account.rewardBox.fetch(
+          findRewardPda(program, user.publicKey, name)
+        );
+        assert.strictEqual(box.balance.toNumber(), 0);
+      }
+    });
+
+    it("他人のVaultが混ざっていると拒否される", async () => {
+      await sleep(1000);
+      await expectError(
+        batchClaim([...pairsOf(user, [""]), ...pairsOf(other, [""])]),
+        "NotVaultOwner"
+      );
+    });
+
+    it("上限（8組）を超えると拒否される", async () => {
+      const pairs = Array(9).fill(pairsOf(user, [""])).flat();
+      await expectError(batchClaim(pairs), "TooManyVaultPairs");
+    });
+  });
+
+  describe("実効利率の内訳（get_rate_info）", () => {
+    const GOAL = 3 * LAMPORTS_PER_SOL;
+    let user;
+
+    const rateInfo = () =>
+      program.methods
+        .getRateInfo()
+        .accounts({ vault: findVaultPda(program, user.publicKey) })
+        .view();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods.setGoalBonus(5000).rpc(); // 達成中は1.5倍
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 7 * LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await program.methods.setGoalBonus(0).rpc();
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+    });
+
+    it("ボーナスがなければ等倍で、リワードも基本の利率", async () => {
+      const rate = await rateInfo();
+      assert.strictEqual(rate.earningBalance.toNumber(), 7 * LAMPORTS_PER_SOL);
+      assert.strictEqual(rate.goalBps.toNumber(), 10000);
+      assert.strictEqual(rate.effectiveBps.toNumber(), 10000);
+
+      const { reward, base } = await accrueOnce(program, user);
+      assert.ok(reward.eq(base));
+    });
+
+    it("目標達成ボーナスが実効利率に反映され、確定額と一致する", async () => {
+      await program.methods
+        .setGoalAmount(new anchor.BN(GOAL))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+      const rate = await rateInfo();
+      assert.strictEqual(rate.goalBps.toNumber(), 15000);
+      assert.strictEqual(rate.effectiveBps.toNumber(), 15000);
+
+      const { reward, base } = await accrueOnce(program, user);
+      assert.ok(reward.eq(base.mul(rate.effectiveBps).divn(10000)));
+    });
+
+    it("Tiered ではしきい値超過分の倍率と目標達成ボーナスが掛け合わされる", async () => {
+      await program.methods.setRewardStrategy({ tiered: {} }).rpc();
+      const rate = await rateInfo();
+      // 5 SOL までは等倍、超過分（約2 SOL）は2倍
+      const balance = rate.earningBalance.toNumber();
+      const threshold = 5 * LAMPORTS_PER_SOL;
+      const weighted = threshold + (balance - threshold) * 2;
+      assert.strictEqual(
+        rate.strategyBps.toNumber(),
+        Math.floor((weighted * 10000) / balance)
+      );
+      assert.strictEqual(
+        rate.effectiveBps.toNumber(),
+        Math.floor((rate.strategyBps.toNumber() * 15000) / 10000)
+      );
+    });
+  });
+
+  describe("閉鎖予定のVault（request_close / cancel_close）", () => {
+    let user;
+
+    const update = (method) =>
+      program.methods[method]()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    it("閉鎖予定の間は入金できない", async () => {
+      await update("requestClose");
+      assert.strictEqual((await fetchVault(program, user)).closing, true);
+      await expectError(
+        deposit(program, user, LAMPORTS_PER_SOL),
+        "VaultClosing"
+      );
+    });
+
+    it("出金は閉鎖予定でもできる", async () => {
+      await withdraw(program, user, LAMPORTS_PER_SOL / 2);
+      assert.strictEqual(
+        (await fetchVault(program, user)).balance.toNumber(),
+        LAMPORTS_PER_SOL / 2
+      );
+    });
+
+    it("取り消すと再び入金できる", async () => {
+      await update("cancelClose");
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      assert.strictEqual(
+        (await fetchVault(program, user)).balance.toNumber(),
+        (3 * LAMPORTS_PER_SOL) / 2
+      );
+    });
+  });
+
+  describe("Vault PDAの余剰SOLの回収（sweep_surplus）", () => {
+    const SURPLUS = 5000;
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    const sweepSurplus = () =>
+      program.methods
+        .sweepSurplus()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    it("直接送られたSOLが持ち主に返り、不変条件が戻る", async () => {
+      const vaultPda = findVaultPda(program, user.publicKey);
+      await sendLamports(provider, vaultPda, SURPLUS);
+
+      const before = await provider.connection.getBalance(user.publicKey);
+      const signature = await sweepSurplus();
+      const after = await provider.connection.getBalance(user.publicKey);
+      assert.strictEqual(after - before, SURPLUS);
+
+      const events = await eventsOf(program, provider, signature);
+      const swept = events.find((e) => e.name === "surplusSweptEvent");
+      assert.strictEqual(swept.data.amount.toNumber(), SURPLUS);
+
+      await program.methods
+        .assertInvariants()
+        .accounts({
+          vault: vaultPda,
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+      assert.strictEqual(
+        (await fetchVault(program, user)).balance.toNumber(),
+        LAMPORTS_PER_SOL
+      );
+    });
+
+    it("余剰がなければ拒否される", async () => {
+      await expectError(sweepSurplus(), "NoSurplus");
+    });
+  });
+
+  describe("リワードの端数の繰り越し（reward_acc）", () => {
+    const BALANCE = 13333; // 毎秒 1.3333 ポイント
+    const ACCRUALS = 5;
+    let user;
+    let cranker;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      cranker = await createUser(provider, 1);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, BALANCE);
+    });
+
+    const accrue = () =>
+      program.methods
+        .crankAccrue()
+        .accounts({ cranker: cranker.publicKey, leaderboard: null })
+        .remainingAccounts([
+          {
+            pubkey: findVaultPda(program, user.publicKey),
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: findRewardPda(program, user.publicKey),
+            isWritable: true,
+            isSigner: false,
+          },
+        ])
+        .signers([cranker])
+        .rpc();
+
+    it("細かく何度確定しても、通しで計算した場合と同じリワードになる", async () => {
+      const vault = await fetchVault(program, user);
+      const start = vault.lastUpdateTime;
+      const rewardBefore = await fetchRewardBox(program, user);
+      for (let i = 0; i < ACCRUALS; i++) {
+        await sleep(1000);
+        await accrue();
+      }
+      const end = (await fetchVault(program, user)).lastUpdateTime;
+      const elapsed = end.sub(start).toNumber();
+      const box = await fetchRewardBox(program, user);
+
+      // 通しの理論値: 残高 × 0.01% × 経過秒数（端数は reward_acc に残る）
+      const ideal = vault.balance.toNumber() * elapsed;
+      assert.strictEqual(
+        box.balance.sub(rewardBefore.balance).toNumber(),
+        Math.floor(ideal / 10000)
+      );
+      const acc = new anchor.BN(ideal % 10000).mul(
+        new anchor.BN(10).pow(new anchor.BN(13))
+      );
+      assert.strictEqual(box.rewardAcc.toString(), acc.toString());
+    });
+  });
+
+  describe("受取人（set_beneficiary / claim_as_beneficiary）", () => {
+    const INACTIVITY_SECS = 3;
+    let user;
+    let beneficiary;
+
+    before(async () => {
+      user = await createUser(provider);
+      beneficiary = await createUser(provider, 1);
+      await initVault(program, user);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+      await program.methods
+        .setBeneficiary(beneficiary.publicKey, new anchor.BN(INACTIVITY_SECS))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    const claim = () =>
+      program.methods
+        .claimAsBeneficiary()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          beneficiary: beneficiary.publicKey,
+        })
+        .signers([beneficiary])
+        .rpc();
+
+    it("無操作期間が経過する前は受け取れない", async () => {
+      await expectError(claim(), "OwnerStillActive");
+    });
+
+    it("持ち主が操作するとタイマーが戻る", async () => {
+      await sleep(2000);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(2000);
+      await expectError(claim(), "OwnerStillActive");
+    });
+
+    it("持ち主以外の crank_accrue ではタイマーは戻らない", async () => {
+      const cranker = await createUser(provider, 1);
+      const vaultPda = findVaultPda(program, user.publicKey);
+      const activity = (await fetchVault(program, user)).lastOwnerActivity;
+      for (let i = 0; i <= INACTIVITY_SECS; i++) {
+        await sleep(1000);
+        await program.methods
+          .crankAccrue()
+          .accounts({ cranker: cranker.publicKey, leaderboard: null })
+          .remainingAccounts([
+            { pubkey: vaultPda, isWritable: true, isSigner: false },
+            {
+              pubkey: findRewardPda(program, user.publicKey),
+              isWritable: true,
+              isSigner: false,
+            },
+          ])
+          .signers([cranker])
+          .rpc();
+      }
+      const vault = await fetchVault(program, user);
+      assert.ok(vault.lastUpdateTime.gt(activity));
+      assert.ok(vault.lastOwnerActivity.eq(activity));
+    });
+
+    it("無操作期間が経過すると元本を受け取れる", async () => {
+      await sleep(1000);
+      const { balance } = await fetchVault(program, user);
+      const before = await provider.connection.getBalance(
+        beneficiary.publicKey
+      );
+      await claim();
+      const after = await provider.connection.getBalance(beneficiary.publicKey);
+      assert.strictEqual(after - before, balance.toNumber());
+      assert.strictEqual(
+        (await fetchVault(program, user)).balance.toNumber(),
+        0
+      );
+    });
+  });
+
+  describe("ロック中の元本へのリワード（locked_reward_mode）", () => {
+    let user;
+
+    // 端数の繰り越しがあるため、期待値との差は1ポイントまで許す
+    const assertClose = (actual, expected) =>
+      assert.ok(actual.sub(expected).abs().lten(1), `${actual} != ${expected}`);
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+      await sleep(2000);
+      await program.methods
+        .convertRewardsToLocked(new anchor.BN(3600))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    after(async () => {
+      await program.methods.setLockedRewardMode({ base: {} }).rpc();
+    });
+
+    it("組み入れたリワードがロック中の元本として記録される", async () => {
+      const vault = await fetchVault(program, user);
+      assert.ok(vault.lockedAmount.toNumber() > 0);
+      assert.ok(vault.lockedAmount.lt(vault.balance));
+    });
+
+    it("None ではロック分にはリワードが付かない", async () => {
+      await program.methods.setLockedRewardMode({ none: {} }).rpc();
+      const { reward, balance, locked, elapsed } = await accrueOnce(
+        program,
+        user
+      );
+      assertClose(reward, balance.sub(locked).mul(elapsed).divn(10000));
+    });
+
+    it("Boosted ではロック分だけ LOCK_BONUS_BPS（1.1倍）の利率になる", async () => {
+      await program.methods.setLockedRewardMode({ boosted: {} }).rpc();
+      const { reward, balance, locked, elapsed } = await accrueOnce(
+        program,
+        user
+      );
+      const weighted = balance.muln(10).add(locked);
+      assertClose(reward, weighted.mul(elapsed).divn(100000));
+    });
+
+    it("Base ではロック分も他の元本と同じ利率", async () => {
+      await program.methods.setLockedRewardMode({ base: {} }).rpc();
+      const { reward, balance, elapsed } = await accrueOnce(program, user);
+      assertClose(reward, balance.mul(elapsed).divn(10000));
+    });
+  });
+
+  describe("入金の最小間隔（min_deposit_interval_secs）", () => {
+    const INTERVAL_SECS = 3;
+    let user;
+
+    before(async () => {
+      await ensureConfig(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await program.methods
+        .setMinDepositInterval(new anchor.BN(INTERVAL_SECS))
+        .rpc();
+    });
+
+    after(async () => {
+      await program.methods.setMinDepositInterval(new anchor.BN(0)).rpc();
+    });
+
+    it("前回の入金から間隔が空いていなければ拒否される", async () => {
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await expectError(
+        deposit(program, user, LAMPORTS_PER_SOL),
+        "DepositTooFrequent"
+      );
+    });
+
+    it("間隔が空けば入金できる", async () => {
+      await sleep((INTERVAL_SECS + 1) * 1000);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      assert.strictEqual(
+        (await fetchVault(program, user)).balance.toNumber(),
+        2 * LAMPORTS_PER_SOL
+      );
+    });
+  });
+
+  describe("プロトコル全体の預かり資産（get_tvl）", () => {
+    const NAME = "tvl";
+    let user;
+    let recipient;
+
+    const tvl = async () => (await program.methods.getTvl().view()).toNumber();
+    const vaultBalance = async () =>
+      (await fetchVault(program, user)).balance.toNumber();
+
+    before(async () => {
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      recipient = await createUser(provider, 1);
+      await initVault(program, user);
+    });
+
+    it("入金で増え、出金で減る", async () => {
+      const before = await tvl();
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+      const deposited = await vaultBalance();
+      assert.strictEqual((await tvl()) - before, deposited);
+
+      await withdraw(program, user, LAMPORTS_PER_SOL / 2);
+      assert.strictEqual(
+        (await tvl()) - before,
+        deposited - LAMPORTS_PER_SOL / 2
+      );
+    });
+
+    it("外部への送金で減る", async () => {
+      const before = await tvl();
+      const balanceBefore = await vaultBalance();
+      await program.methods
+        .transfer(new anchor.BN(LAMPORTS_PER_SOL / 4))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          recipient: recipient.publicKey,
+          recipientOptIn: null,
+        })
+        .signers([user])
+        .rpc();
+      const tvlDrop = before - (await tvl());
+      assert.strictEqual(tvlDrop, balanceBefore - (await vaultBalance()));
+      assert.ok(tvlDrop > 0);
+    });
+
+    it("自分のVault間の移動では変わらない", async () => {
+      await program.methods
+        .splitVault(new anchor.BN(LAMPORTS_PER_SOL / 4), NAME)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          newVault: findVaultPda(program, user.publicKey, NAME),
+          newRewardBox: findRewardPda(program, user.publicKey, NAME),
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+      const before = await tvl();
+      await program.methods
+        .vaultToVault(new anchor.BN(LAMPORTS_PER_SOL / 8))
+        .accounts({
+          fromVault: findVaultPda(program, user.publicKey),
+          fromRewardBox: findRewardPda(program, user.publicKey),
+          toVault: findVaultPda(program, user.publicKey, NAME),
+          toRewardBox: findRewardPda(program, user.publicKey, NAME),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+      assert.strictEqual(await tvl(), before);
+    });
+  });
+
+  describe("入金の期限（deposit の deadline）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    const depositBy = (deadline) =>
+      program.methods
+        .deposit(new anchor.BN(LAMPORTS_PER_SOL), new anchor.BN(deadline))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          matchPool: null,
+          depositSources: null,
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    // バリデータの時計を基準にする
+    const chainNow = async () => {
+      const slot = await provider.connection.getSlot();
+      return provider.connection.getBlockTime(slot);
+    };
+
+    it("期限内なら入金できる", async () => {
+      await depositBy((await chainNow()) + 60);
+      assert.strictEqual(
+        (await fetchVault(program, user)).balance.toNumber(),
+        LAMPORTS_PER_SOL
+      );
+    });
+
+    it("期限を過ぎていれば拒否される", async () => {
+      await expectError(depositBy((await chainNow()) - 60), "DeadlineExceeded");
+    });
+  });
+
+  describe("利率の固定（lock_rate）", () => {
+    const TERM_SECS = 5;
+    let user;
+
+    // 端数の繰り越しがあるため、期待値との差は1ポイントまで許す
+    const assertClose = (actual, expected) =>
+      assert.ok(actual.sub(expected).abs().lten(1), `${actual} != ${expected}`);
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods.setGoalBonus(10000).rpc(); // 達成中は2倍
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 5 * LAMPORTS_PER_SOL);
+      await program.methods
+        .setGoalAmount(new anchor.BN(LAMPORTS_PER_SOL))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+      await program.methods
+        .lockRate(new anchor.BN(TERM_SECS))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+      // 固定した後に全体の利率を下げる
+      await program.methods.setGoalBonus(0).rpc();
+    });
+
+    after(async () => {
+      await program.methods.setGoalBonus(0).rpc();
+    });
+
+    it("固定中は全体の利率を下げても固定した利率のまま", async () => {
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.lockedRateBps.toNumber(), 20000);
+      const { reward, base } = await accrueOnce(program, user);
+      assertClose(reward, base.muln(2));
+    });
+
+    it("期間が終わると現在の利率に戻る", async () => {
+      await sleep(TERM_SECS * 1000);
+      await deposit(program, user, 1); // 期間の終わりをまたぐ分をここで確定させる
+      const { reward, base } = await accrueOnce(program, user);
+      assertClose(reward, base);
+    });
+  });
+
+  describe("bps の範囲チェック", () => {
+    const OVER = 10001;
+    const charity = anchor.web3.Keypair.generate().publicKey;
+
+    before(async () => {
+      await ensureConfig(program);
+    });
+
+    it("set_fees は上限を超える手数料を拒否する", async () => {
+      const grace = new anchor.BN(0);
+      await expectError(
+        program.methods.setFees(OVER, 0, grace).rpc(),
+        "InvalidFee"
+      );
+      await expectError(
+        program.methods.setFees(0, OVER, grace).rpc(),
+        "InvalidFee"
+      );
+    });
+
+    it("set_claim_fee は上限を超える手数料を拒否する", async () => {
+      await expectError(program.methods.setClaimFee(OVER).rpc(), "InvalidFee");
+    });
+
+    it("set_goal_bonus は上限を超えるボーナスを拒否する", async () => {
+      await expectError(
+        program.methods.setGoalBonus(OVER).rpc(),
+        "InvalidGoalBonus"
+      );
+    });
+
+    it("set_early_withdraw_penalty は上限を超えるペナルティを拒否する", async () => {
+      await expectError(
+        program.methods
+          .setEarlyWithdrawPenalty(OVER, { treasury: {} }, charity)
+          .rpc(),
+        "InvalidFee"
+      );
+    });
+
+    it("set_reward_decay は上限を超える減衰率・下限を拒否する", async () => {
+      const period = new anchor.BN(1);
+      await expectError(
+        program.methods.setRewardDecay(period, OVER, 10000).rpc(),
+        "InvalidDecayParams"
+      );
+      await expectError(
+        program.methods.setRewardDecay(period, 0, OVER).rpc(),
+        "InvalidDecayParams"
+      );
+    });
+
+    it("上限ちょうどは設定できる", async () => {
+      await program.methods.setClaimFee(10000).rpc();
+      const config = await program.account.config.fetch(
+        findConfigPda(program)
+      );
+      assert.strictEqual(config.claimFeeBps, 10000);
+      await program.methods.setClaimFee(0).rpc();
+    });
+  });
+
+  describe("Treasuryの閉鎖（close_treasury）", () => {
+    const liabilities = async () =>
+      (
+        await program.account.globalEmission.fetch(
+          findGlobalEmissionPda(program)
+        )
+      ).outstandingLiabilities.toNumber();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureTreasury(program);
+      await ensureGlobalEmission(program);
+    });
+
+    it("未払いリワードが残っていると閉じられない", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(1500);
+      await deposit(program, user, 1); // リワードを確定させる
+      assert.ok((await liabilities()) > 0);
+
+      await expectError(
+        program.methods.closeTreasury().rpc(),
+        "OutstandingLiabilities"
+      );
+      assert.notStrictEqual(
+        await program.account.treasury.fetchNullable(findTreasuryPda(program)),
+        null
+      );
+    });
+
+    it("未払いリワードがなければ閉じられ、残高は管理者へ戻る", async function () {
+      // 同じバリデータ上の他のテストのリワードが残っている場合は確認できない
+      if ((await liabilities()) > 0) {
+        this.skip();
+      }
+      const pda = findTreasuryPda(program);
+      const admin = provider.wallet.publicKey;
+      const treasuryLamports = await provider.connection.getBalance(pda);
+      const adminBefore = await provider.connection.getBalance(admin);
+
+      await program.methods.closeTreasury().rpc();
+
+      assert.strictEqual(
+        await program.account.treasury.fetchNullable(pda),
+        null
+      );
+      const adminAfter = await provider.connection.getBalance(admin);
+      // 差分はトランザクション手数料のみ
+      assert.ok(adminAfter - adminBefore > treasuryLamports - 10000);
+
+      // 以降のテストのために作り直す
+      await ensureTreasury(program);
+    });
+  });
+
+  describe("全額出金と端数の扱い（withdraw_dust_tolerance）", () => {
+    const TOLERANCE = 1000;
+
+    // Vaultのレント免除額
+    async function vaultRent(user) {
+      const info = await provider.connection.getAccountInfo(
+        findVaultPda(program, user.publicKey)
+      );
+      return provider.connection.getMinimumBalanceForRentExemption(
+        info.data.length
+      );
+    }
+
+    async function assertEmptied(user) {
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), 0);
+      assert.strictEqual(
+        await provider.connection.getBalance(
+          findVaultPda(program, user.publicKey)
+        ),
+        await vaultRent(user)
+      );
+    }
+
+    before(async () => {
+      await ensureConfig(program);
+    });
+
+    after(async () => {
+      await program.methods.setWithdrawDustTolerance(new anchor.BN(0)).rpc();
+    });
+
+    it("残高ちょうどを出金すると balance が0になり、レントは残る", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const { balance } = await fetchVault(program, user);
+      await withdraw(program, user, balance.toNumber());
+      await assertEmptied(user);
+    });
+
+    it("残りが許容額以下なら全額を出金する", async () => {
+      await program.methods
+        .setWithdrawDustTolerance(new anchor.BN(TOLERANCE))
+        .rpc();
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const { balance } = await fetchVault(program, user);
+      await withdraw(program, user, balance.toNumber() - TOLERANCE);
+      await assertEmptied(user);
+    });
+
+    it("残りが許容額を超えるなら指定額だけ出金する", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const { balance } = await fetchVault(program, user);
+      await withdraw(program, user, balance.toNumber() - TOLERANCE - 1);
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), TOLERANCE + 1);
+    });
+
+    it("上限を超える許容額は設定できない", async () => {
+      await expectError(
+        program.methods.setWithdrawDustTolerance(new anchor.BN(10001)).rpc(),
+        "InvalidDustTolerance"
+      );
+    });
+  });
+
+  describe("リワードのリーダーボード", () => {
+    const SIZE = 5;
+    const cranker = anchor.web3.Keypair.generate();
+
+    const crankWithLeaderboard = (targets) =>
+      program.methods
+        .crankAccrue()
+        .accounts({
+          cranker: cranker.publicKey,
+          leaderboard: findLeaderboardPda(program),
+        })
+        .remainingAccounts(
+          targets.flatMap((user) => [
+            {
+              pubkey: findVaultPda(program, user.publicKey),
+              isWritable: true,
+              isSigner: false,
+            },
+            {
+              pubkey: findRewardPda(program, user.publicKey),
+              isWritable: true,
+              isSigner: false,
+            },
+          ])
+        )
+        .signers([cranker])
+        .rpc();
+
+    // 空きを除いたエントリ
+    async function entries() {
+      const board = await program.account.leaderboard.fetch(
+        findLeaderboardPda(program)
+      );
+      return board.entries.filter(
+        (e) => !e.vault.equals(anchor.web3.PublicKey.default)
+      );
+    }
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      const existing = await program.account.leaderboard.fetchNullable(
+        findLeaderboardPda(program)
+      );
+      if (!existing) {
+        await program.methods.initializeLeaderboard().rpc();
+      }
+    });
+
+    it("リワードの多い順に記録される", async () => {
+      const users = [];
+      for (let i = 1; i <= SIZE; i++) {
+        const user = await createUser(provider);
+        await initVault(program, user);
+        await deposit(program, user, i * LAMPORTS_PER_SOL);
+        users.push(user);
+      }
+      await sleep(1500);
+      await crankWithLeaderboard(users);
+
+      const board = await entries();
+      assert.strictEqual(board.length, SIZE);
+      for (const user of users) {
+        const vault = findVaultPda(program, user.publicKey);
+        const entry = board.find((e) => e.vault.equals(vault));
+        const reward = await fetchRewardBox(program, user);
+        assert.ok(entry.rewards.eq(reward.balance));
+      }
+      for (let i = 1; i < board.length; i++) {
+        assert.ok(board[i - 1].rewards.gte(board[i].rewards));
+      }
+    });
+
+    it("最小のエントリより多いVaultが入り、最小のエントリが外れる", async () => {
+      const before = await entries();
+      const smallest = before[before.length - 1];
+
+      const user = await createUser(provider, 20);
+      await initVault(program, user);
+      await deposit(program, user, 15 * LAMPORTS_PER_SOL);
+      await sleep(1500);
+      await crankWithLeaderboard([user]);
+
+      const after = await entries();
+      assert.strictEqual(after.length, SIZE);
+      assert.ok(after[0].vault.equals(findVaultPda(program, user.publicKey)));
+      assert.ok(!after.some((e) => e.vault.equals(smallest.vault)));
+    });
+
+    it("最小のエントリ以下のVaultは入らない", async () => {
+      const before = await entries();
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await crankWithLeaderboard([user]); // 残高0なのでリワードも0
+
+      const after = await entries();
+      assert.deepStrictEqual(
+        after.map((e) => e.vault.toBase58()),
+        before.map((e) => e.vault.toBase58())
+      );
+    });
+  });
+
+  describe("Vaultのトークンへの変換（convert_vault）", () => {
+    const mockSwap = anchor.workspace.MockSwap;
+    const TOKENS_PER_SOL = 150_000_000; // モックのレート（decimals 6）
+    let user;
+    let mint;
+    let userAta;
+
+    const vaultPda = () => findVaultPda(program, user.publicKey);
+    const tokenVaultPda = () =>
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("token_vault"), vaultPda().toBuffer(), mint.toBuffer()],
+        program.programId
+      )[0];
+
+    // route / extraAccounts で上限のテスト用に入力を差し替えられる
+    async function convert(lamportsIn, minOut, { route, extraAccounts } = {}) {
+      const swapIx = await mockSwap.methods
+        .swap(new anchor.BN(lamportsIn))
+        .accounts({ user: user.publicKey, userTokenAccount: userAta })
+        .instruction();
+      return program.methods
+        .convertVault(new anchor.BN(minOut), route ?? swapIx.data)
+        .accounts({
+          vault: vaultPda(),
+          rewardBox: findRewardPda(program, user.publicKey),
+          swapProgram: mockSwap.programId,
+          outputMint: mint,
+          userTokenAccount: userAta,
+          user: user.publicKey,
+        })
+        .remainingAccounts([...swapIx.keys, ...(extraAccounts ?? [])])
+        .signers([user])
+        .rpc();
+    }
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureTreasury(program);
+      await ensureGlobalEmission(program);
+      [mint] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("mint")],
+        mockSwap.programId
+      );
+      if (!(await provider.connection.getAccountInfo(mint))) {
+        await mockSwap.methods.initializeMint().rpc();
+      }
+
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+
+      // スワップの出力を受け取るATAを作る（Associated Token Program の冪等な作成）
+      userAta = anchor.utils.token.associatedAddress({
+        mint,
+        owner: user.publicKey,
+      });
+      const createAta = new anchor.web3.TransactionInstruction({
+        programId: anchor.utils.token.ASSOCIATED_PROGRAM_ID,
+        keys: [
+          { pubkey: user.publicKey, isSigner: true, isWritable: true },
+          { pubkey: userAta, isSigner: false, isWritable: true },
+          { pubkey: user.publicKey, isSigner: false, isWritable: false },
+          { pubkey: mint, isSigner: false, isWritable: false },
+          {
+            pubkey: SystemProgram.programId,
+            isSigner: false,
+            isWritable: false,
+          },
+          {
+            pubkey: anchor.utils.token.TOKEN_PROGRAM_ID,
+            isSigner: false,
+            isWritable: false,
+          },
+        ],
+        data: Buffer.from([1]),
+      });
+      await provider.sendAndConfirm(
+        new anchor.web3.Transaction().add(createAta),
+        [user]
+      );
+    });
+
+    it("出力が min_out に届かなければ全体が取り消される", async () => {
+      const { balance } = await fetchVault(program, user);
+      const expected = (balance.toNumber() * TOKENS_PER_SOL) / LAMPORTS_PER_SOL;
+      await expectError(
+        convert(balance.toNumber(), expected + 1),
+        "SlippageExceeded"
+      );
+      assert.ok((await fetchVault(program, user)).balance.eq(balance));
+      assert.strictEqual(await tokenAmount(provider, userAta), 0);
+    });
+
+    it("元本をすべてトークンに替えてトークン用のVaultに入れる", async () => {
+      const { balance } = await fetchVault(program, user);
+      const expected = (balance.toNumber() * TOKENS_PER_SOL) / LAMPORTS_PER_SOL;
+      await convert(balance.toNumber(), expected);
+
+      assert.strictEqual(
+        (await fetchVault(program, user)).balance.toNumber(),
+        0
+      );
+      assert.strictEqual(
+        await tokenAmount(provider, tokenVaultPda()),
+        expected
+      );
+      assert.strictEqual(await tokenAmount(provider, userAta), 0);
+    });
+
+    it("トークン用のVaultから引き出せる", async () => {
+      const amount = await tokenAmount(provider, tokenVaultPda());
+      await program.methods
+        .withdrawTokenVault(new anchor.BN(amount))
+        .accounts({
+          vault: vaultPda(),
+          outputMint: mint,
+          userTokenAccount: userAta,
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      assert.strictEqual(await tokenAmount(provider, tokenVaultPda()), 0);
+      assert.strictEqual(await tokenAmount(provider, userAta), amount);
+    });
+
+    it("上限（256バイト）を超える route は拒否される", async () => {
+      await expectError(
+        convert(0, 0, { route: Buffer.alloc(257) }),
+        "SwapRouteTooLong"
+      );
+    });
+
+    it("上限（16個）を超えるスワップのアカウントは拒否される", async () => {
+      // mock swap の命令は7アカウントなので、同じアカウントを10個足して17個にする
+      const extra = Array(10).fill({
+        pubkey: user.publicKey,
+        isSigner: false,
+        isWritable: false,
+      });
+      await expectError(
+        convert(0, 0, { extraAccounts: extra }),
+        "TooManySwapAccounts"
+      );
+    });
+  });
+
+  describe("単位期間ごとのリワード確定（accrual_period_secs）", () => {
+    const PERIOD_SECS = 4;
+    let user;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      // 入金の時点を期間の起点にするため、入金してから単位期間を設定する
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await program.methods.setAccrualPeriod(new anchor.BN(PERIOD_SECS)).rpc();
+    });
+
+    after(async () => {
+      await program.methods.setAccrualPeriod(new anchor.BN(0)).rpc();
+    });
+
+    it("期間の途中ではリワードが付かず、時刻も進まない", async () => {
+      const before = await fetchVault(program, user);
+      await deposit(program, user, 1);
+
+      const after = await fetchVault(program, user);
+      assert.ok(after.lastUpdateTime.eq(before.lastUpdateTime));
+      assert.strictEqual(
+        (await fetchRewardBox(program, user)).balance.toNumber(),
+        0
+      );
+    });
+
+    it("満了した期間の分だけ確定し、端数の秒は繰り越す", async () => {
+      const before = await fetchVault(program, user);
+      await sleep((PERIOD_SECS + 1) * 1000);
+      await deposit(program, user, 1);
+
+      const after = await fetchVault(program, user);
+      const elapsed = after.lastUpdateTime.sub(before.lastUpdateTime);
+      assert.ok(elapsed.gtn(0));
+      assert.strictEqual(elapsed.toNumber() % PERIOD_SECS, 0);
+      // 期間中の残高は 1 SOL + 1 lamport（途中の入金分）
+      assert.ok(
+        (await fetchRewardBox(program, user)).balance.eq(
+          after.balance.subn(1).mul(elapsed).divn(10000)
+        )
+      );
+    });
+  });
+
+  describe("リワードの自動受け取り（auto_claim）", () => {
+    let user;
+
+    const setAutoClaim = (enabled) =>
+      program.methods
+        .setAutoClaim(enabled)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureTreasury(program);
+      await ensureGlobalEmission(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    it("無効なら入金してもリワードは RewardBox に残る", async () => {
+      await sleep(1500);
+      await deposit(program, user, 1);
+      assert.ok((await fetchRewardBox(program, user)).balance.gtn(0));
+    });
+
+    it("有効なら入金時に確定したリワードがSOLで支払われる", async () => {
+      await setAutoClaim(true);
+      await sleep(1500);
+      const before = await provider.connection.getBalance(user.publicKey);
+      const signature = await deposit(program, user, 1);
+
+      const claimed = (await eventsOf(program, provider, signature)).find(
+        (e) => e.name === "rewardClaimedEvent"
+      );
+      assert.ok(claimed.data.amount.gtn(0));
+      const after = await provider.connection.getBalance(user.publicKey);
+      const payout = claimed.data.amount.sub(claimed.data.fee).toNumber();
+      assert.strictEqual(after - before, payout - 1);
+      assert.strictEqual(
+        (await fetchRewardBox(program, user)).balance.toNumber(),
+        0
+      );
+    });
+  });
+
+  describe("system_program を渡さない出金・送金", () => {
+    const AMOUNT = LAMPORTS_PER_SOL / 2;
+    let user;
+
+    // 命令のアカウントに System Program が含まれていないことを確かめてから送信する
+    async function sendWithoutSystemProgram(builder) {
+      const ix = await builder.instruction();
+      assert.ok(!ix.keys.some((k) => k.pubkey.equals(SystemProgram.programId)));
+      return provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [
+        user,
+      ]);
+    }
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+    });
+
+    it("withdraw は System Program なしで出金できる", async () => {
+      const before = await fetchVault(program, user);
+      await sendWithoutSystemProgram(
+        program.methods.withdraw(new anchor.BN(AMOUNT)).accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+      );
+      const after = await fetchVault(program, user);
+      assert.ok(before.balance.sub(after.balance).eqn(AMOUNT));
+    });
+
+    it("transfer は System Program なしで送金できる", async () => {
+      const recipient = (await createUser(provider, 1)).publicKey;
+      const lamportsBefore = await provider.connection.getBalance(recipient);
+      await sendWithoutSystemProgram(
+        program.methods.transfer(new anchor.BN(AMOUNT)).accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          recipient,
+          recipientOptIn: null,
+        })
+      );
+      assert.strictEqual(
+        (await provider.connection.getBalance(recipient)) - lamportsBefore,
+        AMOUNT
+      );
+    });
+  });
+
+  describe("基本の利率（毎秒0.01%）", () => {
+    let user;
+
+    const projectReward = (secs) =>
+      program.methods
+        .projectReward(new anchor.BN(secs))
+        .accounts({ vault: findVaultPda(program, user.publicKey) })
+        .view();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    for (const [label, secs, expected] of [
+      ["1秒で0.01%", 1, LAMPORTS_PER_SOL / 10000],
+      ["1分で0.6%", 60, (LAMPORTS_PER_SOL * 6) / 1000],
+      ["1時間で36%", 3600, (LAMPORTS_PER_SOL * 36) / 100],
+      ["1日で864%", 86400, (LAMPORTS_PER_SOL * 864) / 100],
+    ]) {
+      it(`${label}が付く`, async () => {
+        assert.strictEqual((await projectReward(secs)).toNumber(), expected);
+      });
+    }
+
+    it("実際の確定額も 残高 × 0.01% × 経過秒数 になる", async () => {
+      const before = await fetchVault(program, user);
+      await sleep(2000);
+      await deposit(program, user, 1);
+      const after = await fetchVault(program, user);
+      const elapsed = after.lastUpdateTime.sub(before.lastUpdateTime);
+      assert.ok(
+        (await fetchRewardBox(program, user)).balance.eq(
+          before.balance.mul(elapsed).divn(10000)
+        )
+      );
+    });
+  });
+
+  describe("リワードの最低保証（min_accrual_per_interval）", () => {
+    const FLOOR = 5;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods
+        .setMinAccrualPerInterval(new anchor.BN(FLOOR))
+        .rpc();
+    });
+
+    after(async () => {
+      await program.methods.setMinAccrualPerInterval(new anchor.BN(0)).rpc();
+    });
+
+    it("少額の元本でも1回の確定で最低額が付く", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 100); // 100 lamports × 数秒では0ポイント
+      await sleep(1500);
+      await deposit(program, user, 1);
+      assert.strictEqual(
+        (await fetchRewardBox(program, user)).balance.toNumber(),
+        FLOOR
+      );
+    });
+
+    it("元本が0のVaultには付かない", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await sleep(1500);
+      await deposit(program, user, 100); // 確定は入金前の元本（0）で行われる
+      assert.strictEqual(
+        (await fetchRewardBox(program, user)).balance.toNumber(),
+        0
+      );
+    });
+  });
+
+  describe("リワードの持ち主の変更（transfer_reward_ownership）", () => {
+    let owner;
+    let rewardOwner;
+
+    const accounts = (signer) => ({
+      vault: findVaultPda(program, owner.publicKey),
+      rewardBox: findRewardPda(program, owner.publicKey),
+      user: signer.publicKey,
+    });
+
+    const claim = (signer) =>
+      program.methods
+        .claimReward()
+        .accounts({ ...accounts(signer), leaderboard: null })
+        .signers([signer])
+        .rpc();
+
+    const transferOwnership = (signer, newOwner) =>
+      program.methods
+        .transferRewardOwnership(newOwner)
+        .accounts({
+          vault: findVaultPda(program, owner.publicKey),
+          rewardBox: findRewardPda(program, owner.publicKey),
+          owner: signer.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureTreasury(program);
+      await ensureGlobalEmission(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      owner = await createUser(provider);
+      rewardOwner = await createUser(provider);
+      await initVault(program, owner);
+      await deposit(program, owner, LAMPORTS_PER_SOL);
+      await transferOwnership(owner, rewardOwner.publicKey);
+    });
+
+    it("Vaultの持ち主は受け取れない", async () => {
+      await sleep(1500);
+      await expectError(claim(owner), "NotRewardOwner");
+    });
+
+    it("Vaultの持ち主はリワードを組み入れて出金できない", async () => {
+      await expectError(
+        program.methods
+          .withdrawAfterCompound(new anchor.BN(1))
+          .accounts(accounts(owner))
+          .signers([owner])
+          .rpc(),
+        "RewardsOwnedByOther"
+      );
+    });
+
+    it("リワードの持ち主が受け取れる", async () => {
+      const before = await provider.connection.getBalance(
+        rewardOwner.publicKey
+      );
+      const signature = await claim(rewardOwner);
+
+      const claimed = (await eventsOf(program, provider, signature)).find(
+        (e) => e.name === "rewardClaimedEvent"
+      );
+      const payout = claimed.data.amount.sub(claimed.data.fee).toNumber();
+      assert.ok(payout > 0);
+      assert.strictEqual(
+        (await provider.connection.getBalance(rewardOwner.publicKey)) - before,
+        payout
+      );
+    });
+
+    it("現在の持ち主だけが変更でき、Vaultの持ち主へ戻すと受け取れる", async () => {
+      await expectError(
+        transferOwnership(owner, owner.publicKey),
+        "NotRewardOwner"
+      );
+      await transferOwnership(rewardOwner, owner.publicKey);
+      const box = await fetchRewardBox(program, owner);
+      assert.ok(box.rewardOwner.equals(anchor.web3.PublicKey.default));
+
+      await sleep(1500);
+      await claim(owner);
+      await expectError(claim(rewardOwner), "NotRewardOwner");
+    });
+  });
+
+  describe("Vaultの経過時間に応じた入金の上限（age_deposit_caps）", () => {
+    const AGED_SECS = 3;
+    const cap = (minAgeSecs, maxSol) => ({
+      minAgeSecs: new anchor.BN(minAgeSecs),
+      maxBalance: new anchor.BN(maxSol * LAMPORTS_PER_SOL),
+    });
+    let user;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      // 作成直後は1 SOLまで、AGED_SECS 秒経てば5 SOLまで
+      await program.methods
+        .setAgeDepositCaps([cap(0, 1), cap(AGED_SECS, 5)])
+        .rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    after(async () => {
+      await program.methods.setAgeDepositCaps([]).rpc();
+    });
+
+    it("作成直後のVaultは低い上限を超えて入金できない", async () => {
+      await deposit(program, user, LAMPORTS_PER_SOL / 2);
+      await expectError(
+        deposit(program, user, LAMPORTS_PER_SOL),
+        "AgeDepositCapExceeded"
+      );
+    });
+
+    it("経過時間が延びると上限が上がる", async () => {
+      await sleep((AGED_SECS + 1) * 1000);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), 2.5 * LAMPORTS_PER_SOL);
+      await expectError(
+        deposit(program, user, 3 * LAMPORTS_PER_SOL),
+        "AgeDepositCapExceeded"
+      );
+    });
+
+    it("段階が多すぎる・昇順でない設定は拒否される", async () => {
+      const tooMany = [0, 1, 2, 3, 4].map((i) => cap(i, i + 1));
+      await expectError(
+        program.methods.setAgeDepositCaps(tooMany).rpc(),
+        "InvalidAgeDepositCaps"
+      );
+      await expectError(
+        program.methods.setAgeDepositCaps([cap(0, 1), cap(0, 2)]).rpc(),
+        "InvalidAgeDepositCaps"
+      );
+    });
+  });
+
+  describe("Vaultの寄付（donate_vault / charity_claim）", () => {
+    let user;
+    let charity;
+
+    const charityClaim = (signer) =>
+      program.methods
+        .charityClaim()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          charity: signer.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      charity = await createUser(provider, 1);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    it("寄付する前は寄付先も受け取れない", async () => {
+      await expectError(charityClaim(charity), "NotDonationCharity");
+    });
+
+    it("寄付すると持ち主は出金できなくなり、取り消せない", async () => {
+      await program.methods
+        .donateVault(charity.publicKey)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      const vault = await fetchVault(program, user);
+      assert.ok(vault.donated);
+      assert.ok(vault.donationCharity.equals(charity.publicKey));
+      await expectError(withdraw(program, user, 1000), "VaultDonated");
+      await expectError(
+        program.methods
+          .donateVault(user.publicKey)
+          .accounts({
+            vault: findVaultPda(program, user.publicKey),
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc(),
+        "VaultDonated"
+      );
+    });
+
+    it("寄付先以外は受け取れない", async () => {
+      await expectError(charityClaim(user), "NotDonationCharity");
+    });
+
+    it("寄付先が元本をすべて受け取れる", async () => {
+      const { balance } = await fetchVault(program, user);
+      const before = await provider.connection.getBalance(charity.publicKey);
+      await charityClaim(charity);
+
+      assert.strictEqual(
+        (await fetchVault(program, user)).balance.toNumber(),
+        0
+      );
+      assert.strictEqual(
+        (await provider.connection.getBalance(charity.publicKey)) - before,
+        balance.toNumber()
+      );
+    });
+  });
+
+  describe("ユーザーごとのVault数の上限（max_vaults_per_user）", () => {
+    let user;
+
+    const setMaxVaults = (max) =>
+      program.methods.setMaxVaultsPerUser(max).rpc();
+
+    const split = (name) =>
+      program.methods
+        .splitVault(new anchor.BN(LAMPORTS_PER_SOL / 10), name)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          newVault: findVaultPda(program, user.publicKey, name),
+          newRewardBox: findRewardPda(program, user.publicKey, name),
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+    const vaultCount = async (user) =>
+      (
+        await program.account.userStats.fetch(
+          findUserStatsPda(program, user.publicKey)
+        )
+      ).vaultCount;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await setMaxVaults(2);
+      user = await createUser(provider);
+    });
+
+    after(async () => {
+      await setMaxVaults(0);
+    });
+
+    it("上限までは initialize と split_vault でVaultを作れる", async () => {
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      assert.strictEqual(await vaultCount(user), 1);
+      await split("second");
+      assert.strictEqual(await vaultCount(user), 2);
+    });
+
+    it("上限を超えるVaultは作れない", async () => {
+      await expectError(split("third"), "VaultLimitExceeded");
+      assert.strictEqual(await vaultCount(user), 2);
+    });
+
+    it("Vaultを閉じると数が減り、また作れるようになる", async () => {
+      await setMaxVaults(1);
+      const other = await createUser(provider);
+      await initVault(program, other);
+      await program.methods
+        .closeVault()
+        .accounts({
+          vault: findVaultPda(program, other.publicKey),
+          rewardBox: findRewardPda(program, other.publicKey),
+          userStats: findUserStatsPda(program, other.publicKey),
+          user: other.publicKey,
+        })
+        .signers([other])
+        .rpc();
+      assert.strictEqual(await vaultCount(other), 0);
+
+      await initVault(program, other);
+      assert.strictEqual(await vaultCount(other), 1);
+    });
+  });
+
+  describe("リワードトークンの発行権限の移譲（rotate_mint_authority）", () => {
+    const newAuthority = Keypair.generate();
+    let rewardMint;
+    let mintAuthority;
+    let user;
+
+    const currentAuthority = async () =>
+      (await provider.connection.getParsedAccountInfo(rewardMint)).value.data
+        .parsed.info.mintAuthority;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      [rewardMint] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("reward_mint")],
+        program.programId
+      );
+      [mintAuthority] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("mint_authority")],
+        program.programId
+      );
+      if (!(await provider.connection.getAccountInfo(rewardMint))) {
+        await program.methods.initializeRewardMint(6).rpc();
+      }
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 5 * LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      // 新しい権限者から mint_authority PDA へ戻す（SPL Token の SetAuthority を直接送る）
+      const data = Buffer.concat([
+        Buffer.from([6, 0, 1]), // SetAuthority, MintTokens, Some
+        mintAuthority.toBuffer(),
+      ]);
+      const ix = new anchor.web3.TransactionInstruction({
+        programId: anchor.utils.token.TOKEN_PROGRAM_ID,
+        keys: [
+          { pubkey: rewardMint, isSigner: false, isWritable: true },
+          {
+            pubkey: newAuthority.publicKey,
+            isSigner: true,
+            isWritable: false,
+          },
+        ],
+        data,
+      });
+      await provider.sendAndConfirm(new anchor.web3.Transaction().add(ix), [
+        newAuthority,
+      ]);
+    });
+
+    it("管理者以外は発行権限を移せない", async () => {
+      const other = await createUser(provider);
+      await expectError(
+        program.methods
+          .rotateMintAuthority(newAuthority.publicKey)
+          .accounts({ admin: other.publicKey })
+          .signers([other])
+          .rpc(),
+        "ConstraintHasOne"
+      );
+    });
+
+    it("発行権限が new_authority に移る", async () => {
+      assert.strictEqual(await currentAuthority(), mintAuthority.toBase58());
+      await program.methods.rotateMintAuthority(newAuthority.publicKey).rpc();
+      assert.strictEqual(
+        await currentAuthority(),
+        newAuthority.publicKey.toBase58()
+      );
+    });
+
+    it("移譲後は claim_reward_token でトークンを発行できない", async () => {
+      await sleep(1000);
+      await expectError(
+        program.methods
+          .claimRewardToken()
+          .accounts({
+            vault: findVaultPda(program, user.publicKey),
+            rewardBox: findRewardPda(program, user.publicKey),
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc(),
+        "ConstraintMintMintAuthority"
+      );
+    });
+  });
+
+  describe("元本0のVaultの確定", () => {
+    let user;
+
+    const crankAccrue = () =>
+      program.methods
+        .crankAccrue()
+        .accounts({ cranker: provider.wallet.publicKey, leaderboard: null })
+        .remainingAccounts([
+          {
+            pubkey: findVaultPda(program, user.publicKey),
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: findRewardPda(program, user.publicKey),
+            isWritable: true,
+            isSigner: false,
+          },
+        ])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    it("元本0なら時刻だけ進み、リワードは変わらない", async () => {
+      const before = await fetchVault(program, user);
+      await sleep(2000);
+      await crankAccrue();
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      assert.ok(vault.lastUpdateTime.gt(before.lastUpdateTime));
+      assert.strictEqual(reward.balance.toNumber(), 0);
+      assert.strictEqual(reward.rewardAcc.toNumber(), 0);
+    });
+
+    it("その後の入金からは通常どおりリワードが付く", async () => {
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const before = await fetchVault(program, user);
+      await sleep(2000);
+      await deposit(program, user, 1);
+
+      const after = await fetchVault(program, user);
+      const elapsed = after.lastUpdateTime.sub(before.lastUpdateTime);
+      assert.ok(elapsed.gtn(0));
+      assert.strictEqual(
+        (await fetchRewardBox(program, user)).balance.toString(),
+        before.balance.mul(elapsed).divn(10000).toString()
+      );
+    });
+  });
+
+  describe("複数ユーザーへの一括入金（batch_deposit_to_vaults）", () => {
+    const AMOUNTS = [
+      LAMPORTS_PER_SOL / 10,
+      LAMPORTS_PER_SOL / 5,
+      (LAMPORTS_PER_SOL * 3) / 10,
+    ];
+    const users = [];
+    let payer;
+
+    const batchDeposit = (targets, amounts) =>
+      program.methods
+        .batchDepositToVaults(amounts.map((a) => new anchor.BN(a)))
+        .accounts({ payer: payer.publicKey })
+        .remainingAccounts(
+          targets.flatMap((user) => [
+            {
+              pubkey: findVaultPda(program, user.publicKey),
+              isWritable: true,
+              isSigner: false,
+            },
+            {
+              pubkey: findRewardPda(program, user.publicKey),
+              isWritable: true,
+              isSigner: false,
+            },
+          ])
+        )
+        .signers([payer])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      payer = await createUser(provider, 2);
+      for (let i = 0; i < AMOUNTS.length; i++) {
+        const user = await createUser(provider, 2);
+        await initVault(program, user);
+        users.push(user);
+      }
+      await deposit(program, users[0], LAMPORTS_PER_SOL);
+    });
+
+    it("1人の payer から各Vaultへ別々の額が入金され、リワードも確定する", async () => {
+      const before = await Promise.all(
+        users.map((u) => fetchVault(program, u))
+      );
+      const payerBefore = await provider.connection.getBalance(payer.publicKey);
+      await sleep(2000);
+
+      await batchDeposit(users, AMOUNTS);
+
+      const after = await Promise.all(users.map((u) => fetchVault(program, u)));
+      for (let i = 0; i < users.length; i++) {
+        assert.strictEqual(
+          after[i].balance.sub(before[i].balance).toNumber(),
+          AMOUNTS[i]
+        );
+      }
+      const total = AMOUNTS.reduce((a, b) => a + b, 0);
+      const payerAfter = await provider.connection.getBalance(payer.publicKey);
+      // 差額は入金の合計と手数料（トランザクション手数料）だけ
+      assert.ok(payerBefore - payerAfter >= total);
+      assert.ok(payerBefore - payerAfter < total + LAMPORTS_PER_SOL / 1000);
+
+      // 既に元本のあったVaultは入金前の残高でリワードが確定している
+      const elapsed = after[0].lastUpdateTime.sub(before[0].lastUpdateTime);
+      assert.ok(elapsed.gtn(0));
+      assert.strictEqual(
+        (await fetchRewardBox(program, users[0])).balance.toString(),
+        before[0].balance.mul(elapsed).divn(10000).toString()
+      );
+    });
+
+    it("入金額の数とVaultの組数が違うと拒否される", async () => {
+      await expectError(
+        batchDeposit(users, AMOUNTS.slice(0, 2)),
+        "DepositAmountsMismatch"
+      );
+    });
+  });
+
+  describe("送金の取り消し期間（transfer_cancel_window_secs）", () => {
+    const WINDOW_SECS = 3;
+    const AMOUNT = LAMPORTS_PER_SOL / 10;
+    let user;
+    let recipient;
+
+    const transfer = () =>
+      program.methods
+        .transfer(new anchor.BN(AMOUNT))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          recipient: recipient.publicKey,
+          recipientOptIn: null,
+        })
+        .signers([user])
+        .rpc();
+
+    const cancel = () =>
+      program.methods
+        .cancelTransfer()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    const claim = (signer) =>
+      program.methods
+        .claimTransfer()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          recipient: signer.publicKey,
+        })
+        .signers([signer])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods
+        .setTransferCancelWindow(new anchor.BN(WINDOW_SECS))
+        .rpc();
+      user = await createUser(provider);
+      recipient = await createUser(provider, 1);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await program.methods.setTransferCancelWindow(new anchor.BN(0)).rpc();
+    });
+
+    it("transfer は送金予約になり、期間中は受け取れない", async () => {
+      const before = await fetchVault(program, user);
+      const recipientBefore = await provider.connection.getBalance(
+        recipient.publicKey
+      );
+      await transfer();
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(before.balance.sub(vault.balance).toNumber(), AMOUNT);
+      assert.strictEqual(vault.pendingTransferAmount.toNumber(), AMOUNT);
+      assert.ok(vault.pendingTransferRecipient.equals(recipient.publicKey));
+      assert.strictEqual(
+        await provider.connection.getBalance(recipient.publicKey),
+        recipientBefore
+      );
+      // 予約中の額はVaultのlamportに残っている
+      await program.methods
+        .assertInvariants()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+
+      await expectError(claim(recipient), "TransferNotClaimable");
+    });
+
+    it("期間中は持ち主が取り消せ、元本に戻る", async () => {
+      const before = await fetchVault(program, user);
+      await cancel();
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.sub(before.balance).toNumber(), AMOUNT);
+      assert.strictEqual(vault.pendingTransferAmount.toNumber(), 0);
+      await expectError(cancel(), "NoPendingTransfer");
+    });
+
+    it("期間が過ぎると取り消せず、受取人だけが受け取れる", async () => {
+      await transfer();
+      await expectError(transfer(), "PendingTransferExists");
+      await sleep((WINDOW_SECS + 1) * 1000);
+
+      await expectError(cancel(), "TransferCancelWindowClosed");
+      const other = await createUser(provider, 1);
+      await expectError(claim(other), "NotTransferRecipient");
+
+      const before = await fetchVault(program, user);
+      const recipientBefore = await provider.connection.getBalance(
+        recipient.publicKey
+      );
+      await claim(recipient);
+
+      assert.strictEqual(
+        (await provider.connection.getBalance(recipient.publicKey)) -
+          recipientBefore,
+        AMOUNT
+      );
+      const vault = await fetchVault(program, user);
+      assert.ok(vault.balance.eq(before.balance));
+      assert.strictEqual(vault.pendingTransferAmount.toNumber(), 0);
+    });
+  });
+
+  describe("2段階の紹介ボーナス（set_referrer）", () => {
+    const LEVEL1_BPS = 500;
+    const LEVEL2_BPS = 200;
+    let top;
+    let middle;
+    let user;
+
+    const setReferrer = (owner, referrer) =>
+      program.methods
+        .setReferrer()
+        .accounts({
+          vault: findVaultPda(program, owner.publicKey),
+          referrerVault: findVaultPda(program, referrer.publicKey),
+          user: owner.publicKey,
+        })
+        .signers([owner])
+        .rpc();
+
+    // 紹介者の (Vault, RewardBox) を近い順に remaining_accounts へ並べる
+    const depositWithReferrers = (amount, referrers) =>
+      program.methods
+        .deposit(new anchor.BN(amount), null)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          matchPool: null,
+          depositSources: null,
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .remainingAccounts(
+          referrers.flatMap((r) => [
+            {
+              pubkey: findVaultPda(program, r.publicKey),
+              isWritable: false,
+              isSigner: false,
+            },
+            {
+              pubkey: findRewardPda(program, r.publicKey),
+              isWritable: true,
+              isSigner: false,
+            },
+          ])
+        )
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods.setReferralBonus(LEVEL1_BPS, LEVEL2_BPS).rpc();
+      top = await createUser(provider, 2);
+      middle = await createUser(provider, 2);
+      user = await createUser(provider);
+      for (const u of [top, middle, user]) {
+        await initVault(program, u);
+      }
+    });
+
+    after(async () => {
+      await program.methods.setReferralBonus(0, 0).rpc();
+    });
+
+    it("user → middle → top の紹介をたどれる", async () => {
+      await setReferrer(middle, top);
+      await setReferrer(user, middle);
+      const vault = await fetchVault(program, user);
+      assert.ok(vault.referrer.equals(findVaultPda(program, middle.publicKey)));
+    });
+
+    it("紹介の循環・自分自身・再設定は拒否される", async () => {
+      await expectError(setReferrer(top, middle), "ReferralCycle");
+      await expectError(setReferrer(top, top), "InvalidReferrer");
+      await expectError(setReferrer(user, top), "ReferrerAlreadySet");
+    });
+
+    it("入金すると2段階の紹介者それぞれにボーナスが付く", async () => {
+      const amount = LAMPORTS_PER_SOL;
+      await depositWithReferrers(amount, [middle, top]);
+
+      const level1 = await fetchRewardBox(program, middle);
+      const level2 = await fetchRewardBox(program, top);
+      assert.strictEqual(
+        level1.balance.toNumber(),
+        (amount * LEVEL1_BPS) / 10000
+      );
+      assert.strictEqual(
+        level2.balance.toNumber(),
+        (amount * LEVEL2_BPS) / 10000
+      );
+    });
+
+    it("紹介者のアカウントを渡さない入金は拒否される", async () => {
+      await expectError(
+        depositWithReferrers(LAMPORTS_PER_SOL, [middle]),
+        "MissingReferrerAccounts"
+      );
+      await expectError(
+        deposit(program, user, LAMPORTS_PER_SOL),
+        "MissingReferrerAccounts"
+      );
+    });
+  });
+
+  describe("自制のための元本の上限（set_self_cap）", () => {
+    let user;
+
+    const setSelfCap = (cap) =>
+      program.methods
+        .setSelfCap(new anchor.BN(cap))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    it("上限は下げられ、上限を超える入金は拒否される", async () => {
+      await setSelfCap(3 * LAMPORTS_PER_SOL);
+      await setSelfCap(2 * LAMPORTS_PER_SOL);
+      assert.strictEqual(
+        (await fetchVault(program, user)).selfCap.toNumber(),
+        2 * LAMPORTS_PER_SOL
+      );
+
+      await deposit(program, user, LAMPORTS_PER_SOL / 2);
+      await expectError(
+        deposit(program, user, LAMPORTS_PER_SOL),
+        "SelfCapExceeded"
+      );
+    });
+
+    it("上限を上げることも外すこともできない", async () => {
+      await expectError(setSelfCap(3 * LAMPORTS_PER_SOL), "SelfCapNotLowered");
+      await expectError(setSelfCap(2 * LAMPORTS_PER_SOL), "SelfCapNotLowered");
+      await expectError(setSelfCap(0), "SelfCapNotLowered");
+      assert.strictEqual(
+        (await fetchVault(program, user)).selfCap.toNumber(),
+        2 * LAMPORTS_PER_SOL
+      );
+    });
+  });
+
+  describe("確定の回数によらないリワード（REWARD_ACC_SCALE）", () => {
+    const BALANCE = 7777;
+    const GOAL_BONUS_BPS = 3333; // 達成中は 1.3333倍
+    const POKES = 5;
+    const SCALE = new anchor.BN(10).pow(new anchor.BN(17));
+    let poked;
+    let once;
+    let cranker;
+
+    const accrue = (user) =>
+      program.methods
+        .crankAccrue()
+        .accounts({ cranker: cranker.publicKey, leaderboard: null })
+        .remainingAccounts([
+          {
+            pubkey: findVaultPda(program, user.publicKey),
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: findRewardPda(program, user.publicKey),
+            isWritable: true,
+            isSigner: false,
+          },
+        ])
+        .signers([cranker])
+        .rpc();
+
+    // 付いたリワードを REWARD_ACC_SCALE 倍（端数込み）で返す
+    const scaledReward = (before, after) =>
+      after.balance
+        .sub(before.balance)
+        .mul(SCALE)
+        .add(after.rewardAcc)
+        .sub(before.rewardAcc);
+
+    // 通しの理論値: 残高 × 0.01% × 1.3333倍 × 経過秒数（REWARD_ACC_SCALE 倍）
+    const ideal = (elapsed) =>
+      new anchor.BN(BALANCE)
+        .muln(elapsed)
+        .muln(10000 + GOAL_BONUS_BPS)
+        .mul(new anchor.BN(10).pow(new anchor.BN(9)));
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods.setGoalBonus(GOAL_BONUS_BPS).rpc();
+      cranker = await createUser(provider, 1);
+      poked = await createUser(provider);
+      once = await createUser(provider);
+      for (const user of [poked, once]) {
+        await initVault(program, user);
+        await deposit(program, user, BALANCE);
+        await program.methods
+          .setGoalAmount(new anchor.BN(1))
+          .accounts({
+            vault: findVaultPda(program, user.publicKey),
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc();
+      }
+    });
+
+    after(async () => {
+      await program.methods.setGoalBonus(0).rpc();
+    });
+
+    it("ランダムな間隔で何度確定しても、1回で確定した場合と同じリワードになる", async () => {
+      const startPoked = (await fetchVault(program, poked)).lastUpdateTime;
+      const startOnce = (await fetchVault(program, once)).lastUpdateTime;
+      const rewardPoked = await fetchRewardBox(program, poked);
+      const rewardOnce = await fetchRewardBox(program, once);
+      for (let i = 0; i < POKES; i++) {
+        await sleep(1000 + Math.floor(Math.random() * 1000));
+        await accrue(poked);
+      }
+      await accrue(once);
+
+      const elapsedPoked = (await fetchVault(program, poked)).lastUpdateTime
+        .sub(startPoked)
+        .toNumber();
+      const elapsedOnce = (await fetchVault(program, once)).lastUpdateTime
+        .sub(startOnce)
+        .toNumber();
+      const poke = scaledReward(
+        rewardPoked,
+        await fetchRewardBox(program, poked)
+      );
+      const single = scaledReward(
+        rewardOnce,
+        await fetchRewardBox(program, once)
+      );
+
+      // どちらも通しの理論値と端数まで一致する
+      assert.strictEqual(poke.toString(), ideal(elapsedPoked).toString());
+      assert.strictEqual(single.toString(), ideal(elapsedOnce).toString());
+      // 1秒あたりのリワードも一致する
+      assert.strictEqual(
+        poke.muln(elapsedOnce).toString(),
+        single.muln(elapsedPoked).toString()
+      );
+    });
+  });
+
+  describe("リワード履歴（enable_reward_history / disable_reward_history）", () => {
+    const REWARD_BOX_SPACE = 81;
+    const HISTORY_LEN = 16;
+    const HISTORY_SPACE = 2 + 16 * HISTORY_LEN;
+    let user;
+
+    const historyAccounts = () => ({
+      vault: findVaultPda(program, user.publicKey),
+      rewardBox: findRewardPda(program, user.publicKey),
+      user: user.publicKey,
+    });
+
+    // RewardBox の末尾の RewardHistory を読む（next, len, entries）
+    async function fetchHistory() {
+      const info = await provider.connection.getAccountInfo(
+        findRewardPda(program, user.publicKey)
+      );
+      const data = info.data.subarray(REWARD_BOX_SPACE);
+      const entries = [];
+      for (let i = 0; i < data[1]; i++) {
+        const offset = 2 + 16 * i;
+        entries.push({
+          timestamp: Number(data.readBigInt64LE(offset)),
+          amount: Number(data.readBigUInt64LE(offset + 8)),
+        });
+      }
+      return { next: data[0], entries };
+    }
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    it("有効にすると RewardBox が広がり、増えたレントをユーザーが支払う", async () => {
+      const rewardPda = findRewardPda(program, user.publicKey);
+      const userBefore = await provider.connection.getBalance(user.publicKey);
+      await program.methods
+        .enableRewardHistory()
+        .accounts(historyAccounts())
+        .signers([user])
+        .rpc();
+
+      const info = await provider.connection.getAccountInfo(rewardPda);
+      assert.strictEqual(info.data.length, REWARD_BOX_SPACE + HISTORY_SPACE);
+      const rent = (len) =>
+        provider.connection.getMinimumBalanceForRentExemption(len);
+      assert.strictEqual(info.lamports, await rent(info.data.length));
+      const userAfter = await provider.connection.getBalance(user.publicKey);
+      assert.strictEqual(
+        userBefore - userAfter,
+        (await rent(info.data.length)) - (await rent(REWARD_BOX_SPACE))
+      );
+      const history = await fetchHistory();
+      assert.strictEqual(history.next, 0);
+      assert.strictEqual(history.entries.length, 0);
+    });
+
+    it("既に有効なら拒否される", async () => {
+      await expectError(
+        program.methods
+          .enableRewardHistory()
+          .accounts(historyAccounts())
+          .signers([user])
+          .rpc(),
+        "RewardHistoryAlreadyEnabled"
+      );
+    });
+
+    it("確定のたびに時刻と付いたリワードが記録される", async () => {
+      const expected = [];
+      for (let i = 0; i < 3; i++) {
+        const rewardBefore = await fetchRewardBox(program, user);
+        await sleep(1100);
+        await deposit(program, user, 1);
+        const rewardAfter = await fetchRewardBox(program, user);
+        const vault = await fetchVault(program, user);
+        expected.push({
+          timestamp: vault.lastUpdateTime.toNumber(),
+          amount: rewardAfter.balance.sub(rewardBefore.balance).toNumber(),
+        });
+      }
+
+      const history = await fetchHistory();
+      assert.strictEqual(history.next, 3);
+      assert.deepStrictEqual(history.entries, expected);
+      assert.ok(history.entries.every((entry) => entry.amount > 0));
+    });
+
+    it("無効にすると元のサイズに戻り、レントの差額が返却される", async () => {
+      const rewardPda = findRewardPda(program, user.publicKey);
+      const before = await provider.connection.getAccountInfo(rewardPda);
+      const rewardBefore = await fetchRewardBox(program, user);
+      const userBefore = await provider.connection.getBalance(user.publicKey);
+      await program.methods
+        .disableRewardHistory()
+        .accounts(historyAccounts())
+        .signers([user])
+        .rpc();
+
+      const after = await provider.connection.getAccountInfo(rewardPda);
+      assert.strictEqual(after.data.length, REWARD_BOX_SPACE);
+      assert.strictEqual(
+        after.lamports,
+        await provider.connection.getMinimumBalanceForRentExemption(
+          REWARD_BOX_SPACE
+        )
+      );
+      const userAfter = await provider.connection.getBalance(user.publicKey);
+      assert.strictEqual(
+        userAfter - userBefore,
+        before.lamports - after.lamports
+      );
+      // RewardBox の残高はそのまま
+      const rewardAfter = await fetchRewardBox(program, user);
+      assert.strictEqual(
+        rewardAfter.balance.toString(),
+        rewardBefore.balance.toString()
+      );
+    });
+
+    it("有効でなければ無効にできない", async () => {
+      await expectError(
+        program.methods
+          .disableRewardHistory()
+          .accounts(historyAccounts())
+          .signers([user])
+          .rpc(),
+        "RewardHistoryNotEnabled"
+      );
+    });
+  });
+
+  describe("連続複利（RewardStrategy::Continuous）", () => {
+    const U64_MAX = new anchor.BN("18446744073709551615");
+    let user;
+
+    const projectReward = (secs) =>
+      program.methods
+        .projectReward(new anchor.BN(secs))
+        .accounts({ vault: findVaultPda(program, user.publicKey) })
+        .view();
+
+    // 近似値と理論値 残高 × (e^(0.01% × 秒数) - 1) の相対誤差が 10^-6 以内
+    const assertClose = (actual, balance, secs) => {
+      const expected = balance * Math.expm1(secs / 10000);
+      assert.ok(
+        Math.abs(actual - expected) <= expected * 1e-6,
+        `${actual} != ${expected}`
+      );
+    };
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods.setRewardStrategy({ continuous: {} }).rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+    });
+
+    for (const secs of [1, 60, 3600, 10000, 86400, 100000]) {
+      it(`${secs}秒の試算が e^x - 1 の値に近い`, async () => {
+        const reward = await projectReward(secs);
+        assertClose(reward.toNumber(), LAMPORTS_PER_SOL, secs);
+      });
+    }
+
+    it("一度に展開する上限（rate × t = 32）をまたいでも e^x - 1 の値に近い", async () => {
+      // 1 SOL では u64 に収まらないので、少額のVaultで比べる
+      const small = await createUser(provider);
+      await initVault(program, small);
+      await deposit(program, small, 10);
+      for (const secs of [320000, 320001, 330000]) {
+        const reward = await program.methods
+          .projectReward(new anchor.BN(secs))
+          .accounts({ vault: findVaultPda(program, small.publicKey) })
+          .view();
+        assertClose(reward.toNumber(), 10, secs);
+      }
+    });
+
+    it("rate × t が大きくても失敗せず、u64 の上限で止まる", async () => {
+      // e^x が u128 に収まらない期間の試算は u64 の上限に収まる
+      const capped = await projectReward(3650 * 86400);
+      assert.strictEqual(capped.toString(), U64_MAX.toString());
+      // 1日ごとに組み入れると数日で u64 の上限に達するが、そこで止まる
+      const projected = await program.methods
+        .projectBalance(new anchor.BN(10), true)
+        .accounts({ vault: findVaultPda(program, user.publicKey) })
+        .view();
+      assert.strictEqual(projected.toString(), U64_MAX.toString());
+    });
+
+    it("実際の確定額も 残高 × (e^(0.01% × 経過秒数) - 1) になる", async () => {
+      const before = await fetchVault(program, user);
+      const rewardBefore = await fetchRewardBox(program, user);
+      await sleep(2000);
+      await deposit(program, user, 1);
+      const after = await fetchVault(program, user);
+      const elapsed = after.lastUpdateTime
+        .sub(before.lastUpdateTime)
+        .toNumber();
+      const reward = (await fetchRewardBox(program, user)).balance.sub(
+        rewardBefore.balance
+      );
+      assertClose(reward.toNumber(), before.balance.toNumber(), elapsed);
+    });
+  });
+
+  describe("手数料の一覧（get_fee_schedule）", () => {
+    const feeSchedule = () => program.methods.getFeeSchedule().view();
+
+    before(async () => {
+      await ensureConfig(program);
+    });
+
+    after(async () => {
+      await program.methods.setFees(0, 0, new anchor.BN(0)).rpc();
+      await program.methods.setClaimFee(0).rpc();
+      await program.methods
+        .setEarlyWithdrawPenalty(
+          0,
+          { treasury: {} },
+          anchor.web3.PublicKey.default
+        )
+        .rpc();
+    });
+
+    it("設定した手数料がすべて返る", async () => {
+      await program.methods.setFees(100, 200, new anchor.BN(3600)).rpc();
+      await program.methods.setClaimFee(300).rpc();
+      await program.methods
+        .setEarlyWithdrawPenalty(
+          400,
+          { treasury: {} },
+          anchor.web3.PublicKey.default
+        )
+        .rpc();
+
+      const schedule = await feeSchedule();
+      assert.strictEqual(schedule.depositFeeBps, 100);
+      assert.strictEqual(schedule.withdrawFeeBps, 200);
+      assert.strictEqual(schedule.claimFeeBps, 300);
+      assert.strictEqual(schedule.earlyWithdrawPenaltyBps, 400);
+      assert.strictEqual(schedule.feeGracePeriodSecs.toNumber(), 3600);
+
+      // Config の値と一致する
+      const config = await program.account.config.fetch(findConfigPda(program));
+      assert.strictEqual(schedule.depositFeeBps, config.depositFeeBps);
+      assert.strictEqual(schedule.withdrawFeeBps, config.withdrawFeeBps);
+      assert.strictEqual(schedule.claimFeeBps, config.claimFeeBps);
+      assert.strictEqual(
+        schedule.earlyWithdrawPenaltyBps,
+        config.earlyWithdrawPenaltyBps
+      );
+    });
+
+    it("手数料を戻すと0が返る", async () => {
+      await program.methods.setFees(0, 0, new anchor.BN(0)).rpc();
+      await program.methods.setClaimFee(0).rpc();
+      const schedule = await feeSchedule();
+      assert.strictEqual(schedule.depositFeeBps, 0);
+      assert.strictEqual(schedule.withdrawFeeBps, 0);
+      assert.strictEqual(schedule.claimFeeBps, 0);
+      assert.strictEqual(schedule.feeGracePeriodSecs.toNumber(), 0);
+    });
+  });
+
+  describe("リワードの端数が残ったVaultの解約", () => {
+    const BALANCE = 13333; // 毎秒 1.3333 ポイント（ほぼ必ず端数が出る）
+    let user;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, BALANCE);
+    });
+
+    it("1ポイント未満の端数は破棄して閉じられる", async () => {
+      await sleep(1500);
+      await withdraw(program, user, BALANCE);
+      await program.methods
+        .claimReward()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          leaderboard: null,
+        })
+        .signers([user])
+        .rpc();
+
+      const box = await fetchRewardBox(program, user);
+      assert.strictEqual(box.balance.toNumber(), 0);
+      // 端数は 1ポイント（REWARD_ACC_SCALE）未満
+      const scale = new anchor.BN(10).pow(new anchor.BN(17));
+      assert.ok(box.rewardAcc.gtn(0));
+      assert.ok(box.rewardAcc.lt(scale));
+      const liabilities = (
+        await program.account.globalEmission.fetch(
+          findGlobalEmissionPda(program)
+        )
+      ).outstandingLiabilities;
+
+      await program.methods
+        .closeVault()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+      assert.strictEqual(
+        await provider.connection.getAccountInfo(
+          findRewardPda(program, user.publicKey)
+        ),
+        null
+      );
+      // 端数は未払いリワードに含まれていないので、集計は変わらない
+      const after = await program.account.globalEmission.fetch(
+        findGlobalEmissionPda(program)
+      );
+      assert.strictEqual(
+        after.outstandingLiabilities.toString(),
+        liabilities.toString()
+      );
+    });
+  });
+
+  describe("作成と最初の入金（initialize_and_deposit）", () => {
+    const AMOUNT = 2 * LAMPORTS_PER_SOL;
+
+    const initializeAndDeposit = (user, amount) =>
+      program.methods
+        .initializeAndDeposit(new anchor.BN(amount))
+        .accounts({ user: user.publicKey })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+    });
+
+    it("1回の呼び出しで入金済みのVaultができる", async () => {
+      const user = await createUser(provider);
+      await initializeAndDeposit(user, AMOUNT);
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), AMOUNT);
+      assert.ok(vault.user.equals(user.publicKey));
+      const info = await provider.connection.getAccountInfo(
+        findVaultPda(program, user.publicKey)
+      );
+      assert.strictEqual(
+        info.lamports,
+        (await provider.connection.getMinimumBalanceForRentExemption(
+          info.data.length
+        )) + AMOUNT
+      );
+      const box = await fetchRewardBox(program, user);
+      assert.strictEqual(box.balance.toNumber(), 0);
+      const stats = await program.account.userStats.fetch(
+        findUserStatsPda(program, user.publicKey)
+      );
+      assert.strictEqual(stats.vaultCount, 1);
+    });
+
+    it("入金に失敗するとVaultも作られない", async () => {
+      const user = await createUser(provider, 1);
+      // 残高不足でシステムプログラムの送金が失敗する
+      await assert.rejects(initializeAndDeposit(user, AMOUNT));
+      assert.strictEqual(
+        await provider.connection.getAccountInfo(
+          findVaultPda(program, user.publicKey)
+        ),
+        null
+      );
+      assert.strictEqual(
+        await provider.connection.getAccountInfo(
+          findRewardPda(program, user.publicKey)
+        ),
+        null
+      );
+    });
+  });
+
+  describe("次の確定の試算（next_accrual_preview）", () => {
+    const BALANCE = LAMPORTS_PER_SOL; // 毎秒 100000 ポイント
+    const PER_SEC = BALANCE / 10000;
+    let user;
+
+    const preview = () =>
+      program.methods
+        .nextAccrualPreview()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .view();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, BALANCE);
+    });
+
+    afterEach(async () => {
+      await program.methods.setAccrualPeriod(new anchor.BN(0)).rpc();
+      await program.methods.setMinAccrualInterval(new anchor.BN(0)).rpc();
+    });
+
+    it("設定がなければ経過秒数ぶんのリワードが付き、次は1秒後", async () => {
+      await sleep(2000);
+      const { amount, secsUntilNext } = await preview();
+      assert.ok(amount.toNumber() >= PER_SEC);
+      assert.strictEqual(amount.toNumber() % PER_SEC, 0);
+      assert.strictEqual(secsUntilNext.toNumber(), 1);
+    });
+
+    it("最小間隔に満たない間は0で、間隔までの残り秒数が返る", async () => {
+      const INTERVAL = 3600;
+      await program.methods
+        .setMinAccrualInterval(new anchor.BN(INTERVAL))
+        .rpc();
+      const { amount, secsUntilNext } = await preview();
+      assert.strictEqual(amount.toNumber(), 0);
+      assert.ok(secsUntilNext.toNumber() <= INTERVAL);
+      assert.ok(secsUntilNext.toNumber() > INTERVAL - 5);
+    });
+
+    it("単位期間の途中では満了した期間の分だけが付き、次の区切りまでの秒数が返る", async () => {
+      const PERIOD = 4;
+      await program.methods.setAccrualPeriod(new anchor.BN(PERIOD)).rpc();
+      const perPeriod = PER_SEC * PERIOD;
+
+      // 単位期間のさまざまな位置で確認する
+      const amounts = [];
+      for (let i = 0; i < 4; i++) {
+        await sleep(1300);
+        const { amount, secsUntilNext } = await preview();
+        assert.ok(secsUntilNext.toNumber() >= 1);
+        assert.ok(secsUntilNext.toNumber() <= PERIOD);
+        // 満了した期間の分だけ（端数の秒は含まない）
+        assert.strictEqual(amount.toNumber() % perPeriod, 0);
+        amounts.push(amount.toNumber());
+      }
+      assert.ok(amounts[amounts.length - 1] > 0);
+    });
+  });
+
+  describe("1回の確定額の上限（max_accrual_per_call）", () => {
+    const PER_SEC = LAMPORTS_PER_SOL / 10000; // 1 SOL で毎秒 100000 ポイント
+    const CAP = 2.5 * PER_SEC; // 1回で確定するのは2秒分まで
+    let user;
+    let cranker;
+
+    const accrue = () =>
+      program.methods
+        .crankAccrue()
+        .accounts({ cranker: cranker.publicKey, leaderboard: null })
+        .remainingAccounts([
+          {
+            pubkey: findVaultPda(program, user.publicKey),
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: findRewardPda(program, user.publicKey),
+            isWritable: true,
+            isSigner: false,
+          },
+        ])
+        .signers([cranker])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      cranker = await createUser(provider, 1);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await program.methods.setMaxAccrualPerCall(new anchor.BN(CAP)).rpc();
+    });
+
+    after(async () => {
+      await program.methods.setMaxAccrualPerCall(new anchor.BN(0)).rpc();
+    });
+
+    it("放置後の確定は上限までで、残りの期間は次の確定に繰り越される", async () => {
+      const start = (await fetchVault(program, user)).lastUpdateTime;
+      const rewardStart = await fetchRewardBox(program, user);
+      await sleep(6000);
+
+      // 1回目は2秒分だけ確定し、last_update_time も2秒だけ進む
+      await accrue();
+      const first = await fetchVault(program, user);
+      const firstBox = await fetchRewardBox(program, user);
+      assert.strictEqual(first.lastUpdateTime.sub(start).toNumber(), 2);
+      assert.strictEqual(
+        firstBox.balance.sub(rewardStart.balance).toNumber(),
+        2 * PER_SEC
+      );
+
+      // 続けて確定すると、1回ごとに上限以下ずつ追いつく
+      let previous = firstBox.balance;
+      for (let i = 0; i < 4; i++) {
+        await accrue();
+        const box = await fetchRewardBox(program, user);
+        assert.ok(box.balance.sub(previous).toNumber() <= CAP);
+        previous = box.balance;
+      }
+
+      // 繰り越した期間のリワードも失われない
+      const end = (await fetchVault(program, user)).lastUpdateTime;
+      assert.ok(end.sub(first.lastUpdateTime).toNumber() > 2);
+      assert.strictEqual(
+        previous.sub(rewardStart.balance).toNumber(),
+        end.sub(start).toNumber() * PER_SEC
+      );
+    });
+  });
+
+  describe("目標達成・ロック中のVaultへの入金（reject_deposit_when_goal_locked）", () => {
+    let user;
+
+    const setGoal = (amount) =>
+      program.methods
+        .setGoalAmount(new anchor.BN(amount))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    const setReject = (reject) =>
+      program.methods.setRejectDepositWhenGoalLocked(reject).rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await setGoal(LAMPORTS_PER_SOL / 2);
+
+      // リワードをロック付き元本に変換してVaultをロックする
+      await sleep(1500);
+      await program.methods
+        .convertRewardsToLocked(new anchor.BN(3600))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    after(async () => {
+      await setReject(false);
+    });
+
+    it("有効なら、目標達成済みでロック中のVaultへの入金は拒否される", async () => {
+      await setReject(true);
+      const before = await fetchVault(program, user);
+      await expectError(
+        deposit(program, user, LAMPORTS_PER_SOL),
+        "GoalLockedDepositRejected"
+      );
+      assert.ok((await fetchVault(program, user)).balance.eq(before.balance));
+    });
+
+    it("目標に届いていなければ、有効でも入金できる", async () => {
+      await setReject(true);
+      const before = await fetchVault(program, user);
+      await setGoal(100 * LAMPORTS_PER_SOL);
+      try {
+        await deposit(program, user, LAMPORTS_PER_SOL);
+      } finally {
+        await setGoal(LAMPORTS_PER_SOL / 2);
+      }
+      const after = await fetchVault(program, user);
+      assert.strictEqual(
+        after.balance.sub(before.balance).toNumber(),
+        LAMPORTS_PER_SOL
+      );
+    });
+
+    it("無効なら、目標達成済みでロック中でも入金できる", async () => {
+      await setReject(false);
+      const before = await fetchVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const after = await fetchVault(program, user);
+      assert.strictEqual(
+        after.balance.sub(before.balance).toNumber(),
+        LAMPORTS_PER_SOL
+      );
+    });
+  });
+
+  describe("未払いリワードの監査（audit_liabilities）", () => {
+    // tests/fixtures/untracked_reward_box.json は集計済み（liability_recorded）なのに
+    // 集計に含まれていない 777 ポイントを持つ RewardBox
+    const untracked = new anchor.web3.PublicKey(
+      "Edx95TdHQTZF7DforcSeW7gtAEDSesrSWHEyYBbUi5wS"
+    );
+    const UNTRACKED_POINTS = 777;
+    const audit = (keys) => auditRewardBoxes(program, keys);
+
+    // untracked 以外の、集計済みのすべての RewardBox（とその残高の合計）
+    async function trackedBoxes() {
+      const boxes = (await program.account.rewardBox.all()).filter(
+        (box) =>
+          box.account.liabilityRecorded && !box.publicKey.equals(untracked)
+      );
+      const total = boxes.reduce(
+        (sum, box) => sum.add(box.account.balance),
+        new anchor.BN(0)
+      );
+      return { keys: boxes.map((box) => box.publicKey), total };
+    }
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+    });
+
+    it("合計は渡した RewardBox の残高の和で、通常の操作ではずれが変わらない", async () => {
+      const before = await trackedBoxes();
+      const first = await audit(before.keys);
+      assert.strictEqual(
+        first.rewardBoxTotal.toString(),
+        before.total.toString()
+      );
+      assert.strictEqual(
+        first.discrepancy.toString(),
+        first.outstandingLiabilities.sub(first.rewardBoxTotal).toString()
+      );
+
+      // 入金・確定・受け取りでは集計と RewardBox が同じだけ動く
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(1500);
+      await deposit(program, user, 1);
+      await program.methods
+        .claimReward()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          leaderboard: null,
+        })
+        .signers([user])
+        .rpc();
+      await sleep(1500);
+      await deposit(program, user, 1);
+
+      const after = await trackedBoxes();
+      const second = await audit(after.keys);
+      assert.ok(second.rewardBoxTotal.gt(first.rewardBoxTotal));
+      assert.strictEqual(
+        second.discrepancy.toString(),
+        first.discrepancy.toString()
+      );
+    });
+
+    it("集計に含まれていない RewardBox があるとずれとして検出される", async () => {
+      const { keys } = await trackedBoxes();
+      const clean = await audit(keys);
+      const corrupted = await audit([...keys, untracked]);
+      assert.strictEqual(
+        corrupted.rewardBoxTotal.sub(clean.rewardBoxTotal).toNumber(),
+        UNTRACKED_POINTS
+      );
+      assert.strictEqual(
+        clean.discrepancy.sub(corrupted.discrepancy).toNumber(),
+        UNTRACKED_POINTS
+      );
+    });
+
+    it("同じ RewardBox を2回渡すと拒否される", async () => {
+      const account = { pubkey: untracked, isWritable: false, isSigner: false };
+      await expectError(
+        program.methods
+          .auditLiabilities(new anchor.BN(0))
+          .remainingAccounts([account, account])
+          .view(),
+        "DuplicateAuditAccount"
+      );
+    });
+  });
+
+  describe("入金ごとの定額リワード（flat_reward_per_deposit）", () => {
+    const FLAT = 1_000_000;
+    let user;
+
+    const setFlat = (enabled) =>
+      program.methods
+        .setFlatReward(enabled)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods
+        .setFlatRewardPerDeposit(new anchor.BN(FLAT))
+        .rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await setFlat(true);
+    });
+
+    after(async () => {
+      await program.methods.setFlatRewardPerDeposit(new anchor.BN(0)).rpc();
+    });
+
+    it("入金するたびに定額だけリワードが増え、時間経過では増えない", async () => {
+      const start = await fetchRewardBox(program, user);
+      for (let i = 1; i <= 3; i++) {
+        await sleep(1500);
+        await deposit(program, user, LAMPORTS_PER_SOL / 10);
+        const box = await fetchRewardBox(program, user);
+        assert.strictEqual(box.balance.sub(start.balance).toNumber(), i * FLAT);
+      }
+      assert.ok((await fetchVault(program, user)).flatReward);
+    });
+
+    it("定額をやめると、利率によるリワードが再び付く", async () => {
+      await setFlat(false);
+      const before = await fetchRewardBox(program, user);
+      await sleep(1500);
+      await deposit(program, user, LAMPORTS_PER_SOL / 10);
+      const after = await fetchRewardBox(program, user);
+      const gained = after.balance.sub(before.balance).toNumber();
+      assert.ok(gained > 0);
+      assert.notStrictEqual(gained, FLAT);
+    });
+  });
+
+  describe("通知のしきい値（notify_threshold）", () => {
+    let user;
+
+    const setThreshold = (threshold) =>
+      program.methods
+        .setNotifyThreshold(new anchor.BN(threshold))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    const crossedBy = async (signature) =>
+      (await eventsOf(program, provider, signature))
+        .filter((e) => e.name === "thresholdCrossedEvent")
+        .map((e) => e.data);
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    it("元本がしきい値に達した入金でだけイベントが出る", async () => {
+      await setThreshold(2 * LAMPORTS_PER_SOL);
+
+      let sig = await deposit(program, user, LAMPORTS_PER_SOL);
+      assert.strictEqual((await crossedBy(sig)).length, 0);
+
+      sig = await deposit(program, user, LAMPORTS_PER_SOL);
+      const crossed = await crossedBy(sig);
+      assert.strictEqual(crossed.length, 1);
+      assert.deepStrictEqual(crossed[0].kind, { balance: {} });
+      assert.strictEqual(
+        crossed[0].threshold.toNumber(),
+        2 * LAMPORTS_PER_SOL
+      );
+      assert.strictEqual(crossed[0].value.toNumber(), 2 * LAMPORTS_PER_SOL);
+
+      // 既に超えている間は出ない
+      sig = await deposit(program, user, LAMPORTS_PER_SOL);
+      assert.strictEqual((await crossedBy(sig)).length, 0);
+    });
+
+    it("リワードの確定でリワード残高がしきい値に達するとイベントが出る", async () => {
+      const before = await fetchRewardBox(program, user);
+      const threshold = before.balance.toNumber() + 1;
+      await setThreshold(threshold);
+      await sleep(1500);
+
+      const sig = await deposit(program, user, LAMPORTS_PER_SOL / 10);
+      const crossed = await crossedBy(sig);
+      assert.strictEqual(crossed.length, 1);
+      assert.deepStrictEqual(crossed[0].kind, { rewards: {} });
+      assert.strictEqual(crossed[0].threshold.toNumber(), threshold);
+      const after = await fetchRewardBox(program, user);
+      assert.strictEqual(crossed[0].value.toNumber(), after.balance.toNumber());
+      assert.ok(after.balance.toNumber() >= threshold);
+
+      await sleep(1500);
+      const next = await deposit(program, user, LAMPORTS_PER_SOL / 10);
+      assert.strictEqual((await crossedBy(next)).length, 0);
+    });
+
+    it("0にすると通知しない", async () => {
+      await setThreshold(0);
+      await sleep(1500);
+      const sig = await deposit(program, user, LAMPORTS_PER_SOL);
+      assert.strictEqual((await crossedBy(sig)).length, 0);
+    });
+  });
+
+  describe("PDAが持ち主のVault（CPI経由）", () => {
+    const integrator = anchor.workspace.MockVaultOwner;
+    const [owner] = anchor.web3.PublicKey.findProgramAddressSync(
+      [Buffer.from("owner")],
+      integrator.programId
+    );
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      // PDAはレントと入金の支払い元になるので、先にSOLを送っておく
+      const tx = new anchor.web3.Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: provider.wallet.publicKey,
+          toPubkey: owner,
+          lamports: 3 * LAMPORTS_PER_SOL,
+        })
+      );
+      await provider.sendAndConfirm(tx);
+    });
+
+    it("他のプログラムがPDAを持ち主にしてVaultを作り、入金できる", async () => {
+      const [configPda] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("config")],
+        program.programId
+      );
+      const [userStats] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("user_stats"), owner.toBuffer()],
+        program.programId
+      );
+      const [globalEmission] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("global_emission")],
+        program.programId
+      );
+      const [treasury] = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("treasury")],
+        program.programId
+      );
+      const vaultPda = findVaultPda(program, owner);
+      const rewardPda = findRewardPda(program, owner);
+
+      await integrator.methods
+        .createVault()
+        .accounts({
+          owner,
+          vault: vaultPda,
+          rewardBox: rewardPda,
+          config: configPda,
+          userStats,
+          saveToGrowProgram: program.programId,
+        })
+        .rpc();
+
+      const created = await program.account.vault.fetch(vaultPda);
+      assert.ok(created.user.equals(owner));
+
+      await integrator.methods
+        .deposit(new anchor.BN(LAMPORTS_PER_SOL))
+        .accounts({
+          owner,
+          vault: vaultPda,
+          rewardBox: rewardPda,
+          config: configPda,
+          globalEmission,
+          treasury,
+          saveToGrowProgram: program.programId,
+        })
+        .rpc();
+
+      const vault = await program.account.vault.fetch(vaultPda);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL);
+    });
+
+    it("PDAの署名がなければ、そのPDAのVaultには入金できない", async () => {
+      const attacker = await createUser(provider);
+      await assert.rejects(
+        program.methods
+          .deposit(new anchor.BN(LAMPORTS_PER_SOL), null)
+          .accounts({
+            vault: findVaultPda(program, owner),
+            rewardBox: findRewardPda(program, owner),
+            matchPool: null,
+            depositSources: null,
+            user: owner,
+            systemProgram: SystemProgram.programId,
+          })
+          .signers([attacker])
+          .rpc()
+      );
+    });
+  });
+
+  describe("作成から最初の入金までの期間のリワード", () => {
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+    });
+
+    after(async () => {
+      await program.methods.setMinAccrualPerInterval(new anchor.BN(0)).rpc();
+    });
+
+    const depositAfterGap = async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      const created = await fetchVault(program, user);
+      await sleep(3000);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const vault = await fetchVault(program, user);
+      const gap = vault.lastUpdateTime.sub(created.lastUpdateTime);
+      assert.ok(gap.toNumber() >= 3);
+      return fetchRewardBox(program, user);
+    };
+
+    it("作成してしばらく経ってから入金しても、元本0だった期間のリワードは付かない", async () => {
+      const box = await depositAfterGap();
+      assert.strictEqual(box.balance.toNumber(), 0);
+      assert.strictEqual(box.rewardAcc.toString(), "0");
+    });
+
+    it("最低額の保証があっても、最初の入金では付かない", async () => {
+      await program.methods
+        .setMinAccrualPerInterval(new anchor.BN(1000))
+        .rpc();
+      const box = await depositAfterGap();
+      assert.strictEqual(box.balance.toNumber(), 0);
+    });
+  });
+
+  describe("閲覧用の権限（view_authority）", () => {
+    let user;
+    let viewer;
+
+    const setViewAuthority = (authority) =>
+      program.methods
+        .setViewAuthority(authority)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    const summaryAs = (signer) =>
+      program.methods
+        .getVaultSummary()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          viewer: signer.publicKey,
+        })
+        .signers([signer])
+        .view();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      viewer = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+    });
+
+    it("持ち主は要約を取得できる", async () => {
+      const summary = await summaryAs(user);
+      assert.strictEqual(summary.balance.toNumber(), LAMPORTS_PER_SOL);
+    });
+
+    it("view_authority に設定したアドレスは要約を取得でき、他の人は取得できない", async () => {
+      const stranger = await createUser(provider);
+      await expectError(summaryAs(viewer), "ViewerNotAuthorized");
+
+      await setViewAuthority(viewer.publicKey);
+      const summary = await summaryAs(viewer);
+      const reward = await fetchRewardBox(program, user);
+      assert.strictEqual(summary.balance.toNumber(), LAMPORTS_PER_SOL);
+      assert.ok(summary.rewardBalance.eq(reward.balance));
+      await expectError(summaryAs(stranger), "ViewerNotAuthorized");
+    });
+
+    it("取り消すと view_authority だったアドレスも取得できなくなる", async () => {
+      await setViewAuthority(anchor.web3.PublicKey.default);
+      await expectError(summaryAs(viewer), "ViewerNotAuthorized");
+      assert.strictEqual(
+        (await summaryAs(user)).balance.toNumber(),
+        LAMPORTS_PER_SOL
+      );
+    });
+  });
+
+  describe("出金時のリワードの没収（reward_forfeit_on_withdraw_bps）", () => {
+    let user;
+
+    const setForfeit = (bps) =>
+      program.methods.setRewardForfeitOnWithdraw(bps).rpc();
+    // 出金で確定した直後（没収前）のリワード残高と、没収の delta を返す
+    const withdrawAndInspect = async () => {
+      const before = await fetchRewardBox(program, user);
+      await sleep(1500);
+      const sig = await withdraw(program, user, LAMPORTS_PER_SOL / 10);
+      const events = await eventsOf(program, provider, sig);
+      const accrued = events.find((e) => e.name === "rewardAccruedEvent");
+      const deltas = events.filter((e) => e.name === "rewardDeltaEvent");
+      const crystallized = accrued
+        ? accrued.data.rewardBoxBalance.toNumber()
+        : before.balance.toNumber();
+      const after = (await fetchRewardBox(program, user)).balance.toNumber();
+      return { crystallized, deltas, after };
+    };
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 5 * LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await setForfeit(0);
+    });
+
+    it("無効なら出金してもリワードは減らない", async () => {
+      await setForfeit(0);
+      const { crystallized, deltas, after } = await withdrawAndInspect();
+      assert.ok(crystallized > 0);
+      assert.strictEqual(deltas.length, 0);
+      assert.strictEqual(after, crystallized);
+    });
+
+    it("有効なら確定した後のリワードから設定した割合が没収される", async () => {
+      await setForfeit(2500);
+      const { crystallized, deltas, after } = await withdrawAndInspect();
+      const forfeit = Math.floor((crystallized * 2500) / 10000);
+      assert.ok(forfeit > 0);
+      assert.strictEqual(deltas.length, 1);
+      assert.strictEqual(deltas[0].data.delta.toNumber(), -forfeit);
+      assert.strictEqual(after, crystallized - forfeit);
+    });
+
+    it("10000bpsを超える割合は設定できない", async () => {
+      await expectError(setForfeit(10001), "InvalidFee");
+    });
+  });
+
+  describe("実現した年率（get_realized_apy）", () => {
+    const SECS_PER_YEAR = 365 * 86400;
+    let user;
+
+    const realizedApy = () =>
+      program.methods
+        .getRealizedApy()
+        .accounts({ vault: findVaultPda(program, user.publicKey) })
+        .view();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    it("入金も受け取りもなければ0", async () => {
+      assert.strictEqual((await realizedApy()).toNumber(), 0);
+    });
+
+    it("入金額・受け取ったリワード・経過時間から年率を計算する", async () => {
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(2000);
+      const signature = await program.methods
+        .claimReward()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          leaderboard: null,
+        })
+        .signers([user])
+        .rpc();
+      const events = await eventsOf(program, provider, signature);
+      const claimed = events
+        .find((e) => e.name === "rewardClaimedEvent")
+        .data.amount.toNumber();
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.totalDeposited.toNumber(), 2 * LAMPORTS_PER_SOL);
+      assert.strictEqual(vault.totalRewardsClaimed.toNumber(), claimed);
+
+      // 手計算: 受け取り / 入金 × (1年 / 経過秒数)。view の時刻は受け取りの直後
+      const claimedAge = vault.lastUpdateTime.sub(vault.createdAt).toNumber();
+      const numerator = new anchor.BN(claimed)
+        .muln(10000)
+        .mul(new anchor.BN(SECS_PER_YEAR));
+      const expected = [0, 1, 2, 3].map((extra) =>
+        numerator
+          .div(new anchor.BN(2 * LAMPORTS_PER_SOL).muln(claimedAge + extra))
+          .toNumber()
+      );
+      const apy = (await realizedApy()).toNumber();
+      assert.ok(expected.includes(apy), `${apy} not in ${expected}`);
+      assert.ok(apy > 0);
+    });
+  });
+
+  describe("リワードが付く元本の下限（reward_min_balance）", () => {
+    let user;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods
+        .setRewardMinBalance(new anchor.BN(2 * LAMPORTS_PER_SOL))
+        .rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    after(async () => {
+      await program.methods.setRewardMinBalance(new anchor.BN(0)).rpc();
+    });
+
+    it("下限未満の元本にはリワードが付かない", async () => {
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(1500);
+      // 2回目の入金で下限に届くが、それまでの期間は下限未満なので付かない
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const box = await fetchRewardBox(program, user);
+      assert.strictEqual(box.balance.toNumber(), 0);
+    });
+
+    it("下限に達した後はリワードが付き始める", async () => {
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), 2 * LAMPORTS_PER_SOL);
+      await sleep(1500);
+      await deposit(program, user, LAMPORTS_PER_SOL / 10);
+      const box = await fetchRewardBox(program, user);
+      assert.ok(box.balance.toNumber() > 0);
+    });
+  });
+
+  describe("端数のリワードの一括整理（sweep_dust）", () => {
+    const dustUsers = [];
+    let richUser;
+
+    const pairsOf = (users) =>
+      users.flatMap((user) => [
+        {
+          pubkey: findVaultPda(program, user.publicKey),
+          isWritable: false,
+          isSigner: false,
+        },
+        {
+          pubkey: findRewardPda(program, user.publicKey),
+          isWritable: true,
+          isSigner: false,
+        },
+      ]);
+    const sweep = (threshold, users) =>
+      program.methods
+        .sweepDust(new anchor.BN(threshold))
+        .remainingAccounts(pairsOf(users))
+        .rpc();
+    const liabilities = async () =>
+      (
+        await program.account.globalEmission.fetch(
+          findGlobalEmissionPda(program)
+        )
+      ).outstandingLiabilities;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      // 少額の元本で少しだけリワードを確定させたVaultと、多く確定させたVault
+      for (let i = 0; i < 3; i++) {
+        const user = await createUser(provider);
+        await initVault(program, user);
+        await deposit(program, user, LAMPORTS_PER_SOL / 100);
+        dustUsers.push(user);
+      }
+      richUser = await createUser(provider);
+      await initVault(program, richUser);
+      await deposit(program, richUser, 5 * LAMPORTS_PER_SOL);
+      await sleep(1500);
+      for (const user of [...dustUsers, richUser]) {
+        await deposit(program, user, LAMPORTS_PER_SOL / 100);
+      }
+    });
+
+    it("しきい値未満のRewardBoxだけを0にし、その分を未払いリワードから除く", async () => {
+      const dust = [];
+      for (const user of dustUsers) {
+        const box = await fetchRewardBox(program, user);
+        assert.ok(box.balance.toNumber() > 0);
+        dust.push(box.balance.toNumber());
+      }
+      const rich = (await fetchRewardBox(program, richUser)).balance;
+      const threshold = Math.max(...dust) + 1;
+      assert.ok(rich.toNumber() >= threshold);
+
+      const treasuryPda = findTreasuryPda(program);
+      const treasuryBefore = await provider.connection.getBalance(treasuryPda);
+      const liabilitiesBefore = await liabilities();
+
+      const sig = await sweep(threshold, [...dustUsers, richUser]);
+
+      const total = dust.reduce((a, b) => a + b, 0);
+      for (const user of dustUsers) {
+        const box = await fetchRewardBox(program, user);
+        assert.strictEqual(box.balance.toNumber(), 0);
+      }
+      assert.ok((await fetchRewardBox(program, richUser)).balance.eq(rich));
+      assert.strictEqual(
+        liabilitiesBefore.sub(await liabilities()).toNumber(),
+        total
+      );
+      // 支払いに充てるはずだったSOLはTreasuryに残る
+      assert.strictEqual(
+        await provider.connection.getBalance(treasuryPda),
+        treasuryBefore
+      );
+
+      const swept = (await eventsOf(program, provider, sig)).find(
+        (e) => e.name === "dustSweptEvent"
+      );
+      assert.strictEqual(swept.data.count, dustUsers.length);
+      assert.strictEqual(swept.data.total.toNumber(), total);
+    });
+
+    it("整理できる端数がなければ拒否される", async () => {
+      await expectError(sweep(1000, dustUsers), "NoDust");
+    });
+
+    it("管理者以外は実行できない", async () => {
+      const stranger = await createUser(provider);
+      await expectError(
+        program.methods
+          .sweepDust(new anchor.BN(1))
+          .accounts({ admin: stranger.publicKey })
+          .remainingAccounts(pairsOf([richUser]))
+          .signers([stranger])
+          .rpc(),
+        "ConstraintHasOne"
+      );
+    });
+  });
+
+  describe("送金先の受け取り登録（require_recipient_opt_in）", () => {
+    let user;
+
+    const optInPda = (recipient) =>
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("recipient_opt_in"), recipient.toBuffer()],
+        program.programId
+      )[0];
+    const transferTo = (recipient, optedIn) =>
+      program.methods
+        .transfer(new anchor.BN(LAMPORTS_PER_SOL / 10))
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          recipient,
+          recipientOptIn: optedIn ? optInPda(recipient) : null,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await program.methods.setRequireRecipientOptIn(true).rpc();
+    });
+
+    after(async () => {
+      await program.methods.setRequireRecipientOptIn(false).rpc();
+    });
+
+    it("受け取りを登録した送金先には送金できる", async () => {
+      const recipient = await createUser(provider);
+      await program.methods
+        .optInRecipient()
+        .accounts({ recipient: recipient.publicKey })
+        .signers([recipient])
+        .rpc();
+
+      const before = await provider.connection.getBalance(recipient.publicKey);
+      await transferTo(recipient.publicKey, true);
+      assert.strictEqual(
+        (await provider.connection.getBalance(recipient.publicKey)) - before,
+        LAMPORTS_PER_SOL / 10
+      );
+    });
+
+    it("登録していない送金先には送金できない", async () => {
+      const recipient = await createUser(provider);
+      const before = await fetchVault(program, user);
+      await expectError(
+        transferTo(recipient.publicKey, false),
+        "RecipientNotOptedIn"
+      );
+      // 他人の登録を渡しても seeds が合わない
+      const other = await createUser(provider);
+      await program.methods
+        .optInRecipient()
+        .accounts({ recipient: other.publicKey })
+        .signers([other])
+        .rpc();
+      await expectError(
+        program.methods
+          .transfer(new anchor.BN(LAMPORTS_PER_SOL / 10))
+          .accounts({
+            vault: findVaultPda(program, user.publicKey),
+            rewardBox: findRewardPda(program, user.publicKey),
+            user: user.publicKey,
+            recipient: recipient.publicKey,
+            recipientOptIn: optInPda(other.publicKey),
+          })
+          .signers([user])
+          .rpc(),
+        "ConstraintSeeds"
+      );
+      assert.ok((await fetchVault(program, user)).balance.eq(before.balance));
+    });
+
+    it("登録を取り消すと送金できなくなり、無効にすれば登録なしで送金できる", async () => {
+      const recipient = await createUser(provider);
+      await program.methods
+        .optInRecipient()
+        .accounts({ recipient: recipient.publicKey })
+        .signers([recipient])
+        .rpc();
+      await program.methods
+        .optOutRecipient()
+        .accounts({ recipient: recipient.publicKey })
+        .signers([recipient])
+        .rpc();
+      assert.strictEqual(
+        await provider.connection.getAccountInfo(optInPda(recipient.publicKey)),
+        null
+      );
+      await expectError(
+        transferTo(recipient.publicKey, false),
+        "RecipientNotOptedIn"
+      );
+
+      await program.methods.setRequireRecipientOptIn(false).rpc();
+      await transferTo(recipient.publicKey, false);
+    });
+  });
+
+  describe("全体の利率とVaultごとの上乗せ（rate_delta_bps）", () => {
+    let boosted;
+    let plain;
+
+    const setDelta = (user, delta) =>
+      program.methods
+        .setRateDelta(delta)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+    const rateOf = async (user) =>
+      program.methods
+        .getRateInfo()
+        .accounts({ vault: findVaultPda(program, user.publicKey) })
+        .view();
+    // 待機してから1 lamport入金し、付いたリワードが rateBps の利率での額か確認する
+    // （前回までの1ポイント未満の端数が繰り越されるので、1ポイント多いことはある）
+    const assertAccruesAt = async (user, rateBps) => {
+      const before = await fetchVault(program, user);
+      const rewardBefore = await fetchRewardBox(program, user);
+      await sleep(1500);
+      await deposit(program, user, 1);
+      const after = await fetchVault(program, user);
+      const rewardAfter = await fetchRewardBox(program, user);
+      const elapsed = after.lastUpdateTime.sub(before.lastUpdateTime);
+      const reward = rewardAfter.balance.sub(rewardBefore.balance);
+      const expected = before.balance
+        .mul(elapsed)
+        .muln(rateBps)
+        .div(new anchor.BN(100_000_000));
+      const extra = reward.sub(expected).toNumber();
+      assert.ok(extra === 0 || extra === 1, `${reward} vs ${expected}`);
+    };
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      boosted = await createUser(provider);
+      plain = await createUser(provider);
+      for (const user of [boosted, plain]) {
+        await initVault(program, user);
+        await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+      }
+      await setDelta(boosted, 5000);
+    });
+
+    after(async () => {
+      await program.methods.setDefaultRewardRate(10000).rpc();
+    });
+
+    it("上乗せしたVaultは全体の利率に上乗せ分を足した利率になる", async () => {
+      assert.strictEqual((await rateOf(boosted)).rateBps.toNumber(), 15000);
+      assert.strictEqual((await rateOf(plain)).rateBps.toNumber(), 10000);
+      await assertAccruesAt(boosted, 15000);
+    });
+
+    it("全体の利率を変えると、上乗せの幅を保ったまま利率が動く", async () => {
+      // 変更前の分は先に確定しておく
+      await deposit(program, boosted, 1);
+      await program.methods.setDefaultRewardRate(20000).rpc();
+
+      const rate = await rateOf(boosted);
+      assert.strictEqual(rate.rateBps.toNumber(), 25000);
+      assert.strictEqual(rate.effectiveBps.toNumber(), 25000);
+      assert.strictEqual((await rateOf(plain)).rateBps.toNumber(), 20000);
+
+      await assertAccruesAt(boosted, 25000);
+    });
+
+    it("全体の利率より大きい引き下げは0で下げ止める", async () => {
+      await setDelta(plain, -30000);
+      const rate = await rateOf(plain);
+      assert.strictEqual(rate.rateBps.toNumber(), 0);
+      assert.strictEqual(rate.effectiveBps.toNumber(), 0);
+      await assertAccruesAt(plain, 0);
+    });
+
+    it("上限を超える倍率・上乗せの幅は設定できない", async () => {
+      const MAX = 50000;
+      await expectError(
+        program.methods.setDefaultRewardRate(MAX + 1).rpc(),
+        "InvalidRewardRate"
+      );
+      await expectError(setDelta(plain, MAX + 1), "InvalidRateDelta");
+      await expectError(setDelta(plain, -(MAX + 1)), "InvalidRateDelta");
+
+      // 上限ちょうどは設定できる
+      await setDelta(plain, -MAX);
+      assert.strictEqual((await rateOf(plain)).rateBps.toNumber(), 0);
+    });
+  });
+
+  describe("残高の記録（snapshot_balance）", () => {
+    const SNAPSHOT_LEN = 8;
+    let user;
+
+    const snapshotsPda = () =>
+      anchor.web3.PublicKey.findProgramAddressSync(
+        [
+          Buffer.from("balance_snapshots"),
+          findVaultPda(program, user.publicKey).toBuffer(),
+        ],
+        program.programId
+      )[0];
+    const snapshot = () =>
+      program.methods
+        .snapshotBalance()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          balanceSnapshots: snapshotsPda(),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    // 古い順に並べた記録
+    const readSnapshots = async () => {
+      const { next, len, entries } =
+        await program.account.balanceSnapshots.fetch(snapshotsPda());
+      const start = (next - len + SNAPSHOT_LEN) % SNAPSHOT_LEN;
+      return Array.from(
+        { length: len },
+        (_, i) => entries[(start + i) % SNAPSHOT_LEN]
+      );
+    };
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    it("記録した時点の元本とリワード残高を読み出せる", async () => {
+      const expected = [];
+      for (let i = 1; i <= 3; i++) {
+        await deposit(program, user, i * LAMPORTS_PER_SOL);
+        await snapshot();
+        const vault = await fetchVault(program, user);
+        const box = await fetchRewardBox(program, user);
+        expected.push({ balance: vault.balance, reward: box.balance });
+      }
+
+      const snapshots = await readSnapshots();
+      assert.strictEqual(snapshots.length, 3);
+      snapshots.forEach((s, i) => {
+        assert.ok(s.balance.eq(expected[i].balance));
+        assert.ok(s.rewardBoxBalance.eq(expected[i].reward));
+        if (i > 0) {
+          assert.ok(s.timestamp.gte(snapshots[i - 1].timestamp));
+        }
+      });
+    });
+
+    it("上限を超えると古い記録から上書きされる", async () => {
+      const before = await readSnapshots();
+      const extra = SNAPSHOT_LEN - before.length + 2;
+      for (let i = 0; i < extra; i++) {
+        await deposit(program, user, LAMPORTS_PER_SOL / 10);
+        await snapshot();
+      }
+
+      const snapshots = await readSnapshots();
+      assert.strictEqual(snapshots.length, SNAPSHOT_LEN);
+      // 最初の2件が消え、3件目が一番古い記録として残る
+      assert.ok(snapshots[0].balance.eq(before[2].balance));
+      const vault = await fetchVault(program, user);
+      assert.ok(snapshots[SNAPSHOT_LEN - 1].balance.eq(vault.balance));
+    });
+  });
+
+  describe("入金手数料によるTreasuryの補充", () => {
+    const FEE_BPS = 500; // 5%
+    let user;
+
+    const treasuryLamports = () =>
+      provider.connection.getBalance(findTreasuryPda(program));
+    const fetchTreasury = () =>
+      program.account.treasury.fetch(findTreasuryPda(program));
+    const fetchEmission = () =>
+      program.account.globalEmission.fetch(findGlobalEmissionPda(program));
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await program.methods.setFees(FEE_BPS, 0, new anchor.BN(0)).rpc();
+    });
+
+    after(async () => {
+      await program.methods.setFees(0, 0, new anchor.BN(0)).rpc();
+    });
+
+    it("入金手数料がTreasuryの原資として記録される", async () => {
+      const fee = (LAMPORTS_PER_SOL * FEE_BPS) / 10000;
+      const lamportsBefore = await treasuryLamports();
+      const treasuryBefore = await fetchTreasury();
+      const emissionBefore = await fetchEmission();
+
+      await deposit(program, user, LAMPORTS_PER_SOL);
+
+      assert.strictEqual(await treasuryLamports(), lamportsBefore + fee);
+      const treasury = await fetchTreasury();
+      assert.strictEqual(
+        treasury.totalFunded.sub(treasuryBefore.totalFunded).toNumber(),
+        fee
+      );
+      const emission = await fetchEmission();
+      assert.strictEqual(
+        emission.totalDepositFees
+          .sub(emissionBefore.totalDepositFees)
+          .toNumber(),
+        fee
+      );
+    });
+
+    it("手数料がなければ記録は増えない", async () => {
+      await program.methods.setFees(0, 0, new anchor.BN(0)).rpc();
+      const treasuryBefore = await fetchTreasury();
+      const emissionBefore = await fetchEmission();
+
+      await deposit(program, user, LAMPORTS_PER_SOL);
+
+      const treasury = await fetchTreasury();
+      assert.ok(treasury.totalFunded.eq(treasuryBefore.totalFunded));
+      const emission = await fetchEmission();
+      assert.ok(emission.totalDepositFees.eq(emissionBefore.totalDepositFees));
+      await program.methods.setFees(FEE_BPS, 0, new anchor.BN(0)).rpc();
+    });
+  });
+
+  describe("レント免除額ぎりぎりまでの出金", () => {
+    const DUST = 1000;
+    let user;
+
+    const vaultPda = () => findVaultPda(program, user.publicKey);
+    const claim = () =>
+      program.methods
+        .claimReward()
+        .accounts({
+          vault: vaultPda(),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          leaderboard: null,
+        })
+        .signers([user])
+        .rpc();
+    const assertInvariants = () =>
+      program.methods
+        .assertInvariants()
+        .accounts({
+          vault: vaultPda(),
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+    async function assertRentExempt(pubkey) {
+      const info = await provider.connection.getAccountInfo(pubkey);
+      const rent =
+        await provider.connection.getMinimumBalanceForRentExemption(
+          info.data.length
+        );
+      assert.ok(info.lamports >= rent);
+      return info.lamports - rent;
+    }
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(2000);
+    });
+
+    it("端数だけ残して出金してもVaultはレント免除のまま", async () => {
+      await withdraw(program, user, LAMPORTS_PER_SOL - DUST);
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), DUST);
+      // レント免除額を超える分がちょうど残った元本になる
+      assert.strictEqual(await assertRentExempt(vaultPda()), DUST);
+      await assertInvariants();
+    });
+
+    it("端数へのリワードを確定して受け取っても整合性が保たれる", async () => {
+      await sleep(2000);
+      const emissionBefore = await program.account.globalEmission.fetch(
+        findGlobalEmissionPda(program)
+      );
+      await claim();
+
+      const box = await fetchRewardBox(program, user);
+      assert.strictEqual(box.balance.toNumber(), 0);
+      const emission = await program.account.globalEmission.fetch(
+        findGlobalEmissionPda(program)
+      );
+      assert.ok(
+        emission.outstandingLiabilities.lte(
+          emissionBefore.outstandingLiabilities
+        )
+      );
+      // Vaultの端数には手を付けず、Treasuryもレント免除額を割らない
+      assert.strictEqual(await assertRentExempt(vaultPda()), DUST);
+      await assertRentExempt(findTreasuryPda(program));
+      await assertInvariants();
+    });
+
+    it("端数も出金すると元本0でレント免除額だけが残る", async () => {
+      await withdraw(program, user, DUST);
+
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), 0);
+      assert.strictEqual(await assertRentExempt(vaultPda()), 0);
+      await assertInvariants();
+    });
+  });
+
+  describe("入金の上乗せ（MatchPool）", () => {
+    const POOL_FUNDS = LAMPORTS_PER_SOL;
+    let sponsor;
+    let user;
+    let poolPda;
+
+    const depositWithMatch = (amount) =>
+      program.methods
+        .deposit(new anchor.BN(amount), null)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          matchPool: poolPda,
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    const balanceOf = async () =>
+      (await fetchVault(program, user)).balance.toNumber();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      sponsor = await createUser(provider);
+      user = await createUser(provider);
+      await initVault(program, user);
+      poolPda = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("match_pool"), sponsor.publicKey.toBuffer()],
+        program.programId
+      )[0];
+
+      await program.methods
+        .createMatchPool()
+        .accounts({ sponsor: sponsor.publicKey })
+        .signers([sponsor])
+        .rpc();
+      await program.methods
+        .fundMatchPool(new anchor.BN(POOL_FUNDS))
+        .accounts({ sponsor: sponsor.publicKey })
+        .signers([sponsor])
+        .rpc();
+      await program.methods.setMatchRatio(10000).rpc();
+    });
+
+    after(async () => {
+      await program.methods.setMatchRatio(0).rpc();
+    });
+
+    it("1:1で入金と同額がプールから上乗せされる", async () => {
+      const amount = LAMPORTS_PER_SOL / 4;
+      await depositWithMatch(amount);
+
+      assert.strictEqual(await balanceOf(), 2 * amount);
+      const pool = await program.account.matchPool.fetch(poolPda);
+      assert.ok(pool.sponsor.equals(sponsor.publicKey));
+      assert.strictEqual(pool.totalMatched.toNumber(), amount);
+    });
+
+    it("MatchPool を渡さなければ上乗せされない", async () => {
+      const before = await balanceOf();
+      await deposit(program, user, LAMPORTS_PER_SOL / 4);
+      assert.strictEqual(await balanceOf(), before + LAMPORTS_PER_SOL / 4);
+    });
+
+    it("割合を変えるとその割合だけ上乗せされる", async () => {
+      await program.methods.setMatchRatio(5000).rpc();
+      const before = await balanceOf();
+      const amount = LAMPORTS_PER_SOL / 10;
+      await depositWithMatch(amount);
+      assert.strictEqual(await balanceOf(), before + amount + amount / 2);
+      await program.methods.setMatchRatio(10000).rpc();
+    });
+
+    it("プールの残高が足りなければある分だけ上乗せする", async () => {
+      const pool = await program.account.matchPool.fetch(poolPda);
+      const remaining = POOL_FUNDS - pool.totalMatched.toNumber();
+      const before = await balanceOf();
+
+      await depositWithMatch(LAMPORTS_PER_SOL);
+
+      assert.strictEqual(
+        await balanceOf(),
+        before + LAMPORTS_PER_SOL + remaining
+      );
+      const after = await program.account.matchPool.fetch(poolPda);
+      assert.strictEqual(after.totalMatched.toNumber(), POOL_FUNDS);
+    });
+
+    it("空のプールでは上乗せせずに入金だけ行う", async () => {
+      const before = await balanceOf();
+      await depositWithMatch(LAMPORTS_PER_SOL / 10);
+      assert.strictEqual(await balanceOf(), before + LAMPORTS_PER_SOL / 10);
+      // Vault の lamports と元本の整合性も保たれている
+      await program.methods
+        .assertInvariants()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+    });
+
+    it("範囲外の割合は設定できない", async () => {
+      await expectError(
+        program.methods.setMatchRatio(10001).rpc(),
+        "InvalidMatchRatio"
+      );
+    });
+  });
+
+  describe("長期間止まっていた時計からの確定", () => {
+    // tests/fixtures/stale_vault(2)(_reward).json のVaultは last_update_time が
+    // 2001年のまま 1000 SOL を預けており、このシードのユーザーが所有している
+    const LAST_UPDATE = 1_000_000_000;
+    const BALANCE = new anchor.BN(1000 * LAMPORTS_PER_SOL);
+    const users = [218, 219].map((fill) =>
+      Keypair.fromSeed(new Uint8Array(32).fill(fill))
+    );
+
+    // Linear: 残高 × 経過秒数 / 10000（1000 SOL なら毎秒ちょうど 1e8 ポイント）
+    const expectedReward = (secs) =>
+      BALANCE.mul(new anchor.BN(secs)).div(new anchor.BN(10000));
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      // 直前の方式も Linear にして、期間全体を Linear で計算させる
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+      for (const user of users) {
+        const sig = await provider.connection.requestAirdrop(
+          user.publicKey,
+          10 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(sig);
+      }
+    });
+
+    after(async () => {
+      await program.methods.setMaxAccrualPerCall(new anchor.BN(0)).rpc();
+      // 付いた巨額のリワードは没収して、未払いリワードの集計から外しておく
+      await program.methods.setRewardForfeitOnWithdraw(10000).rpc();
+      for (const user of users) {
+        await withdraw(program, user, 1);
+      }
+      await program.methods.setRewardForfeitOnWithdraw(0).rpc();
+    });
+
+    it("25年分の経過秒数でも桁あふれせず、式どおりの額が付く", async () => {
+      const [user] = users;
+      const before = await fetchVault(program, user);
+      assert.strictEqual(before.lastUpdateTime.toNumber(), LAST_UPDATE);
+
+      await deposit(program, user, LAMPORTS_PER_SOL);
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      const diff = vault.lastUpdateTime.toNumber() - LAST_UPDATE;
+      assert.ok(diff > 25 * 365 * 24 * 60 * 60);
+      // 切り捨てなしで一致し、端数の繰り越しも残らない
+      assert.ok(reward.balance.eq(expectedReward(diff)));
+      assert.strictEqual(reward.rewardAcc.toString(), "0");
+      assert.ok(vault.balance.eq(BALANCE.addn(LAMPORTS_PER_SOL)));
+    });
+
+    it("1回の確定の上限があれば、上限分の期間だけ確定して残りは繰り越す", async () => {
+      const user = users[1];
+      const HOUR = 60 * 60;
+      const cap = expectedReward(HOUR);
+      await program.methods.setMaxAccrualPerCall(cap).rpc();
+
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      let vault = await fetchVault(program, user);
+      let reward = await fetchRewardBox(program, user);
+      assert.strictEqual(vault.lastUpdateTime.toNumber(), LAST_UPDATE + HOUR);
+      assert.ok(reward.balance.eq(cap));
+
+      // 次の確定は前回の続きから、増えた元本で同じく上限まで
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      vault = await fetchVault(program, user);
+      reward = await fetchRewardBox(program, user);
+      const end = vault.lastUpdateTime.toNumber();
+      assert.ok(end > LAST_UPDATE + HOUR);
+      assert.ok(end <= LAST_UPDATE + 2 * HOUR);
+      const second = BALANCE.addn(LAMPORTS_PER_SOL)
+        .mul(new anchor.BN(end - LAST_UPDATE - HOUR))
+        .div(new anchor.BN(10000));
+      assert.ok(reward.balance.eq(cap.add(second)));
+      assert.ok(second.lte(cap));
+    });
+  });
+
+  describe("ロックする積立（locked savings）", () => {
+    const HALF = 5000;
+    let user;
+
+    const chainNow = async () => {
+      const slot = await provider.connection.getSlot();
+      return provider.connection.getBlockTime(slot);
+    };
+    const setLockedSavings = (owner, fractionBps, unlockAt) =>
+      program.methods
+        .setLockedSavings(fractionBps, new anchor.BN(unlockAt))
+        .accounts({
+          vault: findVaultPda(program, owner.publicKey),
+          user: owner.publicKey,
+        })
+        .signers([owner])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await setLockedSavings(user, HALF, (await chainNow()) + 3600);
+    });
+
+    it("入金の一部がロックされ、残りだけ出金できる", async () => {
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL);
+      assert.strictEqual(vault.lockedBalance.toNumber(), LAMPORTS_PER_SOL / 2);
+
+      await expectError(
+        withdraw(program, user, (LAMPORTS_PER_SOL * 3) / 4),
+        "LockedSavingsNotWithdrawable"
+      );
+      await withdraw(program, user, LAMPORTS_PER_SOL / 2);
+
+      const after = await fetchVault(program, user);
+      assert.strictEqual(after.balance.toNumber(), LAMPORTS_PER_SOL / 2);
+      await expectError(
+        withdraw(program, user, 1),
+        "LockedSavingsNotWithdrawable"
+      );
+    });
+
+    it("ロック中は期限を早められない", async () => {
+      await expectError(
+        setLockedSavings(user, HALF, (await chainNow()) + 60),
+        "InvalidLockedSavingsTerm"
+      );
+    });
+
+    it("範囲外の割合は設定できない", async () => {
+      const vault = await fetchVault(program, user);
+      await expectError(
+        setLockedSavings(user, 10001, vault.lockedBalanceUntil.toNumber()),
+        "InvalidLockedFraction"
+      );
+    });
+
+    it("期限を過ぎると全額出金できる", async () => {
+      const other = await createUser(provider);
+      await initVault(program, other);
+      await setLockedSavings(other, 10000, (await chainNow()) + 6);
+      await deposit(program, other, LAMPORTS_PER_SOL);
+      await expectError(
+        withdraw(program, other, 1),
+        "LockedSavingsNotWithdrawable"
+      );
+
+      await sleep(8000);
+      await withdraw(program, other, LAMPORTS_PER_SOL);
+      const vault = await fetchVault(program, other);
+      assert.strictEqual(vault.balance.toNumber(), 0);
+    });
+  });
+
+  describe("固定レイアウトのイベント（compact_events）", () => {
+    const TAG = 0xc1;
+    let user;
+
+    // ログの "Program data:" から CompactVaultTouched のバイト列を取り出して読む
+    async function compactEventsOf(signature) {
+      await provider.connection.confirmTransaction(signature, "confirmed");
+      const tx = await provider.connection.getTransaction(signature, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      return tx.meta.logMessages
+        .filter((log) => log.startsWith("Program data: "))
+        .map((log) => Buffer.from(log.slice("Program data: ".length), "base64"))
+        .filter((data) => data.length === 57 && data[0] === TAG)
+        .map((data) => ({
+          vault: new anchor.web3.PublicKey(data.subarray(1, 33)),
+          balance: new anchor.BN(data.subarray(33, 41), "le"),
+          rewardBoxBalance: new anchor.BN(data.subarray(41, 49), "le"),
+          lastUpdateTime: new anchor.BN(data.subarray(49, 57), "le"),
+        }));
+    }
+    const depositTx = (amount) =>
+      program.methods
+        .deposit(new anchor.BN(amount), null)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          matchPool: null,
+          depositSources: null,
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    const assertMatchesState = async (event) => {
+      const vault = await fetchVault(program, user);
+      const box = await fetchRewardBox(program, user);
+      assert.ok(event.vault.equals(findVaultPda(program, user.publicKey)));
+      assert.ok(event.balance.eq(vault.balance));
+      assert.ok(event.rewardBoxBalance.eq(box.balance));
+      assert.ok(event.lastUpdateTime.eq(vault.lastUpdateTime));
+    };
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(2000);
+    });
+
+    after(async () => {
+      await program.methods.setCompactEvents(false).rpc();
+    });
+
+    it("無効な間は通常の VaultTouchedEvent だけが出る", async () => {
+      const sig = await depositTx(LAMPORTS_PER_SOL);
+      const events = await eventsOf(program, provider, sig);
+      const touched = events.find((e) => e.name === "vaultTouchedEvent");
+      assert.ok(touched);
+      assert.strictEqual((await compactEventsOf(sig)).length, 0);
+      await assertMatchesState(touched.data);
+    });
+
+    it("有効にすると同じ内容を固定レイアウトで出す", async () => {
+      await program.methods.setCompactEvents(true).rpc();
+      await sleep(2000);
+
+      const sig = await depositTx(LAMPORTS_PER_SOL);
+      const events = await eventsOf(program, provider, sig);
+      assert.ok(!events.some((e) => e.name === "vaultTouchedEvent"));
+      // 他のイベントはそのまま出る
+      assert.ok(events.some((e) => e.name === "balanceDeltaEvent"));
+
+      const compact = await compactEventsOf(sig);
+      assert.strictEqual(compact.length, 1);
+      assert.ok(compact[0].rewardBoxBalance.gtn(0));
+      await assertMatchesState(compact[0]);
+    });
+
+    it("無効に戻すと VaultTouchedEvent に戻る", async () => {
+      await program.methods.setCompactEvents(false).rpc();
+      const sig = await depositTx(LAMPORTS_PER_SOL);
+      const events = await eventsOf(program, provider, sig);
+      assert.ok(events.some((e) => e.name === "vaultTouchedEvent"));
+      assert.strictEqual((await compactEventsOf(sig)).length, 0);
+    });
+  });
+
+  describe("未来の last_update_time の修復（repair_timestamp）", () => {
+    // tests/fixtures/future_vault2(_reward).json のVaultは last_update_time が
+    // 2100年に設定されており、1 SOL を預けている
+    const user = Keypair.fromSeed(new Uint8Array(32).fill(221));
+    const repair = () =>
+      program.methods
+        .repairTimestamp()
+        .accounts({ vault: findVaultPda(program, user.publicKey) })
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      const sig = await provider.connection.requestAirdrop(
+        user.publicKey,
+        10 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    it("管理者以外は修復できない", async () => {
+      const attacker = await createUser(provider, 1);
+      await expectError(
+        program.methods
+          .repairTimestamp()
+          .accounts({
+            vault: findVaultPda(program, user.publicKey),
+            admin: attacker.publicKey,
+          })
+          .signers([attacker])
+          .rpc(),
+        "ConstraintHasOne"
+      );
+    });
+
+    it("現在時刻に戻し、リワードは確定しない", async () => {
+      const before = await fetchVault(program, user);
+      assert.ok(before.lastUpdateTime.toNumber() > Date.now() / 1000 + 1e9);
+
+      const sig = await repair();
+      const events = await eventsOf(program, provider, sig);
+      const repaired = events.find((e) => e.name === "timestampRepairedEvent");
+      assert.ok(repaired.data.previous.eq(before.lastUpdateTime));
+
+      const vault = await fetchVault(program, user);
+      assert.ok(vault.lastUpdateTime.eq(repaired.data.repaired));
+      assert.ok(vault.lastUpdateTime.lt(before.lastUpdateTime));
+      const box = await fetchRewardBox(program, user);
+      assert.strictEqual(box.balance.toNumber(), 0);
+    });
+
+    it("修復後はリワードが付くようになる", async () => {
+      await sleep(2000);
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const box = await fetchRewardBox(program, user);
+      assert.ok(box.balance.gtn(0));
+    });
+
+    it("未来になっていなければ修復できない", async () => {
+      await expectError(repair(), "TimestampNotInFuture");
+    });
+  });
+
+  describe("リワードが付く時間帯（accrual window）", () => {
+    // tests/fixtures/window_vault(2)(_reward).json のVaultは last_update_time が
+    // 2023-11-14 のまま 1 SOL を預けており、このシードのユーザーが所有している
+    const LAST_UPDATE = 1_700_000_000;
+    const DAY = 86400;
+    const fixtureUsers = [222, 223].map((fill) =>
+      Keypair.fromSeed(new Uint8Array(32).fill(fill))
+    );
+    let user;
+
+    // 0時（UTC）から t までのうち、時間帯 [start, end) に入っている秒数
+    function windowSecsUntil(start, end, t) {
+      const days = Math.floor(t / DAY);
+      const secsOfDay = t - days * DAY;
+      if (start < end) {
+        return (
+          days * (end - start) +
+          Math.min(Math.max(secsOfDay, start), end) -
+          start
+        );
+      }
+      return (
+        days * (DAY - start + end) +
+        Math.min(secsOfDay, end) +
+        Math.max(secsOfDay - start, 0)
+      );
+    }
+    const windowSecs = (start, end, from, to) =>
+      windowSecsUntil(start, end, to) - windowSecsUntil(start, end, from);
+    // Linear で 1 SOL なら毎秒ちょうど 1e5 ポイント
+    const rewardFor = (secs) => new anchor.BN(secs).muln(100000);
+
+    const setWindow = (start, end) =>
+      program.methods.setAccrualWindow(start, end).rpc();
+    const chainNow = async () => {
+      const slot = await provider.connection.getSlot();
+      return provider.connection.getBlockTime(slot);
+    };
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      // 直前の方式も Linear にして、期間全体を Linear で計算させる
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+      for (const fixtureUser of fixtureUsers) {
+        const sig = await provider.connection.requestAirdrop(
+          fixtureUser.publicKey,
+          10 * LAMPORTS_PER_SOL
+        );
+        await provider.connection.confirmTransaction(sig);
+      }
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    after(async () => {
+      await setWindow(0, 0);
+      // 長い期間に付いたリワードは没収して、未払いリワードの集計から外しておく
+      await program.methods.setRewardForfeitOnWithdraw(10000).rpc();
+      for (const fixtureUser of fixtureUsers) {
+        await withdraw(program, fixtureUser, 1);
+      }
+      await program.methods.setRewardForfeitOnWithdraw(0).rpc();
+    });
+
+    it("何日もまたぐ期間では、毎日の時間帯の秒数だけ付く", async () => {
+      const [start, end] = [9 * 3600, 17 * 3600]; // 9時〜17時
+      await setWindow(start, end);
+      const owner = fixtureUsers[0];
+
+      await deposit(program, owner, LAMPORTS_PER_SOL);
+
+      const vault = await fetchVault(program, owner);
+      const to = vault.lastUpdateTime.toNumber();
+      const secs = windowSecs(start, end, LAST_UPDATE, to);
+      // 時間帯は1日の1/3
+      assert.ok(Math.abs(secs - (to - LAST_UPDATE) / 3) <= 8 * 3600);
+      const box = await fetchRewardBox(program, owner);
+      assert.ok(box.balance.eq(rewardFor(secs)));
+    });
+
+    it("日付をまたぐ時間帯も数えられる", async () => {
+      const [start, end] = [22 * 3600, 6 * 3600]; // 22時〜翌6時
+      await setWindow(start, end);
+      const owner = fixtureUsers[1];
+
+      await deposit(program, owner, LAMPORTS_PER_SOL);
+
+      const vault = await fetchVault(program, owner);
+      const to = vault.lastUpdateTime.toNumber();
+      const box = await fetchRewardBox(program, owner);
+      assert.ok(
+        box.balance.eq(rewardFor(windowSecs(start, end, LAST_UPDATE, to)))
+      );
+    });
+
+    it("時間帯の外の秒数にはリワードが付かない", async () => {
+      // 数秒後から3秒間だけの時間帯
+      const start = ((await chainNow()) + 4) % DAY;
+      const end = (start + 3) % DAY;
+      await setWindow(start, end);
+
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const from = (await fetchVault(program, user)).lastUpdateTime.toNumber();
+      await sleep(10000);
+      await deposit(program, user, 1);
+
+      const vault = await fetchVault(program, user);
+      const to = vault.lastUpdateTime.toNumber();
+      const secs = windowSecs(start, end, from, to);
+      assert.ok(secs < to - from);
+      const box = await fetchRewardBox(program, user);
+      assert.ok(box.balance.eq(rewardFor(secs)));
+    });
+
+    it("1日の秒数以上は指定できない", async () => {
+      await expectError(setWindow(0, DAY), "InvalidAccrualWindow");
+    });
+  });
+
+  describe("Vaultの一括作成（batch_initialize）", () => {
+    let sponsor;
+
+    const writable = (pubkey) => ({
+      pubkey,
+      isWritable: true,
+      isSigner: false,
+    });
+    const accountsFor = (users) =>
+      users.flatMap((user) => [
+        { pubkey: user.publicKey, isWritable: false, isSigner: false },
+        writable(findVaultPda(program, user.publicKey)),
+        writable(findRewardPda(program, user.publicKey)),
+        writable(findUserStatsPda(program, user.publicKey)),
+      ]);
+    const batchInitialize = (accounts) =>
+      program.methods
+        .batchInitialize()
+        .accounts({ payer: sponsor.publicKey })
+        .remainingAccounts(accounts)
+        .signers([sponsor])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      sponsor = await createUser(provider);
+    });
+
+    it("3人分のVaultとRewardBoxを作り、持ち主は各ユーザーになる", async () => {
+      // 署名もSOLも持たないユーザー
+      const users = [0, 1, 2].map(() => Keypair.generate());
+      const config = await program.account.config.fetch(
+        findConfigPda(program)
+      );
+      const sponsorBefore = await provider.connection.getBalance(
+        sponsor.publicKey
+      );
+
+      await batchInitialize(accountsFor(users));
+
+      for (const user of users) {
+        const vault = await fetchVault(program, user);
+        assert.ok(vault.user.equals(user.publicKey));
+        assert.strictEqual(vault.balance.toNumber(), 0);
+        assert.ok(vault.lastUpdateTime.eq(vault.createdAt));
+        const info = await provider.connection.getAccountInfo(
+          findVaultPda(program, user.publicKey)
+        );
+        assert.ok(info.owner.equals(program.programId));
+        assert.strictEqual(info.data.length, program.account.vault.size);
+        const box = await fetchRewardBox(program, user);
+        assert.strictEqual(box.balance.toNumber(), 0);
+        const stats = await program.account.userStats.fetch(
+          findUserStatsPda(program, user.publicKey)
+        );
+        assert.strictEqual(stats.vaultCount, 1);
+      }
+      const after = await program.account.config.fetch(findConfigPda(program));
+      assert.ok(after.vaultCount.eq(config.vaultCount.addn(3)));
+      // レントはスポンサーが負担する
+      assert.ok(
+        (await provider.connection.getBalance(sponsor.publicKey)) <
+          sponsorBefore
+      );
+    });
+
+    it("作ったVaultは持ち主がそのまま使える", async () => {
+      const user = await createUser(provider);
+      await batchInitialize(accountsFor([user]));
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL);
+    });
+
+    it("ユーザーのPDAでないアカウントは拒否される", async () => {
+      const [user, other] = [Keypair.generate(), Keypair.generate()];
+      const accounts = accountsFor([user]);
+      accounts[1] = writable(findVaultPda(program, other.publicKey));
+      await expectError(batchInitialize(accounts), "InvalidBatchInitAccounts");
+      await expectError(
+        batchInitialize(accounts.slice(0, 3)),
+        "InvalidBatchInitAccounts"
+      );
+    });
+
+    it("既にVaultを持つユーザーは作り直せない", async () => {
+      const user = await createUser(provider);
+      await initVault(program, user);
+      await assert.rejects(batchInitialize(accountsFor([user])));
+    });
+  });
+
+  describe("大口入金のボーナス（large_deposit_bonus_bps）", () => {
+    const BONUS_BPS = 100; // 入金額の1%
+    let user;
+
+    const depositTx = (amount) =>
+      program.methods
+        .deposit(new anchor.BN(amount), null)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          matchPool: null,
+          depositSources: null,
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    const bonusOf = async (signature) =>
+      (await eventsOf(program, provider, signature)).find(
+        (e) => e.name === "largeDepositBonusEvent"
+      );
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider, 20);
+      await initVault(program, user);
+      await program.methods.setLargeDepositBonus(BONUS_BPS).rpc();
+    });
+
+    after(async () => {
+      await program.methods.setLargeDepositBonus(0).rpc();
+    });
+
+    it("元本0のVaultへの最初の入金にはボーナスが付かない", async () => {
+      const sig = await depositTx(LAMPORTS_PER_SOL);
+      assert.strictEqual(await bonusOf(sig), undefined);
+    });
+
+    it("元本を2倍より大きくする入金にはボーナスが付く", async () => {
+      const amount = 2 * LAMPORTS_PER_SOL;
+      const before = await fetchRewardBox(program, user);
+      const sig = await depositTx(amount);
+
+      const event = await bonusOf(sig);
+      assert.ok(event);
+      const bonus = (amount * BONUS_BPS) / 10000;
+      assert.strictEqual(event.data.bonus.toNumber(), bonus);
+      assert.strictEqual(event.data.deposit.toNumber(), amount);
+      assert.strictEqual(
+        event.data.previousBalance.toNumber(),
+        LAMPORTS_PER_SOL
+      );
+      // 経過時間の分のリワードに加えてボーナスが RewardBox に入る
+      const after = await fetchRewardBox(program, user);
+      assert.ok(after.balance.sub(before.balance).gten(bonus));
+    });
+
+    it("元本に比べて小さい入金にはボーナスが付かない", async () => {
+      const sig = await depositTx(LAMPORTS_PER_SOL);
+      assert.strictEqual(await bonusOf(sig), undefined);
+    });
+
+    it("ちょうど2倍にする入金は対象外", async () => {
+      const vault = await fetchVault(program, user);
+      const sig = await depositTx(vault.balance.toNumber());
+      assert.strictEqual(await bonusOf(sig), undefined);
+    });
+
+    it("範囲外の割合は設定できない", async () => {
+      await expectError(
+        program.methods.setLargeDepositBonus(10001).rpc(),
+        "InvalidDepositBonus"
+      );
+    });
+  });
+
+  describe("累計入金額の頭打ち", () => {
+    // ★total_deposited が u64 の上限近くまで溜まったフィクスチャ
+    const user = Keypair.fromSeed(new Uint8Array(32).fill(225));
+    const U64_MAX = "18446744073709551615";
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      const sig = await provider.connection.requestAirdrop(
+        user.publicKey,
+        10 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    it("累計が溢れても入金は通り、上限で止まる", async () => {
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+      let vault = await fetchVault(program, user);
+      assert.equal(vault.totalDeposited.toString(), U64_MAX);
+      assert.equal(vault.balance.toNumber(), 3 * LAMPORTS_PER_SOL);
+
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      vault = await fetchVault(program, user);
+      assert.equal(vault.totalDeposited.toString(), U64_MAX);
+      assert.equal(vault.balance.toNumber(), 4 * LAMPORTS_PER_SOL);
+
+      await program.methods
+        .assertInvariants()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+        })
+        .rpc();
+    });
+  });
+
+  describe("リワードの運用先のVault（move_rewards_to_growth）", () => {
+    let user;
+    const growthVaultPda = () =>
+      findVaultPda(program, user.publicKey, "growth");
+    const growthRewardPda = () =>
+      findRewardPda(program, user.publicKey, "growth");
+    const moveToGrowth = (vault = findVaultPda(program, user.publicKey)) =>
+      program.methods
+        .moveRewardsToGrowth()
+        .accounts({
+          vault,
+          rewardBox:
+            vault.equals(growthVaultPda())
+              ? growthRewardPda()
+              : findRewardPda(program, user.publicKey),
+          growthVault: growthVaultPda(),
+          growthRewardBox: growthRewardPda(),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+    const rateOf = async (vault) =>
+      program.methods.getRateInfo().accounts({ vault }).view();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      await program.methods.setGrowthRateBonus(10000).rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await program.methods.setGrowthRateBonus(0).rpc();
+    });
+
+    it("リワードを運用先へ移し、運用先は上乗せした利率で増える", async () => {
+      await sleep(1500);
+      let sig = await moveToGrowth();
+      const moved = (await eventsOf(program, provider, sig)).find(
+        (e) => e.name === "rewardsMovedToGrowthEvent"
+      );
+      assert.ok(moved.data.amount.gtn(0));
+      assert.ok((await fetchRewardBox(program, user)).balance.eqn(0));
+
+      const growth = await program.account.vault.fetch(growthVaultPda());
+      assert.ok(growth.balance.eq(moved.data.amount));
+      assert.strictEqual(growth.nameLen, 6);
+      const rate = await rateOf(growthVaultPda());
+      assert.strictEqual(rate.rateBps.toNumber(), 20000);
+      const plainRate = await rateOf(findVaultPda(program, user.publicKey));
+      assert.strictEqual(plainRate.rateBps.toNumber(), 10000);
+
+      // ★2回目の振り替えで、それまでの運用先の元本に付いたリワードを確定させる
+      const rewardBefore = await program.account.rewardBox.fetch(
+        growthRewardPda()
+      );
+      await sleep(1500);
+      sig = await moveToGrowth();
+      const again = (await eventsOf(program, provider, sig)).find(
+        (e) => e.name === "rewardsMovedToGrowthEvent"
+      );
+      const after = await program.account.vault.fetch(growthVaultPda());
+      const rewardAfter = await program.account.rewardBox.fetch(
+        growthRewardPda()
+      );
+      const elapsed = after.lastUpdateTime.sub(growth.lastUpdateTime);
+      const reward = rewardAfter.balance.sub(rewardBefore.balance);
+      const expected = growth.balance
+        .mul(elapsed)
+        .muln(20000)
+        .div(new anchor.BN(100_000_000));
+      const extra = reward.sub(expected).toNumber();
+      assert.ok(extra === 0 || extra === 1, `${reward} vs ${expected}`);
+      assert.ok(after.balance.eq(growth.balance.add(again.data.amount)));
+    });
+
+    it("運用先には入金できず、運用先からは振り替えられない", async () => {
+      await expectError(
+        program.methods
+          .deposit(new anchor.BN(LAMPORTS_PER_SOL), null)
+          .accounts({
+            vault: growthVaultPda(),
+            rewardBox: growthRewardPda(),
+            matchPool: null,
+            depositSources: null,
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc(),
+        "GrowthVaultDepositRejected"
+      );
+      await expectError(
+        moveToGrowth(growthVaultPda()),
+        "CannotMoveToSameVault"
+      );
+    });
+
+    it("範囲外の上乗せは設定できない", async () => {
+      await expectError(
+        program.methods.setGrowthRateBonus(10001).rpc(),
+        "InvalidGrowthRateBonus"
+      );
+    });
+  });
+
+  describe("さかのぼって付けるリワードの上限（max_accrual_lookback_secs）", () => {
+    // tests/fixtures/dormant_vault(_reward).json のVaultは last_update_time が
+    // 2001年のまま 1 SOL を預けており、このシードのユーザーが所有している
+    const LAST_UPDATE = 1_000_000_000;
+    const HOUR = 60 * 60;
+    const user = Keypair.fromSeed(new Uint8Array(32).fill(227));
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+      const sig = await provider.connection.requestAirdrop(
+        user.publicKey,
+        10 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    after(async () => {
+      await program.methods.setMaxAccrualLookback(new anchor.BN(0)).rpc();
+    });
+
+    it("負の値は設定できない", async () => {
+      await expectError(
+        program.methods.setMaxAccrualLookback(new anchor.BN(-1)).rpc(),
+        "InvalidAccrualInterval"
+      );
+    });
+
+    it("長く放置されたVaultは上限の期間分だけ付き、時刻は現在まで進む", async () => {
+      await program.methods.setMaxAccrualLookback(new anchor.BN(HOUR)).rpc();
+      const before = await fetchVault(program, user);
+      assert.strictEqual(before.lastUpdateTime.toNumber(), LAST_UPDATE);
+
+      await deposit(program, user, LAMPORTS_PER_SOL);
+
+      const vault = await fetchVault(program, user);
+      const reward = await fetchRewardBox(program, user);
+      // 1 SOL × 3600秒 / 10000（古い25年分は切り捨て）
+      assert.strictEqual(
+        reward.balance.toNumber(),
+        (LAMPORTS_PER_SOL * HOUR) / 10000
+      );
+      assert.ok(vault.lastUpdateTime.toNumber() > Date.now() / 1000 - HOUR);
+    });
+  });
+
+  describe("これまでに払った手数料（get_fees_paid）", () => {
+    let user;
+
+    const treasuryLamports = () =>
+      provider.connection.getBalance(findTreasuryPda(program));
+    const feesPaid = () =>
+      program.methods
+        .getFeesPaid()
+        .accounts({ vault: findVaultPda(program, user.publicKey) })
+        .view();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      await program.methods.setFees(100, 200, new anchor.BN(0)).rpc();
+      await program.methods.setClaimFee(1000).rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    after(async () => {
+      await program.methods.setFees(0, 0, new anchor.BN(0)).rpc();
+      await program.methods.setClaimFee(0).rpc();
+    });
+
+    it("入金・出金・受け取りの手数料の合計になる", async () => {
+      assert.strictEqual((await feesPaid()).toNumber(), 0);
+
+      // 入金・出金の手数料は Treasury の増加分
+      let treasuryBefore = await treasuryLamports();
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      const depositFee = (await treasuryLamports()) - treasuryBefore;
+      assert.strictEqual(depositFee, LAMPORTS_PER_SOL / 100);
+
+      await sleep(1500);
+      treasuryBefore = await treasuryLamports();
+      await withdraw(program, user, LAMPORTS_PER_SOL / 2);
+      const withdrawFee = (await treasuryLamports()) - treasuryBefore;
+      assert.strictEqual(withdrawFee, LAMPORTS_PER_SOL / 100);
+
+      const sig = await program.methods
+        .claimReward()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          leaderboard: null,
+        })
+        .signers([user])
+        .rpc();
+      const claimed = (await eventsOf(program, provider, sig)).find(
+        (e) => e.name === "rewardClaimedEvent"
+      );
+      const claimFee = claimed.data.fee.toNumber();
+      assert.ok(claimFee > 0);
+
+      const total = depositFee + withdrawFee + claimFee;
+      assert.strictEqual((await feesPaid()).toNumber(), total);
+      const vault = await fetchVault(program, user);
+      assert.strictEqual(vault.totalFeesPaid.toNumber(), total);
+    });
+  });
+
+  describe("出金の頻度による利率の引き下げ（withdrawal_score）", () => {
+    const PENALTY_BPS = 3000;
+    const DECAY_SECS = 3;
+    let user;
+
+    const rateOf = () =>
+      program.methods
+        .getRateInfo()
+        .accounts({ vault: findVaultPda(program, user.publicKey) })
+        .view();
+    const scoreAt = (vault, now) =>
+      Math.max(
+        0,
+        vault.withdrawalScore -
+          Math.floor((now - vault.withdrawalScoreAt.toNumber()) / DECAY_SECS)
+      );
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+      await program.methods.setRewardStrategy({ linear: {} }).rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await program.methods
+        .setWithdrawalScorePenalty(0, new anchor.BN(0))
+        .rpc();
+    });
+
+    it("減少の間隔なしでは引き下げを設定できない", async () => {
+      await expectError(
+        program.methods
+          .setWithdrawalScorePenalty(PENALTY_BPS, new anchor.BN(0))
+          .rpc(),
+        "InvalidWithdrawalScorePenalty"
+      );
+    });
+
+    it("頻繁に出金すると、その分だけ利率が下がる", async () => {
+      await program.methods
+        .setWithdrawalScorePenalty(PENALTY_BPS, new anchor.BN(DECAY_SECS))
+        .rpc();
+      await withdraw(program, user, 1000);
+      await withdraw(program, user, 1000);
+      const before = await fetchVault(program, user);
+      assert.strictEqual(before.withdrawalScore, 2);
+      const rate = await rateOf();
+      assert.ok(rate.withdrawalBps.toNumber() < 10000);
+
+      // ★確定時点（期間の終わり）のスコアで下げた利率で付く
+      const rewardBefore = await fetchRewardBox(program, user);
+      await deposit(program, user, 1);
+      const after = await fetchVault(program, user);
+      const rewardAfter = await fetchRewardBox(program, user);
+      const end = after.lastUpdateTime.toNumber();
+      const rateBps = 10000 - scoreAt(before, end) * PENALTY_BPS;
+      const expected = before.balance
+        .mul(after.lastUpdateTime.sub(before.lastUpdateTime))
+        .muln(rateBps)
+        .div(new anchor.BN(100_000_000));
+      const reward = rewardAfter.balance.sub(rewardBefore.balance);
+      const extra = reward.sub(expected).toNumber();
+      assert.ok(extra === 0 || extra === 1, `${reward} vs ${expected}`);
+    });
+
+    it("出金しない期間が続くと元の利率に戻る", async () => {
+      await sleep((2 * DECAY_SECS + 1) * 1000);
+      const rate = await rateOf();
+      assert.strictEqual(rate.withdrawalBps.toNumber(), 10000);
+      assert.strictEqual(rate.effectiveBps.toNumber(), 10000);
+
+      // 次の出金は0から数え直す
+      await withdraw(program, user, 1000);
+      assert.strictEqual((await fetchVault(program, user)).withdrawalScore, 1);
+    });
+  });
+
+  describe("手数料の免除（buy_fee_exemption）", () => {
+    const PRICE = LAMPORTS_PER_SOL / 10;
+    let user;
+
+    const treasuryLamports = () =>
+      provider.connection.getBalance(findTreasuryPda(program));
+    const buy = () =>
+      program.methods
+        .buyFeeExemption()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+      await program.methods.setFees(100, 100, new anchor.BN(0)).rpc();
+      await program.methods.setClaimFee(1000).rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+    });
+
+    after(async () => {
+      await program.methods.setFees(0, 0, new anchor.BN(0)).rpc();
+      await program.methods.setClaimFee(0).rpc();
+      await program.methods.setFeeExemptionPrice(new anchor.BN(0)).rpc();
+    });
+
+    it("価格が決まっていなければ買えない", async () => {
+      await expectError(buy(), "FeeExemptionNotForSale");
+    });
+
+    it("買った後は入金・出金・受け取りの手数料がかからない", async () => {
+      await program.methods.setFeeExemptionPrice(new anchor.BN(PRICE)).rpc();
+      let treasuryBefore = await treasuryLamports();
+      await buy();
+      assert.strictEqual(await treasuryLamports(), treasuryBefore + PRICE);
+      let vault = await fetchVault(program, user);
+      assert.strictEqual(vault.feeExempt, true);
+
+      treasuryBefore = await treasuryLamports();
+      await deposit(program, user, LAMPORTS_PER_SOL);
+      await sleep(1500);
+      await withdraw(program, user, LAMPORTS_PER_SOL / 2);
+      assert.strictEqual(await treasuryLamports(), treasuryBefore);
+      vault = await fetchVault(program, user);
+      assert.strictEqual(vault.balance.toNumber(), LAMPORTS_PER_SOL / 2);
+
+      const sig = await program.methods
+        .claimReward()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          user: user.publicKey,
+          leaderboard: null,
+        })
+        .signers([user])
+        .rpc();
+      const claimed = (await eventsOf(program, provider, sig)).find(
+        (e) => e.name === "rewardClaimedEvent"
+      );
+      assert.ok(claimed.data.amount.gtn(0));
+      assert.strictEqual(claimed.data.fee.toNumber(), 0);
+
+      // 払った手数料は免除の購入額だけ
+      vault = await fetchVault(program, user);
+      assert.strictEqual(vault.totalFeesPaid.toNumber(), PRICE);
+    });
+
+    it("二度は買えない", async () => {
+      await expectError(buy(), "AlreadyFeeExempt");
+    });
+  });
+
+  describe("RewardAccruedEvent のまとめ出し（accrued_event_threshold）", () => {
+    // 2 SOL なら毎秒 2e5 ポイント付くので、数回の確定ごとに1回出る
+    const THRESHOLD = new anchor.BN(500_000);
+    let user;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await program.methods.setAccruedEventThreshold(THRESHOLD).rpc();
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 2 * LAMPORTS_PER_SOL);
+    });
+
+    after(async () => {
+      await program.methods.setAccruedEventThreshold(new anchor.BN(0)).rpc();
+    });
+
+    it("出していない確定額の合計が閾値に達したときだけ出し、残高は毎回増える", async () => {
+      let pending = new anchor.BN(0);
+      let emitted = 0;
+      const ROUNDS = 6;
+      for (let i = 0; i < ROUNDS; i++) {
+        await sleep(1000);
+        const before = await fetchRewardBox(program, user);
+        const sig = await deposit(program, user, 1);
+        const after = await fetchRewardBox(program, user);
+        const accrued = after.balance.sub(before.balance);
+        assert.ok(accrued.gtn(0));
+        pending = pending.add(accrued);
+
+        const event = (await eventsOf(program, provider, sig)).find(
+          (e) => e.name === "rewardAccruedEvent"
+        );
+        if (event) {
+          assert.ok(pending.gte(THRESHOLD));
+          assert.ok(event.data.amount.eq(pending));
+          assert.ok(event.data.rewardBoxBalance.eq(after.balance));
+          pending = new anchor.BN(0);
+          emitted++;
+        } else {
+          assert.ok(pending.lt(THRESHOLD));
+        }
+        const vault = await fetchVault(program, user);
+        assert.ok(vault.unemittedReward.eq(pending));
+      }
+      assert.ok(emitted >= 1 && emitted < ROUNDS, `emitted ${emitted}`);
+    });
+  });
+
+  describe("集計前のリワードの計上（migrate_rewards）", () => {
+    // tests/fixtures/unmigrated_reward_vault(_reward).json は 1 SOL を預け、
+    // 集計に含まれていない 5,000,000 ポイントを持つ（last_update_time は未来なので増えない）
+    const POINTS = 5_000_000;
+    const user = Keypair.fromSeed(new Uint8Array(32).fill(232));
+
+    const migrate = (owner, rewardBox = findRewardPda(program, owner)) =>
+      program.methods
+        .migrateRewards()
+        .accounts({ vault: findVaultPda(program, owner), rewardBox })
+        .rpc();
+    const outstanding = async () =>
+      (
+        await program.account.globalEmission.fetch(
+          findGlobalEmissionPda(program)
+        )
+      ).outstandingLiabilities;
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureGlobalEmission(program);
+      await ensureTreasury(program);
+      const sig = await provider.connection.requestAirdrop(
+        user.publicKey,
+        10 * LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(sig);
+    });
+
+    after(async () => {
+      await fundTreasury(program, LAMPORTS_PER_SOL);
+    });
+
+    it("作成時から集計している RewardBox は計上できない", async () => {
+      const other = await createUser(provider);
+      await initVault(program, other);
+      await expectError(migrate(other.publicKey), "RewardsAlreadyRecorded");
+    });
+
+    it("Vault と組になっていない RewardBox は渡せない", async () => {
+      const other = await createUser(provider);
+      await initVault(program, other);
+      await expectError(
+        migrate(other.publicKey, findRewardPda(program, user.publicKey)),
+        "ConstraintSeeds"
+      );
+    });
+
+    it("version 1 の RewardBox は migrate_vault で集計済みになり、計上できない", async () => {
+      // tests/fixtures/tracked_v1_reward_vault(_reward).json は liability_recorded 追加前の
+      // version 1 で、作成時から集計に含まれている 3,000,000 ポイントを持つ
+      const tracked = Keypair.fromSeed(new Uint8Array(32).fill(233));
+      const rewardPda = findRewardPda(program, tracked.publicKey);
+      const sig = await provider.connection.requestAirdrop(
+        tracked.publicKey,
+        LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(sig);
+      await expectError(
+        migrate(tracked.publicKey),
+        "UnsupportedAccountVersion"
+      );
+
+      await program.methods
+        .migrateVault()
+        .accounts({ user: tracked.publicKey })
+        .signers([tracked])
+        .rpc();
+      const box = await program.account.rewardBox.fetch(rewardPda);
+      assert.strictEqual(box.version, 2);
+      assert.strictEqual(box.liabilityRecorded, true);
+
+      const before = await outstanding();
+      await expectError(migrate(tracked.publicKey), "RewardsAlreadyRecorded");
+      assert.ok((await outstanding()).eq(before));
+    });
+
+    it("集計前のポイントは、集計済みの RewardBox との間で移せない", async () => {
+      // 分割で作る名前付きVaultの RewardBox は作成時から集計済み
+      const NAME = "tracked";
+      await program.methods
+        .splitVault(new anchor.BN(LAMPORTS_PER_SOL / 2), NAME)
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: findRewardPda(program, user.publicKey),
+          newVault: findVaultPda(program, user.publicKey, NAME),
+          newRewardBox: findRewardPda(program, user.publicKey, NAME),
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+
+      await expectError(
+        program.methods
+          .moveRewards(new anchor.BN(1))
+          .accounts({
+            fromVault: findVaultPda(program, user.publicKey),
+            fromRewardBox: findRewardPda(program, user.publicKey),
+            toVault: findVaultPda(program, user.publicKey, NAME),
+            toRewardBox: findRewardPda(program, user.publicKey, NAME),
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc(),
+        "LiabilityTrackingMismatch"
+      );
+      await expectError(
+        program.methods
+          .mergeVaults()
+          .accounts({
+            sourceVault: findVaultPda(program, user.publicKey, NAME),
+            sourceRewardBox: findRewardPda(program, user.publicKey, NAME),
+            vault: findVaultPda(program, user.publicKey),
+            rewardBox: findRewardPda(program, user.publicKey),
+            user: user.publicKey,
+          })
+          .signers([user])
+          .rpc(),
+        "LiabilityTrackingMismatch"
+      );
+      const box = await fetchRewardBox(program, user);
+      assert.strictEqual(box.balance.toNumber(), POINTS);
+    });
+
+    it("version 0 の RewardBox は計上するまで集計に触れず、計上してもずれは増えない", async () => {
+      // tests/fixtures/v0_reward_vault(_reward).json は version 0 で、
+      // 集計に含まれていない 4,000,000 ポイントを持つ（last_update_time は未来）
+      const legacy = Keypair.fromSeed(new Uint8Array(32).fill(234));
+      const vaultPda = findVaultPda(program, legacy.publicKey);
+      const rewardPda = findRewardPda(program, legacy.publicKey);
+      const sig = await provider.connection.requestAirdrop(
+        legacy.publicKey,
+        LAMPORTS_PER_SOL
+      );
+      await provider.connection.confirmTransaction(sig);
+      await program.methods
+        .migrateVault()
+        .accounts({ user: legacy.publicKey })
+        .signers([legacy])
+        .rpc();
+      let box = await program.account.rewardBox.fetch(rewardPda);
+      assert.strictEqual(box.version, 2);
+      assert.strictEqual(box.liabilityRecorded, false);
+
+      // 移行後に確定したリワードも、計上するまでは集計に加わらない
+      await program.methods
+        .repairTimestamp()
+        .accounts({ vault: vaultPda })
+        .rpc();
+      const before = await outstanding();
+      await sleep(1500);
+      await deposit(program, legacy, 1);
+      box = await program.account.rewardBox.fetch(rewardPda);
+      assert.ok(box.balance.gtn(4_000_000));
+      assert.ok((await outstanding()).eq(before));
+
+      const trackedKeys = async () =>
+        (await program.account.rewardBox.all())
+          .filter((b) => b.account.liabilityRecorded)
+          .map((b) => b.publicKey);
+      const baseline = await auditRewardBoxes(program, await trackedKeys());
+
+      const shortfall =
+        before.add(box.balance).toNumber() -
+        (await treasuryAvailable(program, provider));
+      if (shortfall > 0) {
+        await fundTreasury(program, shortfall);
+      }
+      await migrate(legacy.publicKey);
+      assert.ok((await outstanding()).eq(before.add(box.balance)));
+
+      // 計上した RewardBox を含めても、ずれは計上前と変わらない（増えたずれは0）
+      const audited = await auditRewardBoxes(program, await trackedKeys());
+      assert.strictEqual(
+        audited.discrepancy.sub(baseline.discrepancy).toNumber(),
+        0
+      );
+    });
+
+    it("原資が足りるときだけ計上し、計上したリワードは受け取れる", async () => {
+      const rewardPda = findRewardPda(program, user.publicKey);
+      const before = await outstanding();
+
+      // 計上後の未払いリワードに1 lamport足りない原資にする
+      const target = before.toNumber() + POINTS - 1;
+      const available = await treasuryAvailable(program, provider);
+      if (available > target) {
+        await program.methods
+          .adminDrainTreasury(new anchor.BN(available - target))
+          .rpc();
+      } else {
+        await fundTreasury(program, target - available);
+      }
+      await expectError(migrate(user.publicKey), "InsufficientTreasury");
+
+      await fundTreasury(program, 1);
+      const sig = await migrate(user.publicKey);
+      const migrated = (await eventsOf(program, provider, sig)).find(
+        (e) => e.name === "rewardsMigratedEvent"
+      );
+      assert.strictEqual(migrated.data.points.toNumber(), POINTS);
+      const after = await outstanding();
+      assert.ok(after.eq(before.addn(POINTS)));
+      assert.ok(migrated.data.outstandingLiabilities.eq(after));
+      const box = await program.account.rewardBox.fetch(rewardPda);
+      assert.strictEqual(box.liabilityRecorded, true);
+      await expectError(migrate(user.publicKey), "RewardsAlreadyRecorded");
+
+      // 受け取ると、計上した分だけ未払いリワードが減る
+      const walletBefore = await provider.connection.getBalance(user.publicKey);
+      await program.methods
+        .claimReward()
+        .accounts({
+          vault: findVaultPda(program, user.publicKey),
+          rewardBox: rewardPda,
+          user: user.publicKey,
+          leaderboard: null,
+        })
+        .signers([user])
+        .rpc();
+      const walletAfter = await provider.connection.getBalance(user.publicKey);
+      assert.ok(walletAfter > walletBefore + POINTS / 2);
+      assert.ok((await fetchRewardBox(program, user)).balance.eqn(0));
+      assert.ok((await outstanding()).eq(before));
+    });
+  });
+
+  describe("入金元への出金（withdraw_to_source）", () => {
+    let user;
+    let vaultPda;
+    let sourcesPda;
+
+    const withdrawToSource = (amount, destination) =>
+      program.methods
+        .withdrawToSource(new anchor.BN(amount))
+        .accounts({
+          vault: vaultPda,
+          rewardBox: findRewardPda(program, user.publicKey),
+          depositSources: sourcesPda,
+          destination,
+          user: user.publicKey,
+        })
+        .signers([user])
+        .rpc();
+
+    before(async () => {
+      await ensureConfig(program);
+      await ensureTreasury(program);
+      await ensureGlobalEmission(program);
+      user = await createUser(provider);
+      await initVault(program, user);
+      vaultPda = findVaultPda(program, user.publicKey);
+      sourcesPda = anchor.web3.PublicKey.findProgramAddressSync(
+        [Buffer.from("deposit_sources"), vaultPda.toBuffer()],
+        program.programId
+      )[0];
+      await program.methods
+        .enableDepositSources()
+        .accounts({ vault: vaultPda, user: user.publicKey })
+        .signers([user])
+        .rpc();
+      await program.methods
+        .deposit(new anchor.BN(1_000_000), null)
+        .accounts({
+          vault: vaultPda,
+          rewardBox: findRewardPda(program, user.publicKey),
+          matchPool: null,
+          depositSources: sourcesPda,
+          user: user.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([user])
+        .rpc();
+    });
+
+    it("入金したウォレットが入金元として記録される", async () => {
+      const sources = await program.account.depositSources.fetch(sourcesPda);
+      assert.equal(sources.len, 1);
+      assert.ok(sources.sources[0].equals(user.publicKey));
+      assert.ok(sources.vault.equals(vaultPda));
+    });
+
+    it("記録された入金元へは出金できる", async () => {
+      const before = (await fetchVault(program, user)).balance.toNumber();
+      await withdrawToSource(400_000, user.publicKey);
+      const after = (await fetchVault(program, user)).balance.toNumber();
+      assert.equal(before - after, 400_000);
+    });
+
+    it("記録されていないアドレスへは出金できない", async () => {
+      const other = Keypair.generate().publicKey;
+      await expectError(withdrawToSource(100_000, other), "NotDepositSource");
+    });
+  });
+
+  describe("レント免除の最低残高（get_rent_reserve）", () => {
+    let user;
+
+    before(async () => {
+      user = await createUser(provider);
+      await initVault(program, user);
+      await deposit(program, user, 1_000_000);
+    });
+
+    it("Vaultのサイズに必要なレントと一致する", async () => {
+      const vaultPda = findVaultPda(program, user.publicKey);
+      const reserve = await program.methods
+        .getRentReserve()
+        .accounts({ vault: vaultPda })
+        .view();
+      const expected =
+        await provider.connection.getMinimumBalanceForRentExemption(
+          program.account.vault.size
+        );
+      assert.equal(reserve.toNumber(), expected);
+
+      // ★出金できる最大額（balance）を引いた残りがレント分になる
+      const lamports = await provider.connection.getBalance(vaultPda);
+      const maxWithdrawable = (await fetchVault(program, user)).balance;
+      assert.equal(lamports - maxWithdrawable.toNumber(), reserve.toNumber());
+    });
+  });
+});